@@ -0,0 +1,349 @@
+use clap::ValueEnum;
+use termwiz::cell::{AttributeChange, CellAttributes, Intensity, Underline};
+use termwiz::color::ColorAttribute;
+use termwiz::escape::csi::{Cursor, Edit, EraseInDisplay, EraseInLine, Sgr, CSI};
+use termwiz::escape::{parser::Parser, Action, ControlCode};
+use termwiz::surface::{Change, Line, Position, Surface};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Text,
+    Html,
+}
+
+pub fn render(history: &[u8], format: ExportFormat) -> String {
+    let segments = transcript_segments(history);
+    match format {
+        ExportFormat::Text => segments.into_iter().map(|(_, text)| text).collect(),
+        ExportFormat::Html => render_html(&segments),
+    }
+}
+
+/// Columns of the headless screen the history is replayed into; wide enough
+/// that agent output rarely wraps earlier than it did on screen.
+const EXPORT_COLUMNS: usize = 200;
+const EXPORT_ROWS: usize = 50;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct SpanStyle {
+    foreground: ColorAttribute,
+    background: ColorAttribute,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+}
+
+impl SpanStyle {
+    fn from_attributes(attrs: &CellAttributes) -> Self {
+        Self {
+            foreground: attrs.foreground(),
+            background: attrs.background(),
+            bold: attrs.intensity() == Intensity::Bold,
+            italic: attrs.italic(),
+            underline: attrs.underline() != Underline::None,
+        }
+    }
+}
+
+/// Replays the history into a headless termwiz surface, the way the TUI
+/// renders a pane, so carriage returns, cursor movement and erases rewrite
+/// earlier output instead of leaking into the transcript. Rows scrolled off
+/// the top are kept as the start of the transcript.
+struct Transcript {
+    surface: Surface,
+    scrollback: Vec<Line>,
+}
+
+impl Transcript {
+    fn new() -> Self {
+        Self {
+            surface: Surface::new(EXPORT_COLUMNS, EXPORT_ROWS),
+            scrollback: Vec::new(),
+        }
+    }
+
+    fn apply(&mut self, action: Action) {
+        match action {
+            Action::Print(ch) => self.print(&ch.to_string()),
+            Action::PrintString(text) => self.print(&text),
+            Action::Control(ControlCode::LineFeed) => self.print("\n"),
+            Action::Control(ControlCode::CarriageReturn) => self.print("\r"),
+            Action::Control(ControlCode::HorizontalTab) => {
+                let (x, _) = self.surface.cursor_position();
+                self.move_cursor(Position::Absolute((x / 8 + 1) * 8), Position::Relative(0));
+            }
+            Action::Control(ControlCode::Backspace) => {
+                self.move_cursor(Position::Relative(-1), Position::Relative(0));
+            }
+            Action::CSI(CSI::Sgr(sgr)) => self.apply_sgr(sgr),
+            Action::CSI(CSI::Cursor(cursor)) => self.apply_cursor(cursor),
+            Action::CSI(CSI::Edit(Edit::EraseInLine(EraseInLine::EraseToEndOfLine))) => {
+                self.surface
+                    .add_change(Change::ClearToEndOfLine(ColorAttribute::Default));
+            }
+            Action::CSI(CSI::Edit(Edit::EraseInLine(EraseInLine::EraseLine))) => {
+                let (x, _) = self.surface.cursor_position();
+                self.move_cursor(Position::Absolute(0), Position::Relative(0));
+                self.surface
+                    .add_change(Change::ClearToEndOfLine(ColorAttribute::Default));
+                self.move_cursor(Position::Absolute(x), Position::Relative(0));
+            }
+            Action::CSI(CSI::Edit(Edit::EraseInDisplay(EraseInDisplay::EraseToEndOfDisplay))) => {
+                self.surface
+                    .add_change(Change::ClearToEndOfScreen(ColorAttribute::Default));
+            }
+            _ => {}
+        }
+    }
+
+    /// Prints text, keeping any row the surface is about to scroll away.
+    fn print(&mut self, text: &str) {
+        for ch in text.chars() {
+            let (width, height) = self.surface.dimensions();
+            let (x, y) = self.surface.cursor_position();
+            let advances_row = ch == '\n' || (ch != '\r' && x >= width);
+            if advances_row && y + 1 >= height {
+                if let Some(line) = self.surface.screen_lines().first() {
+                    self.scrollback.push(line.clone().into_owned());
+                }
+            }
+            self.surface.add_change(Change::Text(ch.to_string()));
+        }
+    }
+
+    fn move_cursor(&mut self, x: Position, y: Position) {
+        self.surface.add_change(Change::CursorPosition { x, y });
+    }
+
+    fn apply_cursor(&mut self, cursor: Cursor) {
+        match cursor {
+            Cursor::Up(count) => {
+                self.move_cursor(Position::Relative(0), Position::Relative(-(count as isize)))
+            }
+            Cursor::Down(count) => {
+                self.move_cursor(Position::Relative(0), Position::Relative(count as isize))
+            }
+            Cursor::Left(count) => {
+                self.move_cursor(Position::Relative(-(count as isize)), Position::Relative(0))
+            }
+            Cursor::Right(count) => {
+                self.move_cursor(Position::Relative(count as isize), Position::Relative(0))
+            }
+            Cursor::CharacterAbsolute(col) | Cursor::CharacterPositionAbsolute(col) => self
+                .move_cursor(
+                    Position::Absolute(col.as_zero_based() as usize),
+                    Position::Relative(0),
+                ),
+            Cursor::Position { line, col } => self.move_cursor(
+                Position::Absolute(col.as_zero_based() as usize),
+                Position::Absolute(line.as_zero_based() as usize),
+            ),
+            _ => {}
+        }
+    }
+
+    fn apply_sgr(&mut self, sgr: Sgr) {
+        let change = match sgr {
+            Sgr::Reset => Change::AllAttributes(CellAttributes::default()),
+            Sgr::Intensity(value) => Change::Attribute(AttributeChange::Intensity(value)),
+            Sgr::Italic(value) => Change::Attribute(AttributeChange::Italic(value)),
+            Sgr::Underline(value) => Change::Attribute(AttributeChange::Underline(value)),
+            Sgr::Foreground(color) => {
+                Change::Attribute(AttributeChange::Foreground(ColorAttribute::from(color)))
+            }
+            Sgr::Background(color) => {
+                Change::Attribute(AttributeChange::Background(ColorAttribute::from(color)))
+            }
+            _ => return,
+        };
+        self.surface.add_change(change);
+    }
+
+    /// Styled text runs for every row, with trailing blank cells and blank
+    /// rows below the last output dropped.
+    fn segments(&self) -> Vec<(SpanStyle, String)> {
+        let screen = self.surface.screen_lines();
+        let last_row = screen
+            .iter()
+            .rposition(|line| !line.as_str().trim_end().is_empty())
+            .map_or(0, |row| row + 1);
+        let mut segments: Vec<(SpanStyle, String)> = Vec::new();
+        let mut push = |style: SpanStyle, text: &str| match segments.last_mut() {
+            Some((last, buffer)) if *last == style => buffer.push_str(text),
+            _ => segments.push((style, text.to_string())),
+        };
+        let rows = self
+            .scrollback
+            .iter()
+            .chain(screen[..last_row].iter().map(|line| &**line));
+        for line in rows {
+            let cells: Vec<_> = line.visible_cells().collect();
+            let used = cells
+                .iter()
+                .rposition(|cell| {
+                    cell.str() != " " || cell.attrs().background() != ColorAttribute::Default
+                })
+                .map_or(0, |index| index + 1);
+            for cell in &cells[..used] {
+                push(SpanStyle::from_attributes(cell.attrs()), cell.str());
+            }
+            push(SpanStyle::default(), "\n");
+        }
+        segments
+    }
+}
+
+/// Replays the byte stream into styled text runs.
+fn transcript_segments(history: &[u8]) -> Vec<(SpanStyle, String)> {
+    let mut transcript = Transcript::new();
+    let mut parser = Parser::new();
+    parser.parse(history, |action| transcript.apply(action));
+    transcript.segments()
+}
+
+fn render_html(segments: &[(SpanStyle, String)]) -> String {
+    let mut html = String::from("<pre class=\"workforest-transcript\">");
+    for (style, text) in segments {
+        let css = span_css(style);
+        if css.is_empty() {
+            html.push_str(&escape_html(text));
+        } else {
+            html.push_str(&format!(
+                "<span style=\"{}\">{}</span>",
+                css,
+                escape_html(text)
+            ));
+        }
+    }
+    html.push_str("</pre>\n");
+    html
+}
+
+fn span_css(style: &SpanStyle) -> String {
+    let mut rules = Vec::new();
+    if let Some(color) = css_color(&style.foreground) {
+        rules.push(format!("color:{color}"));
+    }
+    if let Some(color) = css_color(&style.background) {
+        rules.push(format!("background-color:{color}"));
+    }
+    if style.bold {
+        rules.push("font-weight:bold".to_string());
+    }
+    if style.italic {
+        rules.push("font-style:italic".to_string());
+    }
+    if style.underline {
+        rules.push("text-decoration:underline".to_string());
+    }
+    rules.join(";")
+}
+
+fn css_color(color: &ColorAttribute) -> Option<String> {
+    let (red, green, blue) = match color {
+        ColorAttribute::Default => return None,
+        ColorAttribute::PaletteIndex(index) => palette_rgb(*index),
+        ColorAttribute::TrueColorWithPaletteFallback(color, _)
+        | ColorAttribute::TrueColorWithDefaultFallback(color) => {
+            let (red, green, blue, _) = color.to_srgb_u8();
+            (red, green, blue)
+        }
+    };
+    Some(format!("#{red:02x}{green:02x}{blue:02x}"))
+}
+
+/// Resolves an xterm 256-color palette index to RGB.
+fn palette_rgb(index: u8) -> (u8, u8, u8) {
+    const ANSI: [(u8, u8, u8); 16] = [
+        (0x00, 0x00, 0x00),
+        (0xcd, 0x00, 0x00),
+        (0x00, 0xcd, 0x00),
+        (0xcd, 0xcd, 0x00),
+        (0x00, 0x00, 0xee),
+        (0xcd, 0x00, 0xcd),
+        (0x00, 0xcd, 0xcd),
+        (0xe5, 0xe5, 0xe5),
+        (0x7f, 0x7f, 0x7f),
+        (0xff, 0x00, 0x00),
+        (0x00, 0xff, 0x00),
+        (0xff, 0xff, 0x00),
+        (0x5c, 0x5c, 0xff),
+        (0xff, 0x00, 0xff),
+        (0x00, 0xff, 0xff),
+        (0xff, 0xff, 0xff),
+    ];
+    match index {
+        0..=15 => ANSI[index as usize],
+        16..=231 => {
+            let cube = index - 16;
+            let level = |value: u8| if value == 0 { 0 } else { 55 + value * 40 };
+            (level(cube / 36), level((cube / 6) % 6), level(cube % 6))
+        }
+        _ => {
+            let grey = 8 + (index - 232) * 10;
+            (grey, grey, grey)
+        }
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(ch),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn text_export_strips_escapes() {
+        let history = b"\x1b[1;31merror\x1b[0m: \x1b[Kdone\r\n";
+        assert_eq!(render(history, ExportFormat::Text), "error: done\n");
+    }
+
+    #[test]
+    fn text_export_keeps_only_redrawn_output() {
+        let history = b"progress 50%\rprogress 100%\r\none\r\ntwo\r\n\x1b[2A\x1b[2Kuno\r\n";
+        assert_eq!(
+            render(history, ExportFormat::Text),
+            "progress 100%\nuno\ntwo\n"
+        );
+    }
+
+    #[test]
+    fn text_export_keeps_rows_scrolled_off_the_screen() {
+        let history: String = (0..EXPORT_ROWS * 2)
+            .map(|line| format!("line {line}\r\n"))
+            .collect();
+        let text = render(history.as_bytes(), ExportFormat::Text);
+        assert_eq!(text, history.replace("\r\n", "\n"));
+    }
+
+    #[test]
+    fn html_export_wraps_colored_runs_in_spans() {
+        let history = b"\x1b[31mred\x1b[0m plain \x1b[1;38;2;1;2;3m<rgb>\x1b[m";
+        assert_eq!(
+            render(history, ExportFormat::Html),
+            "<pre class=\"workforest-transcript\">\
+             <span style=\"color:#cd0000\">red</span> plain \
+             <span style=\"color:#010203;font-weight:bold\">&lt;rgb&gt;</span>\n\
+             </pre>\n"
+        );
+    }
+
+    #[test]
+    fn palette_indices_resolve_to_xterm_colors() {
+        assert_eq!(palette_rgb(9), (0xff, 0x00, 0x00));
+        assert_eq!(palette_rgb(196), (0xff, 0x00, 0x00));
+        assert_eq!(palette_rgb(244), (0x80, 0x80, 0x80));
+    }
+}
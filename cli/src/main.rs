@@ -1,8 +1,21 @@
 use clap::{Parser, Subcommand};
+use export::ExportFormat;
 use reqwest::blocking::Client;
-use serde::Deserialize;
-use std::{error::Error, path::PathBuf, process::Command, thread, time::Duration};
-use workforest_core::config_dir;
+use rusqlite::{Connection, OpenFlags};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    io::{BufRead, BufReader, Read, Write},
+    net::Shutdown,
+    os::unix::net::UnixStream,
+    path::{Path, PathBuf},
+    process::Command,
+    thread,
+    time::Duration,
+};
+use workforest_core::{config_dir, data_dir, encode_agent_token};
+
+mod export;
 
 #[derive(Parser)]
 #[command(name = "workforest")]
@@ -14,6 +27,52 @@ struct Cli {
 #[derive(Subcommand)]
 enum Commands {
     StopServer,
+    /// List agents and their status.
+    Status {
+        /// Read the database directly when the server is not running
+        /// instead of starting it.
+        #[arg(long)]
+        offline: bool,
+        /// Print the agent array as JSON instead of a table.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print an agent's session transcript.
+    Export {
+        name: String,
+        #[arg(long, value_enum, default_value = "text")]
+        format: ExportFormat,
+    },
+    /// Print an agent's scrollback history.
+    Logs {
+        name: String,
+        /// Keep escape sequences instead of printing plain text.
+        #[arg(long)]
+        raw: bool,
+    },
+    /// Print agent status changes as JSON lines until interrupted.
+    Watch {
+        /// Only print transitions into this status.
+        #[arg(long)]
+        filter: Option<String>,
+    },
+}
+
+/// An agent status change as published on the server's `/events` stream.
+#[derive(Debug, Deserialize, Serialize, PartialEq)]
+struct StatusEvent {
+    name: String,
+    from: Option<String>,
+    to: String,
+    timestamp: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct AgentRow {
+    name: String,
+    repo: String,
+    tool: String,
+    status: String,
 }
 
 #[derive(Deserialize)]
@@ -28,6 +87,10 @@ fn main() -> Result<(), Box<dyn Error>> {
 
     match cli.command {
         Some(Commands::StopServer) => stop_server(),
+        Some(Commands::Status { offline, json }) => print_status(offline, json),
+        Some(Commands::Export { name, format }) => export_transcript(&name, format),
+        Some(Commands::Logs { name, raw }) => print_logs(&name, raw),
+        Some(Commands::Watch { filter }) => watch_events(filter.as_deref()),
         None => run_tui(),
     }
 }
@@ -75,12 +138,203 @@ fn stop_server() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+fn print_status(offline: bool, json: bool) -> Result<(), Box<dyn Error>> {
+    let running = read_metadata()?.filter(|metadata| is_server_alive(metadata.port));
+    let agents: Vec<serde_json::Value> = match running {
+        None if offline => read_agents_offline(&data_dir().join("app.db"))?
+            .into_iter()
+            .map(serde_json::to_value)
+            .collect::<Result<_, _>>()?,
+        running => {
+            let metadata = match running {
+                Some(metadata) => metadata,
+                None => ensure_server_running()?,
+            };
+            let base = format!("http://127.0.0.1:{}", metadata.port);
+            let client = Client::new();
+            let mut agents: Vec<serde_json::Value> = client
+                .get(format!("{base}/agents"))
+                .send()?
+                .error_for_status()?
+                .json()?;
+            let outputs: Vec<AgentStatus> = client
+                .get(format!("{base}/agents/output"))
+                .send()?
+                .error_for_status()?
+                .json()?;
+            apply_live_status(&mut agents, &outputs);
+            agents
+        }
+    };
+    if json {
+        println!("{}", serde_json::to_string_pretty(&agents)?);
+    } else {
+        let rows = agents
+            .into_iter()
+            .map(serde_json::from_value)
+            .collect::<Result<Vec<AgentRow>, _>>()?;
+        print!("{}", format_agent_table(&rows));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct AgentStatus {
+    name: String,
+    status: String,
+}
+
+/// The stored status only changes on explicit actions; `/agents/output`
+/// reports whether each session is actually running.
+fn apply_live_status(agents: &mut [serde_json::Value], outputs: &[AgentStatus]) {
+    for agent in agents {
+        let name = agent.get("name").and_then(|name| name.as_str());
+        if let Some(live) = outputs
+            .iter()
+            .find(|output| Some(output.name.as_str()) == name)
+        {
+            agent["status"] = serde_json::Value::String(live.status.clone());
+        }
+    }
+}
+
+/// Lists agents straight from the server's database, opened read-only so
+/// nothing is started or modified.
+fn read_agents_offline(db_path: &Path) -> Result<Vec<AgentRow>, Box<dyn Error>> {
+    if !db_path.exists() {
+        return Ok(Vec::new());
+    }
+    let conn = Connection::open_with_flags(db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+    let mut stmt =
+        conn.prepare("SELECT name, repo, tool, status FROM agents ORDER BY created_at DESC")?;
+    let rows = stmt.query_map([], |row| {
+        Ok(AgentRow {
+            name: row.get(0)?,
+            repo: row.get(1)?,
+            tool: row.get(2)?,
+            status: row.get(3)?,
+        })
+    })?;
+    Ok(rows.collect::<Result<_, _>>()?)
+}
+
+fn format_agent_table(agents: &[AgentRow]) -> String {
+    let headers = ["NAME", "REPO", "TOOL", "STATUS"];
+    let rows: Vec<[&str; 4]> = agents
+        .iter()
+        .map(|agent| [&*agent.name, &*agent.repo, &*agent.tool, &*agent.status])
+        .collect();
+    let mut widths = headers.map(str::len);
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let mut table = String::new();
+    for row in std::iter::once(headers).chain(rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{cell:<width$}"))
+            .collect();
+        table.push_str(cells.join("  ").trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+fn export_transcript(name: &str, format: ExportFormat) -> Result<(), Box<dyn Error>> {
+    let metadata = ensure_server_running()?;
+    let mut url = reqwest::Url::parse(&format!("http://127.0.0.1:{}", metadata.port))?;
+    url.path_segments_mut()
+        .map_err(|_| "server url cannot take a path")?
+        .extend(["agents", name, "history"]);
+    let response = Client::new().get(url).send()?;
+    if !response.status().is_success() {
+        let status = response.status();
+        let message = response.text().unwrap_or_default();
+        return Err(format!("export failed ({status}): {}", message.trim()).into());
+    }
+    let history = response.bytes()?;
+    std::io::stdout().write_all(export::render(&history, format).as_bytes())?;
+    Ok(())
+}
+
+fn print_logs(name: &str, raw: bool) -> Result<(), Box<dyn Error>> {
+    ensure_server_running()?;
+    let history = read_broker_history(&data_dir().join("pty.sock"), name)?;
+    let mut stdout = std::io::stdout();
+    if raw {
+        stdout.write_all(&history)?;
+    } else {
+        stdout.write_all(export::render(&history, ExportFormat::Text).as_bytes())?;
+    }
+    Ok(())
+}
+
+/// Asks the PTY broker for the session's history. `HISTORY` never starts a
+/// session, so reading logs leaves a sleeping agent asleep.
+fn read_broker_history(socket_path: &Path, agent: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut stream = UnixStream::connect(socket_path)?;
+    stream.write_all(format!("HISTORY {}\n", encode_agent_token(agent)).as_bytes())?;
+    let mut reader = BufReader::new(&stream);
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    if let Some(err) = line.strip_prefix("ERR ") {
+        return Err(format!("logs failed: {}", err.trim()).into());
+    }
+    let len: usize = line
+        .strip_prefix("HISTORY ")
+        .and_then(|len| len.trim().parse().ok())
+        .ok_or_else(|| format!("unexpected broker response: {}", line.trim()))?;
+    let mut history = vec![0; len];
+    reader.read_exact(&mut history)?;
+    stream.shutdown(Shutdown::Both)?;
+    Ok(history)
+}
+
+fn watch_events(filter: Option<&str>) -> Result<(), Box<dyn Error>> {
+    let metadata = ensure_server_running()?;
+    let url = format!("http://127.0.0.1:{}/events", metadata.port);
+    // The stream stays open indefinitely, so the default request timeout
+    // would cut it off.
+    let client = Client::builder().timeout(None).build()?;
+    let response = client.get(url).send()?.error_for_status()?;
+    let mut stdout = std::io::stdout();
+    for line in BufReader::new(response).lines() {
+        let line = line?;
+        let Some(data) = line.strip_prefix("data:") else {
+            continue;
+        };
+        let event: StatusEvent = serde_json::from_str(data.trim())?;
+        if matches_filter(&event, filter) {
+            writeln!(stdout, "{}", event_json_line(&event)?)?;
+            stdout.flush()?;
+        }
+    }
+    Ok(())
+}
+
+fn event_json_line(event: &StatusEvent) -> serde_json::Result<String> {
+    serde_json::to_string(event)
+}
+
+fn matches_filter(event: &StatusEvent, filter: Option<&str>) -> bool {
+    filter.is_none_or(|status| event.to == status)
+}
+
 fn ensure_server_running() -> Result<ServerMetadata, Box<dyn Error>> {
     if let Some(metadata) = read_metadata()? {
-        if is_server_alive(metadata.port) {
-            return Ok(metadata);
+        match server_health(metadata.port) {
+            ServerHealth::Healthy => return Ok(metadata),
+            ServerHealth::Degraded(reason) => {
+                return Err(format!(
+                    "server is running but unhealthy ({reason}); restart it with `workforest stop-server`"
+                )
+                .into());
+            }
+            ServerHealth::Down => remove_metadata(),
         }
-        remove_metadata();
     }
 
     start_server()?;
@@ -103,13 +357,27 @@ fn start_server() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn is_server_alive(port: u16) -> bool {
+#[derive(Debug, PartialEq, Eq)]
+enum ServerHealth {
+    Healthy,
+    /// HTTP answers but a subsystem such as the PTY broker is down.
+    Degraded(String),
+    Down,
+}
+
+fn server_health(port: u16) -> ServerHealth {
     let url = format!("http://127.0.0.1:{}/health", port);
-    Client::new()
-        .get(url)
-        .send()
-        .map(|resp| resp.status().is_success())
-        .unwrap_or(false)
+    match Client::new().get(url).send() {
+        Ok(resp) if resp.status().is_success() => ServerHealth::Healthy,
+        Ok(resp) if resp.status() == reqwest::StatusCode::SERVICE_UNAVAILABLE => {
+            ServerHealth::Degraded(resp.text().unwrap_or_default().trim().to_string())
+        }
+        _ => ServerHealth::Down,
+    }
+}
+
+fn is_server_alive(port: u16) -> bool {
+    server_health(port) != ServerHealth::Down
 }
 
 fn wait_for_server_shutdown() {
@@ -142,27 +410,247 @@ fn metadata_path() -> PathBuf {
 }
 
 fn locate_binary(name: &str) -> Result<PathBuf, Box<dyn Error>> {
-    if let Ok(current) = std::env::current_exe() {
-        if let Some(parent) = current.parent() {
-            let candidate = parent.join(name);
-            if candidate.exists() {
-                return Ok(candidate);
-            }
+    let current_exe = std::env::current_exe().ok();
+    let candidates = binary_candidates(name, current_exe.as_deref(), |var| std::env::var_os(var));
+    match candidates.iter().find(|candidate| candidate.is_file()) {
+        Some(found) => Ok(found.clone()),
+        None => Err(binary_not_found_message(name, &candidates).into()),
+    }
+}
+
+/// Every path `locate_binary` checks for `name`, in order: next to the CLI,
+/// the other cargo profile when the CLI runs from a `target` dir, the user
+/// bin dirs (`$XDG_BIN_HOME`, `~/.local/bin`, cargo's bin), then `PATH`.
+fn binary_candidates(
+    name: &str,
+    current_exe: Option<&Path>,
+    var: impl Fn(&str) -> Option<std::ffi::OsString>,
+) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if let Some(exe_dir) = current_exe.and_then(Path::parent) {
+        dirs.push(exe_dir.to_path_buf());
+        if let Some(target) = exe_dir
+            .parent()
+            .filter(|dir| dir.file_name().is_some_and(|name| name == "target"))
+        {
+            dirs.push(target.join("debug"));
+            dirs.push(target.join("release"));
         }
     }
+    let home = var("HOME").map(PathBuf::from);
+    if let Some(xdg_bin) = var("XDG_BIN_HOME") {
+        dirs.push(PathBuf::from(xdg_bin));
+    }
+    if let Some(home) = &home {
+        dirs.push(home.join(".local/bin"));
+    }
+    match var("CARGO_HOME") {
+        Some(cargo_home) => dirs.push(PathBuf::from(cargo_home).join("bin")),
+        None => dirs.extend(home.map(|home| home.join(".cargo/bin"))),
+    }
+    if let Some(paths) = var("PATH") {
+        dirs.extend(std::env::split_paths(&paths));
+    }
+
+    let mut candidates: Vec<PathBuf> = Vec::new();
+    for dir in dirs {
+        let candidate = dir.join(name);
+        if !candidates.contains(&candidate) {
+            candidates.push(candidate);
+        }
+    }
+    candidates
+}
+
+fn binary_not_found_message(name: &str, checked: &[PathBuf]) -> String {
+    let mut message = format!("{name} not found. Checked:");
+    for path in checked {
+        message.push_str(&format!("\n  {}", path.display()));
+    }
+    message.push_str("\nBuild it with `cargo build --workspace` or install it into one of these.");
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if let Some(paths) = std::env::var_os("PATH") {
-        for path in std::env::split_paths(&paths) {
-            let candidate = path.join(name);
-            if candidate.exists() {
-                return Ok(candidate);
+    #[test]
+    fn binary_candidates_cover_cargo_user_and_path_dirs() {
+        let vars = |var: &str| -> Option<std::ffi::OsString> {
+            match var {
+                "HOME" => Some("/home/dev".into()),
+                "XDG_BIN_HOME" => Some("/home/dev/bin".into()),
+                "PATH" => Some("/usr/bin:/home/dev/.local/bin".into()),
+                _ => None,
             }
+        };
+        let candidates = binary_candidates(
+            "workforest-tui",
+            Some(Path::new("/src/workforest/target/release/workforest")),
+            vars,
+        );
+        let expected: Vec<PathBuf> = [
+            "/src/workforest/target/release",
+            "/src/workforest/target/debug",
+            "/home/dev/bin",
+            "/home/dev/.local/bin",
+            "/home/dev/.cargo/bin",
+            "/usr/bin",
+        ]
+        .iter()
+        .map(|dir| Path::new(dir).join("workforest-tui"))
+        .collect();
+        assert_eq!(candidates, expected);
+
+        // Outside a cargo target dir only the CLI's own dir is checked.
+        let candidates = binary_candidates(
+            "workforest-tui",
+            Some(Path::new("/opt/workforest/workforest")),
+            |var| (var == "CARGO_HOME").then(|| "/opt/cargo".into()),
+        );
+        assert_eq!(
+            candidates,
+            [
+                PathBuf::from("/opt/workforest/workforest-tui"),
+                PathBuf::from("/opt/cargo/bin/workforest-tui"),
+            ]
+        );
+    }
+
+    #[test]
+    fn binary_not_found_message_lists_checked_paths() {
+        let message = binary_not_found_message(
+            "workforest-tui",
+            &[
+                PathBuf::from("/opt/workforest/workforest-tui"),
+                PathBuf::from("/usr/bin/workforest-tui"),
+            ],
+        );
+        assert_eq!(
+            message,
+            "workforest-tui not found. Checked:\n  /opt/workforest/workforest-tui\n  /usr/bin/workforest-tui\nBuild it with `cargo build --workspace` or install it into one of these."
+        );
+    }
+
+    #[test]
+    fn offline_status_reads_seeded_database() {
+        let dir = std::env::temp_dir().join(format!("workforest-offline-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("app.db");
+        let conn = Connection::open(&db_path).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE agents (name TEXT PRIMARY KEY, label TEXT NOT NULL, repo TEXT NOT NULL, tool TEXT NOT NULL, status TEXT NOT NULL, worktree_path TEXT NOT NULL, styles TEXT, created_at TEXT NOT NULL, updated_at TEXT NOT NULL);
+             INSERT INTO agents VALUES ('wild-cat', 'wild-cat', 'forest', 'claude', 'sleep', '/tmp/a', NULL, '2024-01-01', '2024-01-01');
+             INSERT INTO agents VALUES ('blue-fox', 'blue-fox', 'forest', 'codex', 'running', '/tmp/b', NULL, '2024-02-01', '2024-02-01');",
+        )
+        .unwrap();
+        drop(conn);
+
+        let agents = read_agents_offline(&db_path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            format_agent_table(&agents),
+            "NAME      REPO    TOOL    STATUS\n\
+             blue-fox  forest  codex   running\n\
+             wild-cat  forest  claude  sleep\n"
+        );
+    }
+
+    fn status_event(from: Option<&str>, to: &str) -> StatusEvent {
+        StatusEvent {
+            name: "wild-cat".to_string(),
+            from: from.map(str::to_string),
+            to: to.to_string(),
+            timestamp: "2024-01-01T00:00:00+00:00".to_string(),
         }
     }
 
-    Err(format!(
-        "{} not found. Build with `cargo build --workspace` or ensure it is in PATH.",
-        name
-    )
-    .into())
+    #[test]
+    fn status_events_serialize_to_single_json_lines() {
+        let event = status_event(Some("sleep"), "running");
+        assert_eq!(
+            event_json_line(&event).unwrap(),
+            r#"{"name":"wild-cat","from":"sleep","to":"running","timestamp":"2024-01-01T00:00:00+00:00"}"#
+        );
+        assert_eq!(
+            event_json_line(&status_event(None, "sleep")).unwrap(),
+            r#"{"name":"wild-cat","from":null,"to":"sleep","timestamp":"2024-01-01T00:00:00+00:00"}"#
+        );
+    }
+
+    #[test]
+    fn watch_filter_matches_transitions_into_status() {
+        let event = status_event(Some("sleep"), "running");
+        assert!(matches_filter(&event, None));
+        assert!(matches_filter(&event, Some("running")));
+        assert!(!matches_filter(&event, Some("sleep")));
+    }
+
+    #[test]
+    fn live_status_overrides_stored_status() {
+        let mut agents = vec![
+            serde_json::json!({"name": "wild-cat", "repo": "forest", "tool": "claude", "status": "running"}),
+            serde_json::json!({"name": "blue-fox", "repo": "forest", "tool": "codex", "status": "sleep"}),
+        ];
+        let outputs = vec![AgentStatus {
+            name: "wild-cat".to_string(),
+            status: "sleep".to_string(),
+        }];
+
+        apply_live_status(&mut agents, &outputs);
+
+        assert_eq!(agents[0]["status"], "sleep");
+        assert_eq!(agents[1]["status"], "sleep");
+        assert_eq!(agents[0]["tool"], "claude");
+    }
+
+    fn fake_broker(reply: &'static [u8]) -> (PathBuf, thread::JoinHandle<String>) {
+        let dir = std::env::temp_dir().join(format!(
+            "workforest-logs-{}-{}",
+            std::process::id(),
+            reply.len()
+        ));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("pty.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&socket_path).unwrap();
+        let handle = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = String::new();
+            BufReader::new(&stream).read_line(&mut request).unwrap();
+            stream.write_all(reply).unwrap();
+            request
+        });
+        (socket_path, handle)
+    }
+
+    #[test]
+    fn logs_read_history_block_from_broker() {
+        let (socket_path, broker) = fake_broker(b"HISTORY 12\n\x1b[1mhi\x1b[0m\r\n");
+
+        let history = read_broker_history(&socket_path, "wild-cat").unwrap();
+        std::fs::remove_dir_all(socket_path.parent().unwrap()).unwrap();
+
+        assert_eq!(broker.join().unwrap(), "HISTORY wild-cat\n");
+        assert_eq!(history, b"\x1b[1mhi\x1b[0m\r\n");
+        assert_eq!(export::render(&history, ExportFormat::Text), "hi\n");
+    }
+
+    #[test]
+    fn logs_surface_broker_errors() {
+        let (socket_path, broker) = fake_broker(b"ERR agent not found\n");
+
+        let err = read_broker_history(&socket_path, "ghost").unwrap_err();
+        std::fs::remove_dir_all(socket_path.parent().unwrap()).unwrap();
+        broker.join().unwrap();
+
+        assert_eq!(err.to_string(), "logs failed: agent not found");
+    }
+
+    #[test]
+    fn offline_status_without_database_is_empty() {
+        let missing = std::env::temp_dir().join("workforest-missing-db/app.db");
+        assert!(read_agents_offline(&missing).unwrap().is_empty());
+    }
 }
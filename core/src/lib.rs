@@ -1,14 +1,163 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::PathBuf;
 
 pub const APP_NAME: &str = "workforest";
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct RepoConfig {
     pub name: String,
     pub path: PathBuf,
-    pub tools: Vec<String>,
+    pub tools: Vec<ToolSpec>,
     pub default_tool: String,
+    /// Directory agent worktrees are created in. Relative paths resolve
+    /// against the repo path; defaults to `data_dir()/trees`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub worktree_root: Option<PathBuf>,
+    /// Prefix generated agent names with the repo name. Overrides the
+    /// `WORKFOREST_PREFIX_AGENT_NAMES` environment default.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix_agent_names: Option<bool>,
+    /// Maximum number of agents that may exist for this repo at once.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_agents: Option<usize>,
+    /// Text typed into every new agent session once the tool is ready,
+    /// followed by Enter.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub initial_input: Option<String>,
+    /// Variables set for every tool session in this repo, on top of the
+    /// environment the server was started with. A tool's own `env` wins,
+    /// and so does the login profile the session's shell reads. The server
+    /// API lists only their names.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+}
+
+/// A tool agents can be started with. In `repos.toml` a tool is either a
+/// plain string, run as a shell command, or a table naming the program,
+/// its arguments and extra environment separately:
+///
+/// ```toml
+/// tools = ["opencode", { name = "opus", command = "claude", args = ["--model", "opus"] }]
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "ToolEntry", into = "ToolEntry")]
+pub struct ToolSpec {
+    /// Shown in tool pickers and recorded as an agent's tool.
+    pub name: String,
+    /// Program to run. Without one, `name` is run as a shell command, as
+    /// plain string entries always are.
+    pub command: Option<String>,
+    pub args: Vec<String>,
+    pub env: BTreeMap<String, String>,
+    /// Set to override the kind guessed by [`ToolSpec::kind`].
+    pub kind: Option<ToolKind>,
+}
+
+/// Whether a tool is a coding agent or an interactive shell. Shells never
+/// finish on their own, so they are badged in the UI and never pruned.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolKind {
+    #[default]
+    Agent,
+    Shell,
+}
+
+const INTERACTIVE_SHELLS: [&str; 7] = ["sh", "bash", "zsh", "fish", "dash", "ksh", "nu"];
+
+impl ToolSpec {
+    /// The configured kind, otherwise [`ToolKind::Shell`] for a known shell
+    /// run without a `-c` script, e.g. `bash` or `/bin/zsh -l`.
+    pub fn kind(&self) -> ToolKind {
+        if let Some(kind) = self.kind {
+            return kind;
+        }
+        let command = self.command.as_deref().unwrap_or(&self.name);
+        let mut words = command
+            .split_whitespace()
+            .chain(self.args.iter().map(String::as_str));
+        let program = words.next().unwrap_or_default();
+        let program = program.rsplit('/').next().unwrap_or(program);
+        let runs_script = words
+            .any(|word| !word.starts_with("--") && word.starts_with('-') && word.contains('c'));
+        if INTERACTIVE_SHELLS.contains(&program) && !runs_script {
+            ToolKind::Shell
+        } else {
+            ToolKind::Agent
+        }
+    }
+}
+
+impl From<&str> for ToolSpec {
+    fn from(command: &str) -> Self {
+        ToolEntry::Command(command.to_string()).into()
+    }
+}
+
+/// How a [`ToolSpec`] is written in config: plain strings stay strings so
+/// existing files round-trip unchanged.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum ToolEntry {
+    Command(String),
+    Spec {
+        name: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        command: Option<String>,
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+        #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+        env: BTreeMap<String, String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        kind: Option<ToolKind>,
+    },
+}
+
+impl From<ToolEntry> for ToolSpec {
+    fn from(entry: ToolEntry) -> Self {
+        match entry {
+            ToolEntry::Command(name) => Self {
+                name,
+                command: None,
+                args: Vec::new(),
+                env: BTreeMap::new(),
+                kind: None,
+            },
+            ToolEntry::Spec {
+                name,
+                command,
+                args,
+                env,
+                kind,
+            } => Self {
+                name,
+                command,
+                args,
+                env,
+                kind,
+            },
+        }
+    }
+}
+
+impl From<ToolSpec> for ToolEntry {
+    fn from(spec: ToolSpec) -> Self {
+        if spec.command.is_none()
+            && spec.args.is_empty()
+            && spec.env.is_empty()
+            && spec.kind.is_none()
+        {
+            return ToolEntry::Command(spec.name);
+        }
+        ToolEntry::Spec {
+            name: spec.name,
+            command: spec.command,
+            args: spec.args,
+            env: spec.env,
+            kind: spec.kind,
+        }
+    }
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
@@ -24,8 +173,16 @@ pub struct TerminalSnapshot {
     pub mouse_button_tracking: bool,
     pub mouse_any_event: bool,
     pub mouse_sgr: bool,
+    #[serde(default)]
+    pub bracketed_paste: bool,
+    #[serde(default)]
+    pub application_cursor_keys: bool,
     pub cursor_visible: bool,
     pub cursor_shape: CursorShape,
+    /// Cursor state of the main screen, saved while the alternate screen is
+    /// active so leaving it restores what the shell had before.
+    #[serde(default)]
+    pub main_cursor: Option<CursorState>,
     pub origin_mode: bool,
     pub wrap_mode: bool,
     pub insert_mode: bool,
@@ -68,6 +225,12 @@ pub struct ModeEntry {
     pub enabled: bool,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct CursorState {
+    pub visible: bool,
+    pub shape: CursorShape,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub enum CursorShape {
     #[default]
@@ -106,6 +269,9 @@ pub enum TerminalUnderline {
     None,
     Single,
     Double,
+    Curly,
+    Dotted,
+    Dashed,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -131,3 +297,89 @@ pub fn data_dir() -> PathBuf {
 pub fn repos_config_path() -> PathBuf {
     config_dir().join("repos.toml")
 }
+
+pub fn server_config_path() -> PathBuf {
+    config_dir().join("config.toml")
+}
+
+/// Encodes an agent name as a single broker protocol token: `%`, whitespace
+/// and control characters become `%XX` so names with spaces survive the
+/// whitespace-separated command line.
+pub fn encode_agent_token(agent: &str) -> String {
+    let mut encoded = String::with_capacity(agent.len());
+    for ch in agent.chars() {
+        if ch == '%' || ch.is_ascii_whitespace() || ch.is_ascii_control() {
+            encoded.push_str(&format!("%{:02X}", ch as u8));
+        } else {
+            encoded.push(ch);
+        }
+    }
+    encoded
+}
+
+/// Reverses [`encode_agent_token`]. Returns `None` for a malformed escape.
+pub fn decode_agent_token(token: &str) -> Option<String> {
+    let bytes = token.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        if bytes[index] == b'%' {
+            let hex = token.get(index + 1..index + 3)?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            index += 3;
+        } else {
+            decoded.push(bytes[index]);
+            index += 1;
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// Parses an on/off setting such as a `WORKFOREST_*` environment flag.
+/// `1`/`true`/`yes`/`on` and `0`/`false`/`no`/`off` are recognised; an unset
+/// or unrecognised value keeps `default`.
+pub fn flag_setting(value: Option<&str>, default: bool) -> bool {
+    match value.map(str::trim) {
+        Some("1" | "true" | "yes" | "on") => true,
+        Some("0" | "false" | "no" | "off") => false,
+        _ => default,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spaced_agent_names_round_trip_through_broker_commands() {
+        let agent = "my agent 100%";
+        let line = format!("RESIZE {} 80 24\n", encode_agent_token(agent));
+        assert_eq!(line, "RESIZE my%20agent%20100%25 80 24\n");
+
+        let mut parts = line.split_whitespace();
+        assert_eq!(parts.next(), Some("RESIZE"));
+        assert_eq!(
+            decode_agent_token(parts.next().unwrap()).as_deref(),
+            Some(agent)
+        );
+        assert_eq!(parts.collect::<Vec<_>>(), ["80", "24"]);
+
+        assert_eq!(decode_agent_token("wild-cat").as_deref(), Some("wild-cat"));
+        assert_eq!(decode_agent_token("bad%2"), None);
+        assert_eq!(decode_agent_token("bad%zz"), None);
+    }
+
+    #[test]
+    fn flag_setting_falls_back_to_the_default() {
+        for value in ["1", "true", "yes", " on "] {
+            assert!(flag_setting(Some(value), false), "{value}");
+        }
+        for value in ["0", "false", "no", " off "] {
+            assert!(!flag_setting(Some(value), true), "{value}");
+        }
+        for value in [None, Some(""), Some("maybe")] {
+            assert!(flag_setting(value, true));
+            assert!(!flag_setting(value, false));
+        }
+    }
+}
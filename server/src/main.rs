@@ -1,48 +1,76 @@
 use axum::{
-    extract::{Path as AxumPath, State},
-    http::StatusCode,
-    response::{IntoResponse, Response},
-    routing::{delete, get, post},
+    extract::{Path as AxumPath, Query, State},
+    http::{header, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{delete, get, post, put},
     Json, Router,
 };
 use chrono::Utc;
-use nix::sys::socket::{sendmsg, ControlMessage, MsgFlags, SockaddrStorage};
+use futures_util::stream::{self, Stream};
+use nix::errno::Errno;
+use nix::sys::signal::{killpg, Signal};
+use nix::sys::socket::{recv, sendmsg, ControlMessage, MsgFlags, SockaddrStorage};
+use nix::unistd::Pid;
 use num_traits::ToPrimitive;
 use petname::petname;
 use portable_pty::{native_pty_system, CommandBuilder, MasterPty, PtySize};
-use rusqlite::{params, Connection};
+use rusqlite::{params, params_from_iter, Connection};
 use serde::{Deserialize, Serialize};
+use settings::settings;
 use std::{
-    collections::{HashMap, HashSet, VecDeque},
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    convert::Infallible,
     error::Error,
-    io::{BufRead, BufReader, IoSlice, Read, Write},
-    net::SocketAddr,
+    ffi::OsStr,
+    io::{self, BufRead, BufReader, IoSlice, Read, Write},
+    net::{Ipv4Addr, SocketAddr},
     os::fd::FromRawFd,
+    os::unix::fs::PermissionsExt,
     os::unix::io::AsRawFd,
     os::unix::net::{UnixListener, UnixStream},
     path::{Path, PathBuf},
     process::{Command, Stdio},
-    sync::{Arc, Mutex},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
     thread,
+    time::{Duration, Instant},
 };
 use termwiz::escape::csi::{
     Cursor, CursorStyle, DecPrivateMode, DecPrivateModeCode, Mode, Sgr, TerminalMode,
     TerminalModeCode,
 };
 use termwiz::escape::esc::EscCode;
-use termwiz::escape::{parser::Parser, Action, Esc};
-use tokio::sync::oneshot;
+use termwiz::escape::{parser::Parser, Action, ControlCode, Esc};
+use tokio::io::AsyncReadExt;
+use tokio::sync::{broadcast, oneshot, watch};
 use workforest_core::{
-    data_dir, repos_config_path, CursorShape, ModeEntry, RepoConfig, RepoConfigFile, ScrollRegion,
-    TerminalAttributes, TerminalBlink, TerminalColor, TerminalIntensity, TerminalSnapshot,
-    TerminalUnderline,
+    data_dir, decode_agent_token, encode_agent_token, repos_config_path, CursorShape, CursorState,
+    ModeEntry, RepoConfig, RepoConfigFile, ScrollRegion, TerminalAttributes, TerminalBlink,
+    TerminalColor, TerminalIntensity, TerminalSnapshot, TerminalUnderline, ToolKind, ToolSpec,
 };
 
+mod settings;
+
 #[derive(Clone)]
 struct AppState {
     shutdown_sender: Arc<tokio::sync::Mutex<Option<oneshot::Sender<()>>>>,
     db: Arc<tokio::sync::Mutex<Connection>>,
     pty_sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+    git_status: Arc<Mutex<HashMap<String, CachedGitStatus>>>,
+    events: broadcast::Sender<StatusEvent>,
+    closing: watch::Receiver<bool>,
+    broker: BrokerHealth,
+}
+
+#[derive(Clone, Copy)]
+struct CachedGitStatus {
+    checked_at: Instant,
+    dirty_count: usize,
 }
 
 struct PtySession {
@@ -51,18 +79,137 @@ struct PtySession {
     child: Box<dyn portable_pty::Child + Send + Sync>,
     size: PtySize,
     history: Arc<Mutex<VecDeque<u8>>>,
+    /// Bytes ever appended to `history`, updated under its lock.
+    written: Arc<AtomicU64>,
     terminal_snapshot: Arc<Mutex<TerminalSnapshot>>,
-    subscribers: Arc<Mutex<Vec<UnixStream>>>,
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+    timings: Option<Arc<Mutex<HistoryTimings>>>,
     _history_handle: thread::JoinHandle<()>,
 }
 
-const HISTORY_LIMIT_BYTES: usize = 2 * 1024 * 1024;
+/// A client receiving a session's output. The id is handed out on attach
+/// so the client can `DETACH` without waiting for a failed write.
+struct Subscriber {
+    id: u64,
+    stream: UnixStream,
+}
+
+static NEXT_SUBSCRIBER_ID: AtomicU64 = AtomicU64::new(1);
+
+impl Subscriber {
+    fn new(stream: UnixStream) -> Self {
+        Self {
+            id: NEXT_SUBSCRIBER_ID.fetch_add(1, Ordering::Relaxed),
+            stream,
+        }
+    }
+
+    /// A subscriber whose reader may stall, such as an HTTP client. Its
+    /// writes never block, so once its socket buffer is full it is dropped
+    /// instead of holding up the session.
+    fn nonblocking(stream: UnixStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        Ok(Self::new(stream))
+    }
+}
+
+/// Offset→time index for a session's history, kept only when timed history
+/// is enabled. Offsets count every byte ever read, so entries stay valid as
+/// the front of the history buffer is trimmed.
+struct HistoryTimings {
+    started: Instant,
+    total_bytes: u64,
+    entries: VecDeque<TimingEntry>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+struct TimingEntry {
+    offset: u64,
+    elapsed_ms: u64,
+}
+
+impl HistoryTimings {
+    fn new(started: Instant) -> Self {
+        Self {
+            started,
+            total_bytes: 0,
+            entries: VecDeque::new(),
+        }
+    }
+
+    fn record(&mut self, chunk_len: usize, now: Instant, retained_len: usize) {
+        self.entries.push_back(TimingEntry {
+            offset: self.total_bytes,
+            elapsed_ms: now.saturating_duration_since(self.started).as_millis() as u64,
+        });
+        self.total_bytes += chunk_len as u64;
+        let retained_start = self.total_bytes.saturating_sub(retained_len as u64);
+        while self
+            .entries
+            .front()
+            .is_some_and(|entry| entry.offset < retained_start)
+        {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Entries with offsets relative to the start of the retained history.
+    fn index_for_history(&self, history_len: usize) -> Vec<TimingEntry> {
+        let start = self.total_bytes.saturating_sub(history_len as u64);
+        self.entries
+            .iter()
+            .filter(|entry| entry.offset >= start)
+            .map(|entry| TimingEntry {
+                offset: entry.offset - start,
+                elapsed_ms: entry.elapsed_ms,
+            })
+            .collect()
+    }
+}
+
+const MAX_DIFF_BYTES: usize = 1024 * 1024;
+const BROKER_MAX_ACCEPT_FAILURES: u32 = 5;
+const BROKER_RETRY_DELAY: Duration = Duration::from_millis(50);
+const INITIAL_INPUT_TIMEOUT: Duration = Duration::from_secs(5);
+const INITIAL_INPUT_SETTLE: Duration = Duration::from_millis(300);
+const STATUS_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const SESSION_EXIT_POLL_INTERVAL: Duration = Duration::from_millis(500);
+const EVENT_CHANNEL_CAPACITY: usize = 64;
+const OUTPUT_TAIL_LINES: usize = 20;
+const OUTPUT_TAIL_BYTES: usize = 16 * 1024;
+const GIT_STATUS_TTL: Duration = Duration::from_secs(15);
+const DB_BUSY_TIMEOUT: Duration = Duration::from_secs(2);
 
 struct PtyBroker {
     socket_path: PathBuf,
+    health: BrokerHealth,
     _handle: thread::JoinHandle<()>,
 }
 
+/// What `/health` knows about the PTY broker: the accept loop clears
+/// `accepting` when it exits, and the socket must still be on disk for
+/// clients to reach it.
+#[derive(Clone)]
+struct BrokerHealth {
+    socket_path: PathBuf,
+    accepting: Arc<AtomicBool>,
+}
+
+impl BrokerHealth {
+    fn check(&self) -> Result<(), String> {
+        if !self.accepting.load(Ordering::Relaxed) {
+            return Err("pty broker stopped accepting connections".to_string());
+        }
+        if !self.socket_path.exists() {
+            return Err(format!(
+                "pty broker socket {} is missing",
+                self.socket_path.display()
+            ));
+        }
+        Ok(())
+    }
+}
+
 impl Drop for PtyBroker {
     fn drop(&mut self) {
         let _ = std::fs::remove_file(&self.socket_path);
@@ -87,11 +234,51 @@ struct Agent {
     output: Option<String>,
     created_at: String,
     updated_at: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    dirty_count: Option<usize>,
 }
 
 #[derive(Deserialize)]
 struct AddRepoRequest {
     path: String,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    dry_run: bool,
+}
+
+#[derive(Deserialize)]
+struct CommitRequest {
+    message: String,
+}
+
+#[derive(Serialize)]
+struct AgentCommit {
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct MergeRequest {
+    /// Branch to merge into; must be the one checked out in the repo.
+    /// Defaults to it.
+    #[serde(default)]
+    into: Option<String>,
+    /// Fail unless the merge is a fast-forward, instead of committing one.
+    #[serde(default)]
+    ff_only: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct AgentMerge {
+    branch: String,
+    into: String,
+    sha: String,
+    fast_forward: bool,
+}
+
+#[derive(Deserialize)]
+struct RenameAgentRequest {
+    label: String,
 }
 
 #[derive(Deserialize)]
@@ -99,6 +286,17 @@ struct AddAgentRequest {
     repo: String,
     tool: String,
     name: Option<String>,
+    /// Start the tool session right away. When false the worktree and row
+    /// are created with status "sleep" and the session starts on first attach.
+    #[serde(default = "default_true")]
+    start: bool,
+    /// Extra arguments appended to the tool command on every launch.
+    #[serde(default)]
+    args: Vec<String>,
+}
+
+fn default_true() -> bool {
+    true
 }
 
 #[derive(Debug)]
@@ -107,11 +305,133 @@ struct ApiError {
     message: String,
 }
 
+#[derive(Serialize)]
+struct ToolAvailability {
+    name: String,
+    available: bool,
+}
+
+#[derive(Deserialize)]
+struct RepoToolsRequest {
+    tools: Vec<ToolSpec>,
+    default_tool: String,
+}
+
+#[derive(Deserialize)]
+struct DeleteRepoQuery {
+    #[serde(default)]
+    cascade: bool,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct AgentFailure {
+    name: String,
+    error: String,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct RepoDeletion {
+    repo: String,
+    removed: bool,
+    deleted_agents: Vec<String>,
+    failed_agents: Vec<AgentFailure>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct PruneQuery {
+    #[serde(default)]
+    dry_run: bool,
+    days: Option<i64>,
+}
+
+#[derive(Serialize)]
+struct PruneReport {
+    dry_run: bool,
+    days: i64,
+    removed: Vec<String>,
+    failed_agents: Vec<AgentFailure>,
+}
+
+#[derive(Deserialize)]
+struct ResetRequest {
+    /// Must be set; a reset throws away everything in the worktree.
+    #[serde(default)]
+    confirm: bool,
+}
+
+#[derive(Deserialize)]
+struct SignalRequest {
+    signal: String,
+}
+
+/// Narrows `GET /agents`; each given field must match exactly.
+#[derive(Debug, Default, Deserialize)]
+struct AgentFilter {
+    repo: Option<String>,
+    tool: Option<String>,
+    status: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DiffQuery {
+    #[serde(default)]
+    stat: bool,
+}
+
+/// `GET /agents/output?since=<agent>:<offset>,...` asks for the lines each
+/// listed agent printed past an offset an earlier response returned.
+#[derive(Default, Deserialize)]
+struct OutputQuery {
+    since: Option<String>,
+}
+
+#[derive(Serialize)]
+struct AgentDiff {
+    diff: String,
+    truncated: bool,
+}
+
+#[derive(Serialize)]
+struct FlushedHistory {
+    name: String,
+    bytes: usize,
+}
+
+/// A change in an agent's live status, published on `/events`. `from` is
+/// `None` for agents seen for the first time and `to` is `"deleted"` once
+/// an agent disappears.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+struct StatusEvent {
+    name: String,
+    from: Option<String>,
+    to: String,
+    timestamp: String,
+}
+
+/// Live PTY sessions reconciled against the agents table.
+#[derive(Debug, PartialEq, Serialize)]
+struct SessionsReport {
+    sessions: Vec<String>,
+    /// Live sessions with no agent row; only reachable through `/sessions`.
+    orphaned: Vec<String>,
+    /// Agents with no live session (normally just sleeping agents).
+    without_session: Vec<String>,
+}
+
 #[derive(Serialize)]
 struct AgentOutput {
     name: String,
     status: String,
     output: Option<String>,
+    /// How the tool last exited, while the agent has no live session.
+    exit_code: Option<u32>,
+    /// Complete lines printed past the requested offset, or the last ones
+    /// when none was given.
+    lines: Option<Vec<String>>,
+    /// Output offset just past `lines`, to ask from on the next poll.
+    offset: Option<u64>,
 }
 
 impl ApiError {
@@ -129,12 +449,33 @@ impl ApiError {
         }
     }
 
+    fn conflict(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::CONFLICT,
+            message: message.into(),
+        }
+    }
+
     fn internal(message: impl Into<String>) -> Self {
         Self {
             status: StatusCode::INTERNAL_SERVER_ERROR,
             message: message.into(),
         }
     }
+
+    fn unavailable(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            message: message.into(),
+        }
+    }
+
+    fn not_writable(message: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::INSUFFICIENT_STORAGE,
+            message: message.into(),
+        }
+    }
 }
 
 impl IntoResponse for ApiError {
@@ -145,33 +486,69 @@ impl IntoResponse for ApiError {
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
+    let settings = settings::init()?;
     let (shutdown_sender, shutdown_receiver) = oneshot::channel();
     let db = Arc::new(tokio::sync::Mutex::new(init_database()?));
     let pty_sessions = Arc::new(Mutex::new(HashMap::new()));
     let broker = start_pty_broker(pty_sessions.clone(), db.clone())?;
+    let (events, _) = broadcast::channel(EVENT_CHANNEL_CAPACITY);
+    let (closing_sender, closing) = watch::channel(false);
+    tokio::spawn(monitor_session_exits(db.clone(), pty_sessions.clone()));
+    tokio::spawn(watch_agent_statuses(
+        db.clone(),
+        pty_sessions.clone(),
+        events.clone(),
+    ));
     let state = AppState {
         shutdown_sender: Arc::new(tokio::sync::Mutex::new(Some(shutdown_sender))),
         db: db.clone(),
         pty_sessions,
+        git_status: Arc::new(Mutex::new(HashMap::new())),
+        events,
+        closing,
+        broker: broker.health.clone(),
     };
 
     let app = Router::new()
         .route("/health", get(health))
         .route("/shutdown", get(shutdown))
         .route("/repos", get(list_repos).post(add_repo))
+        .route("/repos/:name", delete(remove_repo))
+        .route("/repos/:name/tools", put(set_repo_tools))
+        .route("/tools", get(list_tools))
+        .route("/events", get(status_events))
         .route("/agents", get(list_agents).post(add_agent))
-        .route("/agents/:name", delete(delete_agent))
+        .route("/agents/batch", post(add_agents_batch))
+        .route("/agents/:name", delete(delete_agent).patch(rename_agent))
         .route("/agents/:name/restart", post(restart_agent))
+        .route("/agents/:name/sleep", post(sleep_agent))
+        .route("/agents/:name/reset", post(reset_agent))
+        .route("/agents/:name/diff", get(agent_diff))
+        .route("/agents/:name/commit", post(commit_agent))
+        .route("/agents/:name/merge", post(merge_agent))
+        .route("/agents/:name/signal", post(signal_agent))
+        .route("/agents/:name/history", get(agent_history))
+        .route("/agents/:name/history/timing", get(agent_history_timing))
+        .route("/agents/:name/stream", get(agent_output_stream))
         .route("/agents/output", get(agents_output))
+        .route("/sessions", get(list_sessions))
+        .route("/sessions/:name", delete(stop_session))
+        .route("/maintenance/flush", post(flush_histories))
+        .route("/maintenance/prune", post(prune_agents))
         .with_state(state);
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let listener = http_listener_socket(settings.port)?.listen(HTTP_LISTEN_BACKLOG)?;
     let local_addr = listener.local_addr()?;
 
     write_metadata(local_addr)?;
 
     axum::serve(listener, app)
-        .with_graceful_shutdown(wait_for_shutdown(shutdown_receiver))
+        .with_graceful_shutdown(async move {
+            wait_for_shutdown(shutdown_receiver).await;
+            // Event streams never end on their own; close them so the
+            // graceful shutdown is not held open by subscribers.
+            let _ = closing_sender.send(true);
+        })
         .await?;
 
     drop(broker);
@@ -180,13 +557,40 @@ async fn main() -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-async fn health() -> &'static str {
-    "ok"
+const HTTP_LISTEN_BACKLOG: u32 = 1024;
+
+/// Binds the HTTP socket on loopback with `SO_REUSEADDR`, so a pinned port
+/// can be taken again while the previous server's connections sit in
+/// TIME_WAIT.
+fn http_listener_socket(port: u16) -> io::Result<tokio::net::TcpSocket> {
+    let socket = tokio::net::TcpSocket::new_v4()?;
+    socket.set_reuseaddr(true)?;
+    socket.bind(SocketAddr::from((Ipv4Addr::LOCALHOST, port)))?;
+    Ok(socket)
+}
+
+async fn health(State(state): State<AppState>) -> Result<&'static str, ApiError> {
+    state.broker.check().map_err(ApiError::unavailable)?;
+    Ok("ok")
 }
 
 async fn list_repos() -> Result<Json<Vec<RepoConfig>>, ApiError> {
     let config = load_repo_config()?;
-    Ok(Json(config.repos))
+    Ok(Json(
+        config.repos.into_iter().map(redact_repo_env).collect(),
+    ))
+}
+
+/// Stands in for repo `env` values in responses.
+const REDACTED_ENV_VALUE: &str = "<redacted>";
+
+/// A repo as the API returns it: `env` lists its keys but not their
+/// values, which are often tokens.
+fn redact_repo_env(mut repo: RepoConfig) -> RepoConfig {
+    for value in repo.env.values_mut() {
+        *value = REDACTED_ENV_VALUE.to_string();
+    }
+    repo
 }
 
 async fn add_repo(Json(request): Json<AddRepoRequest>) -> Result<Json<RepoConfig>, ApiError> {
@@ -202,1080 +606,5328 @@ async fn add_repo(Json(request): Json<AddRepoRequest>) -> Result<Json<RepoConfig
     }
 
     let mut config = load_repo_config()?;
-    let name = generate_repo_name(&repo_path, &config.repos)?;
+    let name = match request.name.as_deref().map(str::trim) {
+        Some(name) if !name.is_empty() => validate_repo_name(name, &config.repos)?,
+        _ => generate_repo_name(&repo_path, &config.repos)?,
+    };
 
     let repo = RepoConfig {
         name: name.clone(),
         path: repo_path,
-        tools: default_tools(),
-        default_tool: "opencode".to_string(),
+        tools: settings().default_tools.clone(),
+        default_tool: settings().default_tool.clone(),
+        ..RepoConfig::default()
     };
 
+    if request.dry_run {
+        return Ok(Json(repo));
+    }
+
     config.repos.push(repo.clone());
     save_repo_config(&config)?;
 
     Ok(Json(repo))
 }
 
-async fn list_agents(State(state): State<AppState>) -> Result<Json<Vec<Agent>>, ApiError> {
-    let conn = state.db.lock().await;
-    let mut stmt = conn
-        .prepare(
-            "SELECT name, label, repo, tool, status, worktree_path, styles, created_at, updated_at FROM agents ORDER BY created_at DESC",
-        )
-        .map_err(|err| ApiError::internal(err.to_string()))?;
+/// Replaces the tools agents in a repo can be started with. Tools are
+/// commands, so anything on `PATH` (or a path to a wrapper script) works.
+async fn set_repo_tools(
+    AxumPath(name): AxumPath<String>,
+    Json(request): Json<RepoToolsRequest>,
+) -> Result<Json<RepoConfig>, ApiError> {
+    let mut config = load_repo_config()?;
+    let repo = config
+        .repos
+        .iter_mut()
+        .find(|repo| repo.name == name)
+        .ok_or_else(|| ApiError::not_found("repo not found"))?;
+    apply_repo_tools(repo, request)?;
+    let repo = repo.clone();
+    save_repo_config(&config)?;
+    Ok(Json(redact_repo_env(repo)))
+}
 
-    let agents = stmt
-        .query_map([], |row| {
-            let styles: Option<String> = row.get(6)?;
-            let styles =
-                styles.and_then(|value| serde_json::from_str::<serde_json::Value>(&value).ok());
-            Ok(Agent {
-                name: row.get(0)?,
-                label: row.get(1)?,
-                repo: row.get(2)?,
-                tool: row.get(3)?,
-                status: row.get(4)?,
-                worktree_path: row.get(5)?,
-                styles,
-                output: None,
-                created_at: row.get(7)?,
-                updated_at: row.get(8)?,
-            })
-        })
-        .map_err(|err| ApiError::internal(err.to_string()))?;
+fn apply_repo_tools(repo: &mut RepoConfig, request: RepoToolsRequest) -> Result<(), ApiError> {
+    let mut tools: Vec<ToolSpec> = Vec::new();
+    for mut tool in request.tools {
+        tool.name = tool.name.trim().to_string();
+        if tool.name.is_empty() {
+            return Err(ApiError::bad_request("tool names must not be empty"));
+        }
+        if tool
+            .command
+            .as_deref()
+            .is_some_and(|command| command.trim().is_empty())
+        {
+            return Err(ApiError::bad_request(format!(
+                "tool '{}' has an empty command",
+                tool.name
+            )));
+        }
+        if !tools.iter().any(|existing| existing.name == tool.name) {
+            tools.push(tool);
+        }
+    }
+    if tools.is_empty() {
+        return Err(ApiError::bad_request("at least one tool is required"));
+    }
+    let default_tool = request.default_tool.trim();
+    if !tools.iter().any(|tool| tool.name == default_tool) {
+        return Err(ApiError::bad_request(format!(
+            "default tool '{default_tool}' is not in tools"
+        )));
+    }
+    repo.default_tool = default_tool.to_string();
+    repo.tools = tools;
+    Ok(())
+}
 
-    let mut results = Vec::new();
-    for agent in agents {
-        results.push(agent.map_err(|err| ApiError::internal(err.to_string()))?);
+async fn remove_repo(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+    Query(query): Query<DeleteRepoQuery>,
+) -> Result<Response, ApiError> {
+    let mut config = load_repo_config()?;
+    let repo = config
+        .repos
+        .iter()
+        .find(|repo| repo.name == name)
+        .cloned()
+        .ok_or_else(|| ApiError::not_found("repo not found"))?;
+
+    let agents = repo_agent_names(&*state.db.lock().await, &repo.name)?;
+    if !agents.is_empty() && !query.cascade {
+        return Err(ApiError::conflict(format!(
+            "repo has agents: {}",
+            agents.join(", ")
+        )));
     }
 
-    Ok(Json(results))
+    if !query.cascade {
+        // Nothing else to undo, so a failed save keeps its own status.
+        remove_repo_entry(&mut config, &repo.name, save_repo_config)?;
+        return Ok(StatusCode::NO_CONTENT.into_response());
+    }
+
+    // Each agent's `git worktree remove` runs without the database lock.
+    let (db, sessions, git_status) = (
+        state.db.clone(),
+        state.pty_sessions.clone(),
+        state.git_status.clone(),
+    );
+    let deletion = tokio::task::spawn_blocking(move || {
+        delete_repo_cascade(
+            &repo.name,
+            agents,
+            |agent| {
+                remove_agent(&db, &sessions, &repo.path, agent)?;
+                forget_git_status(&git_status, agent);
+                Ok(())
+            },
+            || remove_repo_entry(&mut config, &repo.name, save_repo_config),
+        )
+    })
+    .await
+    .map_err(|err| ApiError::internal(err.to_string()))?;
+    let status = if deletion.removed {
+        StatusCode::OK
+    } else {
+        StatusCode::INTERNAL_SERVER_ERROR
+    };
+    Ok((status, Json(deletion)).into_response())
 }
 
-async fn agents_output(State(state): State<AppState>) -> Result<Json<Vec<AgentOutput>>, ApiError> {
-    let conn = state.db.lock().await;
-    let mut stmt = conn
-        .prepare("SELECT name FROM agents ORDER BY created_at DESC")
+fn remove_repo_entry(
+    config: &mut RepoConfigFile,
+    name: &str,
+    save: impl FnOnce(&RepoConfigFile) -> Result<(), ApiError>,
+) -> Result<(), ApiError> {
+    config.repos.retain(|entry| entry.name != name);
+    save(config)
+}
+
+/// Stops an agent's session, removes its worktree and branch, and deletes
+/// its row. The database is locked only to read and delete the row.
+fn remove_agent(
+    db: &tokio::sync::Mutex<Connection>,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    repo_path: &Path,
+    name: &str,
+) -> Result<(), ApiError> {
+    let worktree_path: String = db
+        .blocking_lock()
+        .query_row(
+            "SELECT worktree_path FROM agents WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )
         .map_err(|err| ApiError::internal(err.to_string()))?;
+    stop_pty_session(name, sessions);
+    delete_worktree(repo_path, Path::new(&worktree_path), name)?;
+    db.blocking_lock()
+        .execute("DELETE FROM agents WHERE name = ?1", params![name])
+        .map_err(db_error)?;
+    Ok(())
+}
 
-    let agents = stmt
-        .query_map([], |row| Ok(row.get::<_, String>(0)?))
+fn repo_agent_names(conn: &Connection, repo: &str) -> Result<Vec<String>, ApiError> {
+    let mut stmt = conn
+        .prepare("SELECT name FROM agents WHERE repo = ?1 ORDER BY name")
         .map_err(|err| ApiError::internal(err.to_string()))?;
+    let names = stmt
+        .query_map(params![repo], |row| row.get::<_, String>(0))
+        .map_err(|err| ApiError::internal(err.to_string()))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    Ok(names)
+}
 
-    let mut outputs = Vec::new();
+/// Deletes every agent in order, continuing past failures, and only removes
+/// the repo itself once all of its agents are gone.
+fn delete_repo_cascade<D, R>(
+    repo: &str,
+    agents: Vec<String>,
+    mut delete_agent_fn: D,
+    remove_repo_fn: R,
+) -> RepoDeletion
+where
+    D: FnMut(&str) -> Result<(), ApiError>,
+    R: FnOnce() -> Result<(), ApiError>,
+{
+    let mut deleted_agents = Vec::new();
+    let mut failed_agents = Vec::new();
     for agent in agents {
-        let name = agent.map_err(|err| ApiError::internal(err.to_string()))?;
-        let status = pty_session_status(&name, &state.pty_sessions);
-        outputs.push(AgentOutput {
-            name: name.clone(),
-            status,
-            output: None,
-        });
+        match delete_agent_fn(&agent) {
+            Ok(()) => deleted_agents.push(agent),
+            Err(err) => failed_agents.push(AgentFailure {
+                name: agent,
+                error: err.message,
+            }),
+        }
     }
 
-    Ok(Json(outputs))
-}
-
-fn pty_session_status(
-    agent_name: &str,
-    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
-) -> String {
-    let sessions = sessions.lock().expect("pty sessions lock");
-    if sessions.contains_key(agent_name) {
-        "running".to_string()
+    let error = if failed_agents.is_empty() {
+        remove_repo_fn().err().map(|err| err.message)
     } else {
-        "sleep".to_string()
+        Some("repo kept because some agents could not be deleted".to_string())
+    };
+
+    RepoDeletion {
+        repo: repo.to_string(),
+        removed: error.is_none(),
+        deleted_agents,
+        failed_agents,
+        error,
     }
 }
 
-async fn add_agent(
-    State(state): State<AppState>,
-    Json(request): Json<AddAgentRequest>,
-) -> Result<Json<Agent>, ApiError> {
+async fn list_tools() -> Result<Json<Vec<ToolAvailability>>, ApiError> {
     let config = load_repo_config()?;
-    let repo = config
-        .repos
-        .iter()
-        .find(|repo| repo.name == request.repo)
-        .ok_or_else(|| ApiError::not_found("repo not found"))?;
+    let path = std::env::var_os("PATH");
+    Ok(Json(tool_availability(&config.repos, path.as_deref())))
+}
 
-    if request.tool.trim().is_empty() {
-        return Err(ApiError::bad_request("tool is required"));
+fn tool_availability(repos: &[RepoConfig], path: Option<&OsStr>) -> Vec<ToolAvailability> {
+    // Repos naming the same tool differently are checked by the first spec.
+    let mut tools: BTreeMap<&str, &ToolSpec> = BTreeMap::new();
+    for tool in repos.iter().flat_map(|repo| &repo.tools) {
+        tools.entry(tool.name.as_str()).or_insert(tool);
     }
+    tools
+        .into_iter()
+        .map(|(name, tool)| ToolAvailability {
+            name: name.to_string(),
+            available: resolves_on_path(tool.command.as_deref().unwrap_or(name), path),
+        })
+        .collect()
+}
 
-    if !repo.tools.iter().any(|tool| tool == &request.tool) {
-        return Err(ApiError::bad_request("tool not configured for repo"));
+fn resolves_on_path(tool: &str, path: Option<&OsStr>) -> bool {
+    let Some(program) = tool.split_whitespace().next() else {
+        return false;
+    };
+    if program.contains('/') {
+        return is_executable(Path::new(program));
     }
+    path.map(|paths| std::env::split_paths(paths).any(|dir| is_executable(&dir.join(program))))
+        .unwrap_or(false)
+}
 
-    let requested_name = request
-        .name
-        .as_deref()
-        .map(str::trim)
-        .filter(|value| !value.is_empty());
-    let agent_name = if let Some(name) = requested_name {
+fn is_executable(path: &Path) -> bool {
+    std::fs::metadata(path)
+        .map(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+async fn list_agents(
+    State(state): State<AppState>,
+    Query(filter): Query<AgentFilter>,
+) -> Result<Json<Vec<Agent>>, ApiError> {
+    let mut results = {
         let conn = state.db.lock().await;
-        let exists: bool = conn
-            .query_row(
-                "SELECT EXISTS(SELECT 1 FROM agents WHERE name = ?1)",
-                params![name],
-                |row| row.get(0),
-            )
-            .map_err(|err| ApiError::internal(err.to_string()))?;
-        if exists {
-            return Err(ApiError::bad_request("agent name already exists"));
+        let sessions = state.pty_sessions.lock().expect("pty sessions lock");
+        query_agents(&conn, &filter, |name| sessions.contains_key(name))
+            .map_err(|err| ApiError::internal(err.to_string()))?
+    };
+
+    // `git status` runs for every agent whose count has gone stale.
+    let cache = state.git_status.clone();
+    let results = tokio::task::spawn_blocking(move || {
+        let now = Instant::now();
+        for agent in &mut results {
+            let worktree_path = PathBuf::from(&agent.worktree_path);
+            agent.dirty_count = cached_dirty_count(&cache, &agent.name, now, || {
+                worktree_dirty_count(&worktree_path)
+            });
         }
-        name.to_string()
+        results
+    })
+    .await
+    .map_err(|err| ApiError::internal(err.to_string()))?;
+
+    Ok(Json(results))
+}
+
+/// Agents matching `filter`, newest first, with the status they report
+/// through [`agent_live_status`]; `running` tells which have a session.
+/// The status filter applies to that live status, not the stored one.
+fn query_agents(
+    conn: &Connection,
+    filter: &AgentFilter,
+    running: impl Fn(&str) -> bool,
+) -> rusqlite::Result<Vec<Agent>> {
+    let mut clauses = Vec::new();
+    let mut values = Vec::new();
+    for (column, value) in [("repo", &filter.repo), ("tool", &filter.tool)] {
+        if let Some(value) = value {
+            values.push(value.as_str());
+            clauses.push(format!("{column} = ?{}", values.len()));
+        }
+    }
+    let where_clause = if clauses.is_empty() {
+        String::new()
     } else {
-        generate_unique_agent_name(state.db.clone()).await?
+        format!("WHERE {}", clauses.join(" AND "))
     };
-    let label = agent_name.clone();
-    let worktree_path = create_worktree(&repo.path, &repo.name, &agent_name)?;
-    start_tool_session(
-        &agent_name,
-        &request.tool,
-        &worktree_path,
-        &state.pty_sessions,
-    )?;
-    let now = Utc::now().to_rfc3339();
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {AGENT_COLUMNS} FROM agents {where_clause} ORDER BY created_at DESC"
+    ))?;
+    let agents = stmt
+        .query_map(params_from_iter(values), agent_from_row)?
+        .map(|agent| {
+            agent.map(|mut agent| {
+                agent.status = agent_live_status(running(&agent.name), &agent.status);
+                agent
+            })
+        })
+        .filter(|agent| match (agent, &filter.status) {
+            (Ok(agent), Some(status)) => &agent.status == status,
+            _ => true,
+        })
+        .collect();
+    agents
+}
 
-    let agent = Agent {
-        name: agent_name,
-        label,
-        repo: repo.name.clone(),
-        tool: request.tool,
-        status: "running".to_string(),
-        worktree_path: worktree_path.to_string_lossy().to_string(),
-        styles: None,
+const AGENT_COLUMNS: &str =
+    "name, label, repo, tool, status, worktree_path, styles, created_at, updated_at";
+
+/// Reads an agent selected with `AGENT_COLUMNS`.
+fn agent_from_row(row: &rusqlite::Row) -> rusqlite::Result<Agent> {
+    let styles: Option<String> = row.get(6)?;
+    let styles = styles.and_then(|value| serde_json::from_str::<serde_json::Value>(&value).ok());
+    Ok(Agent {
+        name: row.get(0)?,
+        label: row.get(1)?,
+        repo: row.get(2)?,
+        tool: row.get(3)?,
+        status: row.get(4)?,
+        worktree_path: row.get(5)?,
+        styles,
         output: None,
-        created_at: now.clone(),
-        updated_at: now,
-    };
+        created_at: row.get(7)?,
+        updated_at: row.get(8)?,
+        dirty_count: None,
+    })
+}
 
+async fn rename_agent(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+    Json(request): Json<RenameAgentRequest>,
+) -> Result<Json<Agent>, ApiError> {
     let conn = state.db.lock().await;
-    conn.execute(
-        "INSERT INTO agents (name, label, repo, tool, status, worktree_path, styles, created_at, updated_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
-        params![
-            agent.name,
-            agent.label,
-            agent.repo,
-            agent.tool,
-            agent.status,
-            agent.worktree_path,
-            agent
-                .styles
-                .as_ref()
-                .map(|value| value.to_string()),
-            agent.created_at,
-            agent.updated_at,
-        ],
+    set_agent_label(&conn, &name, &request.label).map(Json)
+}
+
+/// Changes the display label; the name stays the key used everywhere else.
+fn set_agent_label(conn: &Connection, name: &str, label: &str) -> Result<Agent, ApiError> {
+    let label = label.trim();
+    if label.is_empty() {
+        return Err(ApiError::bad_request("label is required"));
+    }
+    let updated = conn
+        .execute(
+            "UPDATE agents SET label = ?1, updated_at = ?2 WHERE name = ?3",
+            params![label, Utc::now().to_rfc3339(), name],
+        )
+        .map_err(db_error)?;
+    if updated == 0 {
+        return Err(ApiError::not_found("agent not found"));
+    }
+    conn.query_row(
+        &format!("SELECT {AGENT_COLUMNS} FROM agents WHERE name = ?1"),
+        params![name],
+        agent_from_row,
     )
-    .map_err(|err| ApiError::internal(err.to_string()))?;
+    .map_err(|err| ApiError::internal(err.to_string()))
+}
 
-    Ok(Json(agent))
+/// Returns the cached uncommitted-change count for an agent, refreshing it
+/// with `compute` once it is older than `GIT_STATUS_TTL`.
+fn cached_dirty_count<F>(
+    cache: &Mutex<HashMap<String, CachedGitStatus>>,
+    agent: &str,
+    now: Instant,
+    compute: F,
+) -> Option<usize>
+where
+    F: FnOnce() -> Option<usize>,
+{
+    let cached = cache.lock().expect("git status lock").get(agent).copied();
+    if let Some(cached) = cached {
+        if now.saturating_duration_since(cached.checked_at) < GIT_STATUS_TTL {
+            return Some(cached.dirty_count);
+        }
+    }
+    let dirty_count = compute()?;
+    cache.lock().expect("git status lock").insert(
+        agent.to_string(),
+        CachedGitStatus {
+            checked_at: now,
+            dirty_count,
+        },
+    );
+    Some(dirty_count)
 }
 
-async fn delete_agent(
-    State(state): State<AppState>,
-    AxumPath(name): AxumPath<String>,
-) -> Result<StatusCode, ApiError> {
-    let (repo_name, worktree_path) = {
-        let conn = state.db.lock().await;
-        conn.query_row(
-            "SELECT repo, worktree_path FROM agents WHERE name = ?1",
-            params![name.as_str()],
-            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
-        )
-        .map_err(|err| match err {
-            rusqlite::Error::QueryReturnedNoRows => ApiError::not_found("agent not found"),
-            _ => ApiError::internal(err.to_string()),
-        })?
-    };
+/// Drops an agent's cached count after its worktree changed or went away.
+fn forget_git_status(cache: &Mutex<HashMap<String, CachedGitStatus>>, agent: &str) {
+    cache.lock().expect("git status lock").remove(agent);
+}
 
-    let config = load_repo_config()?;
-    let repo = config
-        .repos
-        .iter()
-        .find(|repo| repo.name == repo_name)
-        .ok_or_else(|| ApiError::not_found("repo not found for agent"))?;
+fn worktree_dirty_count(worktree_path: &Path) -> Option<usize> {
+    // A failed reset leaves the agent without a worktree.
+    if !worktree_path.is_dir() {
+        return None;
+    }
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(worktree_path)
+        .args(["status", "--porcelain"])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(porcelain_entry_count(&String::from_utf8_lossy(
+        &output.stdout,
+    )))
+}
 
-    stop_pty_session(&name, &state.pty_sessions);
-    delete_worktree(&repo.path, Path::new(&worktree_path), &name)?;
+fn porcelain_entry_count(output: &str) -> usize {
+    output
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .count()
+}
 
+async fn agents_output(
+    State(state): State<AppState>,
+    Query(query): Query<OutputQuery>,
+) -> Result<Json<Vec<AgentOutput>>, ApiError> {
+    let since =
+        parse_output_since(query.since.as_deref().unwrap_or("")).map_err(ApiError::bad_request)?;
     let conn = state.db.lock().await;
-    conn.execute("DELETE FROM agents WHERE name = ?1", params![name.as_str()])
+    let mut stmt = conn
+        .prepare("SELECT name, status, exit_code FROM agents ORDER BY created_at DESC")
         .map_err(|err| ApiError::internal(err.to_string()))?;
 
-    Ok(StatusCode::NO_CONTENT)
+    let agents = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<u32>>(2)?,
+            ))
+        })
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+
+    let mut outputs = Vec::new();
+    for agent in agents {
+        let (name, stored, exit_code) = agent.map_err(|err| ApiError::internal(err.to_string()))?;
+        let session = state
+            .pty_sessions
+            .lock()
+            .expect("pty sessions lock")
+            .get(&name)
+            .map(|session| (session.history.clone(), session.written.clone()));
+        let running = session.is_some();
+        let (output, lines, offset) = match session {
+            Some((history, written)) => {
+                let history = history.lock().expect("pty history lock");
+                let written = written.load(Ordering::Relaxed);
+                let (lines, offset) =
+                    history_lines_since(&history, written, since.get(&name).copied().unwrap_or(0));
+                let tail = plaintext_tail(&recent_output(&history), OUTPUT_TAIL_LINES);
+                (Some(tail), Some(lines), Some(offset))
+            }
+            None => (None, None, None),
+        };
+        outputs.push(AgentOutput {
+            status: agent_live_status(running, &stored),
+            name,
+            output,
+            exit_code: exit_code.filter(|_| !running),
+            lines,
+            offset,
+        });
+    }
+
+    Ok(Json(outputs))
 }
 
-async fn restart_agent(
-    State(state): State<AppState>,
-    AxumPath(name): AxumPath<String>,
-) -> Result<StatusCode, ApiError> {
-    let (tool, worktree_path) = {
-        let conn = state.db.lock().await;
-        conn.query_row(
-            "SELECT tool, worktree_path FROM agents WHERE name = ?1",
-            params![name.as_str()],
-            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
-        )
-        .map_err(|err| match err {
-            rusqlite::Error::QueryReturnedNoRows => ApiError::not_found("agent not found"),
-            _ => ApiError::internal(err.to_string()),
-        })?
-    };
+/// Parses the `since` list of `GET /agents/output`.
+fn parse_output_since(value: &str) -> Result<HashMap<String, u64>, String> {
+    value
+        .split(',')
+        .filter(|entry| !entry.is_empty())
+        .map(|entry| {
+            let (agent, offset) = entry
+                .rsplit_once(':')
+                .ok_or_else(|| format!("since entry {entry:?} is not <agent>:<offset>"))?;
+            let offset = offset
+                .parse()
+                .map_err(|_| format!("since entry {entry:?} has an invalid offset"))?;
+            Ok((agent.to_string(), offset))
+        })
+        .collect()
+}
 
-    stop_pty_session(&name, &state.pty_sessions);
-    start_tool_session(&name, &tool, Path::new(&worktree_path), &state.pty_sessions)?;
+/// Complete plain-text lines (at most `OUTPUT_TAIL_LINES`, the newest) of
+/// the output past byte `since` of a history that has seen `written` bytes,
+/// and the offset just past them. Offsets always fall after a newline, so
+/// no escape sequence is cut; an offset already trimmed from the history
+/// resumes after the first newline still held, and one past `written`
+/// (a restarted session) starts over.
+fn history_lines_since(history: &VecDeque<u8>, written: u64, since: u64) -> (Vec<String>, u64) {
+    let start = written - history.len() as u64;
+    let since = if since > written { 0 } else { since };
+    let from = if since >= start {
+        (since - start) as usize
+    } else {
+        match history.iter().position(|byte| *byte == b'\n') {
+            Some(index) => index + 1,
+            None => return (Vec::new(), since),
+        }
+    };
+    let Some(end) = history.range(from..).rposition(|byte| *byte == b'\n') else {
+        return (Vec::new(), start + from as u64);
+    };
+    let end = from + end + 1;
+    let bytes: Vec<u8> = history.range(from..end).copied().collect();
+    let mut lines = VecDeque::new();
+    PlaintextLines::new().push(&bytes, |line| {
+        push_capped(&mut lines, line, OUTPUT_TAIL_LINES)
+    });
+    (lines.into(), start + end as u64)
+}
 
-    let now = Utc::now().to_rfc3339();
-    let conn = state.db.lock().await;
-    conn.execute(
-        "UPDATE agents SET status = ?1, updated_at = ?2 WHERE name = ?3",
-        params!["running", now, name.as_str()],
-    )
-    .map_err(|err| ApiError::internal(err.to_string()))?;
+/// The last `OUTPUT_TAIL_BYTES` of a history, starting after the first
+/// newline when cut so a sequence cut in half does not leak into the text.
+fn recent_output(history: &VecDeque<u8>) -> Vec<u8> {
+    let start = history.len().saturating_sub(OUTPUT_TAIL_BYTES);
+    let bytes: Vec<u8> = history.range(start..).copied().collect();
+    let start = if bytes.len() == OUTPUT_TAIL_BYTES {
+        bytes
+            .iter()
+            .position(|byte| *byte == b'\n')
+            .map_or(0, |index| index + 1)
+    } else {
+        0
+    };
+    bytes[start..].to_vec()
+}
 
-    Ok(StatusCode::NO_CONTENT)
+/// Plain text of terminal output, as a line printer would see it: text and
+/// newlines are kept, a carriage return lets the next text overwrite the
+/// line, and every other escape is dropped. Output may arrive in chunks of
+/// any size.
+struct PlaintextLines {
+    parser: Parser,
+    current: String,
+    returned: bool,
 }
 
-async fn shutdown(State(state): State<AppState>) -> &'static str {
-    let mut sender_guard = state.shutdown_sender.lock().await;
-    if let Some(sender) = sender_guard.take() {
-        let _ = sender.send(());
+impl PlaintextLines {
+    fn new() -> Self {
+        Self {
+            parser: Parser::new(),
+            current: String::new(),
+            returned: false,
+        }
     }
-    "shutting down"
-}
 
-async fn wait_for_shutdown(mut shutdown_receiver: oneshot::Receiver<()>) {
-    tokio::select! {
-        _ = tokio::signal::ctrl_c() => {},
-        _ = &mut shutdown_receiver => {},
+    /// Feeds output, calling `on_line` with each line it completes.
+    fn push(&mut self, bytes: &[u8], mut on_line: impl FnMut(String)) {
+        let Self {
+            parser,
+            current,
+            returned,
+        } = self;
+        let print = |current: &mut String, returned: &mut bool, text: &str| {
+            if std::mem::take(returned) {
+                current.clear();
+            }
+            current.push_str(text);
+        };
+        parser.parse(bytes, |action| match action {
+            Action::Print(ch) => print(current, returned, ch.encode_utf8(&mut [0; 4])),
+            Action::PrintString(text) => print(current, returned, &text),
+            Action::Control(ControlCode::CarriageReturn) => *returned = true,
+            Action::Control(ControlCode::Backspace) => {
+                current.pop();
+            }
+            Action::Control(ControlCode::LineFeed) => {
+                *returned = false;
+                on_line(current.trim_end().to_string());
+                current.clear();
+            }
+            _ => {}
+        });
+    }
+
+    /// The line still being written.
+    fn pending(&self) -> &str {
+        self.current.trim_end()
     }
 }
 
-fn start_pty_broker(
-    sessions: Arc<Mutex<HashMap<String, PtySession>>>,
-    db: Arc<tokio::sync::Mutex<Connection>>,
-) -> Result<PtyBroker, Box<dyn Error>> {
-    let socket_path = data_dir().join("pty.sock");
-    if let Some(parent) = socket_path.parent() {
-        std::fs::create_dir_all(parent)?;
+/// Returns at most `max_lines` complete lines of the output's plain text
+/// (see [`PlaintextLines`]) plus the unterminated line still being written,
+/// if any.
+fn plaintext_tail(bytes: &[u8], max_lines: usize) -> String {
+    let mut lines = VecDeque::new();
+    let mut printer = PlaintextLines::new();
+    printer.push(bytes, |line| push_capped(&mut lines, line, max_lines));
+    let mut text: String = lines.into_iter().map(|line| line + "\n").collect();
+    text.push_str(printer.pending());
+    text
+}
+
+fn push_capped(lines: &mut VecDeque<String>, line: String, max_lines: usize) {
+    if lines.len() == max_lines {
+        lines.pop_front();
     }
-    let _ = std::fs::remove_file(&socket_path);
-    let listener = UnixListener::bind(&socket_path)?;
-    let handle = thread::spawn(move || {
-        for stream in listener.incoming() {
-            match stream {
-                Ok(stream) => {
-                    let sessions = sessions.clone();
-                    let db = db.clone();
-                    thread::spawn(move || {
-                        if let Err(err) = handle_pty_connection(stream, sessions, db) {
-                            eprintln!("pty broker error: {err}");
-                        }
-                    });
+    lines.push_back(line);
+}
+
+/// Follows an agent's output as server-sent events, one `data:` event per
+/// line of plain text, starting with its last `OUTPUT_TAIL_LINES` lines.
+/// A sleeping agent's session is started, as on attach.
+async fn agent_output_stream(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let (db, sessions) = (state.db.clone(), state.pty_sessions.clone());
+    let subscription = tokio::task::spawn_blocking(move || {
+        ensure_pty_session(&name, &db, &sessions)?;
+        OutputSubscription::register(&name, &sessions)
+    })
+    .await
+    .map_err(|err| ApiError::internal(err.to_string()))?
+    .map_err(|err| match err.as_str() {
+        "agent not found" => ApiError::not_found(err),
+        _ => ApiError::internal(err),
+    })?;
+
+    let mut printer = PlaintextLines::new();
+    let mut backlog = VecDeque::new();
+    printer.push(&subscription.recent, |line| {
+        push_capped(&mut backlog, line, OUTPUT_TAIL_LINES)
+    });
+    let stream = subscription
+        .stream
+        .try_clone()
+        .and_then(|stream| {
+            stream.set_nonblocking(true)?;
+            tokio::net::UnixStream::from_std(stream)
+        })
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+
+    let events = stream::unfold(
+        (stream, printer, backlog, subscription, state.closing),
+        |(mut stream, mut printer, mut pending, subscription, mut closing)| async move {
+            let mut buffer = [0u8; 4096];
+            loop {
+                if let Some(line) = pending.pop_front() {
+                    let event = Event::default().data(line);
+                    return Some((Ok(event), (stream, printer, pending, subscription, closing)));
                 }
-                Err(err) => {
-                    eprintln!("pty broker accept error: {err}");
-                    break;
+                tokio::select! {
+                    read = stream.read(&mut buffer) => match read {
+                        Ok(0) | Err(_) => return None,
+                        Ok(size) => printer.push(&buffer[..size], |line| pending.push_back(line)),
+                    },
+                    _ = closing.changed() => return None,
                 }
             }
-        }
-    });
-
-    Ok(PtyBroker {
-        socket_path,
-        _handle: handle,
-    })
+        },
+    );
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
 }
 
-fn handle_pty_connection(
+/// A subscriber fed the same output as attached clients, for readers inside
+/// the server. Dropping it unsubscribes; the stream reaches EOF once the
+/// session is gone.
+struct OutputSubscription {
+    /// Output from before the subscription, see [`recent_output`].
+    recent: Vec<u8>,
     stream: UnixStream,
-    sessions: Arc<Mutex<HashMap<String, PtySession>>>,
-    db: Arc<tokio::sync::Mutex<Connection>>,
-) -> Result<(), Box<dyn Error>> {
-    let mut reader = BufReader::new(stream.try_clone()?);
-    let mut line = String::new();
-    loop {
-        line.clear();
-        if reader.read_line(&mut line)? == 0 {
-            break;
-        }
-        let trimmed = line.trim();
-        if trimmed.is_empty() {
-            continue;
+    /// Weak so a subscription does not keep a stopped session's subscriber
+    /// list, and with it the sending end of `stream`, alive.
+    subscribers: Weak<Mutex<Vec<Subscriber>>>,
+    id: u64,
+}
+
+impl OutputSubscription {
+    fn register(
+        agent: &str,
+        sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    ) -> Result<Self, String> {
+        let sessions = sessions.lock().expect("pty sessions lock");
+        let session = sessions.get(agent).ok_or("agent not found")?;
+        // Copied while the subscriber is added so no output falls between.
+        let history = session.history.lock().expect("pty history lock");
+        let (server_stream, client_stream) = UnixStream::pair().map_err(|err| err.to_string())?;
+        let subscriber = Subscriber::nonblocking(server_stream).map_err(|err| err.to_string())?;
+        let id = subscriber.id;
+        let mut subscribers = session.subscribers.lock().expect("pty subscribers lock");
+        prune_closed_subscribers(&mut subscribers);
+        subscribers.push(subscriber);
+        Ok(Self {
+            recent: recent_output(&history),
+            stream: client_stream,
+            subscribers: Arc::downgrade(&session.subscribers),
+            id,
+        })
+    }
+}
+
+impl Drop for OutputSubscription {
+    fn drop(&mut self) {
+        if let Some(subscribers) = self.subscribers.upgrade() {
+            remove_subscriber(&subscribers, self.id);
         }
-        let mut parts = trimmed.split_whitespace();
-        let command = parts.next().unwrap_or("");
-        match command {
-            "ATTACH" => {
-                let agent = parts.next().unwrap_or("");
-                let response = attach_pty(agent, &stream, &sessions, &db);
-                if let Err(err) = response {
-                    let _ = write_response(&stream, &format!("ERR {err}\n"));
-                }
-            }
-            "RESIZE" => {
-                let agent = parts.next().unwrap_or("");
-                let cols = parts.next().and_then(|value| value.parse::<u16>().ok());
-                let rows = parts.next().and_then(|value| value.parse::<u16>().ok());
-                match (cols, rows) {
-                    (Some(cols), Some(rows)) => {
-                        let result = resize_pty(agent, cols, rows, &sessions);
-                        let _ = if result.is_ok() {
-                            write_response(&stream, "OK\n")
-                        } else {
-                            write_response(&stream, "ERR resize failed\n")
-                        };
-                    }
-                    _ => {
-                        let _ = write_response(&stream, "ERR invalid resize\n");
-                    }
-                }
-            }
-            "INPUT" => {
-                let agent = parts.next().unwrap_or("");
-                let len = parts.next().and_then(|value| value.parse::<usize>().ok());
-                match len {
-                    Some(len) => {
-                        let mut payload = vec![0u8; len];
-                        if len > 0 {
-                            if let Err(err) = reader.read_exact(&mut payload) {
-                                let _ = write_response(&stream, &format!("ERR {err}\n"));
-                                continue;
-                            }
+    }
+}
+
+/// Streams agent status changes as server-sent events, one JSON-encoded
+/// [`StatusEvent`] per `status` event.
+async fn status_events(
+    State(state): State<AppState>,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let receiver = state.events.subscribe();
+    let events = stream::unfold(
+        (receiver, state.closing),
+        |(mut receiver, mut closing)| async move {
+            loop {
+                tokio::select! {
+                    received = receiver.recv() => match received {
+                        Ok(event) => {
+                            let data = serde_json::to_string(&event).unwrap_or_default();
+                            let event = Event::default().event("status").data(data);
+                            return Some((Ok(event), (receiver, closing)));
                         }
-                        let result = ensure_pty_session(agent, &db, &sessions)
-                            .map_err(|err| err.to_string())
-                            .and_then(|_| write_pty_input(agent, &payload, &sessions));
-                        let _ = if result.is_ok() {
-                            write_response(&stream, "OK\n")
-                        } else {
-                            write_response(&stream, "ERR input failed\n")
-                        };
-                    }
-                    _ => {
-                        let _ = write_response(&stream, "ERR invalid input\n");
-                    }
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => return None,
+                    },
+                    _ = closing.changed() => return None,
                 }
             }
-            _ => {
-                let _ = write_response(&stream, "ERR unknown command\n");
+        },
+    );
+    Sse::new(events).keep_alive(KeepAlive::default())
+}
+
+/// Polls live agent statuses and publishes every change to `events`.
+async fn watch_agent_statuses(
+    db: Arc<tokio::sync::Mutex<Connection>>,
+    sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+    events: broadcast::Sender<StatusEvent>,
+) {
+    let mut previous = HashMap::new();
+    let mut ticker = tokio::time::interval(STATUS_POLL_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let stored = {
+            let conn = db.lock().await;
+            match stored_agent_statuses(&conn) {
+                Ok(stored) => stored,
+                Err(_) => continue,
             }
+        };
+        let current: HashMap<String, String> = {
+            let sessions = sessions.lock().expect("pty sessions lock");
+            stored
+                .into_iter()
+                .map(|(name, stored)| {
+                    let status = agent_live_status(sessions.contains_key(&name), &stored);
+                    (name, status)
+                })
+                .collect()
+        };
+        let timestamp = Utc::now().to_rfc3339();
+        for event in status_changes(&previous, &current, &timestamp) {
+            let _ = events.send(event);
         }
+        previous = current;
     }
+}
 
-    Ok(())
+fn stored_agent_statuses(conn: &Connection) -> rusqlite::Result<Vec<(String, String)>> {
+    let mut stmt = conn.prepare("SELECT name, status FROM agents ORDER BY created_at")?;
+    let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+    rows.collect()
 }
 
-fn ensure_pty_session(
-    agent: &str,
-    db: &Arc<tokio::sync::Mutex<Connection>>,
-    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
-) -> Result<(), String> {
-    {
-        let sessions = sessions.lock().expect("pty sessions lock");
-        if sessions.contains_key(agent) {
-            return Ok(());
-        }
-    }
+fn agent_names(conn: &Connection) -> rusqlite::Result<Vec<String>> {
+    let mut stmt = conn.prepare("SELECT name FROM agents ORDER BY created_at")?;
+    let names = stmt.query_map([], |row| row.get(0))?;
+    names.collect()
+}
 
-    let (tool, worktree_path) = {
-        let conn = db.blocking_lock();
-        conn.query_row(
-            "SELECT tool, worktree_path FROM agents WHERE name = ?1",
-            params![agent],
-            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
-        )
-        .map_err(|err| err.to_string())?
+fn status_changes(
+    previous: &HashMap<String, String>,
+    current: &HashMap<String, String>,
+    timestamp: &str,
+) -> Vec<StatusEvent> {
+    let event = |name: &str, from: Option<&String>, to: &str| StatusEvent {
+        name: name.to_string(),
+        from: from.cloned(),
+        to: to.to_string(),
+        timestamp: timestamp.to_string(),
     };
+    let mut changes: Vec<StatusEvent> = current
+        .iter()
+        .filter(|(name, status)| previous.get(*name) != Some(status))
+        .map(|(name, status)| event(name, previous.get(name), status))
+        .collect();
+    changes.extend(
+        previous
+            .iter()
+            .filter(|(name, _)| !current.contains_key(*name))
+            .map(|(name, status)| event(name, Some(status), "deleted")),
+    );
+    changes.sort_by(|left, right| left.name.cmp(&right.name));
+    changes
+}
 
-    start_tool_session(agent, &tool, Path::new(&worktree_path), sessions).map_err(|err| err.message)
+async fn list_sessions(State(state): State<AppState>) -> Result<Json<SessionsReport>, ApiError> {
+    let agents = {
+        let conn = state.db.lock().await;
+        agent_names(&conn).map_err(db_error)?
+    };
+    let sessions: Vec<String> = state
+        .pty_sessions
+        .lock()
+        .expect("pty sessions lock")
+        .keys()
+        .cloned()
+        .collect();
+    Ok(Json(reconcile_sessions(sessions, &agents)))
 }
 
-fn attach_pty(
-    agent: &str,
-    stream: &UnixStream,
-    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
-    db: &Arc<tokio::sync::Mutex<Connection>>,
-) -> Result<(), Box<dyn Error>> {
-    if agent.trim().is_empty() {
-        return Err("agent name required".into());
+/// Stops a live session by name, whether or not an agent row exists.
+async fn stop_session(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+) -> Result<StatusCode, ApiError> {
+    if !state
+        .pty_sessions
+        .lock()
+        .expect("pty sessions lock")
+        .contains_key(&name)
+    {
+        return Err(ApiError::not_found("session not found"));
     }
+    stop_pty_session(&name, &state.pty_sessions);
+    Ok(StatusCode::NO_CONTENT)
+}
 
-    ensure_pty_session(agent, db, sessions)?;
-
-    let (history, snapshot, client_stream) = {
-        let mut sessions = sessions.lock().expect("pty sessions lock");
-        let session = sessions.get_mut(agent).ok_or("agent not found")?;
-        let history = session.history.lock().expect("pty history lock");
-        let bytes: Vec<u8> = history.iter().copied().collect();
-        let snapshot = session
-            .terminal_snapshot
-            .lock()
-            .expect("pty terminal snapshot lock")
-            .clone();
-        let (server_stream, client_stream) = UnixStream::pair()?;
-        session
-            .subscribers
-            .lock()
-            .expect("pty subscribers lock")
-            .push(server_stream);
-        (bytes, snapshot, client_stream)
-    };
+fn reconcile_sessions(mut sessions: Vec<String>, agents: &[String]) -> SessionsReport {
+    sessions.sort();
+    let orphaned = sessions
+        .iter()
+        .filter(|name| !agents.contains(name))
+        .cloned()
+        .collect();
+    let mut without_session: Vec<String> = agents
+        .iter()
+        .filter(|name| !sessions.contains(name))
+        .cloned()
+        .collect();
+    without_session.sort();
+    SessionsReport {
+        sessions,
+        orphaned,
+        without_session,
+    }
+}
 
-    let snapshot_json = serde_json::to_string(&snapshot)?;
-    write_response(stream, &format!("MODES {}\n", snapshot_json))?;
-    write_response(stream, &format!("HISTORY {}\n", history.len()))?;
-    if !history.is_empty() {
-        let mut stream = stream.try_clone()?;
-        stream.write_all(&history)?;
+/// The status reported for an agent: `running` while it has a session,
+/// otherwise the `idle`/`error` recorded when its tool exited, or `sleep`.
+fn agent_live_status(running: bool, stored: &str) -> String {
+    match stored {
+        _ if running => "running".to_string(),
+        "idle" | "error" => stored.to_string(),
+        _ => "sleep".to_string(),
     }
+}
 
-    let client_fd = client_stream.as_raw_fd();
-    sendmsg(
-        stream.as_raw_fd(),
-        &[IoSlice::new(b"OK\n")],
-        &[ControlMessage::ScmRights(&[client_fd])],
-        MsgFlags::empty(),
-        None::<&SockaddrStorage>,
-    )?;
-    Ok(())
+/// Polls sessions for tools that exited on their own, drops them and
+/// records the outcome on the agent row.
+async fn monitor_session_exits(
+    db: Arc<tokio::sync::Mutex<Connection>>,
+    sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+) {
+    let mut ticker = tokio::time::interval(SESSION_EXIT_POLL_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let exited = reap_exited_sessions(&sessions);
+        if exited.is_empty() {
+            continue;
+        }
+        let conn = db.lock().await;
+        for (name, code) in exited {
+            let _ = record_session_exit(&conn, &name, code);
+        }
+    }
 }
 
-fn resize_pty(
-    agent: &str,
-    cols: u16,
-    rows: u16,
-    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
-) -> Result<(), Box<dyn Error>> {
+/// Removes sessions whose child has exited, returning their exit codes.
+fn reap_exited_sessions(sessions: &Arc<Mutex<HashMap<String, PtySession>>>) -> Vec<(String, u32)> {
     let mut sessions = sessions.lock().expect("pty sessions lock");
-    let session = sessions
-        .get_mut(agent)
-        .ok_or_else(|| "agent not found".to_string())?;
-    let size = PtySize {
-        rows,
-        cols,
-        pixel_width: 0,
-        pixel_height: 0,
-    };
-    session.master.resize(size)?;
-    session.size = size;
-    Ok(())
+    let exited: Vec<(String, u32)> = sessions
+        .iter_mut()
+        .filter_map(|(name, session)| match session.child.try_wait() {
+            Ok(Some(status)) => Some((name.clone(), status.exit_code())),
+            _ => None,
+        })
+        .collect();
+    for (name, _) in &exited {
+        sessions.remove(name);
+    }
+    exited
 }
 
-fn write_pty_input(
-    agent: &str,
-    payload: &[u8],
-    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
-) -> Result<(), String> {
-    let mut sessions = sessions.lock().expect("pty sessions lock");
-    let session = sessions
-        .get_mut(agent)
-        .ok_or_else(|| "agent not found".to_string())?;
-    let mut writer = session.writer.lock().expect("pty writer lock");
-    writer.write_all(payload).map_err(|err| err.to_string())?;
-    writer.flush().map_err(|err| err.to_string())?;
-    Ok(())
+fn record_session_exit(conn: &Connection, name: &str, code: u32) -> rusqlite::Result<usize> {
+    let status = if code == 0 { "idle" } else { "error" };
+    conn.execute(
+        "UPDATE agents SET status = ?1, exit_code = ?2, updated_at = ?3 WHERE name = ?4",
+        params![status, code, Utc::now().to_rfc3339(), name],
+    )
 }
 
-fn write_response(stream: &UnixStream, response: &str) -> Result<(), Box<dyn Error>> {
-    let mut stream = stream.try_clone()?;
-    stream.write_all(response.as_bytes())?;
-    Ok(())
+async fn add_agent(
+    State(state): State<AppState>,
+    Json(request): Json<AddAgentRequest>,
+) -> Result<Json<Agent>, ApiError> {
+    let config = load_repo_config()?;
+    let (db, sessions) = (state.db.clone(), state.pty_sessions.clone());
+    let mut results =
+        tokio::task::spawn_blocking(move || create_agents(&db, &sessions, &config, &[request]))
+            .await
+            .map_err(|err| ApiError::internal(err.to_string()))?;
+    let agent = results.pop().expect("one result per request")?;
+    Ok(Json(agent))
 }
 
-fn init_database() -> Result<Connection, Box<dyn Error>> {
-    let data_dir = data_dir();
-    std::fs::create_dir_all(&data_dir)?;
-    let db_path = data_dir.join("app.db");
-    let conn = Connection::open(db_path)?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS agents (
-            name TEXT PRIMARY KEY,
-            label TEXT NOT NULL,
-            repo TEXT NOT NULL,
-            tool TEXT NOT NULL,
-            status TEXT NOT NULL,
-            worktree_path TEXT NOT NULL,
-            styles TEXT,
-            created_at TEXT NOT NULL,
-            updated_at TEXT NOT NULL
-        )",
-        [],
-    )?;
-    Ok(conn)
+/// Outcome of one item of `POST /agents/batch`, in request order: the
+/// created agent, or the error a single `POST /agents` would have returned.
+#[derive(Serialize)]
+struct BatchAgentResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    agent: Option<Agent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<BatchAgentError>,
 }
 
-fn load_repo_config() -> Result<RepoConfigFile, ApiError> {
-    let path = repos_config_path();
-    if !path.exists() {
-        return Ok(RepoConfigFile::default());
-    }
-    let data = std::fs::read_to_string(path).map_err(|err| ApiError::internal(err.to_string()))?;
-    toml::from_str(&data).map_err(|err| ApiError::internal(err.to_string()))
+#[derive(Serialize)]
+struct BatchAgentError {
+    status: u16,
+    message: String,
 }
 
-fn save_repo_config(config: &RepoConfigFile) -> Result<(), ApiError> {
-    let config_path = repos_config_path();
-    let config_dir = config_path
-        .parent()
-        .ok_or_else(|| ApiError::internal("config dir missing"))?;
-    std::fs::create_dir_all(config_dir).map_err(|err| ApiError::internal(err.to_string()))?;
-    let data = toml::to_string_pretty(config).map_err(|err| ApiError::internal(err.to_string()))?;
-    std::fs::write(config_path, data).map_err(|err| ApiError::internal(err.to_string()))?;
-    Ok(())
+/// Creates several agents best-effort: a failing item is reported and
+/// the rest are still created.
+async fn add_agents_batch(
+    State(state): State<AppState>,
+    Json(requests): Json<Vec<AddAgentRequest>>,
+) -> Result<Json<Vec<BatchAgentResult>>, ApiError> {
+    let config = load_repo_config()?;
+    let (db, sessions) = (state.db.clone(), state.pty_sessions.clone());
+    let results =
+        tokio::task::spawn_blocking(move || create_agents(&db, &sessions, &config, &requests))
+            .await
+            .map_err(|err| ApiError::internal(err.to_string()))?;
+    Ok(Json(
+        results
+            .into_iter()
+            .map(|result| match result {
+                Ok(agent) => BatchAgentResult {
+                    agent: Some(agent),
+                    error: None,
+                },
+                Err(err) => BatchAgentResult {
+                    agent: None,
+                    error: Some(BatchAgentError {
+                        status: err.status.as_u16(),
+                        message: err.message,
+                    }),
+                },
+            })
+            .collect(),
+    ))
 }
 
-fn default_tools() -> Vec<String> {
-    vec![
-        "opencode".to_string(),
-        "claude".to_string(),
-        "codex".to_string(),
-    ]
+/// An agent about to be created: its request checked and its name chosen.
+struct NewAgent<'a> {
+    repo: &'a RepoConfig,
+    name: String,
+    tool: &'a str,
+    args: &'a [String],
+    start: bool,
 }
 
-fn is_git_repo(path: &Path) -> bool {
-    path.join(".git").exists()
+/// Creates an agent per request, in order. Requests are checked and named
+/// under the database lock, worktrees are created and sessions started
+/// without it, and the rows are then inserted under it again, so other
+/// requests are not held up behind `git worktree add`.
+fn create_agents(
+    db: &tokio::sync::Mutex<Connection>,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    config: &RepoConfigFile,
+    requests: &[AddAgentRequest],
+) -> Vec<Result<Agent, ApiError>> {
+    let planned = {
+        let conn = db.blocking_lock();
+        let mut planned: Vec<Result<NewAgent, ApiError>> = Vec::new();
+        for request in requests {
+            let accepted: Vec<&NewAgent> = planned.iter().flatten().collect();
+            planned.push(plan_agent(&conn, config, request, &accepted));
+        }
+        planned
+    };
+    let prepared: Vec<_> = planned
+        .into_iter()
+        .map(|agent| {
+            let agent = agent?;
+            let worktree_path = prepare_agent(sessions, &agent)?;
+            Ok((agent, worktree_path))
+        })
+        .collect();
+    let conn = db.blocking_lock();
+    prepared
+        .into_iter()
+        .map(|prepared| {
+            let (agent, worktree_path) = prepared?;
+            record_agent(&conn, sessions, &agent, &worktree_path)
+        })
+        .collect()
 }
 
-fn generate_repo_name(path: &Path, repos: &[RepoConfig]) -> Result<String, ApiError> {
-    let base_name = path
-        .file_name()
-        .and_then(|name| name.to_str())
-        .ok_or_else(|| ApiError::bad_request("invalid repo path"))?;
+/// Checks a request and picks its agent's name, counting the agents
+/// `accepted` earlier in the same batch as already created.
+fn plan_agent<'a>(
+    conn: &Connection,
+    config: &'a RepoConfigFile,
+    request: &'a AddAgentRequest,
+    accepted: &[&NewAgent],
+) -> Result<NewAgent<'a>, ApiError> {
+    let repo = config
+        .repos
+        .iter()
+        .find(|repo| repo.name == request.repo)
+        .ok_or_else(|| ApiError::not_found("repo not found"))?;
 
-    Ok(generate_repo_name_with_suffix(base_name, repos, || {
-        petname(2, "-")
-    }))
+    if request.tool.trim().is_empty() {
+        return Err(ApiError::bad_request("tool is required"));
+    }
+
+    if !repo.tools.iter().any(|tool| tool.name == request.tool) {
+        return Err(ApiError::bad_request("tool not configured for repo"));
+    }
+    validate_launch_args(&request.args)?;
+    let pending = accepted
+        .iter()
+        .filter(|agent| agent.repo.name == repo.name)
+        .count();
+    ensure_agent_capacity(conn, repo, pending)?;
+    let taken = |name: &str| -> Result<bool, ApiError> {
+        Ok(accepted.iter().any(|agent| agent.name == name) || agent_exists(conn, name)?)
+    };
+
+    let requested_name = request
+        .name
+        .as_deref()
+        .map(str::trim)
+        .filter(|value| !value.is_empty());
+    let name = if let Some(name) = requested_name {
+        if taken(name)? {
+            return Err(ApiError::bad_request("agent name already exists"));
+        }
+        name.to_string()
+    } else {
+        let prefix = prefix_agent_names(repo).then_some(repo.name.as_str());
+        generate_agent_name_with(prefix, || petname(2, "-"), taken)?
+    };
+    Ok(NewAgent {
+        repo,
+        name,
+        tool: &request.tool,
+        args: &request.args,
+        start: request.start,
+    })
 }
 
-fn generate_repo_name_with_suffix<F>(
-    base_name: &str,
-    repos: &[RepoConfig],
-    mut suffix_fn: F,
-) -> String
-where
-    F: FnMut() -> String,
-{
-    let existing: HashSet<String> = repos.iter().map(|repo| repo.name.clone()).collect();
+fn agent_exists(conn: &Connection, name: &str) -> Result<bool, ApiError> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM agents WHERE name = ?1)",
+        params![name],
+        |row| row.get(0),
+    )
+    .map_err(|err| ApiError::internal(err.to_string()))
+}
 
-    if !existing.contains(base_name) {
-        return base_name.to_string();
+fn validate_launch_args(args: &[String]) -> Result<(), ApiError> {
+    if args.iter().any(|arg| arg.contains(['\0', '\n', '\r'])) {
+        return Err(ApiError::bad_request(
+            "agent args must not contain control characters",
+        ));
     }
+    Ok(())
+}
 
-    loop {
-        let candidate = format!("{}-{}", base_name, suffix_fn());
-        if !existing.contains(&candidate) {
-            return candidate;
+/// What a tool session runs: a command line for `sh -lc`, so tools see the
+/// login environment, plus the variables the tool's spec sets.
+#[derive(Debug, Clone, PartialEq)]
+struct ToolLaunch {
+    command: String,
+    env: BTreeMap<String, String>,
+}
+
+impl ToolLaunch {
+    /// A spec with a `command` runs that program with its arguments quoted
+    /// as-is; a plain tool is used as written. `args` from the agent follow
+    /// the spec's own.
+    fn new(tool: &ToolSpec, args: &[String]) -> Self {
+        let program = match &tool.command {
+            Some(command) => shell_quote(command),
+            None => tool.name.clone(),
+        };
+        let args: Vec<String> = tool.args.iter().chain(args).cloned().collect();
+        Self {
+            command: launch_command(&program, &args),
+            env: tool.env.clone(),
         }
     }
-}
 
-async fn generate_unique_agent_name(
-    db: Arc<tokio::sync::Mutex<Connection>>,
-) -> Result<String, ApiError> {
-    let conn = db.lock().await;
-    loop {
-        let candidate = petname(2, "-");
-        let exists: bool = conn
-            .query_row(
-                "SELECT EXISTS(SELECT 1 FROM agents WHERE name = ?1)",
-                params![candidate.as_str()],
-                |row| row.get(0),
-            )
-            .map_err(|err| ApiError::internal(err.to_string()))?;
-        if !exists {
-            return Ok(candidate);
+    /// The launch for `tool` in `repo`, with the repo's `env` under the
+    /// tool's.
+    fn in_repo(repo: &RepoConfig, tool: &str, args: &[String]) -> Self {
+        let mut launch = Self::new(&repo_tool_spec(repo, tool), args);
+        for (key, value) in &repo.env {
+            launch
+                .env
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
         }
+        launch
     }
 }
 
-fn create_worktree(
-    repo_path: &Path,
-    repo_name: &str,
-    agent_name: &str,
-) -> Result<PathBuf, ApiError> {
-    let trees_dir = data_dir().join("trees");
-    std::fs::create_dir_all(&trees_dir).map_err(|err| ApiError::internal(err.to_string()))?;
-    let kebab_name = to_kebab(agent_name);
-    let worktree_path = trees_dir.join(format!("{}-{}", repo_name, kebab_name));
+/// The repo's spec for `tool`. Tools since removed from the config run as
+/// a plain command, as every tool did before specs.
+fn repo_tool_spec(repo: &RepoConfig, tool: &str) -> ToolSpec {
+    repo.tools
+        .iter()
+        .find(|spec| spec.name == tool)
+        .cloned()
+        .unwrap_or_else(|| ToolSpec::from(tool))
+}
 
-    if worktree_path.exists() {
-        return Err(ApiError::bad_request("worktree already exists"));
+/// Builds the shell command for a tool launch, quoting each extra argument
+/// so it reaches the tool verbatim.
+fn launch_command(tool: &str, args: &[String]) -> String {
+    let mut command = tool.to_string();
+    for arg in args {
+        command.push(' ');
+        command.push_str(&shell_quote(arg));
     }
+    command
+}
 
-    let branch_name = format!("agent/{}", kebab_name);
-    let status = Command::new("git")
-        .arg("-C")
-        .arg(repo_path)
-        .args(["worktree", "add", "-b"])
-        .arg(&branch_name)
-        .arg(&worktree_path)
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status()
-        .map_err(|err| ApiError::internal(err.to_string()))?;
-
-    if !status.success() {
-        return Err(ApiError::internal("git worktree add failed"));
+fn shell_quote(arg: &str) -> String {
+    let safe = !arg.is_empty()
+        && arg
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || "-_=.,/:@+%".contains(ch));
+    if safe {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', "'\\''"))
     }
+}
 
-    Ok(worktree_path)
+/// Looks up the launch command, worktree and initial input for an existing
+/// agent, with the tool specs of its entry in `repos`. The config is loaded
+/// by callers before they take the database lock.
+fn agent_launch_spec(
+    conn: &Connection,
+    repos: &[RepoConfig],
+    name: &str,
+) -> rusqlite::Result<(ToolLaunch, String, Option<String>)> {
+    let (repo, tool, worktree_path, args) = conn.query_row(
+        "SELECT repo, tool, worktree_path, args FROM agents WHERE name = ?1",
+        params![name],
+        |row| {
+            let args: Option<String> = row.get(3)?;
+            let args: Vec<String> = args
+                .and_then(|value| serde_json::from_str(&value).ok())
+                .unwrap_or_default();
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                args,
+            ))
+        },
+    )?;
+    let repo = repos.iter().find(|entry| entry.name == repo);
+    let launch = repo.map_or_else(
+        || ToolLaunch::new(&ToolSpec::from(tool.as_str()), &args),
+        |repo| ToolLaunch::in_repo(repo, &tool, &args),
+    );
+    let initial_input = repo.and_then(|repo| repo.initial_input.clone());
+    Ok((launch, worktree_path, initial_input))
 }
 
-fn start_tool_session(
-    agent_name: &str,
-    tool: &str,
-    worktree_path: &Path,
+/// Repos whose tool specs launch agents. An unreadable config leaves every
+/// tool running as a plain command rather than failing the launch.
+fn launch_repos() -> Vec<RepoConfig> {
+    load_repo_config()
+        .map(|config| config.repos)
+        .unwrap_or_default()
+}
+
+/// Leaves nothing behind for an agent that was never recorded.
+fn discard_agent(
     sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    agent: &NewAgent,
+    worktree_path: &Path,
+) {
+    stop_pty_session(&agent.name, sessions);
+    let _ = delete_worktree(&agent.repo.path, worktree_path, &agent.name);
+}
+
+/// Creates the agent's worktree and optionally starts its tool session.
+fn prepare_agent(
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    agent: &NewAgent,
+) -> Result<PathBuf, ApiError> {
+    let repo = agent.repo;
+    let worktree_path = create_worktree(&repo.path, &worktree_root(repo), &repo.name, &agent.name)?;
+    if agent.start {
+        if let Err(err) = start_tool_session(
+            &agent.name,
+            &ToolLaunch::in_repo(repo, agent.tool, agent.args),
+            &worktree_path,
+            repo.initial_input.as_deref(),
+            sessions,
+        ) {
+            discard_agent(sessions, agent, &worktree_path);
+            return Err(err);
+        }
+    }
+    Ok(worktree_path)
+}
+
+/// Inserts a prepared agent's row, discarding the agent if that fails.
+/// The repo's limit is checked again under the same lock as the insert,
+/// since other agents may have been added while the worktree was made.
+fn record_agent(
+    conn: &Connection,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    new_agent: &NewAgent,
+    worktree_path: &Path,
+) -> Result<Agent, ApiError> {
+    let now = Utc::now().to_rfc3339();
+
+    let agent = Agent {
+        name: new_agent.name.clone(),
+        label: new_agent.name.clone(),
+        repo: new_agent.repo.name.clone(),
+        tool: new_agent.tool.to_string(),
+        status: if new_agent.start { "running" } else { "sleep" }.to_string(),
+        worktree_path: worktree_path.to_string_lossy().to_string(),
+        styles: None,
+        output: None,
+        created_at: now.clone(),
+        updated_at: now,
+        dirty_count: None,
+    };
+
+    if let Err(err) = ensure_agent_capacity(conn, new_agent.repo, 0) {
+        discard_agent(sessions, new_agent, worktree_path);
+        return Err(err);
+    }
+    let args = (!new_agent.args.is_empty()).then(|| serde_json::json!(new_agent.args).to_string());
+    let inserted = conn.execute(
+        "INSERT INTO agents (name, label, repo, tool, status, worktree_path, styles, created_at, updated_at, args) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        params![
+            agent.name,
+            agent.label,
+            agent.repo,
+            agent.tool,
+            agent.status,
+            agent.worktree_path,
+            agent
+                .styles
+                .as_ref()
+                .map(|value| value.to_string()),
+            agent.created_at,
+            agent.updated_at,
+            args,
+        ],
+    );
+    if let Err(err) = inserted {
+        discard_agent(sessions, new_agent, worktree_path);
+        return Err(db_error(err));
+    }
+
+    Ok(agent)
+}
+
+async fn delete_agent(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+) -> Result<StatusCode, ApiError> {
+    let (repo_name, worktree_path) = {
+        let conn = state.db.lock().await;
+        conn.query_row(
+            "SELECT repo, worktree_path FROM agents WHERE name = ?1",
+            params![name.as_str()],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        )
+        .map_err(|err| match err {
+            rusqlite::Error::QueryReturnedNoRows => ApiError::not_found("agent not found"),
+            _ => ApiError::internal(err.to_string()),
+        })?
+    };
+
+    let config = load_repo_config()?;
+    let repo = config
+        .repos
+        .iter()
+        .find(|repo| repo.name == repo_name)
+        .ok_or_else(|| ApiError::not_found("repo not found for agent"))?;
+
+    stop_pty_session(&name, &state.pty_sessions);
+    delete_worktree(&repo.path, Path::new(&worktree_path), &name)?;
+
+    let conn = state.db.lock().await;
+    conn.execute("DELETE FROM agents WHERE name = ?1", params![name.as_str()])
+        .map_err(db_error)?;
+    forget_git_status(&state.git_status, &name);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn restart_agent(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+) -> Result<StatusCode, ApiError> {
+    let repos = launch_repos();
+    let (command, worktree_path, initial_input) = {
+        let conn = state.db.lock().await;
+        agent_launch_spec(&conn, &repos, &name).map_err(|err| match err {
+            rusqlite::Error::QueryReturnedNoRows => ApiError::not_found("agent not found"),
+            _ => ApiError::internal(err.to_string()),
+        })?
+    };
+
+    stop_pty_session(&name, &state.pty_sessions);
+    start_tool_session(
+        &name,
+        &command,
+        Path::new(&worktree_path),
+        initial_input.as_deref(),
+        &state.pty_sessions,
+    )?;
+
+    let now = Utc::now().to_rfc3339();
+    let conn = state.db.lock().await;
+    conn.execute(
+        "UPDATE agents SET status = ?1, exit_code = NULL, updated_at = ?2 WHERE name = ?3",
+        params!["running", now, name.as_str()],
+    )
+    .map_err(db_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn reset_agent(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+    Json(request): Json<ResetRequest>,
+) -> Result<StatusCode, ApiError> {
+    if !request.confirm {
+        return Err(ApiError::bad_request(
+            "reset discards all worktree changes; resend with confirm set to true",
+        ));
+    }
+    let repo_name = {
+        let conn = state.db.lock().await;
+        conn.query_row(
+            "SELECT repo FROM agents WHERE name = ?1",
+            params![name.as_str()],
+            |row| row.get::<_, String>(0),
+        )
+        .map_err(|err| match err {
+            rusqlite::Error::QueryReturnedNoRows => ApiError::not_found("agent not found"),
+            _ => ApiError::internal(err.to_string()),
+        })?
+    };
+    let config = load_repo_config()?;
+    let repo = config
+        .repos
+        .into_iter()
+        .find(|repo| repo.name == repo_name)
+        .ok_or_else(|| ApiError::not_found("repo not found for agent"))?;
+
+    let (db, sessions) = (state.db.clone(), state.pty_sessions.clone());
+    let agent = name.clone();
+    tokio::task::spawn_blocking(move || reset_agent_worktree(&db, &sessions, &repo, &agent, true))
+        .await
+        .map_err(|err| ApiError::internal(err.to_string()))??;
+    forget_git_status(&state.git_status, &name);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Stops the agent, replaces its worktree and branch with fresh ones from
+/// the repo's current HEAD, and optionally restarts the tool. The agent row
+/// and name are kept. The git and session work runs without the database
+/// lock. If the new worktree cannot be made, the agent is marked `error`
+/// and no longer points at the removed one.
+fn reset_agent_worktree(
+    db: &tokio::sync::Mutex<Connection>,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    repo: &RepoConfig,
+    name: &str,
+    restart: bool,
+) -> Result<PathBuf, ApiError> {
+    let (command, worktree_path, _) = {
+        let conn = db.blocking_lock();
+        agent_launch_spec(&conn, std::slice::from_ref(repo), name).map_err(|err| match err {
+            rusqlite::Error::QueryReturnedNoRows => ApiError::not_found("agent not found"),
+            _ => db_error(err),
+        })?
+    };
+
+    stop_pty_session(name, sessions);
+    delete_worktree(&repo.path, Path::new(&worktree_path), name)?;
+    let worktree_path = match create_worktree(&repo.path, &worktree_root(repo), &repo.name, name) {
+        Ok(path) => path,
+        Err(err) => {
+            update_reset_agent(db, name, "error", "")?;
+            return Err(err);
+        }
+    };
+    let worktree = worktree_path.to_string_lossy().to_string();
+    if restart {
+        if let Err(err) = start_tool_session(
+            name,
+            &command,
+            &worktree_path,
+            repo.initial_input.as_deref(),
+            sessions,
+        ) {
+            update_reset_agent(db, name, "error", &worktree)?;
+            return Err(err);
+        }
+    }
+
+    let status = if restart { "running" } else { "sleep" };
+    update_reset_agent(db, name, status, &worktree)?;
+    Ok(worktree_path)
+}
+
+fn update_reset_agent(
+    db: &tokio::sync::Mutex<Connection>,
+    name: &str,
+    status: &str,
+    worktree: &str,
 ) -> Result<(), ApiError> {
-    let mut sessions = sessions.lock().expect("pty sessions lock");
-    if sessions.contains_key(agent_name) {
-        return Ok(());
+    let now = Utc::now().to_rfc3339();
+    db.blocking_lock()
+        .execute(
+            "UPDATE agents SET status = ?1, exit_code = NULL, worktree_path = ?2, updated_at = ?3 WHERE name = ?4",
+            params![status, worktree, now, name],
+        )
+        .map_err(db_error)?;
+    Ok(())
+}
+
+/// Stops the agent's session but keeps its worktree and branch. The next
+/// `INPUT` or `ATTACH` from the broker starts it again.
+async fn sleep_agent(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+) -> Result<StatusCode, ApiError> {
+    let conn = state.db.lock().await;
+    put_agent_to_sleep(&conn, &state.pty_sessions, &name)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn put_agent_to_sleep(
+    conn: &Connection,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    name: &str,
+) -> Result<(), ApiError> {
+    let now = Utc::now().to_rfc3339();
+    let updated = conn
+        .execute(
+            "UPDATE agents SET status = 'sleep', exit_code = NULL, updated_at = ?1 WHERE name = ?2",
+            params![now, name],
+        )
+        .map_err(db_error)?;
+    if updated == 0 {
+        return Err(ApiError::not_found("agent not found"));
+    }
+    stop_pty_session(name, sessions);
+    Ok(())
+}
+
+async fn signal_agent(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+    Json(request): Json<SignalRequest>,
+) -> Result<StatusCode, ApiError> {
+    let signal = parse_signal(&request.signal)?;
+    let sessions = state.pty_sessions.lock().expect("pty sessions lock");
+    let session = sessions
+        .get(&name)
+        .ok_or_else(|| ApiError::not_found("agent session not running"))?;
+    let process_group = session
+        .master
+        .process_group_leader()
+        .or_else(|| session.child.process_id().map(|pid| pid as i32))
+        .ok_or_else(|| ApiError::internal("agent process not found"))?;
+    killpg(Pid::from_raw(process_group), signal)
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+fn parse_signal(name: &str) -> Result<Signal, ApiError> {
+    let name = name.trim().to_ascii_uppercase();
+    match name.strip_prefix("SIG").unwrap_or(&name) {
+        "INT" => Ok(Signal::SIGINT),
+        "TERM" => Ok(Signal::SIGTERM),
+        "KILL" => Ok(Signal::SIGKILL),
+        "HUP" => Ok(Signal::SIGHUP),
+        _ => Err(ApiError::bad_request(format!("unsupported signal: {name}"))),
+    }
+}
+
+async fn agent_diff(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+    Query(query): Query<DiffQuery>,
+) -> Result<Json<AgentDiff>, ApiError> {
+    let worktree_path = {
+        let conn = state.db.lock().await;
+        agent_worktree(&conn, &name)?
+    };
+    let worktree_path = existing_worktree(&worktree_path)?;
+
+    let output = diff_command(worktree_path, query.stat)
+        .output()
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(ApiError::internal(format!(
+            "git diff failed: {}",
+            stderr.trim()
+        )));
+    }
+
+    let (diff, truncated) = truncate_diff(&output.stdout, MAX_DIFF_BYTES);
+    Ok(Json(AgentDiff { diff, truncated }))
+}
+
+/// Stages everything in the agent's worktree and commits it, returning the
+/// new commit. A clean worktree or a failing commit is a 400 carrying git's
+/// output.
+async fn commit_agent(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+    Json(request): Json<CommitRequest>,
+) -> Result<Json<AgentCommit>, ApiError> {
+    let message = commit_message(&request.message)?;
+    let worktree_path = {
+        let conn = state.db.lock().await;
+        agent_worktree(&conn, &name)?
+    };
+    let sha = commit_worktree(existing_worktree(&worktree_path)?, message)?;
+    forget_git_status(&state.git_status, &name);
+    Ok(Json(AgentCommit { sha }))
+}
+
+fn agent_worktree(conn: &Connection, name: &str) -> Result<String, ApiError> {
+    conn.query_row(
+        "SELECT worktree_path FROM agents WHERE name = ?1",
+        params![name],
+        |row| row.get(0),
+    )
+    .map_err(|err| match err {
+        rusqlite::Error::QueryReturnedNoRows => ApiError::not_found("agent not found"),
+        _ => ApiError::internal(err.to_string()),
+    })
+}
+
+fn commit_message(message: &str) -> Result<&str, ApiError> {
+    let message = message.trim();
+    if message.is_empty() {
+        return Err(ApiError::bad_request("commit message is required"));
+    }
+    if message.contains('\0') {
+        return Err(ApiError::bad_request(
+            "commit message must not contain NUL bytes",
+        ));
     }
+    Ok(message)
+}
+
+/// Runs `git add -A` and `git commit` in the worktree and returns the new
+/// HEAD. The message is fed on stdin rather than argv, so nothing in it can
+/// be taken as a git option.
+fn commit_worktree(worktree_path: &Path, message: &str) -> Result<String, ApiError> {
+    let add = Command::new("git")
+        .arg("-C")
+        .arg(worktree_path)
+        .args(["add", "-A"])
+        .output()
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    if !add.status.success() {
+        return Err(ApiError::bad_request(git_failure("git add", &add)));
+    }
+
+    let mut child = Command::new("git")
+        .arg("-C")
+        .arg(worktree_path)
+        .args(["commit", "--file=-"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin
+            .write_all(message.as_bytes())
+            .map_err(|err| ApiError::internal(err.to_string()))?;
+    }
+    let commit = child
+        .wait_with_output()
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    if !commit.status.success() {
+        return Err(ApiError::bad_request(git_failure("git commit", &commit)));
+    }
+
+    rev_parse(worktree_path, "HEAD")
+}
+
+fn rev_parse(repo_path: &Path, rev: &str) -> Result<String, ApiError> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo_path)
+        .args(["rev-parse", "--verify", rev])
+        .output()
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    if !output.status.success() {
+        return Err(ApiError::internal(git_failure("git rev-parse", &output)));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// git reports some failures, like "nothing to commit", on stdout.
+fn git_failure(command: &str, output: &std::process::Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let detail = if stderr.trim().is_empty() {
+        String::from_utf8_lossy(&output.stdout)
+    } else {
+        stderr
+    };
+    format!("{command} failed: {}", detail.trim())
+}
+
+/// Merges the agent's `agent/<name>` branch into the branch checked out in
+/// its repo. Uncommitted work in the agent's worktree is refused since it
+/// would be left out; a conflicting merge is aborted and comes back as a 409
+/// naming the conflicted paths.
+async fn merge_agent(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+    Json(request): Json<MergeRequest>,
+) -> Result<Json<AgentMerge>, ApiError> {
+    let (repo_name, worktree_path) = {
+        let conn = state.db.lock().await;
+        conn.query_row(
+            "SELECT repo, worktree_path FROM agents WHERE name = ?1",
+            params![name.as_str()],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        )
+        .map_err(|err| match err {
+            rusqlite::Error::QueryReturnedNoRows => ApiError::not_found("agent not found"),
+            _ => ApiError::internal(err.to_string()),
+        })?
+    };
+    let config = load_repo_config()?;
+    let repo = config
+        .repos
+        .iter()
+        .find(|repo| repo.name == repo_name)
+        .ok_or_else(|| ApiError::not_found("repo not found for agent"))?;
+
+    ensure_committed(existing_worktree(&worktree_path)?)?;
+    let branch = agent_branch(&name);
+    let merged = merge_branch(
+        &repo.path,
+        &branch,
+        request.into.as_deref(),
+        request.ff_only,
+    )?;
+    Ok(Json(merged))
+}
+
+/// The branch an agent's worktree is created on.
+fn agent_branch(agent_name: &str) -> String {
+    format!("agent/{}", to_kebab(agent_name))
+}
+
+fn ensure_committed(worktree_path: &Path) -> Result<(), ApiError> {
+    match worktree_dirty_count(worktree_path) {
+        Some(0) => Ok(()),
+        Some(count) => Err(ApiError::conflict(format!(
+            "agent worktree has {count} uncommitted changes; commit them before merging"
+        ))),
+        None => Err(ApiError::internal("git status failed in agent worktree")),
+    }
+}
+
+/// Merges `branch` into the checked-out branch of `repo_path`, which `into`
+/// must name when given: merging elsewhere would mean switching branches
+/// under the user's feet. Uncommitted changes in the repo are refused
+/// rather than mixed into the merge.
+fn merge_branch(
+    repo_path: &Path,
+    branch: &str,
+    into: Option<&str>,
+    ff_only: bool,
+) -> Result<AgentMerge, ApiError> {
+    let current = checked_out_branch(repo_path)?;
+    if let Some(into) = into.filter(|into| *into != current) {
+        return Err(ApiError::bad_request(format!(
+            "{into} is not checked out in the repo ({current} is); check it out to merge into it"
+        )));
+    }
+    match worktree_dirty_count(repo_path) {
+        Some(0) => {}
+        Some(count) => {
+            return Err(ApiError::conflict(format!(
+                "repo has {count} uncommitted changes; commit or stash them before merging"
+            )))
+        }
+        None => return Err(ApiError::internal("git status failed in repo")),
+    }
+    let tip = rev_parse(repo_path, branch)
+        .map_err(|_| ApiError::not_found(format!("branch {branch} not found")))?;
+    let output = merge_command(repo_path, branch, ff_only)
+        .output()
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    if !output.status.success() {
+        let conflicts = conflicted_paths(repo_path);
+        if conflicts.is_empty() {
+            return Err(ApiError::bad_request(git_failure("git merge", &output)));
+        }
+        let _ = Command::new("git")
+            .arg("-C")
+            .arg(repo_path)
+            .args(["merge", "--abort"])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+        return Err(ApiError::conflict(format!(
+            "merging {branch} into {current} conflicts in {}",
+            conflicts.join(", ")
+        )));
+    }
+    let sha = rev_parse(repo_path, "HEAD")?;
+    Ok(AgentMerge {
+        branch: branch.to_string(),
+        into: current,
+        fast_forward: sha == tip,
+        sha,
+    })
+}
+
+fn merge_command(repo_path: &Path, branch: &str, ff_only: bool) -> Command {
+    let mut command = Command::new("git");
+    command
+        .arg("-C")
+        .arg(repo_path)
+        .args(["merge", "--no-edit"]);
+    if ff_only {
+        command.arg("--ff-only");
+    }
+    command.arg(branch);
+    command
+}
+
+fn checked_out_branch(repo_path: &Path) -> Result<String, ApiError> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo_path)
+        .args(["symbolic-ref", "--quiet", "--short", "HEAD"])
+        .output()
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    if !output.status.success() {
+        return Err(ApiError::bad_request(
+            "repo has no branch checked out to merge into",
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Paths left unmerged by a failed merge.
+fn conflicted_paths(repo_path: &Path) -> Vec<String> {
+    Command::new("git")
+        .arg("-C")
+        .arg(repo_path)
+        .args(["diff", "--name-only", "--diff-filter=U"])
+        .output()
+        .map(|output| {
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Returns the raw PTY history for an agent: the live buffer while its
+/// session runs, otherwise the last flushed history file.
+async fn agent_history(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+) -> Result<Response, ApiError> {
+    let bytes = read_agent_history(&name, &state.pty_sessions, &history_dir())
+        .map_err(ApiError::internal)?
+        .ok_or_else(|| ApiError::not_found("no history for agent"))?;
+    Ok(([(header::CONTENT_TYPE, "application/octet-stream")], bytes).into_response())
+}
+
+/// The agent's output without touching its session, so reading it never
+/// wakes a sleeping agent. `None` when it has neither a session nor a
+/// flushed history file.
+fn read_agent_history(
+    name: &str,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    dir: &Path,
+) -> Result<Option<Vec<u8>>, String> {
+    let live = {
+        let sessions = sessions.lock().expect("pty sessions lock");
+        sessions.get(name).map(|session| session.history.clone())
+    };
+    if let Some(history) = live {
+        let history = history.lock().expect("pty history lock");
+        return Ok(Some(history.iter().copied().collect()));
+    }
+    let path = history_file_path(dir, name);
+    match std::fs::read(&path) {
+        Ok(bytes) => Ok(Some(bytes)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(format!("{}: {err}", path.display())),
+    }
+}
+
+/// Returns the offset→time index matching `agent_history`, or 404 when the
+/// agent's history was recorded without timings.
+async fn agent_history_timing(
+    State(state): State<AppState>,
+    AxumPath(name): AxumPath<String>,
+) -> Result<Json<Vec<TimingEntry>>, ApiError> {
+    let live = {
+        let sessions = state.pty_sessions.lock().expect("pty sessions lock");
+        sessions
+            .get(&name)
+            .map(|session| (session.history.clone(), session.timings.clone()))
+    };
+    match live {
+        Some((history, Some(timings))) => {
+            let history_len = history.lock().expect("pty history lock").len();
+            let timings = timings.lock().expect("pty timings lock");
+            Ok(Json(timings.index_for_history(history_len)))
+        }
+        Some((_, None)) => Err(ApiError::not_found("timed history is disabled")),
+        None => {
+            let path = timing_file_path(&history_dir(), &name);
+            match std::fs::read_to_string(&path) {
+                Ok(data) => Ok(Json(parse_timing_index(&data))),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    Err(ApiError::not_found("no timed history for agent"))
+                }
+                Err(err) => Err(ApiError::internal(format!("{}: {err}", path.display()))),
+            }
+        }
+    }
+}
+
+/// The agent's worktree, or 404 when it was removed behind the server's back
+/// (git would otherwise fail with a confusing 500).
+fn existing_worktree(worktree_path: &str) -> Result<&Path, ApiError> {
+    let path = Path::new(worktree_path);
+    if path.is_dir() {
+        Ok(path)
+    } else {
+        Err(ApiError::not_found(format!(
+            "worktree not found: {worktree_path}"
+        )))
+    }
+}
+
+fn diff_command(worktree_path: &Path, stat: bool) -> Command {
+    let mut command = Command::new("git");
+    command.arg("-C").arg(worktree_path).arg("diff");
+    if stat {
+        command.arg("--stat");
+    }
+    command
+}
+
+fn truncate_diff(output: &[u8], limit: usize) -> (String, bool) {
+    let text = String::from_utf8_lossy(output);
+    if text.len() <= limit {
+        return (text.into_owned(), false);
+    }
+    let mut end = limit;
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    (text[..end].to_string(), true)
+}
+
+async fn flush_histories(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<FlushedHistory>>, ApiError> {
+    type SessionHistory = (
+        String,
+        Arc<Mutex<VecDeque<u8>>>,
+        Option<Arc<Mutex<HistoryTimings>>>,
+    );
+    let histories: Vec<SessionHistory> = {
+        let sessions = state.pty_sessions.lock().expect("pty sessions lock");
+        sessions
+            .iter()
+            .map(|(name, session)| {
+                (
+                    name.clone(),
+                    session.history.clone(),
+                    session.timings.clone(),
+                )
+            })
+            .collect()
+    };
+
+    let mut flushed = tokio::task::spawn_blocking(move || {
+        let dir = history_dir();
+        std::fs::create_dir_all(&dir).map_err(|err| ApiError::internal(err.to_string()))?;
+
+        let mut flushed = Vec::new();
+        for (name, history, timings) in histories {
+            // Copy under the locks so the reader is not blocked behind the fsync.
+            let (history, index) = {
+                let history = history.lock().expect("pty history lock");
+                let index = timings.as_ref().map(|timings| {
+                    timings
+                        .lock()
+                        .expect("pty timings lock")
+                        .index_for_history(history.len())
+                });
+                (history.clone(), index)
+            };
+            let bytes = flush_agent_history(&dir, &name, &history, index.as_deref())
+                .map_err(|err| ApiError::internal(format!("{}: {err}", dir.display())))?;
+            flushed.push(FlushedHistory { name, bytes });
+        }
+        Ok::<_, ApiError>(flushed)
+    })
+    .await
+    .map_err(|err| ApiError::internal(err.to_string()))??;
+    flushed.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(Json(flushed))
+}
+
+async fn prune_agents(
+    State(state): State<AppState>,
+    Query(query): Query<PruneQuery>,
+) -> Result<Json<PruneReport>, ApiError> {
+    let days = query
+        .days
+        .or(settings().retention_days)
+        .ok_or_else(|| ApiError::bad_request("retention is not configured"))?;
+    if days < 0 {
+        return Err(ApiError::bad_request("retention days must not be negative"));
+    }
+
+    let config = load_repo_config()?;
+    let candidates = {
+        let conn = state.db.lock().await;
+        let sessions = state.pty_sessions.lock().expect("pty sessions lock");
+        retention_candidates(&conn, &config, |name| sessions.contains_key(name))?
+    };
+    let cutoff = Utc::now() - chrono::Duration::days(days);
+    let stale = stale_agents(&candidates, cutoff);
+
+    let mut report = PruneReport {
+        dry_run: query.dry_run,
+        days,
+        removed: Vec::new(),
+        failed_agents: Vec::new(),
+    };
+    if query.dry_run {
+        report.removed = stale;
+        return Ok(Json(report));
+    }
+
+    // Each agent's `git worktree remove` runs without the database lock.
+    let (db, sessions, git_status) = (
+        state.db.clone(),
+        state.pty_sessions.clone(),
+        state.git_status.clone(),
+    );
+    let report = tokio::task::spawn_blocking(move || {
+        for name in stale {
+            let repo_name = candidates
+                .iter()
+                .find(|candidate| candidate.name == name)
+                .map(|candidate| candidate.repo.as_str())
+                .unwrap_or_default();
+            let result = config
+                .repos
+                .iter()
+                .find(|repo| repo.name == repo_name)
+                .ok_or_else(|| ApiError::not_found("repo not found for agent"))
+                .and_then(|repo| remove_agent(&db, &sessions, &repo.path, &name));
+            match result {
+                Ok(()) => {
+                    forget_git_status(&git_status, &name);
+                    report.removed.push(name);
+                }
+                Err(err) => report.failed_agents.push(AgentFailure {
+                    name,
+                    error: err.message,
+                }),
+            }
+        }
+        report
+    })
+    .await
+    .map_err(|err| ApiError::internal(err.to_string()))?;
+
+    Ok(Json(report))
+}
+
+struct RetentionCandidate {
+    name: String,
+    repo: String,
+    status: String,
+    updated_at: String,
+    kind: ToolKind,
+}
+
+/// Every agent with the status `/agents` reports for it, so an agent whose
+/// tool exited stays `idle` or `error` rather than counting as asleep.
+/// `running` tells which agents have a session.
+fn retention_candidates(
+    conn: &Connection,
+    config: &RepoConfigFile,
+    running: impl Fn(&str) -> bool,
+) -> Result<Vec<RetentionCandidate>, ApiError> {
+    let mut stmt = conn
+        .prepare("SELECT name, repo, tool, status, updated_at FROM agents ORDER BY name")
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    let candidates = stmt
+        .query_map([], |row| {
+            let name: String = row.get(0)?;
+            let repo: String = row.get(1)?;
+            let tool: String = row.get(2)?;
+            let stored_status: String = row.get(3)?;
+            Ok(RetentionCandidate {
+                status: agent_live_status(running(&name), &stored_status),
+                kind: tool_kind(config, &repo, &tool),
+                updated_at: row.get(4)?,
+                name,
+                repo,
+            })
+        })
+        .map_err(|err| ApiError::internal(err.to_string()))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    Ok(candidates)
+}
+
+/// The kind of an agent's tool, looked up in its repo's config.
+fn tool_kind(config: &RepoConfigFile, repo: &str, tool: &str) -> ToolKind {
+    config
+        .repos
+        .iter()
+        .find(|entry| entry.name == repo)
+        .map_or_else(|| ToolSpec::from(tool), |repo| repo_tool_spec(repo, tool))
+        .kind()
+}
+
+/// Agents that are asleep and were last updated before `cutoff`. Rows with
+/// unparseable timestamps are kept, and so are shells, which are expected
+/// to sit unused for long stretches.
+fn stale_agents(candidates: &[RetentionCandidate], cutoff: chrono::DateTime<Utc>) -> Vec<String> {
+    candidates
+        .iter()
+        .filter(|candidate| candidate.status == "sleep" && candidate.kind == ToolKind::Agent)
+        .filter(|candidate| {
+            chrono::DateTime::parse_from_rfc3339(&candidate.updated_at)
+                .map(|updated_at| updated_at < cutoff)
+                .unwrap_or(false)
+        })
+        .map(|candidate| candidate.name.clone())
+        .collect()
+}
+
+async fn shutdown(State(state): State<AppState>) -> &'static str {
+    let mut sender_guard = state.shutdown_sender.lock().await;
+    if let Some(sender) = sender_guard.take() {
+        let _ = sender.send(());
+    }
+    "shutting down"
+}
+
+async fn wait_for_shutdown(mut shutdown_receiver: oneshot::Receiver<()>) {
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {},
+        _ = &mut shutdown_receiver => {},
+    }
+}
+
+fn start_pty_broker(
+    sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+    db: Arc<tokio::sync::Mutex<Connection>>,
+) -> Result<PtyBroker, Box<dyn Error>> {
+    let socket_path = data_dir().join("pty.sock");
+    if let Some(parent) = socket_path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let listener = bind_broker_socket(&socket_path)?;
+    let health = BrokerHealth {
+        socket_path: socket_path.clone(),
+        accepting: Arc::new(AtomicBool::new(true)),
+    };
+    let accepting = health.accepting.clone();
+    let rebind_path = socket_path.clone();
+    let handle = thread::spawn(move || {
+        let err = run_accept_loop(
+            listener,
+            |listener| listener.accept().map(|(stream, _)| stream),
+            || bind_broker_socket(&rebind_path),
+            |stream| {
+                let sessions = sessions.clone();
+                let db = db.clone();
+                thread::spawn(move || {
+                    if let Err(err) = handle_pty_connection(stream, sessions, db) {
+                        eprintln!("pty broker error: {err}");
+                    }
+                });
+            },
+        );
+        eprintln!("pty broker giving up after repeated accept errors: {err}");
+        accepting.store(false, Ordering::Relaxed);
+    });
+
+    Ok(PtyBroker {
+        socket_path,
+        health,
+        _handle: handle,
+    })
+}
+
+fn bind_broker_socket(socket_path: &Path) -> io::Result<UnixListener> {
+    let _ = std::fs::remove_file(socket_path);
+    UnixListener::bind(socket_path)
+}
+
+/// Accepts connections until `BROKER_MAX_ACCEPT_FAILURES` errors happen in
+/// a row, returning the last one. Transient errors are retried on the same
+/// listener; anything else rebinds the socket first, since the listener
+/// itself is likely broken.
+fn run_accept_loop<L, S>(
+    mut listener: L,
+    mut accept: impl FnMut(&L) -> io::Result<S>,
+    mut rebind: impl FnMut() -> io::Result<L>,
+    mut handle: impl FnMut(S),
+) -> io::Error {
+    let mut failures = 0;
+    loop {
+        let err = match accept(&listener) {
+            Ok(stream) => {
+                failures = 0;
+                handle(stream);
+                continue;
+            }
+            Err(err) => err,
+        };
+        failures += 1;
+        eprintln!("pty broker accept error: {err}");
+        if failures >= BROKER_MAX_ACCEPT_FAILURES {
+            return err;
+        }
+        thread::sleep(BROKER_RETRY_DELAY);
+        if !is_transient_accept_error(&err) {
+            match rebind() {
+                Ok(rebound) => listener = rebound,
+                Err(bind_err) => eprintln!("pty broker rebind error: {bind_err}"),
+            }
+        }
+    }
+}
+
+fn is_transient_accept_error(err: &io::Error) -> bool {
+    matches!(
+        err.kind(),
+        io::ErrorKind::Interrupted
+            | io::ErrorKind::WouldBlock
+            | io::ErrorKind::ConnectionAborted
+            | io::ErrorKind::ConnectionReset
+    ) || matches!(
+        err.raw_os_error().map(Errno::from_raw),
+        Some(Errno::EMFILE | Errno::ENFILE | Errno::ENOBUFS | Errno::ENOMEM)
+    )
+}
+
+fn handle_pty_connection(
+    stream: UnixStream,
+    sessions: Arc<Mutex<HashMap<String, PtySession>>>,
+    db: Arc<tokio::sync::Mutex<Connection>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut line = String::new();
+    loop {
+        line.clear();
+        if reader.read_line(&mut line)? == 0 {
+            break;
+        }
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            continue;
+        }
+        let mut parts = trimmed.split_whitespace();
+        let command = parts.next().unwrap_or("");
+        let Some(agent) = decode_agent_token(parts.next().unwrap_or("")) else {
+            let _ = write_response(&stream, "ERR invalid agent name\n");
+            continue;
+        };
+        let agent = agent.as_str();
+        match command {
+            "ATTACH" | "FRESH" => {
+                let response = if command == "FRESH" {
+                    fresh_pty_session(agent, &db, &sessions)
+                        .map_err(Into::into)
+                        .and_then(|()| attach_pty(agent, &stream, &sessions, &db))
+                } else {
+                    attach_pty(agent, &stream, &sessions, &db)
+                };
+                if let Err(err) = response {
+                    let _ = write_response(&stream, &format!("ERR {err}\n"));
+                }
+            }
+            "HISTORY" => {
+                let response = read_agent_history(agent, &sessions, &history_dir())
+                    .and_then(|history| history.ok_or_else(|| "no history for agent".to_string()));
+                let _ = match response {
+                    Ok(history) => write_response(&stream, &format!("HISTORY {}\n", history.len()))
+                        .and_then(|()| Ok((&stream).write_all(&history)?)),
+                    Err(err) => write_response(&stream, &format!("ERR {err}\n")),
+                };
+            }
+            "DETACH" => {
+                let response = match parts.next().and_then(|value| value.parse::<u64>().ok()) {
+                    Some(id) if detach_subscriber(agent, id, &sessions) => "OK\n",
+                    Some(_) => "ERR unknown subscriber\n",
+                    None => "ERR invalid detach\n",
+                };
+                let _ = write_response(&stream, response);
+            }
+            "RESIZE" => {
+                let cols = parts.next().and_then(|value| value.parse::<u16>().ok());
+                let rows = parts.next().and_then(|value| value.parse::<u16>().ok());
+                match (cols, rows) {
+                    (Some(cols), Some(rows)) => {
+                        let result = resize_pty(agent, cols, rows, &sessions);
+                        let _ = if result.is_ok() {
+                            write_response(&stream, "OK\n")
+                        } else {
+                            write_response(&stream, "ERR resize failed\n")
+                        };
+                    }
+                    _ => {
+                        let _ = write_response(&stream, "ERR invalid resize\n");
+                    }
+                }
+            }
+            "INPUT" => {
+                let len = parts.next().and_then(|value| value.parse::<usize>().ok());
+                match len {
+                    Some(len) => {
+                        let mut payload = vec![0u8; len];
+                        if len > 0 {
+                            if let Err(err) = reader.read_exact(&mut payload) {
+                                let _ = write_response(&stream, &format!("ERR {err}\n"));
+                                continue;
+                            }
+                        }
+                        let result = ensure_pty_session(agent, &db, &sessions)
+                            .and_then(|_| write_pty_input(agent, &payload, &sessions));
+                        let _ = match result {
+                            Ok(()) => write_response(&stream, "OK\n"),
+                            Err(err) => write_response(&stream, &format!("ERR {err}\n")),
+                        };
+                    }
+                    _ => {
+                        let _ = write_response(&stream, "ERR invalid input\n");
+                    }
+                }
+            }
+            _ => {
+                let _ = write_response(&stream, "ERR unknown command\n");
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn ensure_pty_session(
+    agent: &str,
+    db: &Arc<tokio::sync::Mutex<Connection>>,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+) -> Result<(), String> {
+    {
+        let sessions = sessions.lock().expect("pty sessions lock");
+        if sessions.contains_key(agent) {
+            return Ok(());
+        }
+    }
+
+    let repos = launch_repos();
+    let (command, worktree_path, initial_input) = {
+        let conn = db.blocking_lock();
+        agent_launch_spec(&conn, &repos, agent).map_err(|err| match err {
+            rusqlite::Error::QueryReturnedNoRows => "agent not found".to_string(),
+            err => err.to_string(),
+        })?
+    };
+
+    start_tool_session(
+        agent,
+        &command,
+        Path::new(&worktree_path),
+        initial_input.as_deref(),
+        sessions,
+    )
+    .map_err(|err| err.message)
+}
+
+/// Replaces the agent's session with a new one, for a client recovering
+/// from a stuck session. Unlike restart this leaves the agent row alone.
+fn fresh_pty_session(
+    agent: &str,
+    db: &Arc<tokio::sync::Mutex<Connection>>,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+) -> Result<(), String> {
+    stop_pty_session(agent, sessions);
+    ensure_pty_session(agent, db, sessions)
+}
+
+fn attach_pty(
+    agent: &str,
+    stream: &UnixStream,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+    db: &Arc<tokio::sync::Mutex<Connection>>,
+) -> Result<(), Box<dyn Error>> {
+    if agent.trim().is_empty() {
+        return Err("agent name required".into());
+    }
+
+    ensure_pty_session(agent, db, sessions)?;
+
+    // The subscriber is registered while the history is copied so no output
+    // falls between the two; it is rolled back if the handoff fails.
+    let (history, snapshot, subscribers, subscriber_id, client_stream) = {
+        let mut sessions = sessions.lock().expect("pty sessions lock");
+        let session = sessions.get_mut(agent).ok_or("agent not found")?;
+        let history = session.history.lock().expect("pty history lock");
+        let bytes: Vec<u8> = history.iter().copied().collect();
+        let snapshot = session
+            .terminal_snapshot
+            .lock()
+            .expect("pty terminal snapshot lock")
+            .clone();
+        let (server_stream, client_stream) = UnixStream::pair()?;
+        let subscriber = Subscriber::new(server_stream);
+        let subscriber_id = subscriber.id;
+        let mut subscribers = session.subscribers.lock().expect("pty subscribers lock");
+        prune_closed_subscribers(&mut subscribers);
+        subscribers.push(subscriber);
+        drop(subscribers);
+        (
+            bytes,
+            snapshot,
+            session.subscribers.clone(),
+            subscriber_id,
+            client_stream,
+        )
+    };
+
+    complete_attach(
+        stream,
+        &subscribers,
+        subscriber_id,
+        &snapshot,
+        &history,
+        &client_stream,
+    )
+}
+
+fn complete_attach(
+    stream: &UnixStream,
+    subscribers: &Arc<Mutex<Vec<Subscriber>>>,
+    subscriber_id: u64,
+    snapshot: &TerminalSnapshot,
+    history: &[u8],
+    client_stream: &UnixStream,
+) -> Result<(), Box<dyn Error>> {
+    let result = send_attach_handoff(stream, subscriber_id, snapshot, history, client_stream);
+    if result.is_err() {
+        remove_subscriber(subscribers, subscriber_id);
+    }
+    result
+}
+
+fn send_attach_handoff(
+    stream: &UnixStream,
+    subscriber_id: u64,
+    snapshot: &TerminalSnapshot,
+    history: &[u8],
+    client_stream: &UnixStream,
+) -> Result<(), Box<dyn Error>> {
+    let snapshot_json = serde_json::to_string(snapshot)?;
+    write_response(stream, &format!("MODES {}\n", snapshot_json))?;
+    write_response(stream, &format!("HISTORY {}\n", history.len()))?;
+    if !history.is_empty() {
+        let mut stream = stream.try_clone()?;
+        stream.write_all(history)?;
+    }
+
+    let client_fd = client_stream.as_raw_fd();
+    let ok = format!("OK {subscriber_id}\n");
+    sendmsg(
+        stream.as_raw_fd(),
+        &[IoSlice::new(ok.as_bytes())],
+        &[ControlMessage::ScmRights(&[client_fd])],
+        MsgFlags::empty(),
+        None::<&SockaddrStorage>,
+    )?;
+    Ok(())
+}
+
+/// Removes the subscriber, returning whether it was still registered.
+fn remove_subscriber(subscribers: &Arc<Mutex<Vec<Subscriber>>>, subscriber_id: u64) -> bool {
+    let mut subscribers = subscribers.lock().expect("pty subscribers lock");
+    let before = subscribers.len();
+    subscribers.retain(|subscriber| subscriber.id != subscriber_id);
+    subscribers.len() != before
+}
+
+fn detach_subscriber(
+    agent: &str,
+    subscriber_id: u64,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+) -> bool {
+    let subscribers = {
+        let sessions = sessions.lock().expect("pty sessions lock");
+        match sessions.get(agent) {
+            Some(session) => session.subscribers.clone(),
+            None => return false,
+        }
+    };
+    remove_subscriber(&subscribers, subscriber_id)
+}
+
+/// Drops subscribers whose client end is already closed, such as one-shot
+/// readers like `workforest logs` that never take the PTY handoff. Writes
+/// only notice a closed peer once the session produces output, so an idle
+/// session would otherwise hold on to them.
+fn prune_closed_subscribers(subscribers: &mut Vec<Subscriber>) {
+    subscribers.retain(|subscriber| {
+        let mut probe = [0u8; 1];
+        // Clients never write to their end, so a readable socket means EOF.
+        !matches!(
+            recv(
+                subscriber.stream.as_raw_fd(),
+                &mut probe,
+                MsgFlags::MSG_PEEK | MsgFlags::MSG_DONTWAIT,
+            ),
+            Ok(0)
+        )
+    });
+}
+
+fn resize_pty(
+    agent: &str,
+    cols: u16,
+    rows: u16,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+) -> Result<(), Box<dyn Error>> {
+    let mut sessions = sessions.lock().expect("pty sessions lock");
+    let session = sessions
+        .get_mut(agent)
+        .ok_or_else(|| "agent not found".to_string())?;
+    let size = PtySize {
+        rows,
+        cols,
+        pixel_width: 0,
+        pixel_height: 0,
+    };
+    session.master.resize(size)?;
+    session.size = size;
+    Ok(())
+}
+
+fn write_pty_input(
+    agent: &str,
+    payload: &[u8],
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+) -> Result<(), String> {
+    let mut sessions = sessions.lock().expect("pty sessions lock");
+    let session = sessions
+        .get_mut(agent)
+        .ok_or_else(|| "agent not found".to_string())?;
+    let mut writer = session.writer.lock().expect("pty writer lock");
+    writer.write_all(payload).map_err(|err| err.to_string())?;
+    writer.flush().map_err(|err| err.to_string())?;
+    Ok(())
+}
+
+fn write_response(stream: &UnixStream, response: &str) -> Result<(), Box<dyn Error>> {
+    let mut stream = stream.try_clone()?;
+    stream.write_all(response.as_bytes())?;
+    Ok(())
+}
+
+fn init_database() -> Result<Connection, Box<dyn Error>> {
+    let data_dir = data_dir();
+    std::fs::create_dir_all(&data_dir)?;
+    let conn = open_database(&data_dir.join("app.db"))?;
+    create_schema(&conn)?;
+    Ok(conn)
+}
+
+/// Other processes (the CLI's offline reader, backups) may briefly hold a
+/// lock; reads and writes alike wait for it instead of failing straight away.
+fn open_database(path: &Path) -> rusqlite::Result<Connection> {
+    let conn = Connection::open(path)?;
+    conn.busy_timeout(DB_BUSY_TIMEOUT)?;
+    Ok(conn)
+}
+
+fn is_busy(err: &rusqlite::Error) -> bool {
+    matches!(
+        err.sqlite_error_code(),
+        Some(rusqlite::ErrorCode::DatabaseBusy | rusqlite::ErrorCode::DatabaseLocked)
+    )
+}
+
+fn db_error(err: rusqlite::Error) -> ApiError {
+    if is_busy(&err) {
+        ApiError::unavailable("database is locked by another process; try again shortly")
+    } else {
+        ApiError::internal(err.to_string())
+    }
+}
+
+fn create_schema(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS agents (
+            name TEXT PRIMARY KEY,
+            label TEXT NOT NULL,
+            repo TEXT NOT NULL,
+            tool TEXT NOT NULL,
+            status TEXT NOT NULL,
+            worktree_path TEXT NOT NULL,
+            styles TEXT,
+            created_at TEXT NOT NULL,
+            updated_at TEXT NOT NULL,
+            args TEXT,
+            exit_code INTEGER
+        )",
+        [],
+    )?;
+    // Databases created before per-agent args lack the column.
+    if conn.prepare("SELECT args FROM agents LIMIT 0").is_err() {
+        conn.execute("ALTER TABLE agents ADD COLUMN args TEXT", [])?;
+    }
+    if conn
+        .prepare("SELECT exit_code FROM agents LIMIT 0")
+        .is_err()
+    {
+        conn.execute("ALTER TABLE agents ADD COLUMN exit_code INTEGER", [])?;
+    }
+    Ok(())
+}
+
+fn load_repo_config() -> Result<RepoConfigFile, ApiError> {
+    let path = repos_config_path();
+    if !path.exists() {
+        return Ok(RepoConfigFile::default());
+    }
+    let data = std::fs::read_to_string(path).map_err(|err| ApiError::internal(err.to_string()))?;
+    toml::from_str(&data).map_err(|err| ApiError::internal(err.to_string()))
+}
+
+fn save_repo_config(config: &RepoConfigFile) -> Result<(), ApiError> {
+    save_repo_config_to(&repos_config_path(), config)
+}
+
+fn save_repo_config_to(config_path: &Path, config: &RepoConfigFile) -> Result<(), ApiError> {
+    let config_dir = config_path
+        .parent()
+        .ok_or_else(|| ApiError::internal("config dir missing"))?;
+    std::fs::create_dir_all(config_dir).map_err(|err| config_write_error(config_dir, err))?;
+    let data = toml::to_string_pretty(config).map_err(|err| ApiError::internal(err.to_string()))?;
+    std::fs::write(config_path, data).map_err(|err| config_write_error(config_path, err))?;
+    Ok(())
+}
+
+/// Maps a failed config write to an error naming the path. Permission and
+/// read-only failures use 507 so clients can tell them apart from transient
+/// server errors.
+fn config_write_error(path: &Path, err: std::io::Error) -> ApiError {
+    match err.kind() {
+        std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::ReadOnlyFilesystem => {
+            ApiError::not_writable(format!(
+                "{} is not writable ({err}); check permissions on the config directory",
+                path.display()
+            ))
+        }
+        _ => ApiError::internal(format!("{}: {err}", path.display())),
+    }
+}
+
+fn is_git_repo(path: &Path) -> bool {
+    path.join(".git").exists()
+}
+
+fn generate_repo_name(path: &Path, repos: &[RepoConfig]) -> Result<String, ApiError> {
+    let base_name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| ApiError::bad_request("invalid repo path"))?;
+
+    Ok(generate_repo_name_with_suffix(base_name, repos, || {
+        petname(2, "-")
+    }))
+}
+
+fn validate_repo_name(name: &str, repos: &[RepoConfig]) -> Result<String, ApiError> {
+    if name.contains('/') || name.chars().any(char::is_whitespace) {
+        return Err(ApiError::bad_request(
+            "repo name may not contain '/' or whitespace",
+        ));
+    }
+    if repos.iter().any(|repo| repo.name == name) {
+        return Err(ApiError::bad_request("repo name already exists"));
+    }
+    Ok(name.to_string())
+}
+
+fn generate_repo_name_with_suffix<F>(
+    base_name: &str,
+    repos: &[RepoConfig],
+    mut suffix_fn: F,
+) -> String
+where
+    F: FnMut() -> String,
+{
+    let existing: HashSet<String> = repos.iter().map(|repo| repo.name.clone()).collect();
+
+    if !existing.contains(base_name) {
+        return base_name.to_string();
+    }
+
+    loop {
+        let candidate = format!("{}-{}", base_name, suffix_fn());
+        if !existing.contains(&candidate) {
+            return candidate;
+        }
+    }
+}
+
+/// Refuses another agent for `repo` once it holds `max_agents`, counting
+/// `pending` agents about to be added alongside the stored ones.
+fn ensure_agent_capacity(
+    conn: &Connection,
+    repo: &RepoConfig,
+    pending: usize,
+) -> Result<(), ApiError> {
+    let Some(max_agents) = repo.max_agents else {
+        return Ok(());
+    };
+    let count: usize = conn
+        .query_row(
+            "SELECT COUNT(*) FROM agents WHERE repo = ?1",
+            params![repo.name.as_str()],
+            |row| row.get(0),
+        )
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    if count + pending >= max_agents {
+        return Err(ApiError::bad_request(format!(
+            "repo {} already has the maximum of {} agents",
+            repo.name, max_agents
+        )));
+    }
+    Ok(())
+}
+
+fn prefix_agent_names(repo: &RepoConfig) -> bool {
+    repo.prefix_agent_names
+        .unwrap_or(settings().prefix_agent_names)
+}
+
+fn generate_agent_name_with<F, E>(
+    prefix: Option<&str>,
+    mut petname_fn: F,
+    mut exists_fn: E,
+) -> Result<String, ApiError>
+where
+    F: FnMut() -> String,
+    E: FnMut(&str) -> Result<bool, ApiError>,
+{
+    loop {
+        let candidate = match prefix {
+            Some(prefix) => format!("{}-{}", prefix, petname_fn()),
+            None => petname_fn(),
+        };
+        if !exists_fn(&candidate)? {
+            return Ok(candidate);
+        }
+    }
+}
+
+fn worktree_root(repo: &RepoConfig) -> PathBuf {
+    match &repo.worktree_root {
+        Some(root) if root.is_absolute() => root.clone(),
+        Some(root) => repo.path.join(root),
+        None => data_dir().join("trees"),
+    }
+}
+
+/// Worktree directories are named `<repo>-<agent>`; an agent name that
+/// already carries the repo prefix is used as is.
+fn worktree_path_for(root: &Path, repo_name: &str, agent_name: &str) -> PathBuf {
+    let agent = to_kebab(agent_name);
+    if agent.starts_with(&format!("{repo_name}-")) {
+        root.join(agent)
+    } else {
+        root.join(format!("{repo_name}-{agent}"))
+    }
+}
+
+fn create_worktree(
+    repo_path: &Path,
+    root: &Path,
+    repo_name: &str,
+    agent_name: &str,
+) -> Result<PathBuf, ApiError> {
+    std::fs::create_dir_all(root).map_err(|err| ApiError::internal(err.to_string()))?;
+    let worktree_path = worktree_path_for(root, repo_name, agent_name);
+
+    if worktree_path.exists() {
+        return Err(ApiError::bad_request("worktree already exists"));
+    }
+
+    let branch_name = agent_branch(agent_name);
+    let unborn = !has_commits(repo_path);
+    let mut command = Command::new("git");
+    command.arg("-C").arg(repo_path).args(["worktree", "add"]);
+    if unborn {
+        // Only git 2.42+ can add a worktree on an orphan branch.
+        command.args(["--orphan", "-b"]);
+    } else {
+        command.arg("-b");
+    }
+    let output = command
+        .arg(&branch_name)
+        .arg(&worktree_path)
+        .stdout(Stdio::null())
+        .output()
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        // Older git rejects `--orphan`; the real problem is the missing commit.
+        if unborn && stderr.contains("unknown option") {
+            return Err(ApiError::bad_request(NO_COMMITS_MESSAGE));
+        }
+        return Err(ApiError::internal(format!(
+            "git worktree add failed: {}",
+            stderr.trim()
+        )));
+    }
+
+    Ok(worktree_path)
+}
+
+const NO_COMMITS_MESSAGE: &str =
+    "repository has no commits yet; create an initial commit before adding agents";
+
+/// False when HEAD is unborn, i.e. the repository has no commits.
+fn has_commits(repo_path: &Path) -> bool {
+    Command::new("git")
+        .arg("-C")
+        .arg(repo_path)
+        .args(["rev-parse", "--verify", "--quiet", "HEAD"])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+/// The session's process: the server's environment is inherited and the
+/// launch's variables replace any with the same name. `sh -lc` reads the
+/// login profile after that, so a profile exporting one of them wins.
+fn tool_command(launch: &ToolLaunch, worktree_path: &Path) -> CommandBuilder {
+    let mut cmd = CommandBuilder::new("sh");
+    cmd.arg("-lc");
+    cmd.arg(&launch.command);
+    for (key, value) in &launch.env {
+        cmd.env(key, value);
+    }
+    cmd.cwd(worktree_path);
+    cmd
+}
+
+fn start_tool_session(
+    agent_name: &str,
+    launch: &ToolLaunch,
+    worktree_path: &Path,
+    initial_input: Option<&str>,
+    sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+) -> Result<(), ApiError> {
+    let mut sessions = sessions.lock().expect("pty sessions lock");
+    if sessions.contains_key(agent_name) {
+        return Ok(());
+    }
+
+    let pty_system = native_pty_system();
+    let size = PtySize::default();
+    let pair = pty_system
+        .openpty(size)
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    let child = pair
+        .slave
+        .spawn_command(tool_command(launch, worktree_path))
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+
+    let history = Arc::new(Mutex::new(VecDeque::new()));
+    let written = Arc::new(AtomicU64::new(0));
+    let timings = settings()
+        .timed_history
+        .then(|| Arc::new(Mutex::new(HistoryTimings::new(Instant::now()))));
+    let terminal_snapshot = Arc::new(Mutex::new(default_terminal_snapshot()));
+    let subscribers = Arc::new(Mutex::new(Vec::new()));
+    let master_fd = pair
+        .master
+        .as_raw_fd()
+        .ok_or_else(|| ApiError::internal("missing master fd"))?;
+    let history_handle = spawn_history_reader(
+        master_fd,
+        history.clone(),
+        written.clone(),
+        timings.clone(),
+        terminal_snapshot.clone(),
+        subscribers.clone(),
+    );
+    let writer = pair
+        .master
+        .take_writer()
+        .map_err(|err| ApiError::internal(err.to_string()))?;
+    let writer = Arc::new(Mutex::new(writer));
+    send_initial_input(initial_input, history.clone(), writer.clone());
+    sessions.insert(
+        agent_name.to_string(),
+        PtySession {
+            master: pair.master,
+            writer,
+            child,
+            size,
+            history,
+            written,
+            terminal_snapshot,
+            subscribers,
+            timings,
+            _history_handle: history_handle,
+        },
+    );
+
+    Ok(())
+}
+
+/// Types `input` into a fresh session, followed by Enter. Waits for the
+/// tool's first output (or `INITIAL_INPUT_TIMEOUT`) and then a short settle
+/// delay, so the keystrokes land in the tool's prompt rather than being
+/// swallowed during startup.
+fn send_initial_input(
+    input: Option<&str>,
+    history: Arc<Mutex<VecDeque<u8>>>,
+    writer: Arc<Mutex<Box<dyn Write + Send>>>,
+) -> Option<thread::JoinHandle<()>> {
+    let input = input.filter(|input| !input.is_empty())?.to_string();
+    Some(thread::spawn(move || {
+        let deadline = Instant::now() + INITIAL_INPUT_TIMEOUT;
+        while history.lock().expect("history lock").is_empty() && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(50));
+        }
+        thread::sleep(INITIAL_INPUT_SETTLE);
+        let mut writer = writer.lock().expect("pty writer lock");
+        let _ = writer.write_all(input.as_bytes());
+        let _ = writer.write_all(b"\r");
+        let _ = writer.flush();
+    }))
+}
+
+fn stop_pty_session(agent_name: &str, sessions: &Arc<Mutex<HashMap<String, PtySession>>>) {
+    let mut sessions = sessions.lock().expect("pty sessions lock");
+    if let Some(mut session) = sessions.remove(agent_name) {
+        let _ = session.child.kill();
+    }
+}
+
+fn spawn_history_reader(
+    fd: i32,
+    history: Arc<Mutex<VecDeque<u8>>>,
+    written: Arc<AtomicU64>,
+    timings: Option<Arc<Mutex<HistoryTimings>>>,
+    terminal_snapshot: Arc<Mutex<TerminalSnapshot>>,
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
+        let mut buffer = [0u8; 4096];
+        let mut parser = Parser::new();
+        loop {
+            match file.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(size) => {
+                    {
+                        let mut history = history.lock().expect("pty history lock");
+                        for byte in &buffer[..size] {
+                            history.push_back(*byte);
+                        }
+                        written.fetch_add(size as u64, Ordering::Relaxed);
+                        trim_history_to_boundary(&mut history, settings().history_limit_bytes);
+                        if let Some(timings) = &timings {
+                            timings.lock().expect("pty timings lock").record(
+                                size,
+                                Instant::now(),
+                                history.len(),
+                            );
+                        }
+                    }
+                    {
+                        let mut snapshot = terminal_snapshot
+                            .lock()
+                            .expect("pty terminal snapshot lock");
+                        parser.parse(&buffer[..size], |action| {
+                            apply_action_to_snapshot(action, &mut snapshot);
+                        });
+                    }
+                    let mut subs = subscribers.lock().expect("pty subscribers lock");
+                    broadcast_output(&mut subs, &buffer[..size]);
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+        }
+    })
+}
+
+/// Sends output to every subscriber, dropping those that fail, including
+/// nonblocking ones that cannot take it right away.
+fn broadcast_output(subscribers: &mut Vec<Subscriber>, bytes: &[u8]) {
+    subscribers.retain_mut(|subscriber| subscriber.stream.write_all(bytes).is_ok());
+}
+
+fn history_dir() -> PathBuf {
+    data_dir().join("history")
+}
+
+/// File name stem for an agent's history files. The name is escaped rather
+/// than kebab-cased so distinct agents never share a file.
+fn history_file_stem(agent_name: &str) -> String {
+    encode_agent_token(agent_name).replace('/', "%2F")
+}
+
+fn history_file_path(dir: &Path, agent_name: &str) -> PathBuf {
+    dir.join(format!("{}.log", history_file_stem(agent_name)))
+}
+
+fn timing_file_path(dir: &Path, agent_name: &str) -> PathBuf {
+    dir.join(format!("{}.timing", history_file_stem(agent_name)))
+}
+
+/// Writes the history log and, when timings are kept, its sidecar index.
+fn flush_agent_history(
+    dir: &Path,
+    agent_name: &str,
+    history: &VecDeque<u8>,
+    timing_index: Option<&[TimingEntry]>,
+) -> std::io::Result<usize> {
+    let bytes = flush_history_file(&history_file_path(dir, agent_name), history)?;
+    if let Some(index) = timing_index {
+        std::fs::write(
+            timing_file_path(dir, agent_name),
+            encode_timing_index(index),
+        )?;
+    }
+    Ok(bytes)
+}
+
+/// One `offset elapsed_ms` pair per line.
+fn encode_timing_index(entries: &[TimingEntry]) -> String {
+    entries
+        .iter()
+        .map(|entry| format!("{} {}\n", entry.offset, entry.elapsed_ms))
+        .collect()
+}
+
+fn parse_timing_index(data: &str) -> Vec<TimingEntry> {
+    data.lines()
+        .filter_map(|line| {
+            let (offset, elapsed_ms) = line.split_once(' ')?;
+            Some(TimingEntry {
+                offset: offset.parse().ok()?,
+                elapsed_ms: elapsed_ms.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+fn flush_history_file(path: &Path, history: &VecDeque<u8>) -> std::io::Result<usize> {
+    let mut file = std::fs::File::create(path)?;
+    let (front, back) = history.as_slices();
+    file.write_all(front)?;
+    file.write_all(back)?;
+    file.sync_all()?;
+    Ok(history.len())
+}
+
+fn default_terminal_snapshot() -> TerminalSnapshot {
+    TerminalSnapshot {
+        cursor_visible: true,
+        wrap_mode: true,
+        ..TerminalSnapshot::default()
+    }
+}
+
+fn trim_history_to_boundary(history: &mut VecDeque<u8>, limit: usize) {
+    if history.len() <= limit {
+        return;
+    }
+    let overflow = history.len() - limit;
+    let bytes: Vec<u8> = history.iter().copied().collect();
+    let drop_count = find_safe_history_start(&bytes, overflow);
+    for _ in 0..drop_count {
+        history.pop_front();
+    }
+}
+
+fn find_safe_history_start(bytes: &[u8], overflow: usize) -> usize {
+    let mut index = 0;
+    let mut last_safe = 0;
+    while index < bytes.len() {
+        if bytes[index] == 0x1b {
+            if let Some(next) = bytes.get(index + 1).copied() {
+                match next {
+                    b'[' => {
+                        index = parse_csi_sequence(bytes, index + 2);
+                    }
+                    b']' | b'P' | b'^' | b'_' => {
+                        index = parse_string_sequence(bytes, index + 2);
+                    }
+                    _ => {
+                        index = (index + 2).min(bytes.len());
+                    }
+                }
+            } else {
+                break;
+            }
+        } else {
+            index += 1;
+        }
+        if index <= overflow {
+            last_safe = index;
+        }
+    }
+    last_safe
+}
+
+fn parse_csi_sequence(bytes: &[u8], start: usize) -> usize {
+    let mut index = start;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        index += 1;
+        if (0x40..=0x7e).contains(&byte) {
+            break;
+        }
+    }
+    index
+}
+
+fn parse_string_sequence(bytes: &[u8], start: usize) -> usize {
+    let mut index = start;
+    while index < bytes.len() {
+        let byte = bytes[index];
+        if byte == 0x07 {
+            return index + 1;
+        }
+        if byte == 0x1b && bytes.get(index + 1) == Some(&b'\\') {
+            return index + 2;
+        }
+        index += 1;
+    }
+    index
+}
+
+fn apply_action_to_snapshot(action: Action, snapshot: &mut TerminalSnapshot) {
+    match action {
+        Action::CSI(csi) => apply_csi_to_snapshot(csi, snapshot),
+        Action::Esc(esc) => apply_esc_to_snapshot(esc, snapshot),
+        _ => {}
+    }
+}
+
+fn apply_esc_to_snapshot(esc: Esc, snapshot: &mut TerminalSnapshot) {
+    if let Esc::Code(code) = esc {
+        if matches!(code, EscCode::FullReset) {
+            *snapshot = default_terminal_snapshot();
+        }
+    }
+}
+
+fn apply_csi_to_snapshot(csi: termwiz::escape::csi::CSI, snapshot: &mut TerminalSnapshot) {
+    match csi {
+        termwiz::escape::csi::CSI::Mode(mode) => apply_mode_to_snapshot(mode, snapshot),
+        termwiz::escape::csi::CSI::Sgr(sgr) => apply_sgr_to_snapshot(sgr, snapshot),
+        termwiz::escape::csi::CSI::Cursor(cursor) => apply_cursor_to_snapshot(cursor, snapshot),
+        _ => {}
+    }
+}
+
+fn apply_cursor_to_snapshot(cursor: Cursor, snapshot: &mut TerminalSnapshot) {
+    match cursor {
+        Cursor::SetTopAndBottomMargins { top, bottom } => {
+            let top = top.as_zero_based() as usize;
+            let bottom = bottom.as_zero_based() as usize;
+            if top < bottom {
+                snapshot.scroll_region = Some(ScrollRegion { top, bottom });
+            } else {
+                snapshot.scroll_region = None;
+            }
+        }
+        Cursor::CursorStyle(style) => {
+            snapshot.cursor_shape = match style {
+                CursorStyle::Default => CursorShape::Default,
+                CursorStyle::BlinkingBlock => CursorShape::BlinkingBlock,
+                CursorStyle::SteadyBlock => CursorShape::SteadyBlock,
+                CursorStyle::BlinkingUnderline => CursorShape::BlinkingUnderline,
+                CursorStyle::SteadyUnderline => CursorShape::SteadyUnderline,
+                CursorStyle::BlinkingBar => CursorShape::BlinkingBar,
+                CursorStyle::SteadyBar => CursorShape::SteadyBar,
+            };
+        }
+        _ => {}
+    }
+}
+
+fn apply_mode_to_snapshot(mode: Mode, snapshot: &mut TerminalSnapshot) {
+    match mode {
+        Mode::SetDecPrivateMode(mode) => apply_dec_private_mode(mode, snapshot, true),
+        Mode::ResetDecPrivateMode(mode) => apply_dec_private_mode(mode, snapshot, false),
+        Mode::SetMode(mode) => apply_terminal_mode(mode, snapshot, true),
+        Mode::ResetMode(mode) => apply_terminal_mode(mode, snapshot, false),
+        _ => {}
+    }
+}
+
+fn apply_dec_private_mode(mode: DecPrivateMode, snapshot: &mut TerminalSnapshot, enabled: bool) {
+    let code = match mode {
+        DecPrivateMode::Code(code) => code,
+        DecPrivateMode::Unspecified(code) => {
+            set_mode_entry(&mut snapshot.dec_private_modes, code, enabled);
+            return;
+        }
+    };
+    if let Some(value) = code.to_u16() {
+        set_mode_entry(&mut snapshot.dec_private_modes, value, enabled);
+    }
+    match code {
+        DecPrivateModeCode::ShowCursor => snapshot.cursor_visible = enabled,
+        DecPrivateModeCode::StartBlinkingCursor if enabled => {
+            snapshot.cursor_shape = CursorShape::BlinkingBlock;
+        }
+        DecPrivateModeCode::OriginMode => snapshot.origin_mode = enabled,
+        DecPrivateModeCode::AutoWrap => snapshot.wrap_mode = enabled,
+        DecPrivateModeCode::ClearAndEnableAlternateScreen
+        | DecPrivateModeCode::EnableAlternateScreen
+        | DecPrivateModeCode::OptEnableAlternateScreen => {
+            switch_snapshot_screen(snapshot, enabled);
+        }
+        DecPrivateModeCode::MouseTracking => snapshot.mouse_tracking = enabled,
+        DecPrivateModeCode::ButtonEventMouse => snapshot.mouse_button_tracking = enabled,
+        DecPrivateModeCode::AnyEventMouse => snapshot.mouse_any_event = enabled,
+        DecPrivateModeCode::SGRMouse => snapshot.mouse_sgr = enabled,
+        DecPrivateModeCode::BracketedPaste => snapshot.bracketed_paste = enabled,
+        DecPrivateModeCode::ApplicationCursorKeys => {
+            snapshot.application_cursor_keys = enabled;
+        }
+        _ => {}
+    }
+}
+
+/// Each screen keeps its own cursor visibility and shape, so a full-screen
+/// app hiding the cursor does not leave the shell's prompt without one.
+fn switch_snapshot_screen(snapshot: &mut TerminalSnapshot, alt_screen: bool) {
+    if alt_screen && !snapshot.alt_screen {
+        snapshot.main_cursor = Some(CursorState {
+            visible: snapshot.cursor_visible,
+            shape: snapshot.cursor_shape.clone(),
+        });
+    } else if !alt_screen && snapshot.alt_screen {
+        if let Some(main_cursor) = snapshot.main_cursor.take() {
+            snapshot.cursor_visible = main_cursor.visible;
+            snapshot.cursor_shape = main_cursor.shape;
+        }
+    }
+    snapshot.alt_screen = alt_screen;
+}
+
+fn apply_terminal_mode(mode: TerminalMode, snapshot: &mut TerminalSnapshot, enabled: bool) {
+    let code = match mode {
+        TerminalMode::Code(code) => code,
+        TerminalMode::Unspecified(code) => {
+            set_mode_entry(&mut snapshot.terminal_modes, code, enabled);
+            return;
+        }
+    };
+    if let Some(value) = code.to_u16() {
+        set_mode_entry(&mut snapshot.terminal_modes, value, enabled);
+    }
+    match code {
+        TerminalModeCode::Insert => snapshot.insert_mode = enabled,
+        TerminalModeCode::ShowCursor => snapshot.cursor_visible = enabled,
+        _ => {}
+    }
+}
+
+fn apply_sgr_to_snapshot(sgr: Sgr, snapshot: &mut TerminalSnapshot) {
+    match sgr {
+        Sgr::Reset => snapshot.attributes = TerminalAttributes::default(),
+        Sgr::Intensity(value) => {
+            snapshot.attributes.intensity = match value {
+                termwiz::cell::Intensity::Normal => TerminalIntensity::Normal,
+                termwiz::cell::Intensity::Bold => TerminalIntensity::Bold,
+                termwiz::cell::Intensity::Half => TerminalIntensity::Faint,
+            };
+        }
+        Sgr::Underline(value) => {
+            snapshot.attributes.underline = match value {
+                termwiz::cell::Underline::None => TerminalUnderline::None,
+                termwiz::cell::Underline::Single => TerminalUnderline::Single,
+                termwiz::cell::Underline::Double => TerminalUnderline::Double,
+                termwiz::cell::Underline::Curly => TerminalUnderline::Curly,
+                termwiz::cell::Underline::Dotted => TerminalUnderline::Dotted,
+                termwiz::cell::Underline::Dashed => TerminalUnderline::Dashed,
+            };
+        }
+        Sgr::Blink(value) => {
+            snapshot.attributes.blink = match value {
+                termwiz::cell::Blink::None => TerminalBlink::None,
+                termwiz::cell::Blink::Slow => TerminalBlink::Slow,
+                termwiz::cell::Blink::Rapid => TerminalBlink::Rapid,
+            };
+        }
+        Sgr::Italic(value) => snapshot.attributes.italic = value,
+        Sgr::Inverse(value) => snapshot.attributes.inverse = value,
+        Sgr::Invisible(value) => snapshot.attributes.hidden = value,
+        Sgr::StrikeThrough(value) => snapshot.attributes.strikethrough = value,
+        Sgr::Foreground(color) => {
+            snapshot.attributes.foreground = color_to_snapshot(color.into());
+        }
+        Sgr::Background(color) => {
+            snapshot.attributes.background = color_to_snapshot(color.into());
+        }
+        _ => {}
+    }
+}
+
+fn color_to_snapshot(color: termwiz::color::ColorAttribute) -> TerminalColor {
+    match color {
+        termwiz::color::ColorAttribute::Default => TerminalColor::Default,
+        termwiz::color::ColorAttribute::TrueColorWithDefaultFallback(color)
+        | termwiz::color::ColorAttribute::TrueColorWithPaletteFallback(color, _) => {
+            let (r, g, b, _) = color.as_rgba_u8();
+            TerminalColor::Rgb { r, g, b }
+        }
+        termwiz::color::ColorAttribute::PaletteIndex(index) => TerminalColor::Ansi(index),
+    }
+}
+
+fn set_mode_entry(entries: &mut Vec<ModeEntry>, code: u16, enabled: bool) {
+    if let Some(entry) = entries.iter_mut().find(|entry| entry.code == code) {
+        entry.enabled = enabled;
+    } else {
+        entries.push(ModeEntry { code, enabled });
+    }
+}
+
+fn delete_worktree(
+    repo_path: &Path,
+    worktree_path: &Path,
+    agent_name: &str,
+) -> Result<(), ApiError> {
+    if worktree_path.exists() {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(repo_path)
+            .args(["worktree", "remove", "-f"])
+            .arg(worktree_path)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status()
+            .map_err(|err| ApiError::internal(err.to_string()))?;
+
+        if !status.success() {
+            return Err(ApiError::internal("git worktree remove failed"));
+        }
+    }
+
+    let branch_name = agent_branch(agent_name);
+    let _ = Command::new("git")
+        .arg("-C")
+        .arg(repo_path)
+        .args(["branch", "-D", &branch_name])
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+
+    if worktree_path.exists() {
+        std::fs::remove_dir_all(worktree_path)
+            .map_err(|err| ApiError::internal(err.to_string()))?;
+    }
+
+    Ok(())
+}
+
+fn to_kebab(value: &str) -> String {
+    value.trim().to_lowercase().replace([' ', '_'], "-")
+}
+
+fn write_metadata(addr: SocketAddr) -> Result<(), Box<dyn Error>> {
+    let config_dir = workforest_core::config_dir();
+    std::fs::create_dir_all(&config_dir)
+        .map_err(|err| config_write_error(&config_dir, err).message)?;
+
+    let metadata = ServerMetadata {
+        pid: std::process::id(),
+        port: addr.port(),
+    };
+
+    let metadata_path = config_dir.join("server.json");
+    let data = serde_json::to_string_pretty(&metadata)?;
+    std::fs::write(&metadata_path, data)
+        .map_err(|err| config_write_error(&metadata_path, err).message)?;
+
+    Ok(())
+}
+
+fn remove_metadata() {
+    let metadata_path = workforest_core::config_dir().join("server.json");
+    let _ = std::fs::remove_file(metadata_path);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use std::path::PathBuf;
+
+    fn shell_launch(command: &str) -> ToolLaunch {
+        ToolLaunch {
+            command: command.to_string(),
+            env: BTreeMap::new(),
+        }
+    }
+
+    fn repo_named(name: &str) -> RepoConfig {
+        RepoConfig {
+            name: name.to_string(),
+            path: PathBuf::from("/tmp"),
+            ..RepoConfig::default()
+        }
+    }
+
+    /// A temp dir holding `repo`, a git repository without commits.
+    fn unborn_repo_fixture(label: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("workforest-{}-{}", label, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let repo = dir.join("repo");
+        std::fs::create_dir_all(&repo).unwrap();
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(&repo)
+            .args(["init", "-q"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        dir
+    }
+
+    fn git_repo_fixture(label: &str) -> PathBuf {
+        let dir = unborn_repo_fixture(label);
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir.join("repo"))
+            .args([
+                "-c",
+                "user.name=test",
+                "-c",
+                "user.email=test@example.com",
+                "commit",
+                "-q",
+                "--allow-empty",
+                "-m",
+                "init",
+            ])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        dir
+    }
+
+    /// `(major, minor)` of the installed git.
+    fn git_version() -> (u32, u32) {
+        let output = Command::new("git").arg("--version").output().unwrap();
+        let text = String::from_utf8(output.stdout).unwrap();
+        let version = text.trim().trim_start_matches("git version ");
+        let mut parts = version.split('.').map(|part| part.parse().unwrap_or(0));
+        (parts.next().unwrap_or(0), parts.next().unwrap_or(0))
+    }
+
+    #[test]
+    fn unwritable_config_maps_to_clear_error() {
+        let path = Path::new("/etc/workforest/repos.toml");
+        for kind in [
+            std::io::ErrorKind::PermissionDenied,
+            std::io::ErrorKind::ReadOnlyFilesystem,
+        ] {
+            let err = config_write_error(path, std::io::Error::from(kind));
+            assert_eq!(err.status, StatusCode::INSUFFICIENT_STORAGE);
+            assert!(err
+                .message
+                .starts_with("/etc/workforest/repos.toml is not writable"));
+        }
+    }
+
+    #[test]
+    fn other_config_write_failures_stay_internal() {
+        let path = Path::new("/tmp/repos.toml");
+        let err = config_write_error(path, std::io::Error::other("disk on fire"));
+        assert_eq!(err.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(err.message, "/tmp/repos.toml: disk on fire");
+    }
+
+    #[test]
+    fn repo_without_commits_is_detected() {
+        let dir = git_repo_fixture("unborn-detect");
+        let empty = unborn_repo_fixture("unborn-detect-empty");
+
+        let committed = has_commits(&dir.join("repo"));
+        let unborn = has_commits(&empty.join("repo"));
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::remove_dir_all(&empty).unwrap();
+
+        assert!(committed);
+        assert!(!unborn);
+    }
+
+    #[test]
+    fn worktree_on_repo_without_commits_reports_clear_error() {
+        let dir = unborn_repo_fixture("unborn");
+
+        let result = create_worktree(&dir.join("repo"), &dir.join("trees"), "demo", "wild-cat");
+        let created = result.as_ref().map(|path| path.exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        if git_version() >= (2, 42) {
+            assert!(created.unwrap());
+        } else {
+            let err = created.unwrap_err();
+            assert_eq!(err.status, StatusCode::BAD_REQUEST);
+            assert_eq!(err.message, NO_COMMITS_MESSAGE);
+        }
+    }
+
+    #[test]
+    fn provision_without_start_creates_no_session() {
+        let dir = git_repo_fixture("provision");
+        let repo = RepoConfig {
+            path: dir.join("repo"),
+            worktree_root: Some(dir.join("trees")),
+            ..repo_named("demo")
+        };
+        let conn = database_with_agents("demo", 0);
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+
+        let agent =
+            provision_agent(&conn, &sessions, &repo, "wild-cat", "claude", &[], false).unwrap();
+
+        assert_eq!(agent.status, "sleep");
+        assert!(sessions.lock().unwrap().is_empty());
+        assert!(Path::new(&agent.worktree_path).join(".git").exists());
+        let status: String = conn
+            .query_row(
+                "SELECT status FROM agents WHERE name = 'wild-cat'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(status, "sleep");
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn label_rename_keeps_name_and_rejects_blank_labels() {
+        let conn = database_with_agents("demo", 1);
+
+        let agent = set_agent_label(&conn, "agent-0", "  Fix login flow ").unwrap();
+        assert_eq!(agent.name, "agent-0");
+        assert_eq!(agent.label, "Fix login flow");
+        assert!(!agent.updated_at.is_empty());
+
+        let err = set_agent_label(&conn, "agent-0", "   ").unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        let err = set_agent_label(&conn, "ghost", "Ghost").unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+        let label: String = conn
+            .query_row(
+                "SELECT label FROM agents WHERE name = 'agent-0'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(label, "Fix login flow");
+    }
+
+    #[test]
+    fn batch_creates_valid_items_and_reports_failures() {
+        let dir = git_repo_fixture("batch");
+        let config = RepoConfigFile {
+            repos: vec![RepoConfig {
+                path: dir.join("repo"),
+                worktree_root: Some(dir.join("trees")),
+                tools: vec![ToolSpec::from("claude")],
+                ..repo_named("demo")
+            }],
+        };
+        let db = tokio::sync::Mutex::new(database_with_agents("demo", 0));
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        let request = |repo: &str, tool: &str, name: &str| AddAgentRequest {
+            repo: repo.to_string(),
+            tool: tool.to_string(),
+            name: Some(name.to_string()),
+            start: false,
+            args: Vec::new(),
+        };
+
+        let results = create_agents(
+            &db,
+            &sessions,
+            &config,
+            &[
+                request("demo", "claude", "wild-cat"),
+                request("ghost", "claude", "blue-fox"),
+                request("demo", "codex", "red-owl"),
+                request("demo", "claude", "wild-cat"),
+                request("demo", "claude", "calm-elk"),
+            ],
+        );
+        let outcome: Vec<Result<String, (u16, String)>> = results
+            .into_iter()
+            .map(|result| {
+                result
+                    .map(|agent| agent.name)
+                    .map_err(|err| (err.status.as_u16(), err.message))
+            })
+            .collect();
+        let names = agent_names(&db.blocking_lock()).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            outcome,
+            vec![
+                Ok("wild-cat".to_string()),
+                Err((404, "repo not found".to_string())),
+                Err((400, "tool not configured for repo".to_string())),
+                Err((400, "agent name already exists".to_string())),
+                Ok("calm-elk".to_string()),
+            ]
+        );
+        assert_eq!(names, vec!["wild-cat", "calm-elk"]);
+    }
+
+    #[test]
+    fn failed_provision_removes_its_worktree() {
+        let dir = git_repo_fixture("provision-cleanup");
+        let repo = RepoConfig {
+            path: dir.join("repo"),
+            worktree_root: Some(dir.join("trees")),
+            ..repo_named("demo")
+        };
+        // A table without the expected columns makes the insert fail.
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute("CREATE TABLE agents (name TEXT PRIMARY KEY)", [])
+            .unwrap();
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+
+        let result = provision_agent(&conn, &sessions, &repo, "wild-cat", "claude", &[], false);
+        let worktree = worktree_path_for(&dir.join("trees"), "demo", "wild-cat");
+        let branch = Command::new("git")
+            .arg("-C")
+            .arg(dir.join("repo"))
+            .args(["branch", "--list", "agent/wild-cat"])
+            .output()
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_err());
+        assert!(!worktree.exists());
+        assert!(String::from_utf8_lossy(&branch.stdout).trim().is_empty());
+    }
+
+    #[test]
+    fn agent_limit_is_checked_again_when_recording() {
+        let dir = git_repo_fixture("record-capacity");
+        let repo = RepoConfig {
+            path: dir.join("repo"),
+            worktree_root: Some(dir.join("trees")),
+            max_agents: Some(1),
+            ..repo_named("demo")
+        };
+        let conn = database_with_agents("demo", 0);
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        let agent = NewAgent {
+            repo: &repo,
+            name: "wild-cat".to_string(),
+            tool: "claude",
+            args: &[],
+            start: false,
+        };
+        let worktree = prepare_agent(&sessions, &agent).unwrap();
+        // Another request fills the repo while the worktree is created.
+        provision_agent(&conn, &sessions, &repo, "calm-owl", "claude", &[], false).unwrap();
+
+        let result = record_agent(&conn, &sessions, &agent, &worktree);
+        let names = agent_names(&conn).unwrap();
+        let worktree_left = worktree.exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(result.unwrap_err().status, StatusCode::BAD_REQUEST);
+        assert_eq!(names, vec!["calm-owl"]);
+        assert!(!worktree_left);
+    }
+
+    fn busy_error() -> rusqlite::Error {
+        rusqlite::Error::SqliteFailure(
+            rusqlite::ffi::Error::new(rusqlite::ffi::SQLITE_BUSY),
+            Some("database is locked".to_string()),
+        )
+    }
+
+    #[test]
+    fn busy_database_surfaces_clear_error() {
+        let err = db_error(busy_error());
+        assert_eq!(err.status, StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn locked_database_is_waited_for() {
+        let dir = std::env::temp_dir().join(format!("workforest-busy-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let db_path = dir.join("app.db");
+        let conn = open_database(&db_path).unwrap();
+        create_schema(&conn).unwrap();
+        let holder = Connection::open(&db_path).unwrap();
+        holder.execute_batch("BEGIN EXCLUSIVE").unwrap();
+        let release = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(60));
+            holder.execute_batch("COMMIT").unwrap();
+        });
+
+        let inserted = conn.execute(
+            "INSERT INTO agents (name, label, repo, tool, status, worktree_path, created_at, updated_at) VALUES ('wild-cat', 'wild-cat', 'demo', 'claude', 'sleep', '/tmp', '', '')",
+            [],
+        );
+        let count: rusqlite::Result<i64> =
+            conn.query_row("SELECT COUNT(*) FROM agents", [], |row| row.get(0));
+        release.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(inserted.unwrap(), 1);
+        assert_eq!(count.unwrap(), 1);
+    }
+
+    #[test]
+    fn reset_recreates_worktree_and_keeps_agent_row() {
+        let dir = git_repo_fixture("reset");
+        let repo = RepoConfig {
+            path: dir.join("repo"),
+            worktree_root: Some(dir.join("trees")),
+            ..repo_named("demo")
+        };
+        let conn = database_with_agents("demo", 0);
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        let args = vec!["--verbose".to_string()];
+        let agent =
+            provision_agent(&conn, &sessions, &repo, "wild-cat", "claude", &args, false).unwrap();
+        let scratch = Path::new(&agent.worktree_path).join("scratch.txt");
+        std::fs::write(&scratch, "work in progress").unwrap();
+
+        let db = tokio::sync::Mutex::new(conn);
+
+        let worktree = reset_agent_worktree(&db, &sessions, &repo, "wild-cat", false).unwrap();
+        let scratch_survived = scratch.exists();
+        let fresh = worktree.join(".git").exists();
+        let (command, stored_path, _) =
+            agent_launch_spec(&db.blocking_lock(), std::slice::from_ref(&repo), "wild-cat")
+                .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(!scratch_survived);
+        assert!(fresh);
+        assert_eq!(stored_path, worktree.to_string_lossy());
+        assert_eq!(command.command, "claude --verbose");
+    }
+
+    #[test]
+    fn failed_reset_marks_the_agent_error_without_a_worktree() {
+        let dir = git_repo_fixture("reset-failure");
+        let mut repo = RepoConfig {
+            path: dir.join("repo"),
+            worktree_root: Some(dir.join("trees")),
+            ..repo_named("demo")
+        };
+        let conn = database_with_agents("demo", 0);
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        let agent =
+            provision_agent(&conn, &sessions, &repo, "wild-cat", "claude", &[], false).unwrap();
+        let db = tokio::sync::Mutex::new(conn);
+        // A worktree root under a plain file cannot be created.
+        std::fs::write(dir.join("blocker"), "").unwrap();
+        repo.worktree_root = Some(dir.join("blocker").join("trees"));
+
+        let result = reset_agent_worktree(&db, &sessions, &repo, "wild-cat", false);
+        let old_worktree_exists = Path::new(&agent.worktree_path).exists();
+        let (status, worktree_path): (String, String) = db
+            .blocking_lock()
+            .query_row(
+                "SELECT status, worktree_path FROM agents WHERE name = 'wild-cat'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(result.is_err());
+        assert!(!old_worktree_exists);
+        assert_eq!(status, "error");
+        assert_eq!(worktree_path, "");
+    }
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn initial_input_is_typed_once_the_tool_has_output() {
+        let buffer = SharedBuffer::default();
+        let writer: Arc<Mutex<Box<dyn Write + Send>>> =
+            Arc::new(Mutex::new(Box::new(buffer.clone())));
+        let history = Arc::new(Mutex::new(VecDeque::from(b"> ".to_vec())));
+
+        let handle = send_initial_input(Some("load CONTEXT.md"), history, writer).unwrap();
+        handle.join().unwrap();
+
+        assert_eq!(&*buffer.0.lock().unwrap(), b"load CONTEXT.md\r");
+    }
+
+    #[test]
+    fn initial_input_is_skipped_when_unconfigured() {
+        let buffer = SharedBuffer::default();
+        let writer: Arc<Mutex<Box<dyn Write + Send>>> =
+            Arc::new(Mutex::new(Box::new(buffer.clone())));
+        let history = Arc::new(Mutex::new(VecDeque::new()));
+
+        assert!(send_initial_input(None, history.clone(), writer.clone()).is_none());
+        assert!(send_initial_input(Some(""), history, writer).is_none());
+        assert!(buffer.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn launch_command_appends_quoted_args() {
+        assert_eq!(launch_command("claude", &[]), "claude");
+        let args = vec![
+            "--model".to_string(),
+            "opus".to_string(),
+            "it's here".to_string(),
+        ];
+        assert_eq!(
+            launch_command("claude", &args),
+            "claude --model opus 'it'\\''s here'"
+        );
+        assert!(validate_launch_args(&args).is_ok());
+        assert!(validate_launch_args(&["a\nb".to_string()]).is_err());
+    }
+
+    #[test]
+    fn repos_toml_accepts_plain_and_structured_tools() {
+        let config: RepoConfigFile = toml::from_str(
+            r#"
+            [[repos]]
+            name = "demo"
+            path = "/src/demo"
+            default_tool = "opus"
+            tools = [
+                "claude --model sonnet",
+                { name = "opus", command = "claude", args = ["--model", "opus"], env = { CLAUDE_CONFIG_DIR = "/tmp/opus" } },
+            ]
+            "#,
+        )
+        .unwrap();
+        let tools = &config.repos[0].tools;
+        assert_eq!(tools[0], ToolSpec::from("claude --model sonnet"));
+        assert_eq!(tools[1].name, "opus");
+        assert_eq!(tools[1].command.as_deref(), Some("claude"));
+        assert_eq!(tools[1].args, vec!["--model", "opus"]);
+        assert_eq!(tools[1].env["CLAUDE_CONFIG_DIR"], "/tmp/opus");
+
+        // Plain tools are written back as plain strings.
+        let written = toml::to_string_pretty(&config).unwrap();
+        let reread: RepoConfigFile = toml::from_str(&written).unwrap();
+        assert_eq!(reread.repos[0].tools, *tools);
+        assert!(written.contains("\"claude --model sonnet\""), "{written}");
+    }
+
+    #[test]
+    fn structured_tools_launch_their_command_with_env() {
+        let mut repo = repo_named("demo");
+        repo.tools = vec![ToolSpec {
+            name: "opus".to_string(),
+            command: Some("my claude".to_string()),
+            args: vec!["--model".to_string(), "opus".to_string()],
+            env: BTreeMap::from([("WORKFOREST_TOOL_TEST".to_string(), "from-spec".to_string())]),
+            kind: None,
+        }];
+
+        let launch = ToolLaunch::new(&repo_tool_spec(&repo, "opus"), &["--verbose".to_string()]);
+        assert_eq!(launch.command, "'my claude' --model opus --verbose");
+        assert_eq!(launch.env["WORKFOREST_TOOL_TEST"], "from-spec");
+        let removed = ToolLaunch::new(&repo_tool_spec(&repo, "codex --fast"), &[]);
+        assert_eq!(removed, shell_launch("codex --fast"));
+
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        let launch = ToolLaunch {
+            command: "printf '%s\\n' \"$WORKFOREST_TOOL_TEST\"; sleep 30".to_string(),
+            ..launch
+        };
+        start_tool_session("wild-cat", &launch, Path::new("/tmp"), None, &sessions).unwrap();
+        let history = sessions.lock().unwrap()["wild-cat"].history.clone();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let output = loop {
+            let output: Vec<u8> = history.lock().unwrap().iter().copied().collect();
+            if output.windows(9).any(|window| window == b"from-spec") || Instant::now() > deadline {
+                break output;
+            }
+            thread::sleep(Duration::from_millis(20));
+        };
+        stop_pty_session("wild-cat", &sessions);
+        assert!(
+            output.windows(9).any(|window| window == b"from-spec"),
+            "{}",
+            String::from_utf8_lossy(&output)
+        );
+    }
+
+    #[test]
+    fn repo_env_applies_under_the_tool_env() {
+        let mut repo = repo_named("demo");
+        repo.env = BTreeMap::from([
+            ("SHARED".to_string(), "repo".to_string()),
+            ("REPO_ONLY".to_string(), "repo".to_string()),
+        ]);
+        repo.tools = vec![ToolSpec {
+            env: BTreeMap::from([("SHARED".to_string(), "tool".to_string())]),
+            ..ToolSpec::from("claude")
+        }];
+
+        let launch = ToolLaunch::in_repo(&repo, "claude", &[]);
+        assert_eq!(launch.env["SHARED"], "tool");
+        assert_eq!(launch.env["REPO_ONLY"], "repo");
+        let cmd = tool_command(&launch, Path::new("/tmp"));
+        assert_eq!(cmd.get_env("REPO_ONLY"), Some(std::ffi::OsStr::new("repo")));
+        assert_eq!(cmd.get_cwd(), Some(&std::ffi::OsString::from("/tmp")));
+
+        // Without any env the session inherits the server's unchanged.
+        let cmd = tool_command(
+            &ToolLaunch::in_repo(&repo_named("demo"), "claude", &[]),
+            Path::new("/tmp"),
+        );
+        assert_eq!(cmd.iter_extra_env_as_str().count(), 0);
+
+        // The API lists the names but not the values.
+        let shown = redact_repo_env(repo);
+        assert_eq!(
+            shown.env.keys().collect::<Vec<_>>(),
+            ["REPO_ONLY", "SHARED"]
+        );
+        assert!(shown.env.values().all(|value| value == REDACTED_ENV_VALUE));
+    }
+
+    #[test]
+    fn initial_input_comes_from_the_loaded_repos() {
+        let conn = database_with_agents("demo", 1);
+        let repo = RepoConfig {
+            initial_input: Some("load CONTEXT.md".to_string()),
+            ..repo_named("demo")
+        };
+
+        let (_, _, configured) =
+            agent_launch_spec(&conn, std::slice::from_ref(&repo), "agent-0").unwrap();
+        let (_, _, unknown_repo) = agent_launch_spec(&conn, &[], "agent-0").unwrap();
+
+        assert_eq!(configured.as_deref(), Some("load CONTEXT.md"));
+        assert_eq!(unknown_repo, None);
+    }
+
+    #[test]
+    fn stored_args_are_used_on_restart() {
+        let dir = git_repo_fixture("launch-args");
+        let repo = RepoConfig {
+            path: dir.join("repo"),
+            worktree_root: Some(dir.join("trees")),
+            ..repo_named("demo")
+        };
+        let conn = database_with_agents("demo", 1);
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        let args = vec!["--verbose".to_string()];
+
+        let agent =
+            provision_agent(&conn, &sessions, &repo, "wild-cat", "claude", &args, false).unwrap();
+        let (command, worktree_path, _) = agent_launch_spec(&conn, &[], "wild-cat").unwrap();
+        let (plain, _, _) = agent_launch_spec(&conn, &[], "agent-0").unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(command.command, launch_command("claude", &args));
+        assert_eq!(command.command, "claude --verbose");
+        assert_eq!(worktree_path, agent.worktree_path);
+        assert_eq!(plain.command, "claude");
+    }
+
+    #[test]
+    fn schema_adds_args_column_to_old_databases() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute(
+            "CREATE TABLE agents (name TEXT PRIMARY KEY, label TEXT NOT NULL, repo TEXT NOT NULL, tool TEXT NOT NULL, status TEXT NOT NULL, worktree_path TEXT NOT NULL, styles TEXT, created_at TEXT NOT NULL, updated_at TEXT NOT NULL)",
+            [],
+        )
+        .unwrap();
+        create_schema(&conn).unwrap();
+        assert!(conn.prepare("SELECT args FROM agents").is_ok());
+    }
+
+    #[test]
+    fn porcelain_entries_are_counted() {
+        assert_eq!(porcelain_entry_count(""), 0);
+        assert_eq!(porcelain_entry_count(" M src/main.rs\n?? notes.txt\n"), 2);
+    }
+
+    #[test]
+    fn dirty_count_is_cached_until_stale() {
+        let cache = Mutex::new(HashMap::new());
+        let start = Instant::now();
+        let calls = std::cell::Cell::new(0);
+        let lookup = |now, value| {
+            cached_dirty_count(&cache, "wild-cat", now, || {
+                calls.set(calls.get() + 1);
+                Some(value)
+            })
+        };
+        assert_eq!(lookup(start, 3), Some(3));
+        assert_eq!(lookup(start + Duration::from_secs(1), 5), Some(3));
+        assert_eq!(lookup(start + GIT_STATUS_TTL, 5), Some(5));
+        assert_eq!(calls.get(), 2);
+
+        // A commit, reset or delete forgets the count before it goes stale.
+        forget_git_status(&cache, "wild-cat");
+        assert_eq!(lookup(start + GIT_STATUS_TTL, 0), Some(0));
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[test]
+    fn stale_agents_selects_old_sleeping_agents() {
+        let candidate = |name: &str, status: &str, updated_at: &str| RetentionCandidate {
+            name: name.to_string(),
+            repo: "demo".to_string(),
+            status: status.to_string(),
+            updated_at: updated_at.to_string(),
+            kind: ToolKind::Agent,
+        };
+        let candidates = vec![
+            candidate("old-sleep", "sleep", "2024-01-01T00:00:00+00:00"),
+            candidate("old-running", "running", "2024-01-01T00:00:00+00:00"),
+            candidate("new-sleep", "sleep", "2024-03-01T00:00:00+00:00"),
+            candidate("offset-sleep", "sleep", "2024-02-10T23:00:00-05:00"),
+            candidate("bad-time", "sleep", "yesterday"),
+        ];
+        let cutoff = chrono::DateTime::parse_from_rfc3339("2024-02-11T00:00:00+00:00")
+            .unwrap()
+            .with_timezone(&Utc);
+        assert_eq!(stale_agents(&candidates, cutoff), vec!["old-sleep"]);
+
+        let shell = RetentionCandidate {
+            kind: ToolKind::Shell,
+            ..candidate("old-shell", "sleep", "2024-01-01T00:00:00+00:00")
+        };
+        assert!(stale_agents(&[shell], cutoff).is_empty());
+    }
+
+    #[test]
+    fn old_failed_agents_are_not_pruned() {
+        let conn = database_with_agents("demo", 3);
+        conn.execute(
+            "UPDATE agents SET updated_at = '2024-01-01T00:00:00+00:00'",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE agents SET status = 'error' WHERE name = 'agent-0'",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE agents SET status = 'idle' WHERE name = 'agent-1'",
+            [],
+        )
+        .unwrap();
+        let config = RepoConfigFile {
+            repos: vec![repo_named("demo")],
+        };
+
+        let candidates = retention_candidates(&conn, &config, |_| false).unwrap();
+        let statuses: Vec<_> = candidates
+            .iter()
+            .map(|candidate| candidate.status.as_str())
+            .collect();
+        assert_eq!(statuses, ["error", "idle", "sleep"]);
+        assert_eq!(stale_agents(&candidates, Utc::now()), vec!["agent-2"]);
+    }
+
+    #[test]
+    fn tool_kind_comes_from_the_repo_config() {
+        let mut repo = repo_named("demo");
+        repo.tools = vec![
+            ToolSpec::from("zsh"),
+            ToolSpec {
+                kind: Some(ToolKind::Shell),
+                ..ToolSpec::from("my-repl")
+            },
+        ];
+        let config = RepoConfigFile { repos: vec![repo] };
+        assert_eq!(tool_kind(&config, "demo", "zsh"), ToolKind::Shell);
+        assert_eq!(tool_kind(&config, "demo", "my-repl"), ToolKind::Shell);
+        assert_eq!(tool_kind(&config, "demo", "claude"), ToolKind::Agent);
+        // Tools since removed from the config are still recognised by name.
+        assert_eq!(tool_kind(&config, "gone", "bash -l"), ToolKind::Shell);
+        assert_eq!(
+            tool_kind(&config, "gone", "bash -lc 'make test'"),
+            ToolKind::Agent
+        );
+    }
+
+    #[test]
+    fn repo_cascade_deletes_agents_before_repo() {
+        let conn = database_with_agents("demo", 2);
+        let agents = repo_agent_names(&conn, "demo").unwrap();
+        let events = std::cell::RefCell::new(Vec::new());
+        let deletion = delete_repo_cascade(
+            "demo",
+            agents,
+            |agent| {
+                events.borrow_mut().push(format!("agent:{agent}"));
+                Ok(())
+            },
+            || {
+                events.borrow_mut().push("repo".to_string());
+                Ok(())
+            },
+        );
+        assert_eq!(
+            events.into_inner(),
+            vec!["agent:agent-0", "agent:agent-1", "repo"]
+        );
+        assert!(deletion.removed);
+        assert_eq!(deletion.deleted_agents, vec!["agent-0", "agent-1"]);
+        assert!(deletion.failed_agents.is_empty());
+    }
+
+    #[test]
+    fn failed_repo_removal_is_an_error() {
+        // A file where the config directory should be cannot be written.
+        let dir = std::env::temp_dir().join(format!("workforest-rm-repo-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let blocker = dir.join("config");
+        std::fs::write(&blocker, "").unwrap();
+        let path = blocker.join("repos.toml");
+
+        let mut config = RepoConfigFile {
+            repos: vec![repo_named("webapp")],
+        };
+        let err = remove_repo_entry(&mut config, "webapp", |config| {
+            save_repo_config_to(&path, config)
+        })
+        .unwrap_err();
+        assert_eq!(err.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert!(err.message.contains("config"), "{}", err.message);
+        assert!(!path.exists());
+
+        let path = dir.join("writable").join("repos.toml");
+        remove_repo_entry(&mut config, "webapp", |config| {
+            save_repo_config_to(&path, config)
+        })
+        .unwrap();
+        assert!(path.exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn repo_cascade_reports_partial_failures() {
+        let agents = vec!["a".to_string(), "b".to_string(), "c".to_string()];
+        let mut repo_removed = false;
+        let deletion = delete_repo_cascade(
+            "demo",
+            agents,
+            |agent| {
+                if agent == "b" {
+                    Err(ApiError::internal("git worktree remove failed"))
+                } else {
+                    Ok(())
+                }
+            },
+            || {
+                repo_removed = true;
+                Ok(())
+            },
+        );
+        assert!(!repo_removed);
+        assert!(!deletion.removed);
+        assert_eq!(deletion.deleted_agents, vec!["a", "c"]);
+        assert_eq!(
+            deletion.failed_agents,
+            vec![AgentFailure {
+                name: "b".to_string(),
+                error: "git worktree remove failed".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn signal_names_map_to_signals() {
+        assert_eq!(parse_signal("INT").unwrap(), Signal::SIGINT);
+        assert_eq!(parse_signal("sigterm").unwrap(), Signal::SIGTERM);
+        assert_eq!(parse_signal("KILL").unwrap(), Signal::SIGKILL);
+        assert_eq!(parse_signal(" hup ").unwrap(), Signal::SIGHUP);
+    }
+
+    #[test]
+    fn unknown_signal_is_rejected() {
+        for name in ["USR1", "STOP", ""] {
+            let err = parse_signal(name).unwrap_err();
+            assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[test]
+    fn diff_command_runs_in_worktree() {
+        let command = diff_command(Path::new("/tmp/tree"), false);
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(command.get_program(), "git");
+        assert_eq!(args, ["-C", "/tmp/tree", "diff"]);
+
+        let command = diff_command(Path::new("/tmp/tree"), true);
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["-C", "/tmp/tree", "diff", "--stat"]);
+    }
+
+    #[test]
+    fn commit_stages_everything_and_returns_the_sha() {
+        let dir = git_repo_fixture("commit-agent");
+        let repo = dir.join("repo");
+        for (key, value) in [("user.name", "test"), ("user.email", "test@example.com")] {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(&repo)
+                .args(["config", key, value])
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+
+        let err = commit_worktree(&repo, "nothing yet").unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert!(err.message.contains("nothing to commit"), "{}", err.message);
+
+        std::fs::write(repo.join("notes.txt"), "progress\n").unwrap();
+        let message = commit_message("  --amend\n\nfrom the agent  ").unwrap();
+        let sha = commit_worktree(&repo, message).unwrap();
+        let log = Command::new("git")
+            .arg("-C")
+            .arg(&repo)
+            .args(["log", "-1", "--format=%H%n%B"])
+            .output()
+            .unwrap();
+        let log = String::from_utf8_lossy(&log.stdout);
+        assert!(
+            log.starts_with(&format!("{sha}\n--amend\n\nfrom the agent")),
+            "{log}"
+        );
+        assert_eq!(worktree_dirty_count(&repo), Some(0));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    fn configure_git_user(repo: &Path) {
+        for (key, value) in [("user.name", "test"), ("user.email", "test@example.com")] {
+            let status = Command::new("git")
+                .arg("-C")
+                .arg(repo)
+                .args(["config", key, value])
+                .status()
+                .unwrap();
+            assert!(status.success());
+        }
+    }
+
+    #[test]
+    fn merge_command_fast_forwards_or_commits() {
+        let command = merge_command(Path::new("/tmp/repo"), "agent/wild-cat", false);
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(command.get_program(), "git");
+        assert_eq!(
+            args,
+            ["-C", "/tmp/repo", "merge", "--no-edit", "agent/wild-cat"]
+        );
+
+        let command = merge_command(Path::new("/tmp/repo"), "agent/wild-cat", true);
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(
+            args,
+            [
+                "-C",
+                "/tmp/repo",
+                "merge",
+                "--no-edit",
+                "--ff-only",
+                "agent/wild-cat"
+            ]
+        );
+    }
+
+    #[test]
+    fn merge_fast_forwards_committed_agent_work() {
+        let dir = git_repo_fixture("merge-agent");
+        let repo = dir.join("repo");
+        configure_git_user(&repo);
+        let worktree = create_worktree(&repo, &dir.join("trees"), "repo", "wild-cat").unwrap();
+
+        std::fs::write(worktree.join("notes.txt"), "progress\n").unwrap();
+        let err = ensure_committed(&worktree).unwrap_err();
+        assert_eq!(err.status, StatusCode::CONFLICT);
+        let sha = commit_worktree(&worktree, "progress").unwrap();
+        assert!(ensure_committed(&worktree).is_ok());
+
+        let err = merge_branch(&repo, "agent/wild-cat", Some("release"), false).unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        let into = checked_out_branch(&repo).unwrap();
+        let merged = merge_branch(&repo, "agent/wild-cat", Some(&into), true).unwrap();
+        assert_eq!(
+            (merged.sha.as_str(), merged.fast_forward),
+            (sha.as_str(), true)
+        );
+        assert_eq!(merged.into, into);
+        assert!(repo.join("notes.txt").exists());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn merge_refuses_a_dirty_repo_and_a_missing_branch() {
+        let dir = git_repo_fixture("merge-refused");
+        let repo = dir.join("repo");
+        configure_git_user(&repo);
+        create_worktree(&repo, &dir.join("trees"), "repo", "wild-cat").unwrap();
+        assert_eq!(agent_branch("Wild Cat"), "agent/wild-cat");
+
+        let err = merge_branch(&repo, "agent/calm-owl", None, false).unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+
+        std::fs::write(repo.join("scratch.txt"), "unsaved\n").unwrap();
+        let err = merge_branch(&repo, &agent_branch("wild-cat"), None, false).unwrap_err();
+        assert_eq!(err.status, StatusCode::CONFLICT);
+        assert!(err.message.contains("uncommitted"), "{}", err.message);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn conflicting_merge_is_aborted_and_reported() {
+        let dir = git_repo_fixture("merge-conflict");
+        let repo = dir.join("repo");
+        configure_git_user(&repo);
+        let worktree = create_worktree(&repo, &dir.join("trees"), "repo", "wild-cat").unwrap();
+        std::fs::write(worktree.join("notes.txt"), "from the agent\n").unwrap();
+        commit_worktree(&worktree, "agent notes").unwrap();
+        std::fs::write(repo.join("notes.txt"), "from the user\n").unwrap();
+        let head = commit_worktree(&repo, "user notes").unwrap();
+
+        let err = merge_branch(&repo, "agent/wild-cat", None, false).unwrap_err();
+        assert_eq!(err.status, StatusCode::CONFLICT);
+        assert!(
+            err.message.contains("conflicts in notes.txt"),
+            "{}",
+            err.message
+        );
+        // The repo is left as it was before the merge.
+        assert_eq!(rev_parse(&repo, "HEAD").unwrap(), head);
+        assert_eq!(worktree_dirty_count(&repo), Some(0));
+        assert_eq!(
+            std::fs::read_to_string(repo.join("notes.txt")).unwrap(),
+            "from the user\n"
+        );
+
+        let err = merge_branch(&repo, "agent/wild-cat", None, true).unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        assert!(
+            err.message.starts_with("git merge failed"),
+            "{}",
+            err.message
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn commit_message_must_not_be_blank() {
+        assert_eq!(commit_message(" fix \n").unwrap(), "fix");
+        assert_eq!(
+            commit_message(" \n").unwrap_err().status,
+            StatusCode::BAD_REQUEST
+        );
+        assert!(commit_message("a\0b").is_err());
+    }
+
+    #[test]
+    fn deleted_worktree_is_not_found() {
+        let dir = git_repo_fixture("diff-missing");
+        assert!(existing_worktree(dir.to_str().unwrap()).is_ok());
+        let missing = dir.join("gone");
+        let err = existing_worktree(missing.to_str().unwrap()).unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn diff_is_truncated_at_char_boundary() {
+        assert_eq!(
+            truncate_diff(b"+added\n", 64),
+            ("+added\n".to_string(), false)
+        );
+
+        let (diff, truncated) = truncate_diff("+caf\u{e9}\n".as_bytes(), 5);
+        assert!(truncated);
+        assert_eq!(diff, "+caf");
+    }
+
+    #[test]
+    fn sleeping_stops_the_session_and_keeps_the_agent() {
+        let conn = database_with_agents("forest", 1);
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        start_tool_session(
+            "agent-0",
+            &shell_launch("sleep 30"),
+            Path::new("/tmp"),
+            None,
+            &sessions,
+        )
+        .unwrap();
+
+        put_agent_to_sleep(&conn, &sessions, "agent-0").unwrap();
+        assert!(sessions.lock().unwrap().is_empty());
+        let status: String = conn
+            .query_row(
+                "SELECT status FROM agents WHERE name = 'agent-0'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(status, "sleep");
+
+        let err = put_agent_to_sleep(&conn, &sessions, "missing").unwrap_err();
+        assert_eq!(err.status, StatusCode::NOT_FOUND);
+    }
+
+    /// Creates an agent's worktree, optionally starts its tool session,
+    /// and records it, as `create_agents` does for a checked request.
+    fn provision_agent(
+        conn: &Connection,
+        sessions: &Arc<Mutex<HashMap<String, PtySession>>>,
+        repo: &RepoConfig,
+        agent_name: &str,
+        tool: &str,
+        args: &[String],
+        start: bool,
+    ) -> Result<Agent, ApiError> {
+        let agent = NewAgent {
+            repo,
+            name: agent_name.to_string(),
+            tool,
+            args,
+            start,
+        };
+        let worktree_path = prepare_agent(sessions, &agent)?;
+        record_agent(conn, sessions, &agent, &worktree_path)
+    }
+
+    fn database_with_agents(repo: &str, count: usize) -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        for index in 0..count {
+            conn.execute(
+                "INSERT INTO agents (name, label, repo, tool, status, worktree_path, created_at, updated_at) VALUES (?1, ?1, ?2, 'claude', 'running', '/tmp', '', '')",
+                params![format!("agent-{index}"), repo],
+            )
+            .unwrap();
+        }
+        conn
+    }
+
+    #[test]
+    fn agent_filters_narrow_and_combine() {
+        let conn = database_with_agents("forest", 3);
+        conn.execute(
+            "INSERT INTO agents (name, label, repo, tool, status, worktree_path, created_at, updated_at) VALUES ('other', 'other', 'meadow', 'codex', 'idle', '/tmp', '', '')",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE agents SET status = 'sleep' WHERE name = 'agent-2'",
+            [],
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE agents SET tool = 'codex' WHERE name = 'agent-0'",
+            [],
+        )
+        .unwrap();
+        let names = |filter: AgentFilter| {
+            // agent-1 is stored as running but its session is gone.
+            let mut names: Vec<String> = query_agents(&conn, &filter, |name| name == "agent-0")
+                .unwrap()
+                .into_iter()
+                .map(|agent| agent.name)
+                .collect();
+            names.sort();
+            names
+        };
 
-    let pty_system = native_pty_system();
-    let size = PtySize::default();
-    let pair = pty_system
-        .openpty(size)
-        .map_err(|err| ApiError::internal(err.to_string()))?;
-    let mut cmd = CommandBuilder::new("sh");
-    cmd.arg("-lc");
-    cmd.arg(tool);
-    cmd.cwd(worktree_path);
-    let child = pair
-        .slave
-        .spawn_command(cmd)
-        .map_err(|err| ApiError::internal(err.to_string()))?;
+        assert_eq!(names(AgentFilter::default()).len(), 4);
+        assert_eq!(
+            names(AgentFilter {
+                repo: Some("meadow".to_string()),
+                ..AgentFilter::default()
+            }),
+            ["other"]
+        );
+        assert_eq!(
+            names(AgentFilter {
+                tool: Some("codex".to_string()),
+                ..AgentFilter::default()
+            }),
+            ["agent-0", "other"]
+        );
+        assert_eq!(
+            names(AgentFilter {
+                status: Some("running".to_string()),
+                ..AgentFilter::default()
+            }),
+            ["agent-0"]
+        );
+        assert_eq!(
+            names(AgentFilter {
+                status: Some("sleep".to_string()),
+                ..AgentFilter::default()
+            }),
+            ["agent-1", "agent-2"]
+        );
+        assert_eq!(
+            names(AgentFilter {
+                status: Some("idle".to_string()),
+                ..AgentFilter::default()
+            }),
+            ["other"]
+        );
+        assert_eq!(
+            names(AgentFilter {
+                repo: Some("forest".to_string()),
+                tool: Some("codex".to_string()),
+                status: Some("running".to_string()),
+            }),
+            ["agent-0"]
+        );
+        // Values are bound, not spliced into the SQL.
+        assert!(names(AgentFilter {
+            repo: Some("' OR '1' = '1".to_string()),
+            ..AgentFilter::default()
+        })
+        .is_empty());
+    }
 
-    let history = Arc::new(Mutex::new(VecDeque::new()));
-    let terminal_snapshot = Arc::new(Mutex::new(default_terminal_snapshot()));
-    let subscribers = Arc::new(Mutex::new(Vec::new()));
-    let master_fd = pair
-        .master
-        .as_raw_fd()
-        .ok_or_else(|| ApiError::internal("missing master fd"))?;
-    let history_handle = spawn_history_reader(
-        master_fd,
-        history.clone(),
-        terminal_snapshot.clone(),
-        subscribers.clone(),
-    );
-    let writer = pair
-        .master
-        .take_writer()
-        .map_err(|err| ApiError::internal(err.to_string()))?;
-    sessions.insert(
-        agent_name.to_string(),
-        PtySession {
-            master: pair.master,
-            writer: Arc::new(Mutex::new(writer)),
-            child,
-            size,
-            history,
-            terminal_snapshot,
-            subscribers,
-            _history_handle: history_handle,
-        },
-    );
+    #[test]
+    fn accept_loop_survives_transient_and_listener_errors() {
+        let mut script = VecDeque::from([
+            Err(io::Error::from(io::ErrorKind::Interrupted)),
+            Ok(1),
+            Err(io::Error::other("listener broke")),
+            Ok(2),
+        ]);
+        let mut generations = 0;
+        let mut handled = Vec::new();
 
-    Ok(())
-}
+        let err = run_accept_loop(
+            0,
+            |generation: &u32| {
+                script
+                    .pop_front()
+                    .unwrap_or_else(|| Err(io::Error::other("fatal")))
+                    .map(|stream| (*generation, stream))
+            },
+            || {
+                generations += 1;
+                Ok(generations)
+            },
+            |accepted| handled.push(accepted),
+        );
 
-fn stop_pty_session(agent_name: &str, sessions: &Arc<Mutex<HashMap<String, PtySession>>>) {
-    let mut sessions = sessions.lock().expect("pty sessions lock");
-    if let Some(mut session) = sessions.remove(agent_name) {
-        let _ = session.child.kill();
+        assert_eq!(handled, vec![(0, 1), (1, 2)]);
+        assert_eq!(err.to_string(), "fatal");
+        // One rebind for the broken listener, then one after every failure
+        // in the final run except the last.
+        assert_eq!(generations, BROKER_MAX_ACCEPT_FAILURES);
     }
-}
 
-fn spawn_history_reader(
-    fd: i32,
-    history: Arc<Mutex<VecDeque<u8>>>,
-    terminal_snapshot: Arc<Mutex<TerminalSnapshot>>,
-    subscribers: Arc<Mutex<Vec<UnixStream>>>,
-) -> thread::JoinHandle<()> {
-    thread::spawn(move || {
-        let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
-        let mut buffer = [0u8; 4096];
-        let mut parser = Parser::new();
-        loop {
-            match file.read(&mut buffer) {
-                Ok(0) => break,
-                Ok(size) => {
-                    {
-                        let mut history = history.lock().expect("pty history lock");
-                        for byte in &buffer[..size] {
-                            history.push_back(*byte);
-                        }
-                        trim_history_to_boundary(&mut history, HISTORY_LIMIT_BYTES);
-                    }
-                    {
-                        let mut snapshot = terminal_snapshot
-                            .lock()
-                            .expect("pty terminal snapshot lock");
-                        parser.parse(&buffer[..size], |action| {
-                            apply_action_to_snapshot(action, &mut snapshot);
-                        });
-                    }
-                    let mut subs = subscribers.lock().expect("pty subscribers lock");
-                    subs.retain_mut(|stream| stream.write_all(&buffer[..size]).is_ok());
+    #[test]
+    fn accept_loop_rebinds_a_real_socket() {
+        let dir = std::env::temp_dir().join(format!("workforest-rebind-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("pty.sock");
+        let listener = bind_broker_socket(&socket_path).unwrap();
+        let mut failed_once = false;
+        let mut accepted = 0;
+        let mut connected = 0;
+
+        let err = run_accept_loop(
+            listener,
+            |listener| {
+                if !failed_once {
+                    failed_once = true;
+                    return Err(io::Error::other("simulated listener failure"));
                 }
-                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => continue,
-                Err(_) => break,
-            }
-        }
-    })
-}
+                if accepted == 1 {
+                    return Err(io::Error::from(io::ErrorKind::Interrupted));
+                }
+                let client = UnixStream::connect(&socket_path)?;
+                accepted += 1;
+                listener.accept().map(|(stream, _)| (stream, client))
+            },
+            || bind_broker_socket(&socket_path),
+            |_| connected += 1,
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
 
-fn default_terminal_snapshot() -> TerminalSnapshot {
-    TerminalSnapshot {
-        cursor_visible: true,
-        wrap_mode: true,
-        ..TerminalSnapshot::default()
+        assert_eq!(connected, 1);
+        assert_eq!(err.kind(), io::ErrorKind::Interrupted);
     }
-}
 
-fn trim_history_to_boundary(history: &mut VecDeque<u8>, limit: usize) {
-    if history.len() <= limit {
-        return;
+    #[test]
+    fn broker_health_requires_accept_loop_and_socket() {
+        let dir = std::env::temp_dir().join(format!("workforest-broker-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("pty.sock");
+        let _listener = UnixListener::bind(&socket_path).unwrap();
+        let health = BrokerHealth {
+            socket_path: socket_path.clone(),
+            accepting: Arc::new(AtomicBool::new(true)),
+        };
+        assert_eq!(health.check(), Ok(()));
+
+        health.accepting.store(false, Ordering::Relaxed);
+        assert_eq!(
+            health.check(),
+            Err("pty broker stopped accepting connections".to_string())
+        );
+
+        health.accepting.store(true, Ordering::Relaxed);
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(health.check().unwrap_err().contains("is missing"));
     }
-    let overflow = history.len() - limit;
-    let bytes: Vec<u8> = history.iter().copied().collect();
-    let drop_count = find_safe_history_start(&bytes, overflow);
-    for _ in 0..drop_count {
-        history.pop_front();
+
+    #[test]
+    fn status_changes_report_new_changed_and_deleted_agents() {
+        let statuses = |pairs: &[(&str, &str)]| -> HashMap<String, String> {
+            pairs
+                .iter()
+                .map(|(name, status)| (name.to_string(), status.to_string()))
+                .collect()
+        };
+        let previous = statuses(&[
+            ("blue-fox", "running"),
+            ("old-owl", "sleep"),
+            ("wild-cat", "sleep"),
+        ]);
+        let current = statuses(&[
+            ("blue-fox", "running"),
+            ("new-elk", "sleep"),
+            ("wild-cat", "running"),
+        ]);
+
+        let changes = status_changes(&previous, &current, "2024-01-01T00:00:00Z");
+        let summary: Vec<(&str, Option<&str>, &str)> = changes
+            .iter()
+            .map(|event| {
+                (
+                    event.name.as_str(),
+                    event.from.as_deref(),
+                    event.to.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("new-elk", None, "sleep"),
+                ("old-owl", Some("sleep"), "deleted"),
+                ("wild-cat", Some("sleep"), "running"),
+            ]
+        );
+        assert!(status_changes(&current, &current, "").is_empty());
     }
-}
 
-fn find_safe_history_start(bytes: &[u8], overflow: usize) -> usize {
-    let mut index = 0;
-    let mut last_safe = 0;
-    while index < bytes.len() {
-        if bytes[index] == 0x1b {
-            if let Some(next) = bytes.get(index + 1).copied() {
-                match next {
-                    b'[' => {
-                        index = parse_csi_sequence(bytes, index + 2);
-                    }
-                    b']' | b'P' | b'^' | b'_' => {
-                        index = parse_string_sequence(bytes, index + 2);
-                    }
-                    _ => {
-                        index = (index + 2).min(bytes.len());
-                    }
-                }
-            } else {
-                break;
-            }
-        } else {
-            index += 1;
-        }
-        if index <= overflow {
-            last_safe = index;
-        }
+    #[test]
+    fn agent_capacity_rejects_when_limit_reached() {
+        let conn = database_with_agents("demo", 2);
+        let mut repo = repo_named("demo");
+        repo.max_agents = Some(3);
+        assert!(ensure_agent_capacity(&conn, &repo, 0).is_ok());
+        // Agents accepted earlier in the same batch count too.
+        assert!(ensure_agent_capacity(&conn, &repo, 1).is_err());
+
+        repo.max_agents = Some(2);
+        let err = ensure_agent_capacity(&conn, &repo, 0).unwrap_err();
+        assert_eq!(err.status, StatusCode::BAD_REQUEST);
+
+        let other = RepoConfig {
+            max_agents: Some(1),
+            ..repo_named("other")
+        };
+        assert!(ensure_agent_capacity(&conn, &other, 0).is_ok());
     }
-    last_safe
-}
 
-fn parse_csi_sequence(bytes: &[u8], start: usize) -> usize {
-    let mut index = start;
-    while index < bytes.len() {
-        let byte = bytes[index];
-        index += 1;
-        if (0x40..=0x7e).contains(&byte) {
-            break;
-        }
+    #[test]
+    fn agent_capacity_unlimited_when_unset() {
+        let conn = database_with_agents("demo", 5);
+        assert!(ensure_agent_capacity(&conn, &repo_named("demo"), 0).is_ok());
     }
-    index
-}
 
-fn parse_string_sequence(bytes: &[u8], start: usize) -> usize {
-    let mut index = start;
-    while index < bytes.len() {
-        let byte = bytes[index];
-        if byte == 0x07 {
-            return index + 1;
+    #[test]
+    fn agent_name_applies_repo_prefix() {
+        let name =
+            generate_agent_name_with(Some("myrepo"), || "wild-cat".to_string(), |_| Ok(false))
+                .unwrap();
+        assert_eq!(name, "myrepo-wild-cat");
+    }
+
+    #[test]
+    fn agent_name_rerolls_prefixed_collisions() {
+        let mut petnames = vec!["blue-fox", "wild-cat"];
+        let mut checked = Vec::new();
+        let name = generate_agent_name_with(
+            Some("myrepo"),
+            || petnames.pop().unwrap().to_string(),
+            |candidate| {
+                checked.push(candidate.to_string());
+                Ok(candidate == "myrepo-wild-cat")
+            },
+        )
+        .unwrap();
+        assert_eq!(name, "myrepo-blue-fox");
+        assert_eq!(checked, vec!["myrepo-wild-cat", "myrepo-blue-fox"]);
+    }
+
+    #[test]
+    fn repo_name_uses_base_when_unique() {
+        let repos = vec![repo_named("other")];
+        let name = generate_repo_name_with_suffix("demo", &repos, || "suffix".to_string());
+        assert_eq!(name, "demo");
+    }
+
+    #[test]
+    fn repo_name_uses_suffix_on_collision() {
+        let repos = vec![repo_named("demo")];
+        let mut suffixes = VecDeque::from(vec!["alpha".to_string()]);
+        let name = generate_repo_name_with_suffix("demo", &repos, || suffixes.pop_front().unwrap());
+        assert_eq!(name, "demo-alpha");
+    }
+
+    #[test]
+    fn repo_name_rerolls_on_suffix_collision() {
+        let repos = vec![repo_named("demo"), repo_named("demo-alpha")];
+        let mut suffixes = VecDeque::from(vec!["alpha".to_string(), "bravo".to_string()]);
+        let name = generate_repo_name_with_suffix("demo", &repos, || suffixes.pop_front().unwrap());
+        assert_eq!(name, "demo-bravo");
+    }
+
+    #[test]
+    fn explicit_repo_name_is_used_when_unique() {
+        let repos = vec![repo_named("demo")];
+        assert_eq!(validate_repo_name("forest", &repos).unwrap(), "forest");
+    }
+
+    #[test]
+    fn explicit_repo_name_rejects_collisions_and_bad_chars() {
+        let repos = vec![repo_named("demo")];
+        for name in ["demo", "my repo", "a/b"] {
+            let err = validate_repo_name(name, &repos).unwrap_err();
+            assert_eq!(err.status, StatusCode::BAD_REQUEST);
         }
-        if byte == 0x1b && bytes.get(index + 1) == Some(&b'\\') {
-            return index + 2;
+    }
+
+    #[test]
+    fn snapshot_keeps_extended_underline_styles() {
+        let cases = [
+            (b"\x1b[4:3m".as_slice(), "Curly"),
+            (b"\x1b[4:4m".as_slice(), "Dotted"),
+            (b"\x1b[4:5m".as_slice(), "Dashed"),
+            (b"\x1b[21m".as_slice(), "Double"),
+        ];
+        for (bytes, expected) in cases {
+            let mut snapshot = default_terminal_snapshot();
+            Parser::new().parse(bytes, |action| {
+                apply_action_to_snapshot(action, &mut snapshot)
+            });
+            assert_eq!(format!("{:?}", snapshot.attributes.underline), expected);
         }
-        index += 1;
     }
-    index
-}
 
-fn apply_action_to_snapshot(action: Action, snapshot: &mut TerminalSnapshot) {
-    match action {
-        Action::CSI(csi) => apply_csi_to_snapshot(csi, snapshot),
-        Action::Esc(esc) => apply_esc_to_snapshot(esc, snapshot),
-        _ => {}
+    #[test]
+    fn snapshot_tracks_bracketed_paste() {
+        let mut snapshot = default_terminal_snapshot();
+        let mut parser = Parser::new();
+        parser.parse(b"\x1b[?2004h", |action| {
+            apply_action_to_snapshot(action, &mut snapshot)
+        });
+        assert!(snapshot.bracketed_paste);
+        parser.parse(b"\x1b[?2004l", |action| {
+            apply_action_to_snapshot(action, &mut snapshot)
+        });
+        assert!(!snapshot.bracketed_paste);
     }
-}
 
-fn apply_esc_to_snapshot(esc: Esc, snapshot: &mut TerminalSnapshot) {
-    if let Esc::Code(code) = esc {
-        if matches!(code, EscCode::FullReset) {
-            *snapshot = default_terminal_snapshot();
-        }
+    #[test]
+    fn snapshot_tracks_application_cursor_keys() {
+        let mut snapshot = default_terminal_snapshot();
+        Parser::new().parse(b"\x1b[?1h", |action| {
+            apply_action_to_snapshot(action, &mut snapshot)
+        });
+        assert!(snapshot.application_cursor_keys);
     }
-}
 
-fn apply_csi_to_snapshot(csi: termwiz::escape::csi::CSI, snapshot: &mut TerminalSnapshot) {
-    match csi {
-        termwiz::escape::csi::CSI::Mode(mode) => apply_mode_to_snapshot(mode, snapshot),
-        termwiz::escape::csi::CSI::Sgr(sgr) => apply_sgr_to_snapshot(sgr, snapshot),
-        termwiz::escape::csi::CSI::Cursor(cursor) => apply_cursor_to_snapshot(cursor, snapshot),
-        _ => {}
+    #[test]
+    fn history_lines_resume_from_the_returned_offset() {
+        let mut history: VecDeque<u8> = b"one\ntwo\nthr".iter().copied().collect();
+        let (lines, offset) = history_lines_since(&history, 11, 0);
+        assert_eq!(lines, ["one", "two"]);
+        assert_eq!(offset, 8);
+
+        // The same line printed again is new output, not an overlap.
+        history.extend(b"ee\ntwo\n");
+        let (lines, offset) = history_lines_since(&history, 18, offset);
+        assert_eq!(lines, ["three", "two"]);
+        assert_eq!(offset, 18);
+        assert_eq!(history_lines_since(&history, 18, offset), (vec![], 18));
+
+        // Trimmed past the offset: resume at the first whole line held.
+        history.drain(..6);
+        history.extend(b"four\n");
+        let (lines, offset) = history_lines_since(&history, 23, 2);
+        assert_eq!(lines, ["three", "two", "four"]);
+        assert_eq!(offset, 23);
+
+        // An offset past the output belongs to an earlier session.
+        assert_eq!(history_lines_since(&history, 23, 99).0.len(), 3);
+
+        assert_eq!(parse_output_since("").unwrap(), HashMap::new());
+        assert_eq!(
+            parse_output_since("wild-cat:8,calm-owl:0").unwrap(),
+            HashMap::from([("wild-cat".to_string(), 8), ("calm-owl".to_string(), 0)])
+        );
+        assert!(parse_output_since("wild-cat").is_err());
+        assert!(parse_output_since("wild-cat:x").is_err());
     }
-}
 
-fn apply_cursor_to_snapshot(cursor: Cursor, snapshot: &mut TerminalSnapshot) {
-    match cursor {
-        Cursor::SetTopAndBottomMargins { top, bottom } => {
-            let top = top.as_zero_based() as usize;
-            let bottom = bottom.as_zero_based() as usize;
-            if top < bottom {
-                snapshot.scroll_region = Some(ScrollRegion { top, bottom });
-            } else {
-                snapshot.scroll_region = None;
-            }
-        }
-        Cursor::CursorStyle(style) => {
-            snapshot.cursor_shape = match style {
-                CursorStyle::Default => CursorShape::Default,
-                CursorStyle::BlinkingBlock => CursorShape::BlinkingBlock,
-                CursorStyle::SteadyBlock => CursorShape::SteadyBlock,
-                CursorStyle::BlinkingUnderline => CursorShape::BlinkingUnderline,
-                CursorStyle::SteadyUnderline => CursorShape::SteadyUnderline,
-                CursorStyle::BlinkingBar => CursorShape::BlinkingBar,
-                CursorStyle::SteadyBar => CursorShape::SteadyBar,
-            };
+    #[test]
+    fn plaintext_tail_keeps_the_last_printed_lines() {
+        let output = b"one\r\n\x1b[1;32mtwo\x1b[0m\r\n50%\r100%\r\nthree\r\nprompt$ ";
+        assert_eq!(plaintext_tail(output, 3), "two\n100%\nthree\nprompt$");
+        assert_eq!(plaintext_tail(b"done\r\n", 3), "done\n");
+    }
+
+    #[test]
+    fn plaintext_lines_survive_arbitrary_chunking() {
+        let output = "caf\u{e9} \x1b[1;32mok\x1b[0m\r\n50%\r100%\r\nnext".as_bytes();
+        for split in 0..output.len() {
+            let mut printer = PlaintextLines::new();
+            let mut lines = Vec::new();
+            printer.push(&output[..split], |line| lines.push(line));
+            printer.push(&output[split..], |line| lines.push(line));
+            assert_eq!(lines, ["caf\u{e9} ok", "100%"], "split at {split}");
+            assert_eq!(printer.pending(), "next");
         }
-        _ => {}
     }
-}
 
-fn apply_mode_to_snapshot(mode: Mode, snapshot: &mut TerminalSnapshot) {
-    match mode {
-        Mode::SetDecPrivateMode(mode) => apply_dec_private_mode(mode, snapshot, true),
-        Mode::ResetDecPrivateMode(mode) => apply_dec_private_mode(mode, snapshot, false),
-        Mode::SetMode(mode) => apply_terminal_mode(mode, snapshot, true),
-        Mode::ResetMode(mode) => apply_terminal_mode(mode, snapshot, false),
-        _ => {}
+    #[test]
+    fn output_subscription_follows_the_session() {
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        assert!(OutputSubscription::register("wild-cat", &sessions).is_err());
+        start_tool_session(
+            "wild-cat",
+            &shell_launch("printf 'before\\n'; sleep 0.5; printf 'after\\n'; sleep 30"),
+            Path::new("/tmp"),
+            None,
+            &sessions,
+        )
+        .unwrap();
+        let history = sessions.lock().unwrap()["wild-cat"].history.clone();
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !history.lock().unwrap().contains(&b'\n') && Instant::now() < deadline {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        let subscription = OutputSubscription::register("wild-cat", &sessions).unwrap();
+        assert!(String::from_utf8_lossy(&subscription.recent).contains("before"));
+        let mut stream = subscription.stream.try_clone().unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(5)))
+            .unwrap();
+        let mut live = Vec::new();
+        let mut buffer = [0u8; 256];
+        while !String::from_utf8_lossy(&live).contains("after") {
+            let size = stream.read(&mut buffer).unwrap();
+            assert!(size > 0);
+            live.extend_from_slice(&buffer[..size]);
+        }
+
+        let subscribers = sessions.lock().unwrap()["wild-cat"].subscribers.clone();
+        assert_eq!(subscribers.lock().unwrap().len(), 1);
+        drop(subscription);
+        assert!(subscribers.lock().unwrap().is_empty());
+        stop_pty_session("wild-cat", &sessions);
     }
-}
 
-fn apply_dec_private_mode(mode: DecPrivateMode, snapshot: &mut TerminalSnapshot, enabled: bool) {
-    let code = match mode {
-        DecPrivateMode::Code(code) => code,
-        DecPrivateMode::Unspecified(code) => {
-            set_mode_entry(&mut snapshot.dec_private_modes, code, enabled);
-            return;
+    #[test]
+    fn stalled_subscriber_is_dropped_without_blocking_output() {
+        let (stalled, _never_read) = UnixStream::pair().unwrap();
+        let stalled = Subscriber::nonblocking(stalled).unwrap();
+        let (subscribers, live_id, live_client) = registered_subscriber();
+        subscribers.lock().unwrap().push(stalled);
+        let reader = thread::spawn(move || {
+            let mut received = Vec::new();
+            (&live_client).read_to_end(&mut received).unwrap();
+            received.len()
+        });
+
+        // Far more than a socket buffer holds.
+        let chunk = [b'x'; 4096];
+        for _ in 0..512 {
+            broadcast_output(&mut subscribers.lock().unwrap(), &chunk);
         }
-    };
-    if let Some(value) = code.to_u16() {
-        set_mode_entry(&mut snapshot.dec_private_modes, value, enabled);
+        let ids: Vec<u64> = subscribers.lock().unwrap().iter().map(|s| s.id).collect();
+        assert_eq!(ids, [live_id]);
+
+        drop(subscribers);
+        assert_eq!(reader.join().unwrap(), 512 * chunk.len());
     }
-    match code {
-        DecPrivateModeCode::ShowCursor => snapshot.cursor_visible = enabled,
-        DecPrivateModeCode::StartBlinkingCursor => {
-            if enabled {
-                snapshot.cursor_shape = CursorShape::BlinkingBlock;
+
+    #[test]
+    fn snapshot_restores_main_cursor_after_alt_screen() {
+        let mut snapshot = default_terminal_snapshot();
+        let mut parser = Parser::new();
+        parser.parse(b"\x1b[4 q\x1b[?1049h\x1b[?25l\x1b[6 q", |action| {
+            apply_action_to_snapshot(action, &mut snapshot)
+        });
+        assert!(!snapshot.cursor_visible);
+        assert!(matches!(
+            snapshot.main_cursor,
+            Some(CursorState { visible: true, .. })
+        ));
+        parser.parse(b"\x1b[?1049l", |action| {
+            apply_action_to_snapshot(action, &mut snapshot)
+        });
+        assert!(snapshot.cursor_visible);
+        assert!(matches!(
+            snapshot.cursor_shape,
+            CursorShape::SteadyUnderline
+        ));
+        assert!(snapshot.main_cursor.is_none());
+    }
+
+    fn registered_subscriber() -> (Arc<Mutex<Vec<Subscriber>>>, u64, UnixStream) {
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        let subscriber = Subscriber::new(server_stream);
+        let subscriber_id = subscriber.id;
+        let subscribers = Arc::new(Mutex::new(vec![subscriber]));
+        (subscribers, subscriber_id, client_stream)
+    }
+
+    #[test]
+    fn reconcile_sessions_finds_orphans_and_sleeping_agents() {
+        let names =
+            |names: &[&str]| -> Vec<String> { names.iter().map(|name| name.to_string()).collect() };
+        let report = reconcile_sessions(
+            names(&["wild-cat", "ghost-owl"]),
+            &names(&["wild-cat", "blue-fox"]),
+        );
+        assert_eq!(
+            report,
+            SessionsReport {
+                sessions: names(&["ghost-owl", "wild-cat"]),
+                orphaned: names(&["ghost-owl"]),
+                without_session: names(&["blue-fox"]),
             }
-        }
-        DecPrivateModeCode::OriginMode => snapshot.origin_mode = enabled,
-        DecPrivateModeCode::AutoWrap => snapshot.wrap_mode = enabled,
-        DecPrivateModeCode::ClearAndEnableAlternateScreen
-        | DecPrivateModeCode::EnableAlternateScreen
-        | DecPrivateModeCode::OptEnableAlternateScreen => snapshot.alt_screen = enabled,
-        DecPrivateModeCode::MouseTracking => snapshot.mouse_tracking = enabled,
-        DecPrivateModeCode::ButtonEventMouse => snapshot.mouse_button_tracking = enabled,
-        DecPrivateModeCode::AnyEventMouse => snapshot.mouse_any_event = enabled,
-        DecPrivateModeCode::SGRMouse => snapshot.mouse_sgr = enabled,
-        _ => {}
+        );
+
+        let matched = reconcile_sessions(names(&["wild-cat"]), &names(&["wild-cat"]));
+        assert!(matched.orphaned.is_empty());
+        assert!(matched.without_session.is_empty());
     }
-}
 
-fn apply_terminal_mode(mode: TerminalMode, snapshot: &mut TerminalSnapshot, enabled: bool) {
-    let code = match mode {
-        TerminalMode::Code(code) => code,
-        TerminalMode::Unspecified(code) => {
-            set_mode_entry(&mut snapshot.terminal_modes, code, enabled);
-            return;
-        }
-    };
-    if let Some(value) = code.to_u16() {
-        set_mode_entry(&mut snapshot.terminal_modes, value, enabled);
+    #[test]
+    fn broker_reports_deleted_agents_as_not_found() {
+        let (server_stream, mut client) = UnixStream::pair().unwrap();
+        let db = Arc::new(tokio::sync::Mutex::new(database_with_agents("demo", 0)));
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        client.write_all(b"INPUT ghost 1\nkATTACH ghost\n").unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+
+        handle_pty_connection(server_stream, sessions.clone(), db).unwrap();
+        let mut replies = String::new();
+        client.read_to_string(&mut replies).unwrap();
+
+        assert_eq!(replies, "ERR agent not found\nERR agent not found\n");
+        assert!(sessions.lock().unwrap().is_empty());
     }
-    match code {
-        TerminalModeCode::Insert => snapshot.insert_mode = enabled,
-        TerminalModeCode::ShowCursor => snapshot.cursor_visible = enabled,
-        _ => {}
+
+    #[test]
+    fn broker_history_leaves_a_sleeping_agent_asleep() {
+        let agent = format!("sleepy-logs-{}", std::process::id());
+        let conn = database_with_agents("demo", 0);
+        conn.execute(
+            "INSERT INTO agents (name, label, repo, tool, status, worktree_path, created_at, updated_at) VALUES (?1, ?1, 'demo', 'claude', 'sleep', '/tmp', '', '')",
+            params![agent],
+        )
+        .unwrap();
+        let db = Arc::new(tokio::sync::Mutex::new(conn));
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+
+        let (server_stream, mut client) = UnixStream::pair().unwrap();
+        client
+            .write_all(format!("HISTORY {agent}\n").as_bytes())
+            .unwrap();
+        client.shutdown(std::net::Shutdown::Write).unwrap();
+        handle_pty_connection(server_stream, sessions.clone(), db.clone()).unwrap();
+        let mut reply = String::new();
+        client.read_to_string(&mut reply).unwrap();
+
+        assert_eq!(reply, "ERR no history for agent\n");
+        assert!(sessions.lock().unwrap().is_empty());
+        let status: String = db
+            .blocking_lock()
+            .query_row(
+                "SELECT status FROM agents WHERE name = ?1",
+                params![agent],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(status, "sleep");
     }
-}
 
-fn apply_sgr_to_snapshot(sgr: Sgr, snapshot: &mut TerminalSnapshot) {
-    match sgr {
-        Sgr::Reset => snapshot.attributes = TerminalAttributes::default(),
-        Sgr::Intensity(value) => {
-            snapshot.attributes.intensity = match value {
-                termwiz::cell::Intensity::Normal => TerminalIntensity::Normal,
-                termwiz::cell::Intensity::Bold => TerminalIntensity::Bold,
-                termwiz::cell::Intensity::Half => TerminalIntensity::Faint,
-            };
-        }
-        Sgr::Underline(value) => {
-            snapshot.attributes.underline = match value {
-                termwiz::cell::Underline::None => TerminalUnderline::None,
-                termwiz::cell::Underline::Single => TerminalUnderline::Single,
-                termwiz::cell::Underline::Double => TerminalUnderline::Double,
-                _ => TerminalUnderline::Single,
-            };
-        }
-        Sgr::Blink(value) => {
-            snapshot.attributes.blink = match value {
-                termwiz::cell::Blink::None => TerminalBlink::None,
-                termwiz::cell::Blink::Slow => TerminalBlink::Slow,
-                termwiz::cell::Blink::Rapid => TerminalBlink::Rapid,
-            };
-        }
-        Sgr::Italic(value) => snapshot.attributes.italic = value,
-        Sgr::Inverse(value) => snapshot.attributes.inverse = value,
-        Sgr::Invisible(value) => snapshot.attributes.hidden = value,
-        Sgr::StrikeThrough(value) => snapshot.attributes.strikethrough = value,
-        Sgr::Foreground(color) => {
-            snapshot.attributes.foreground = color_to_snapshot(color.into());
-        }
-        Sgr::Background(color) => {
-            snapshot.attributes.background = color_to_snapshot(color.into());
-        }
-        _ => {}
+    #[test]
+    fn flushed_history_is_read_without_a_session() {
+        let dir = std::env::temp_dir().join(format!("workforest-history-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        assert_eq!(read_agent_history("wild-cat", &sessions, &dir), Ok(None));
+        std::fs::write(history_file_path(&dir, "wild-cat"), b"done\r\n").unwrap();
+        assert_eq!(
+            read_agent_history("wild-cat", &sessions, &dir),
+            Ok(Some(b"done\r\n".to_vec()))
+        );
+        let _ = std::fs::remove_dir_all(&dir);
     }
-}
 
-fn color_to_snapshot(color: termwiz::color::ColorAttribute) -> TerminalColor {
-    match color {
-        termwiz::color::ColorAttribute::Default => TerminalColor::Default,
-        termwiz::color::ColorAttribute::TrueColorWithDefaultFallback(color)
-        | termwiz::color::ColorAttribute::TrueColorWithPaletteFallback(color, _) => {
-            let (r, g, b, _) = color.as_rgba_u8();
-            TerminalColor::Rgb { r, g, b }
-        }
-        termwiz::color::ColorAttribute::PaletteIndex(index) => TerminalColor::Ansi(index),
+    #[tokio::test]
+    async fn pinned_port_rebinds_while_connections_linger() {
+        let socket = http_listener_socket(0).unwrap();
+        assert!(socket.reuseaddr().unwrap());
+        let listener = socket.listen(HTTP_LISTEN_BACKLOG).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // Closing the accepted side first leaves it in TIME_WAIT.
+        let client = tokio::net::TcpStream::connect(("127.0.0.1", port))
+            .await
+            .unwrap();
+        let (accepted, _) = listener.accept().await.unwrap();
+        drop(accepted);
+        drop(client);
+        drop(listener);
+
+        let socket = http_listener_socket(port).unwrap();
+        assert_eq!(socket.local_addr().unwrap().port(), port);
+        socket.listen(HTTP_LISTEN_BACKLOG).unwrap();
     }
-}
 
-fn set_mode_entry(entries: &mut Vec<ModeEntry>, code: u16, enabled: bool) {
-    if let Some(entry) = entries.iter_mut().find(|entry| entry.code == code) {
-        entry.enabled = enabled;
-    } else {
-        entries.push(ModeEntry { code, enabled });
+    #[test]
+    fn fresh_session_replaces_the_running_one() {
+        let conn = Connection::open_in_memory().unwrap();
+        create_schema(&conn).unwrap();
+        conn.execute(
+            "INSERT INTO agents (name, label, repo, tool, status, worktree_path, created_at, updated_at) VALUES ('wild-cat', 'wild-cat', 'repo', 'sleep 30', 'running', '/tmp', '', '')",
+            [],
+        )
+        .unwrap();
+        let db = Arc::new(tokio::sync::Mutex::new(conn));
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        let pid = |sessions: &Arc<Mutex<HashMap<String, PtySession>>>| {
+            sessions.lock().unwrap()["wild-cat"].child.process_id()
+        };
+
+        ensure_pty_session("wild-cat", &db, &sessions).unwrap();
+        let first = pid(&sessions);
+        // Without the stop, ensure keeps the session it already has.
+        ensure_pty_session("wild-cat", &db, &sessions).unwrap();
+        assert_eq!(pid(&sessions), first);
+
+        fresh_pty_session("wild-cat", &db, &sessions).unwrap();
+        let second = pid(&sessions);
+        assert!(second.is_some());
+        assert_ne!(second, first);
+
+        stop_pty_session("wild-cat", &sessions);
+        assert!(sessions.lock().unwrap().is_empty());
     }
-}
 
-fn delete_worktree(
-    repo_path: &Path,
-    worktree_path: &Path,
-    agent_name: &str,
-) -> Result<(), ApiError> {
-    if worktree_path.exists() {
-        let status = Command::new("git")
-            .arg("-C")
-            .arg(repo_path)
-            .args(["worktree", "remove", "-f"])
-            .arg(worktree_path)
-            .stdout(Stdio::null())
-            .stderr(Stdio::null())
-            .status()
-            .map_err(|err| ApiError::internal(err.to_string()))?;
+    #[test]
+    fn exited_sessions_are_reaped_with_their_exit_code() {
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        start_tool_session(
+            "wild-cat",
+            &shell_launch("exit 3"),
+            Path::new("/tmp"),
+            None,
+            &sessions,
+        )
+        .unwrap();
+        start_tool_session(
+            "calm-owl",
+            &shell_launch("sleep 30"),
+            Path::new("/tmp"),
+            None,
+            &sessions,
+        )
+        .unwrap();
 
-        if !status.success() {
-            return Err(ApiError::internal("git worktree remove failed"));
+        let deadline = Instant::now() + Duration::from_secs(5);
+        let mut exited = Vec::new();
+        while exited.is_empty() && Instant::now() < deadline {
+            std::thread::sleep(Duration::from_millis(20));
+            exited = reap_exited_sessions(&sessions);
         }
+
+        assert_eq!(exited, vec![("wild-cat".to_string(), 3)]);
+        assert!(!sessions.lock().unwrap().contains_key("wild-cat"));
+        assert!(sessions.lock().unwrap().contains_key("calm-owl"));
+        stop_pty_session("calm-owl", &sessions);
     }
 
-    let branch_name = format!("agent/{}", to_kebab(agent_name));
-    let _ = Command::new("git")
-        .arg("-C")
-        .arg(repo_path)
-        .args(["branch", "-D", &branch_name])
-        .stdout(Stdio::null())
-        .stderr(Stdio::null())
-        .status();
+    #[test]
+    fn recorded_exits_map_to_idle_or_error() {
+        let conn = database_with_agents("demo", 2);
+        record_session_exit(&conn, "agent-0", 0).unwrap();
+        record_session_exit(&conn, "agent-1", 127).unwrap();
 
-    if worktree_path.exists() {
-        std::fs::remove_dir_all(worktree_path)
-            .map_err(|err| ApiError::internal(err.to_string()))?;
+        let stored = stored_agent_statuses(&conn).unwrap();
+        assert_eq!(
+            stored,
+            vec![
+                ("agent-0".to_string(), "idle".to_string()),
+                ("agent-1".to_string(), "error".to_string()),
+            ]
+        );
+        let code: Option<u32> = conn
+            .query_row(
+                "SELECT exit_code FROM agents WHERE name = 'agent-1'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(code, Some(127));
+
+        assert_eq!(agent_live_status(false, "error"), "error");
+        assert_eq!(agent_live_status(true, "error"), "running");
+        assert_eq!(agent_live_status(false, "running"), "sleep");
     }
 
-    Ok(())
-}
+    #[test]
+    fn closed_subscribers_are_pruned() {
+        let (open_server, _open_client) = UnixStream::pair().unwrap();
+        let (closed_server, closed_client) = UnixStream::pair().unwrap();
+        drop(closed_client);
+        let open = Subscriber::new(open_server);
+        let open_id = open.id;
+        let mut subscribers = vec![open, Subscriber::new(closed_server)];
 
-fn to_kebab(value: &str) -> String {
-    value.trim().to_lowercase().replace([' ', '_'], "-")
-}
+        prune_closed_subscribers(&mut subscribers);
 
-fn write_metadata(addr: SocketAddr) -> Result<(), Box<dyn Error>> {
-    let config_dir = workforest_core::config_dir();
-    std::fs::create_dir_all(&config_dir)?;
+        assert_eq!(subscribers.len(), 1);
+        assert_eq!(subscribers[0].id, open_id);
+    }
 
-    let metadata = ServerMetadata {
-        pid: std::process::id(),
-        port: addr.port(),
-    };
+    #[test]
+    fn failed_attach_handoff_drops_subscriber() {
+        let (subscribers, subscriber_id, client_stream) = registered_subscriber();
+        let (broker_side, tui_side) = UnixStream::pair().unwrap();
+        drop(tui_side);
 
-    let metadata_path = config_dir.join("server.json");
-    let data = serde_json::to_string_pretty(&metadata)?;
-    std::fs::write(metadata_path, data)?;
+        let result = complete_attach(
+            &broker_side,
+            &subscribers,
+            subscriber_id,
+            &default_terminal_snapshot(),
+            b"history",
+            &client_stream,
+        );
 
-    Ok(())
-}
+        assert!(result.is_err());
+        assert!(subscribers.lock().unwrap().is_empty());
+    }
 
-fn remove_metadata() {
-    let metadata_path = workforest_core::config_dir().join("server.json");
-    let _ = std::fs::remove_file(metadata_path);
-}
+    #[test]
+    fn successful_attach_handoff_keeps_subscriber() {
+        let (subscribers, subscriber_id, client_stream) = registered_subscriber();
+        let (broker_side, _tui_side) = UnixStream::pair().unwrap();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::VecDeque;
-    use std::path::PathBuf;
+        let result = complete_attach(
+            &broker_side,
+            &subscribers,
+            subscriber_id,
+            &default_terminal_snapshot(),
+            b"history",
+            &client_stream,
+        );
 
-    fn repo_named(name: &str) -> RepoConfig {
-        RepoConfig {
-            name: name.to_string(),
-            path: PathBuf::from("/tmp"),
-            tools: Vec::new(),
-            default_tool: String::new(),
-        }
+        assert!(result.is_ok());
+        assert_eq!(subscribers.lock().unwrap().len(), 1);
     }
 
     #[test]
-    fn repo_name_uses_base_when_unique() {
-        let repos = vec![repo_named("other")];
-        let name = generate_repo_name_with_suffix("demo", &repos, || "suffix".to_string());
-        assert_eq!(name, "demo");
+    fn detach_removes_only_the_named_subscriber() {
+        let sessions = Arc::new(Mutex::new(HashMap::new()));
+        start_tool_session(
+            "wild-cat",
+            &shell_launch("sleep 30"),
+            Path::new("/tmp"),
+            None,
+            &sessions,
+        )
+        .unwrap();
+        let subscribers = sessions.lock().unwrap()["wild-cat"].subscribers.clone();
+        let (first, _first_client) = UnixStream::pair().unwrap();
+        let (second, _second_client) = UnixStream::pair().unwrap();
+        let first = Subscriber::new(first);
+        let first_id = first.id;
+        let second = Subscriber::new(second);
+        let second_id = second.id;
+        subscribers.lock().unwrap().extend([first, second]);
+
+        assert!(detach_subscriber("wild-cat", first_id, &sessions));
+        assert!(!detach_subscriber("wild-cat", first_id, &sessions));
+        assert!(!detach_subscriber("calm-owl", second_id, &sessions));
+        let remaining: Vec<u64> = subscribers.lock().unwrap().iter().map(|s| s.id).collect();
+        assert_eq!(remaining, vec![second_id]);
+
+        stop_pty_session("wild-cat", &sessions);
     }
 
     #[test]
-    fn repo_name_uses_suffix_on_collision() {
-        let repos = vec![repo_named("demo")];
-        let mut suffixes = VecDeque::from(vec!["alpha".to_string()]);
-        let name = generate_repo_name_with_suffix("demo", &repos, || suffixes.pop_front().unwrap());
-        assert_eq!(name, "demo-alpha");
+    fn tool_availability_checks_path() {
+        let dir = std::env::temp_dir().join(format!("workforest-tools-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let claude = dir.join("claude");
+        std::fs::write(&claude, "#!/bin/sh\n").unwrap();
+        std::fs::set_permissions(&claude, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let codex = dir.join("codex");
+        std::fs::write(&codex, "not executable").unwrap();
+        std::fs::set_permissions(&codex, std::fs::Permissions::from_mode(0o644)).unwrap();
+
+        let mut repo = repo_named("demo");
+        repo.tools = vec![
+            ToolSpec::from("opencode"),
+            ToolSpec::from("claude --model opus"),
+            ToolSpec::from("codex"),
+        ];
+        let mut other = repo_named("other");
+        other.tools = vec![ToolSpec::from("codex")];
+        let path = std::env::join_paths([&dir]).unwrap();
+        let tools = tool_availability(&[repo, other], Some(path.as_os_str()));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let summary: Vec<(&str, bool)> = tools
+            .iter()
+            .map(|tool| (tool.name.as_str(), tool.available))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("claude --model opus", true),
+                ("codex", false),
+                ("opencode", false),
+            ]
+        );
     }
 
     #[test]
-    fn repo_name_rerolls_on_suffix_collision() {
-        let repos = vec![repo_named("demo"), repo_named("demo-alpha")];
-        let mut suffixes = VecDeque::from(vec!["alpha".to_string(), "bravo".to_string()]);
-        let name = generate_repo_name_with_suffix("demo", &repos, || suffixes.pop_front().unwrap());
-        assert_eq!(name, "demo-bravo");
+    fn repo_tools_replace_the_list_and_default() {
+        let mut repo = repo_named("demo");
+        let request = |tools: &[&str], default_tool: &str| RepoToolsRequest {
+            tools: tools.iter().map(|tool| ToolSpec::from(*tool)).collect(),
+            default_tool: default_tool.to_string(),
+        };
+
+        apply_repo_tools(
+            &mut repo,
+            request(&[" aider ", "./bin/agent.sh", "aider"], "aider"),
+        )
+        .unwrap();
+        let names = |repo: &RepoConfig| -> Vec<String> {
+            repo.tools.iter().map(|tool| tool.name.clone()).collect()
+        };
+        assert_eq!(names(&repo), vec!["aider", "./bin/agent.sh"]);
+        assert_eq!(repo.default_tool, "aider");
+
+        for (tools, default_tool) in [
+            (&["aider"][..], "claude"),
+            (&[][..], ""),
+            (&["aider", " "][..], "aider"),
+        ] {
+            let err = apply_repo_tools(&mut repo, request(tools, default_tool)).unwrap_err();
+            assert_eq!(err.status, StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(names(&repo), vec!["aider", "./bin/agent.sh"]);
+    }
+
+    #[test]
+    fn worktree_path_uses_configured_root() {
+        let mut repo = repo_named("demo");
+        repo.path = PathBuf::from("/src/demo");
+
+        assert_eq!(worktree_root(&repo), data_dir().join("trees"));
+
+        repo.worktree_root = Some(PathBuf::from("/fast/trees"));
+        let root = worktree_root(&repo);
+        assert_eq!(
+            worktree_path_for(&root, &repo.name, "Wild Cat"),
+            PathBuf::from("/fast/trees/demo-wild-cat")
+        );
+        assert_eq!(
+            worktree_path_for(&root, &repo.name, "demo-wild-cat"),
+            PathBuf::from("/fast/trees/demo-wild-cat")
+        );
+
+        repo.worktree_root = Some(PathBuf::from("../trees"));
+        assert_eq!(worktree_root(&repo), PathBuf::from("/src/demo/../trees"));
     }
 
     #[test]
@@ -1292,6 +5944,71 @@ mod tests {
         assert_eq!(start, 5);
     }
 
+    #[test]
+    fn flushed_history_matches_buffer() {
+        let dir = std::env::temp_dir().join(format!("workforest-flush-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut history: VecDeque<u8> = b"world\x1b[0m".iter().copied().collect();
+        for byte in b"hello ".iter().rev() {
+            history.push_front(*byte);
+        }
+
+        let path = history_file_path(&dir, "Wild Cat");
+        let bytes = flush_history_file(&path, &history).unwrap();
+        let on_disk = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(bytes, history.len());
+        assert_eq!(on_disk, history.iter().copied().collect::<Vec<u8>>());
+        assert!(path.ends_with("Wild%20Cat.log"));
+        assert_ne!(
+            history_file_path(&dir, "my agent"),
+            history_file_path(&dir, "my-agent")
+        );
+        assert!(history_file_path(&dir, "a/b").ends_with("a%2Fb.log"));
+    }
+
+    #[test]
+    fn timing_index_round_trips() {
+        let start = Instant::now();
+        let mut timings = HistoryTimings::new(start);
+        timings.record(5, start, 5);
+        timings.record(7, start + Duration::from_millis(40), 12);
+        timings.record(3, start + Duration::from_millis(1250), 10);
+
+        let index = timings.index_for_history(10);
+        assert_eq!(
+            index,
+            vec![
+                TimingEntry {
+                    offset: 0,
+                    elapsed_ms: 40,
+                },
+                TimingEntry {
+                    offset: 7,
+                    elapsed_ms: 1250,
+                },
+            ]
+        );
+        assert_eq!(parse_timing_index(&encode_timing_index(&index)), index);
+    }
+
+    #[test]
+    fn flush_without_timings_writes_only_history() {
+        let dir = std::env::temp_dir().join(format!("workforest-untimed-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let history: VecDeque<u8> = b"hello".iter().copied().collect();
+
+        let bytes = flush_agent_history(&dir, "Wild Cat", &history, None).unwrap();
+        let on_disk = std::fs::read(history_file_path(&dir, "Wild Cat")).unwrap();
+        let timing_exists = timing_file_path(&dir, "Wild Cat").exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(bytes, 5);
+        assert_eq!(on_disk, b"hello");
+        assert!(!timing_exists);
+    }
+
     #[test]
     fn history_trim_allows_plain_cut() {
         let history = b"hello world";
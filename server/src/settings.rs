@@ -0,0 +1,217 @@
+use serde::Deserialize;
+use std::path::Path;
+use std::sync::OnceLock;
+use workforest_core::{flag_setting, server_config_path, ToolSpec};
+
+/// Server settings from `config.toml` in the config directory. Every field
+/// is optional in the file, and the `WORKFOREST_*` environment variables
+/// listed on [`Settings::apply_env`] override it.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    /// HTTP port on loopback; `0` picks an ephemeral one.
+    pub port: u16,
+    /// Output kept per session and replayed to clients that attach.
+    pub history_limit_bytes: usize,
+    /// Record when each chunk of output arrived, for timed replay.
+    pub timed_history: bool,
+    /// Default for repos that do not set `prefix_agent_names`.
+    pub prefix_agent_names: bool,
+    /// Age in days after which sleeping agents are pruned by
+    /// `/maintenance/prune`; unset leaves pruning to explicit requests.
+    pub retention_days: Option<i64>,
+    /// Tools newly added repos start with.
+    pub default_tools: Vec<ToolSpec>,
+    pub default_tool: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            port: 0,
+            history_limit_bytes: 2 * 1024 * 1024,
+            timed_history: false,
+            prefix_agent_names: false,
+            retention_days: None,
+            default_tools: ["opencode", "claude", "codex"]
+                .into_iter()
+                .map(ToolSpec::from)
+                .collect(),
+            default_tool: "opencode".to_string(),
+        }
+    }
+}
+
+static SETTINGS: OnceLock<Settings> = OnceLock::new();
+
+/// Loads the settings for this process. Called once at startup so a bad
+/// config file stops the server instead of being silently replaced.
+pub fn init() -> Result<&'static Settings, String> {
+    let settings = Settings::load(&server_config_path())?;
+    Ok(SETTINGS.get_or_init(|| settings))
+}
+
+/// The settings loaded by [`init`]; defaults when it was never called.
+pub fn settings() -> &'static Settings {
+    SETTINGS.get_or_init(Settings::default)
+}
+
+impl Settings {
+    /// Reads `path`, which may be missing, then applies the environment.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let mut settings = match std::fs::read_to_string(path) {
+            Ok(data) => Self::parse(&data).map_err(|err| format!("{}: {err}", path.display()))?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(err) => return Err(format!("{}: {err}", path.display())),
+        };
+        settings.apply_env(|key| std::env::var(key).ok())?;
+        Ok(settings)
+    }
+
+    fn parse(data: &str) -> Result<Self, String> {
+        let settings: Self = toml::from_str(data).map_err(|err| err.to_string())?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if !self
+            .default_tools
+            .iter()
+            .any(|tool| tool.name == self.default_tool)
+        {
+            return Err(format!(
+                "default_tool '{}' is not in default_tools",
+                self.default_tool
+            ));
+        }
+        if self.retention_days.is_some_and(|days| days < 0) {
+            return Err("retention_days must not be negative".to_string());
+        }
+        Ok(())
+    }
+
+    /// Overrides fields from `WORKFOREST_PORT`, `WORKFOREST_HISTORY_LIMIT_BYTES`,
+    /// `WORKFOREST_TIMED_HISTORY`, `WORKFOREST_PREFIX_AGENT_NAMES` and
+    /// `WORKFOREST_RETENTION_DAYS`. Numbers that do not parse are rejected
+    /// rather than ignored.
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        if let Some(value) = var("WORKFOREST_PORT") {
+            self.port = configured_port(Some(&value))?;
+        }
+        if let Some(value) = var("WORKFOREST_HISTORY_LIMIT_BYTES") {
+            self.history_limit_bytes = parse_number("WORKFOREST_HISTORY_LIMIT_BYTES", &value)?;
+        }
+        if let Some(value) = var("WORKFOREST_TIMED_HISTORY") {
+            self.timed_history = flag_setting(Some(&value), false);
+        }
+        if let Some(value) = var("WORKFOREST_PREFIX_AGENT_NAMES") {
+            self.prefix_agent_names = flag_setting(Some(&value), false);
+        }
+        if let Some(value) = var("WORKFOREST_RETENTION_DAYS") {
+            self.retention_days = match value.trim() {
+                "" => None,
+                _ => Some(parse_number("WORKFOREST_RETENTION_DAYS", &value)?),
+            };
+        }
+        self.validate()
+    }
+}
+
+/// Parses `WORKFOREST_PORT`. Unset, empty or `0` keeps an ephemeral port;
+/// anything else that is not a port is rejected rather than ignored.
+pub fn configured_port(value: Option<&str>) -> Result<u16, String> {
+    match value.map(str::trim) {
+        None | Some("") => Ok(0),
+        Some(value) => value
+            .parse()
+            .map_err(|_| format!("invalid WORKFOREST_PORT: {value}")),
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(name: &str, value: &str) -> Result<T, String> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid {name}: {value}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn env(pairs: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = pairs
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned()
+    }
+
+    #[test]
+    fn partial_config_keeps_defaults_for_the_rest() {
+        let settings = Settings::parse("port = 4180\nretention_days = 30\n").unwrap();
+        assert_eq!(
+            settings,
+            Settings {
+                port: 4180,
+                retention_days: Some(30),
+                ..Settings::default()
+            }
+        );
+        assert_eq!(Settings::parse("").unwrap(), Settings::default());
+
+        let settings = Settings::parse(
+            "default_tool = \"aider\"\ndefault_tools = [\"aider\", { name = \"opus\", command = \"claude\" }]\n",
+        )
+        .unwrap();
+        assert_eq!(settings.default_tools[0], ToolSpec::from("aider"));
+        assert_eq!(settings.default_tools[1].command.as_deref(), Some("claude"));
+    }
+
+    #[test]
+    fn invalid_config_is_rejected() {
+        assert!(Settings::parse("port = \"http\"\n").is_err());
+        assert!(Settings::parse("default_tool = \"aider\"\n").is_err());
+        assert!(Settings::parse("retention_days = -1\n").is_err());
+    }
+
+    #[test]
+    fn environment_overrides_the_file() {
+        let mut settings =
+            Settings::parse("port = 4180\ntimed_history = true\nretention_days = 30\n").unwrap();
+        settings
+            .apply_env(env(&[
+                ("WORKFOREST_PORT", "5000"),
+                ("WORKFOREST_TIMED_HISTORY", "0"),
+                ("WORKFOREST_PREFIX_AGENT_NAMES", "yes"),
+                ("WORKFOREST_HISTORY_LIMIT_BYTES", "1024"),
+                ("WORKFOREST_RETENTION_DAYS", ""),
+            ]))
+            .unwrap();
+        assert_eq!(settings.port, 5000);
+        assert!(!settings.timed_history);
+        assert!(settings.prefix_agent_names);
+        assert_eq!(settings.history_limit_bytes, 1024);
+        assert_eq!(settings.retention_days, None);
+
+        // Unset variables leave the file's values alone.
+        let mut settings = Settings::parse("port = 4180\n").unwrap();
+        settings.apply_env(env(&[])).unwrap();
+        assert_eq!(settings.port, 4180);
+
+        assert!(settings
+            .apply_env(env(&[("WORKFOREST_RETENTION_DAYS", "soon")]))
+            .is_err());
+    }
+
+    #[test]
+    fn configured_port_defaults_to_ephemeral() {
+        assert_eq!(configured_port(None), Ok(0));
+        assert_eq!(configured_port(Some(" ")), Ok(0));
+        assert_eq!(configured_port(Some("4180")), Ok(4180));
+        assert!(configured_port(Some("http")).is_err());
+        assert!(configured_port(Some("70000")).is_err());
+    }
+}
@@ -56,7 +56,7 @@ impl EventLoop {
         let poller = Poller::new()?;
         let events = Events::new();
         let stdin_fd = io::stdin().as_raw_fd();
-        set_nonblocking(stdin_fd)?;
+        set_nonblocking(stdin_fd, true)?;
 
         let (sigwinch_read, sigwinch_write) = create_nonblocking_pipe()?;
         register(SIGWINCH, sigwinch_write)?;
@@ -110,19 +110,24 @@ impl EventLoop {
         Ok(self.queue.pop_front())
     }
 
+    /// Puts stdin back into blocking mode so a foreground child process can
+    /// read it normally, dropping any input queued for the TUI.
+    pub fn suspend(&mut self) -> io::Result<()> {
+        self.queue.clear();
+        set_nonblocking(self.stdin_fd, false)
+    }
+
+    pub fn resume(&mut self) -> io::Result<()> {
+        set_nonblocking(self.stdin_fd, true)?;
+        drain_pipe(self.sigwinch_read)
+    }
+
     fn read_stdin_events(&mut self) -> io::Result<Vec<UIEvent>> {
         let raw = read_pipe(self.stdin_fd)?;
         if raw.is_empty() {
             return Ok(Vec::new());
         }
-        let events = self.parser.parse_as_vec(&raw, false);
-        Ok(events
-            .into_iter()
-            .map(|event| UIEvent {
-                raw: raw.clone(),
-                event,
-            })
-            .collect())
+        Ok(parse_ui_events(&mut self.parser, raw))
     }
 
     fn handle_sigwinch(&mut self) -> io::Result<Vec<UIEvent>> {
@@ -151,13 +156,49 @@ impl Drop for EventLoop {
     }
 }
 
-fn set_nonblocking(fd: RawFd) -> io::Result<()> {
+/// Turns one read from stdin into events, each carrying the bytes it was
+/// parsed from. Only a bracketed paste (the host terminal is asked for DEC
+/// 2004) becomes a `Paste`; fast typing stays individual keystrokes. Bytes
+/// are fed one at a time so every event can be paired with its own input.
+pub fn parse_ui_events(parser: &mut InputParser, raw: Vec<u8>) -> Vec<UIEvent> {
+    let mut events = Vec::new();
+    let mut pending = Vec::new();
+    for byte in raw {
+        pending.push(byte);
+        parser.parse(
+            &[byte],
+            |event| {
+                events.push(UIEvent {
+                    raw: std::mem::take(&mut pending),
+                    event,
+                })
+            },
+            true,
+        );
+    }
+    // Resolve anything held back, e.g. a lone ESC at the end of the read.
+    parser.parse(
+        &[],
+        |event| {
+            events.push(UIEvent {
+                raw: std::mem::take(&mut pending),
+                event,
+            })
+        },
+        false,
+    );
+    events
+}
+
+fn set_nonblocking(fd: RawFd, enabled: bool) -> io::Result<()> {
     let flags = fcntl(fd, FcntlArg::F_GETFL).map_err(to_io_error)?;
-    let flags = OFlag::from_bits_truncate(flags) | OFlag::O_NONBLOCK;
+    let mut flags = OFlag::from_bits_truncate(flags);
+    flags.set(OFlag::O_NONBLOCK, enabled);
     fcntl(fd, FcntlArg::F_SETFL(flags)).map_err(to_io_error)?;
     Ok(())
 }
 
+#[allow(clippy::needless_return)]
 fn create_nonblocking_pipe() -> io::Result<(RawFd, RawFd)> {
     #[cfg(any(
         target_os = "linux",
@@ -188,8 +229,8 @@ fn create_nonblocking_pipe() -> io::Result<(RawFd, RawFd)> {
         let (read_fd, write_fd) = pipe().map_err(to_io_error)?;
         let read_fd = read_fd.into_raw_fd();
         let write_fd = write_fd.into_raw_fd();
-        set_nonblocking(read_fd)?;
-        set_nonblocking(write_fd)?;
+        set_nonblocking(read_fd, true)?;
+        set_nonblocking(write_fd, true)?;
         Ok((read_fd, write_fd))
     }
 }
@@ -1,7 +1,10 @@
 use crossterm::{
-    event::{DisableMouseCapture, EnableMouseCapture},
+    clipboard::CopyToClipboard,
+    event::{DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    terminal::{
+        disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen, SetTitle,
+    },
 };
 use nix::fcntl::{fcntl, FcntlArg, OFlag};
 use nix::sys::socket::{recvmsg, ControlMessageOwned, MsgFlags, SockaddrStorage};
@@ -10,52 +13,57 @@ use ratatui::{
     layout::{Alignment, Constraint, Layout, Margin, Rect},
     style::{Color, Modifier, Style},
     text::{Line, Span},
-    widgets::{Block, Paragraph},
+    widgets::{Block, Clear, Paragraph},
     Terminal,
 };
 use std::io::IoSliceMut;
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
     error::Error,
     io::{self, Read, Write},
     os::fd::FromRawFd,
     os::unix::io::{AsRawFd, RawFd},
     os::unix::net::UnixStream,
-    path::PathBuf,
+    path::{Path, PathBuf},
+    process::Command,
     sync::{
         atomic::{AtomicBool, Ordering},
-        mpsc::{self, Receiver, Sender, TryRecvError},
+        mpsc::{self, Receiver, Sender, SyncSender, TryRecvError, TrySendError},
         Arc,
     },
     thread,
     time::{Duration, Instant},
 };
-use termwiz::cell::{AttributeChange, CellAttributes};
-use termwiz::color::ColorAttribute;
+use termwiz::cell::{AttributeChange, Cell, CellAttributes};
+use termwiz::color::{ColorAttribute, SrgbaTuple};
 use termwiz::escape::csi::{
     Cursor, CursorStyle, DecPrivateMode, DecPrivateModeCode, Edit, EraseInDisplay, EraseInLine,
-    Mode, Sgr, TerminalMode, TerminalModeCode, CSI,
+    Mode, Sgr, TabulationClear, TerminalMode, TerminalModeCode, Window, CSI,
 };
 use termwiz::escape::esc::EscCode;
-use termwiz::escape::osc::OperatingSystemCommand;
-use termwiz::escape::{parser::Parser, Action, ControlCode, Esc};
+use termwiz::escape::osc::{
+    ColorOrQuery, DynamicColorNumber, ITermProprietary, OperatingSystemCommand,
+};
+use termwiz::escape::{parser::Parser, Action, ControlCode, DeviceControlMode, Esc, KittyImage};
 use termwiz::surface::{Change, Line as TermwizLine, Position as TermwizPosition, Surface};
 
 use termwiz::input::{InputEvent, KeyCode, KeyEvent, Modifiers, MouseButtons, MouseEvent};
 
 mod event;
+mod replay;
 mod theme;
 mod windows;
 
-use event::EventLoop;
+use event::{EventLoop, UIEvent};
 
 use reqwest::blocking::Client;
 use serde::{Deserialize, Serialize};
-use theme::{ICON_ACTIVE, ICON_ERROR, ICON_IDLE, THEME};
+use theme::{status_icons, THEME};
 use windows::{handle_window_key_event, render_window, WindowId};
 use workforest_core::{
-    data_dir, CursorShape, RepoConfig, ScrollRegion, TerminalAttributes, TerminalBlink,
-    TerminalColor, TerminalIntensity, TerminalSnapshot, TerminalUnderline,
+    data_dir, encode_agent_token, flag_setting, CursorShape, RepoConfig, ScrollRegion,
+    TerminalAttributes, TerminalBlink, TerminalColor, TerminalIntensity, TerminalSnapshot,
+    TerminalUnderline, ToolKind, ToolSpec,
 };
 
 #[allow(dead_code)]
@@ -69,6 +77,10 @@ struct Agent {
     worktree_path: String,
     output: Option<String>,
     #[serde(default)]
+    dirty_count: Option<usize>,
+    #[serde(default)]
+    exit_code: Option<u32>,
+    #[serde(default)]
     debug_data: DebugData,
 }
 
@@ -92,6 +104,8 @@ struct HistoryDebug {
 #[derive(Serialize)]
 struct AddRepoRequest {
     path: String,
+    name: Option<String>,
+    dry_run: bool,
 }
 
 #[derive(Deserialize)]
@@ -99,6 +113,19 @@ struct AgentOutput {
     name: String,
     status: String,
     output: Option<String>,
+    #[serde(default)]
+    exit_code: Option<u32>,
+    /// Lines printed since the offset the poll asked from.
+    #[serde(default)]
+    lines: Vec<String>,
+    #[serde(default)]
+    offset: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct ToolAvailability {
+    name: String,
+    available: bool,
 }
 
 #[derive(Serialize)]
@@ -118,16 +145,72 @@ struct RestartAgentTarget {
     label: String,
 }
 
+struct RenameAgentTarget {
+    name: String,
+    label: String,
+}
+
+struct MergeAgentTarget {
+    name: String,
+    label: String,
+}
+
+#[derive(Serialize)]
+struct MergeRequest {
+    ff_only: bool,
+}
+
+#[derive(Deserialize)]
+struct AgentMerge {
+    into: String,
+    sha: String,
+    fast_forward: bool,
+}
+
+#[derive(Serialize)]
+struct SignalRequest {
+    signal: String,
+}
+
+#[derive(Deserialize)]
+struct AgentDiff {
+    diff: String,
+    truncated: bool,
+}
+
+struct DiffView {
+    name: String,
+    label: String,
+    lines: Vec<String>,
+    truncated: bool,
+    stat: bool,
+    scroll: usize,
+    page: usize,
+}
+
 enum DeleteAgentAction {
     Cancel,
     Delete,
 }
 
+#[derive(Debug, PartialEq, Eq)]
 enum RestartAgentAction {
     Cancel,
     Restart,
 }
 
+#[derive(Debug, PartialEq, Eq)]
+enum MergeAgentAction {
+    Cancel,
+    Merge,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum AddRepoStep {
+    Path,
+    Name,
+}
+
 enum AgentField {
     Repo,
     Name,
@@ -136,28 +219,214 @@ enum AgentField {
 }
 
 const SCROLLBACK_LIMIT: usize = 5000;
+/// Chunks (of up to 4 KiB) queued between a PTY reader and the UI before
+/// the reader waits for the UI to catch up.
+const PTY_CHANNEL_CHUNKS: usize = 64;
+/// Bytes parsed per view per frame, so a flooding view cannot starve drawing.
+const PTY_PUMP_BUDGET_BYTES: usize = 256 * 1024;
+const RESIZE_DEBOUNCE: Duration = Duration::from_millis(75);
+const IMAGE_PLACEHOLDER: &str = "[image]";
+const CELL_WIDTH_PX: usize = 8;
+const CELL_HEIGHT_PX: usize = 16;
+/// Status messages kept for the message log (`L`); older ones are dropped.
+const STATUS_LOG_CAPACITY: usize = 50;
+/// How long a status message stays on screen as a toast.
+const STATUS_TOAST_DURATION: Duration = Duration::from_secs(4);
+/// Toasts shown at once; the newest win.
+const STATUS_TOAST_LIMIT: usize = 3;
+/// Lines kept in the combined tail feed (`t`); older ones are dropped.
+const TAIL_FEED_CAPACITY: usize = 1000;
+/// How often agent output is polled while the tail window is open.
+const TAIL_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+struct StatusEntry {
+    message: String,
+    at: Instant,
+    /// Hidden from the toast stack but still listed in the log.
+    dismissed: bool,
+    /// Times the message was pushed in a row.
+    count: usize,
+}
+
+impl StatusEntry {
+    /// The message as shown, with a `×N` suffix once it has repeated.
+    fn text(&self) -> String {
+        if self.count > 1 {
+            format!("{} ×{}", self.message, self.count)
+        } else {
+            self.message.clone()
+        }
+    }
+}
+
+/// Recent status messages, newest last. New messages are appended so a
+/// burst of them can still be read back after the toasts fade; a message
+/// repeating the newest one bumps its count instead.
+#[derive(Default)]
+struct StatusLog {
+    entries: VecDeque<StatusEntry>,
+}
+
+impl StatusLog {
+    fn push(&mut self, message: String, now: Instant) {
+        if let Some(last) = self.entries.back_mut() {
+            if last.message == message {
+                last.count += 1;
+                last.at = now;
+                last.dismissed = false;
+                return;
+            }
+        }
+        if self.entries.len() == STATUS_LOG_CAPACITY {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(StatusEntry {
+            message,
+            at: now,
+            dismissed: false,
+            count: 1,
+        });
+    }
+
+    /// Clears the toast stack without forgetting the messages.
+    fn dismiss(&mut self) {
+        for entry in &mut self.entries {
+            entry.dismissed = true;
+        }
+    }
+
+    #[cfg(test)]
+    fn latest(&self) -> Option<&str> {
+        self.entries.back().map(|entry| entry.message.as_str())
+    }
+
+    /// Messages still young enough to show, oldest first, each with how
+    /// far through its display time it is (0.0 new, 1.0 about to expire).
+    fn toasts(&self, now: Instant) -> Vec<(String, f32)> {
+        let mut toasts: Vec<(String, f32)> = self
+            .entries
+            .iter()
+            .rev()
+            .filter(|entry| !entry.dismissed)
+            .map(|entry| (entry, now.saturating_duration_since(entry.at)))
+            .take_while(|(_, age)| *age < STATUS_TOAST_DURATION)
+            .take(STATUS_TOAST_LIMIT)
+            .map(|(entry, age)| {
+                let progress = age.as_secs_f32() / STATUS_TOAST_DURATION.as_secs_f32();
+                (entry.text(), progress)
+            })
+            .collect();
+        toasts.reverse();
+        toasts
+    }
+}
+
+struct TailLine {
+    agent: String,
+    label: String,
+    text: String,
+}
+
+/// Merged feed of the lines running agents print, oldest first. Each poll
+/// asks the server for the lines every agent printed past the output offset
+/// the previous poll returned.
+#[derive(Default)]
+struct TailFeed {
+    lines: VecDeque<TailLine>,
+    /// Output offset each agent's last poll returned.
+    offsets: HashMap<String, u64>,
+}
+
+impl TailFeed {
+    /// Adds one poll's worth of `(agent, label, new lines, offset)`. New
+    /// lines from different agents are interleaved one at a time so a
+    /// chatty agent does not bury the others under a block of its own output.
+    fn ingest(&mut self, polls: &[(&str, &str, &[String], u64)]) {
+        let mut pending: Vec<(usize, VecDeque<String>)> = Vec::new();
+        for (index, (agent, _, lines, offset)) in polls.iter().enumerate() {
+            pending.push((index, lines.iter().cloned().collect()));
+            self.offsets.insert(agent.to_string(), *offset);
+        }
+        while pending.iter().any(|(_, fresh)| !fresh.is_empty()) {
+            for (index, fresh) in &mut pending {
+                let Some(text) = fresh.pop_front() else {
+                    continue;
+                };
+                let (agent, label, _, _) = polls[*index];
+                if self.lines.len() == TAIL_FEED_CAPACITY {
+                    self.lines.pop_front();
+                }
+                self.lines.push_back(TailLine {
+                    agent: agent.to_string(),
+                    label: label.to_string(),
+                    text,
+                });
+            }
+        }
+    }
+
+    /// Forgets the offsets of agents that stopped running, so output after
+    /// a restart is read from its start.
+    fn retain_agents(&mut self, running: &std::collections::HashSet<&str>) {
+        self.offsets
+            .retain(|agent, _| running.contains(agent.as_str()));
+    }
+
+    /// The `since` query of the next poll, e.g. `wild-cat:120,calm-owl:0`.
+    fn since_query(&self) -> String {
+        let mut entries: Vec<String> = self
+            .offsets
+            .iter()
+            .map(|(agent, offset)| format!("{agent}:{offset}"))
+            .collect();
+        entries.sort();
+        entries.join(",")
+    }
+}
 
 struct App {
     server_url: String,
     client: Client,
     agents: Vec<Agent>,
     repos: Vec<RepoConfig>,
+    tool_availability: HashMap<String, bool>,
     windows: Vec<WindowId>,
     focused_window: Option<WindowId>,
     input: String,
+    repo_name_input: String,
+    add_repo_step: AddRepoStep,
     agent_name_input: String,
     agent_filter_input: String,
     selected_repo: usize,
+    /// Highlighted entry in the repos window (`l`).
+    repo_list_selected: usize,
+    /// The repos window is asking to confirm deleting the highlighted repo.
+    confirming_repo_delete: bool,
     selected_tool: usize,
     selected_agent: usize,
     agent_scroll: usize,
+    wrap_navigation: bool,
+    sidebar_density: SidebarDensity,
+    mouse_capture: bool,
+    faint_blend: bool,
+    /// Cursor blink half-period; `None` keeps the cursor steady.
+    cursor_blink: Option<Duration>,
     agent_field: AgentField,
-    status_message: Option<String>,
+    status_log: StatusLog,
+    tail_feed: TailFeed,
+    /// Persistent banner for config writes the server could not make; stays
+    /// up until a later write succeeds.
+    config_error: Option<String>,
     animation_start: Instant,
     delete_agent: Option<DeleteAgentTarget>,
     delete_agent_action: DeleteAgentAction,
+    rename_agent: Option<RenameAgentTarget>,
+    rename_input: String,
     restart_agent: Option<RestartAgentTarget>,
     restart_agent_action: RestartAgentAction,
+    merge_agent: Option<MergeAgentTarget>,
+    merge_agent_action: MergeAgentAction,
+    diff_view: Option<DiffView>,
     pty_socket_path: PathBuf,
     pty_views: HashMap<String, PtyView>,
     pending_pty: HashMap<String, PendingPtyAttach>,
@@ -167,6 +436,94 @@ struct App {
     preview_area: Option<Rect>,
     preview_agent: Option<String>,
     debug_sidebar: bool,
+    /// Columns of the debug sidebar; `<` and `>` resize it.
+    debug_sidebar_width: u16,
+    /// Widest the debug sidebar fits at, from the last draw.
+    debug_sidebar_limit: Option<u16>,
+    /// Debug lines scrolled off the top with `[` and `]`, clamped on render.
+    debug_scroll: usize,
+    /// Sidebar filter typed after `/`; see `agent_matches_filter`.
+    agent_list_filter: String,
+    editing_agent_list_filter: bool,
+    /// Debug override: preview the screen the agent is not using.
+    show_inactive_surface: bool,
+    /// Command to run in the foreground with the TUI suspended, picked up
+    /// by the main loop.
+    pending_command: Option<ExternalCommand>,
+    /// Text to copy to the host clipboard via OSC 52 on the next loop turn.
+    pending_clipboard: Option<String>,
+    /// Mirror the focused agent's window title onto the outer terminal.
+    forward_title: bool,
+    /// `/` search in the focused agent's scrollback.
+    preview_search: Option<PreviewSearch>,
+    /// First `g` of a `gg` jump to the top of the preview.
+    pending_g: bool,
+}
+
+/// A search through an agent's scrollback and screen, started with `/`
+/// while the focused agent is scrolled back.
+struct PreviewSearch {
+    agent: String,
+    query: String,
+    /// The query is still being typed in the footer.
+    editing: bool,
+    /// The match last jumped to.
+    current: Option<SearchMatch>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct SearchMatch {
+    /// Index into `PtyView::preview_lines`.
+    line: usize,
+    /// Cell columns covered, end exclusive.
+    start: usize,
+    end: usize,
+}
+
+/// Agent sidebar layout: `Comfortable` gives each agent a padded name and
+/// repo block, `Compact` a single line so small terminals fit more agents.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum SidebarDensity {
+    Comfortable,
+    Compact,
+}
+
+impl SidebarDensity {
+    /// Parses `WORKFOREST_SIDEBAR_DENSITY`; only `compact` changes the default.
+    fn from_setting(value: Option<&str>) -> Self {
+        match value.map(str::trim) {
+            Some(value) if value.eq_ignore_ascii_case("compact") => SidebarDensity::Compact,
+            _ => SidebarDensity::Comfortable,
+        }
+    }
+
+    fn entry_height(self) -> usize {
+        match self {
+            SidebarDensity::Comfortable => 4,
+            SidebarDensity::Compact => 1,
+        }
+    }
+
+    fn toggled(self) -> Self {
+        match self {
+            SidebarDensity::Comfortable => SidebarDensity::Compact,
+            SidebarDensity::Compact => SidebarDensity::Comfortable,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum WorktreeTool {
+    Shell,
+    Editor,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct ExternalCommand {
+    program: String,
+    args: Vec<String>,
+    cwd: PathBuf,
+    env: Vec<(String, String)>,
 }
 
 struct PtyView {
@@ -175,6 +532,10 @@ struct PtyView {
     alt_surface: Surface,
     use_alt_screen: bool,
     mouse_tracking: bool,
+    /// DEC 2004: the agent wants pastes wrapped in `CSI 200~`/`CSI 201~`.
+    bracketed_paste: bool,
+    /// DECCKM: arrows go to the agent as `ESC O A` rather than `ESC [ A`.
+    application_cursor_keys: bool,
     mouse_sgr: bool,
     saved_cursor_main: Option<(usize, usize)>,
     saved_cursor_alt: Option<(usize, usize)>,
@@ -185,6 +546,56 @@ struct PtyView {
     scroll_region: Option<(usize, usize)>,
     scrollback: Vec<TermwizLine>,
     scroll_offset: usize,
+    resize_debounce: ResizeDebounce,
+    ended: bool,
+    origin_mode: bool,
+    pending_wrap: bool,
+    attributes: CellAttributes,
+    /// Jump back to the tail on new output even when scrolled up.
+    follow: bool,
+    tab_stops: BTreeSet<usize>,
+    insert_mode: bool,
+    /// Default foreground and background set by the agent with OSC 10/11.
+    default_foreground: Option<SrgbaTuple>,
+    default_background: Option<SrgbaTuple>,
+    /// The broker's id for this view's output stream, released on drop.
+    subscription: Option<BrokerSubscription>,
+    /// Connection reused for this agent's INPUT, RESIZE and DETACH.
+    control: BrokerControl,
+    /// Window title last set by the agent with OSC 0/2.
+    title: Option<String>,
+}
+
+struct BrokerSubscription {
+    socket_path: PathBuf,
+    id: u64,
+}
+
+#[derive(Default)]
+struct ResizeDebounce {
+    pending: Option<((u16, u16), Instant)>,
+}
+
+impl ResizeDebounce {
+    fn request(&mut self, size: (u16, u16), now: Instant) {
+        match self.pending {
+            Some((pending, _)) if pending == size => {}
+            _ => self.pending = Some((size, now)),
+        }
+    }
+
+    fn cancel(&mut self) {
+        self.pending = None;
+    }
+
+    fn take_ready(&mut self, now: Instant) -> Option<(u16, u16)> {
+        let (size, requested_at) = self.pending?;
+        if now.duration_since(requested_at) < RESIZE_DEBOUNCE {
+            return None;
+        }
+        self.pending = None;
+        Some(size)
+    }
 }
 
 struct PtyReader {
@@ -192,38 +603,82 @@ struct PtyReader {
     handle: Option<thread::JoinHandle<()>>,
 }
 
+/// Whether an attach reuses the agent's session or has the broker replace
+/// it first (`FRESH`), for recovering from a stuck or mis-sized session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum AttachMode {
+    Reuse,
+    Fresh,
+}
+
+impl AttachMode {
+    fn command(self) -> &'static str {
+        match self {
+            AttachMode::Reuse => "ATTACH",
+            AttachMode::Fresh => "FRESH",
+        }
+    }
+}
+
 struct PendingPtyAttach {
     size: (u16, u16),
 }
 
 struct AttachResult {
     agent: String,
-    result: Result<(PtyView, HistoryDebug, TerminalSnapshot), String>,
+    result: Result<AttachedView, String>,
     size: (u16, u16),
 }
 
+struct AttachedView {
+    view: PtyView,
+    history_debug: HistoryDebug,
+    snapshot: TerminalSnapshot,
+    /// Why the broker's MODES payload was ignored, when it was malformed.
+    modes_error: Option<String>,
+}
+
+struct AttachResponse {
+    fd: RawFd,
+    subscriber_id: Option<u64>,
+    history: Vec<u8>,
+    snapshot: TerminalSnapshot,
+    modes_error: Option<String>,
+}
+
 fn main() -> Result<(), Box<dyn Error>> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "--replay") {
+        return replay::run(&args[1..]);
+    }
+
     let server_url =
         std::env::var("WORKFOREST_SERVER_URL").unwrap_or_else(|_| "http://127.0.0.1:0".to_string());
 
+    let mut app = App::new(server_url);
+
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+    execute!(stdout, EnterAlternateScreen, EnableBracketedPaste)?;
+    let mut mouse_captured = app.mouse_capture;
+    set_mouse_capture(&mut stdout, mouse_captured)?;
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     let mut event_loop = EventLoop::new()?;
 
-    let mut app = App::new(server_url);
     app.refresh_data();
     let mut last_refresh = Instant::now();
+    let mut last_tail_poll = last_refresh;
     let mut actions = Vec::new();
     let mut dirty = true;
-    let mut last_blink_on =
-        !app.focused_agent.is_some() || (app.animation_start.elapsed().as_millis() / 700) % 2 == 0;
+    let mut last_blink_on = app.focused_agent.is_none()
+        || cursor_blink_on(app.animation_start.elapsed(), app.cursor_blink);
+    // Title currently forwarded to the outer terminal, if any.
+    let mut outer_title: Option<String> = None;
 
     'main_loop: loop {
-        let blink_on = !app.focused_agent.is_some()
-            || (app.animation_start.elapsed().as_millis() / 700) % 2 == 0;
+        let blink_on = app.focused_agent.is_none()
+            || cursor_blink_on(app.animation_start.elapsed(), app.cursor_blink);
         if blink_on != last_blink_on {
             last_blink_on = blink_on;
             dirty = true;
@@ -231,6 +686,14 @@ fn main() -> Result<(), Box<dyn Error>> {
         if last_refresh.elapsed() >= Duration::from_secs(5) {
             app.refresh_data();
             last_refresh = Instant::now();
+            last_tail_poll = last_refresh;
+            dirty = true;
+        }
+        if app.focused_window == Some(WindowId::Tail)
+            && last_tail_poll.elapsed() >= TAIL_POLL_INTERVAL
+        {
+            app.refresh_tail();
+            last_tail_poll = Instant::now();
             dirty = true;
         }
 
@@ -240,6 +703,38 @@ fn main() -> Result<(), Box<dyn Error>> {
         if app.handle_attach_results() {
             dirty = true;
         }
+        if app.check_focused_agent() {
+            dirty = true;
+        }
+        if app.apply_pending_resizes() {
+            dirty = true;
+        }
+
+        if app.mouse_capture != mouse_captured {
+            set_mouse_capture(terminal.backend_mut(), app.mouse_capture)?;
+            mouse_captured = app.mouse_capture;
+        }
+
+        if let Some(command) = app.pending_command.take() {
+            if let Err(err) =
+                run_suspended(&mut terminal, &mut event_loop, mouse_captured, &command)?
+            {
+                app.set_status(err);
+            }
+            dirty = true;
+        }
+
+        if let Some(text) = app.pending_clipboard.take() {
+            execute!(
+                terminal.backend_mut(),
+                CopyToClipboard::to_clipboard_from(text)
+            )?;
+        }
+
+        if let Some(update) = title_update(app.forwarded_title(), outer_title.as_deref()) {
+            outer_title =
+                apply_title_update(terminal.backend_mut(), update, outer_title.is_some())?;
+        }
 
         if dirty {
             terminal.draw(|frame| draw(frame, &mut app))?;
@@ -250,17 +745,32 @@ fn main() -> Result<(), Box<dyn Error>> {
         if let Some(ui_event) = event_loop.poll(poll_timeout)? {
             let mut handled = false;
             if app.focused_agent.is_some() {
+                let held_g = app.pending_g;
                 if let InputEvent::Key(ref key) = ui_event.event {
                     if key.key == KeyCode::Char('d') && key.modifiers.contains(Modifiers::CTRL) {
                         app.focused_agent = None;
+                        app.pending_g = false;
+                        handled = true;
+                        dirty = true;
+                    } else if app.search_focused_view(key) || app.scroll_focused_view(key) {
                         handled = true;
                         dirty = true;
                     }
                 }
                 if !handled && !ui_event.raw.is_empty() {
                     if let Some(agent) = app.focused_agent.clone() {
-                        if let Err(err) = send_input(&app.pty_socket_path, &agent, &ui_event.raw) {
-                            app.set_status(err);
+                        app.pending_g = false;
+                        let view = app.pty_views.get_mut(&agent);
+                        let payload = focused_input_payload(view.as_deref(), &ui_event, held_g);
+                        let mut detached = BrokerControl::default();
+                        let control = match view {
+                            Some(view) => &mut view.control,
+                            None => &mut detached,
+                        };
+                        if let Err(err) =
+                            send_input(control, &app.pty_socket_path, &agent, &payload)
+                        {
+                            app.report_broker_error(&agent, err);
                         }
                     }
                     handled = true;
@@ -281,6 +791,19 @@ fn main() -> Result<(), Box<dyn Error>> {
                         }
                         dirty = true;
                     }
+                    InputEvent::Paste(text) => {
+                        // Outside an agent a paste is typed key by key, e.g.
+                        // into the add-repo path.
+                        let mut parser = termwiz::input::InputParser::new();
+                        for event in parser.parse_as_vec(text.as_bytes(), false) {
+                            if let InputEvent::Key(key) = event {
+                                if handle_key_event(&mut app, key)? {
+                                    break 'main_loop;
+                                }
+                            }
+                        }
+                        dirty = true;
+                    }
                     InputEvent::Resized { .. } => {
                         dirty = true;
                     }
@@ -294,16 +817,186 @@ fn main() -> Result<(), Box<dyn Error>> {
     }
 
     disable_raw_mode()?;
+    if mouse_captured {
+        set_mouse_capture(terminal.backend_mut(), false)?;
+    }
+    if outer_title.is_some() {
+        apply_title_update(terminal.backend_mut(), TitleUpdate::Restore, true)?;
+    }
     execute!(
         terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
+        DisableBracketedPaste,
+        LeaveAlternateScreen
     )?;
     terminal.show_cursor()?;
 
     Ok(())
 }
 
+#[derive(Debug, PartialEq, Eq)]
+enum TitleUpdate {
+    Set(String),
+    Restore,
+}
+
+/// What to do to the outer terminal's title given the title that should
+/// be showing and the one forwarded last.
+fn title_update(desired: Option<&str>, current: Option<&str>) -> Option<TitleUpdate> {
+    match (desired, current) {
+        (Some(desired), Some(current)) if desired == current => None,
+        (Some(desired), _) => Some(TitleUpdate::Set(desired.to_string())),
+        (None, Some(_)) => Some(TitleUpdate::Restore),
+        (None, None) => None,
+    }
+}
+
+/// Applies a title update, returning the title now forwarded. The user's
+/// own title is saved on the xterm title stack (`CSI 22 t`) before the first
+/// change and popped (`CSI 23 t`) to restore it, since it cannot be read.
+fn apply_title_update(
+    out: &mut impl Write,
+    update: TitleUpdate,
+    forwarding: bool,
+) -> io::Result<Option<String>> {
+    match update {
+        TitleUpdate::Set(title) => {
+            if !forwarding {
+                out.write_all(b"\x1b[22;0t")?;
+            }
+            execute!(out, SetTitle(&title))?;
+            Ok(Some(title))
+        }
+        TitleUpdate::Restore => {
+            out.write_all(b"\x1b[23;0t")?;
+            out.flush()?;
+            Ok(None)
+        }
+    }
+}
+
+/// Hands the terminal to `command` until it exits, then restores the TUI.
+fn run_suspended(
+    terminal: &mut Terminal<CrosstermBackend<io::Stdout>>,
+    event_loop: &mut EventLoop,
+    mouse_captured: bool,
+    command: &ExternalCommand,
+) -> Result<Result<(), String>, Box<dyn Error>> {
+    disable_raw_mode()?;
+    if mouse_captured {
+        set_mouse_capture(terminal.backend_mut(), false)?;
+    }
+    execute!(
+        terminal.backend_mut(),
+        DisableBracketedPaste,
+        LeaveAlternateScreen
+    )?;
+    terminal.show_cursor()?;
+    event_loop.suspend()?;
+
+    let result = Command::new(&command.program)
+        .args(&command.args)
+        .current_dir(&command.cwd)
+        .envs(command.env.iter().map(|(key, value)| (key, value)))
+        .status()
+        .map_err(|err| format!("failed to run {}: {err}", command.program))
+        .and_then(|status| {
+            if status.success() {
+                Ok(())
+            } else {
+                Err(format!("{} exited with {status}", command.program))
+            }
+        });
+
+    event_loop.resume()?;
+    enable_raw_mode()?;
+    execute!(
+        terminal.backend_mut(),
+        EnterAlternateScreen,
+        EnableBracketedPaste
+    )?;
+    if mouse_captured {
+        set_mouse_capture(terminal.backend_mut(), true)?;
+    }
+    terminal.clear()?;
+    Ok(result)
+}
+
+/// Builds the command that opens `worktree` in a shell or editor. The
+/// command comes from `WORKFOREST_SHELL_COMMAND` / `WORKFOREST_EDITOR_COMMAND`
+/// when set, otherwise `$SHELL` / `$EDITOR`; editors are given `.` to open.
+fn worktree_command(
+    tool: WorktreeTool,
+    agent: &str,
+    worktree: &Path,
+    var: impl Fn(&str) -> Option<String>,
+) -> ExternalCommand {
+    let (override_var, standard_var, fallback) = match tool {
+        WorktreeTool::Shell => ("WORKFOREST_SHELL_COMMAND", "SHELL", "/bin/sh"),
+        WorktreeTool::Editor => ("WORKFOREST_EDITOR_COMMAND", "EDITOR", "vi"),
+    };
+    let configured = [override_var, standard_var]
+        .into_iter()
+        .filter_map(&var)
+        .find(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| fallback.to_string());
+    let mut words = configured.split_whitespace().map(str::to_string);
+    let program = words.next().unwrap_or_else(|| fallback.to_string());
+    let mut args: Vec<String> = words.collect();
+    if tool == WorktreeTool::Editor {
+        args.push(".".to_string());
+    }
+    ExternalCommand {
+        program,
+        args,
+        cwd: worktree.to_path_buf(),
+        env: vec![("WORKFOREST_AGENT".to_string(), agent.to_string())],
+    }
+}
+
+fn set_mouse_capture(out: &mut impl io::Write, enabled: bool) -> io::Result<()> {
+    if enabled {
+        execute!(out, EnableMouseCapture)
+    } else {
+        execute!(out, DisableMouseCapture)
+    }
+}
+
+const DEFAULT_CURSOR_BLINK: Duration = Duration::from_millis(700);
+const DEFAULT_DEBUG_SIDEBAR_WIDTH: u16 = 32;
+const MIN_DEBUG_SIDEBAR_WIDTH: u16 = 16;
+
+/// Parses `WORKFOREST_DEBUG_SIDEBAR_WIDTH`; unparseable values fall back to
+/// the default and narrow ones are raised to the minimum.
+fn debug_sidebar_width(value: Option<&str>) -> u16 {
+    value
+        .and_then(|value| value.trim().parse().ok())
+        .unwrap_or(DEFAULT_DEBUG_SIDEBAR_WIDTH)
+        .max(MIN_DEBUG_SIDEBAR_WIDTH)
+}
+
+/// Parses `WORKFOREST_CURSOR_BLINK_MS`; `0` disables blinking and anything
+/// unparseable falls back to the default cadence.
+fn cursor_blink_interval(value: Option<&str>) -> Option<Duration> {
+    match value.map(|value| value.trim().parse::<u64>()) {
+        Some(Ok(0)) => None,
+        Some(Ok(millis)) => Some(Duration::from_millis(millis)),
+        _ => Some(DEFAULT_CURSOR_BLINK),
+    }
+}
+
+fn cursor_blink_on(elapsed: Duration, interval: Option<Duration>) -> bool {
+    match interval {
+        Some(interval) => (elapsed.as_millis() / interval.as_millis()).is_multiple_of(2),
+        None => true,
+    }
+}
+
+/// Mouse capture is on unless `WORKFOREST_MOUSE_CAPTURE` turns it off, which
+/// gives the outer terminal back its native selection.
+fn mouse_capture_enabled(value: Option<&str>) -> bool {
+    flag_setting(value, true)
+}
+
 impl App {
     fn new(server_url: String) -> Self {
         let (attach_sender, attach_receiver) = mpsc::channel();
@@ -312,6 +1005,7 @@ impl App {
             client: Client::new(),
             agents: Vec::new(),
             repos: Vec::new(),
+            tool_availability: HashMap::new(),
             windows: vec![
                 WindowId::Root,
                 WindowId::AddRepo,
@@ -319,22 +1013,52 @@ impl App {
                 WindowId::ShowRepos,
                 WindowId::DeleteAgent,
                 WindowId::RestartAgent,
+                WindowId::MergeAgent,
+                WindowId::Diff,
+                WindowId::StatusLog,
+                WindowId::RenameAgent,
+                WindowId::Tail,
             ],
             focused_window: None,
             input: String::new(),
+            repo_name_input: String::new(),
+            add_repo_step: AddRepoStep::Path,
             agent_name_input: String::new(),
             agent_filter_input: String::new(),
             selected_repo: 0,
+            repo_list_selected: 0,
+            confirming_repo_delete: false,
             selected_tool: 0,
             selected_agent: 0,
             agent_scroll: 0,
+            wrap_navigation: env_flag("WORKFOREST_WRAP_NAVIGATION"),
+            sidebar_density: SidebarDensity::from_setting(
+                std::env::var("WORKFOREST_SIDEBAR_DENSITY").ok().as_deref(),
+            ),
+            mouse_capture: mouse_capture_enabled(
+                std::env::var("WORKFOREST_MOUSE_CAPTURE").ok().as_deref(),
+            ),
+            faint_blend: flag_setting(
+                std::env::var("WORKFOREST_FAINT_BLEND").ok().as_deref(),
+                true,
+            ),
+            cursor_blink: cursor_blink_interval(
+                std::env::var("WORKFOREST_CURSOR_BLINK_MS").ok().as_deref(),
+            ),
             agent_field: AgentField::Repo,
-            status_message: None,
+            status_log: StatusLog::default(),
+            tail_feed: TailFeed::default(),
+            config_error: None,
             animation_start: Instant::now(),
             delete_agent: None,
             delete_agent_action: DeleteAgentAction::Cancel,
+            rename_agent: None,
+            rename_input: String::new(),
             restart_agent: None,
             restart_agent_action: RestartAgentAction::Cancel,
+            merge_agent: None,
+            merge_agent_action: MergeAgentAction::Cancel,
+            diff_view: None,
             pty_socket_path: data_dir().join("pty.sock"),
             pty_views: HashMap::new(),
             pending_pty: HashMap::new(),
@@ -342,8 +1066,23 @@ impl App {
             attach_receiver,
             focused_agent: None,
             preview_area: None,
+            debug_sidebar_limit: None,
             preview_agent: None,
             debug_sidebar: false,
+            debug_sidebar_width: debug_sidebar_width(
+                std::env::var("WORKFOREST_DEBUG_SIDEBAR_WIDTH")
+                    .ok()
+                    .as_deref(),
+            ),
+            debug_scroll: 0,
+            show_inactive_surface: false,
+            agent_list_filter: String::new(),
+            editing_agent_list_filter: false,
+            pending_command: None,
+            pending_clipboard: None,
+            forward_title: env_flag("WORKFOREST_FORWARD_TITLE"),
+            preview_search: None,
+            pending_g: false,
         }
     }
 
@@ -353,12 +1092,25 @@ impl App {
             .iter()
             .map(|agent| (agent.name.clone(), agent.debug_data.clone()))
             .collect();
-        self.repos = fetch_repos(&self.client, &self.server_url).unwrap_or_else(|err| {
-            self.status_message = Some(err);
-            Vec::new()
-        });
+        self.repos = match fetch_repos(&self.client, &self.server_url) {
+            Ok(repos) => repos,
+            Err(err @ FetchError::Transport(_)) => {
+                // The server is unreachable; keep showing the last known state
+                // rather than clearing the sidebar.
+                self.set_status(err.to_string());
+                return;
+            }
+            Err(err) => {
+                self.set_status(err.to_string());
+                Vec::new()
+            }
+        };
+        self.clamp_repo_selection();
+        if let Ok(tools) = fetch_tools(&self.client, &self.server_url) {
+            self.tool_availability = tools;
+        }
         self.agents = fetch_agents(&self.client, &self.server_url).unwrap_or_else(|err| {
-            self.status_message = Some(err);
+            self.set_status(err.to_string());
             Vec::new()
         });
         for agent in &mut self.agents {
@@ -371,20 +1123,10 @@ impl App {
         } else if self.selected_agent >= self.agents.len() {
             self.selected_agent = self.agents.len() - 1;
         }
-        match fetch_agents_output(&self.client, &self.server_url) {
-            Ok(outputs) => {
-                for agent in &mut self.agents {
-                    if let Some(entry) = outputs.get(&agent.name) {
-                        agent.status = entry.status.clone();
-                        agent.output = entry.output.clone();
-                    } else {
-                        agent.status = "sleep".to_string();
-                        agent.output = None;
-                    }
-                }
-            }
+        match fetch_agents_output(&self.client, &self.server_url, &self.tail_feed) {
+            Ok(outputs) => self.apply_agents_output(outputs),
             Err(err) => {
-                self.status_message = Some(err);
+                self.set_status(err.to_string());
                 for agent in &mut self.agents {
                     agent.status = "sleep".to_string();
                     agent.output = None;
@@ -403,48 +1145,263 @@ impl App {
         }
     }
 
-    fn set_status(&mut self, message: impl Into<String>) {
-        self.status_message = Some(message.into());
-    }
-
-    fn pump_pty_output(&mut self, actions: &mut Vec<Action>) -> bool {
-        let mut updated = false;
-        let mut status_error = None;
-        let socket_path = self.pty_socket_path.clone();
-        for view in self.pty_views.values_mut() {
-            loop {
-                let chunk = match view.receiver.try_recv() {
-                    Ok(chunk) => chunk,
-                    Err(TryRecvError::Empty) => break,
-                    Err(TryRecvError::Disconnected) => break,
-                };
-                actions.clear();
-                view.parser.parse(&chunk, |action| actions.push(action));
-                for action in actions.drain(..) {
-                    if let Some(reply) = apply_action_to_view(action, view) {
-                        if let Err(err) = send_input(&socket_path, &view.agent, &reply) {
-                            status_error = Some(err);
-                        }
-                    }
-                }
-                updated = true;
-            }
+    /// Keeps the add-agent selection in range after a refresh, since a
+    /// repo's tool list can be replaced while the window is open.
+    fn clamp_repo_selection(&mut self) {
+        if self.selected_repo >= self.repos.len() {
+            self.selected_repo = 0;
         }
-        if let Some(err) = status_error {
-            self.set_status(err);
+        if let Some(repo) = self.repos.get(self.selected_repo) {
+            if self.selected_tool >= repo.tools.len() {
+                self.selected_tool = default_tool_index(repo);
+            }
         }
-        updated
+        sync_filtered_selection(self);
     }
 
-    fn ensure_pty_view(&mut self, agent_name: &str, area: Rect) {
+    fn apply_agents_output(&mut self, outputs: HashMap<String, AgentOutput>) {
+        for agent in &mut self.agents {
+            if let Some(entry) = outputs.get(&agent.name) {
+                agent.status = entry.status.clone();
+                agent.output = entry.output.clone();
+                agent.exit_code = entry.exit_code;
+            } else {
+                agent.status = "sleep".to_string();
+                agent.output = None;
+                agent.exit_code = None;
+            }
+        }
+        let polls: Vec<(&str, &str, &[String], u64)> = self
+            .agents
+            .iter()
+            .filter_map(|agent| {
+                let entry = outputs.get(&agent.name)?;
+                let offset = entry.offset?;
+                Some((
+                    agent.name.as_str(),
+                    agent.label.as_str(),
+                    entry.lines.as_slice(),
+                    offset,
+                ))
+            })
+            .collect();
+        self.tail_feed.ingest(&polls);
+        let running = polls.iter().map(|(agent, _, _, _)| *agent).collect();
+        self.tail_feed.retain_agents(&running);
+    }
+
+    /// Polls just agent output, for the tail window's faster refresh.
+    fn refresh_tail(&mut self) {
+        match fetch_agents_output(&self.client, &self.server_url, &self.tail_feed) {
+            Ok(outputs) => self.apply_agents_output(outputs),
+            Err(err) => self.set_status(err.to_string()),
+        }
+    }
+
+    /// Scrolls the focused agent's scrollback for Shift+PgUp/PgDn/Home/End
+    /// on the main screen, and for plain PgUp/PgDn, `G` and `gg` once it is
+    /// scrolled back. Returns false when the key should go to the agent.
+    fn scroll_focused_view(&mut self, key: &KeyEvent) -> bool {
+        let Some(view) = self
+            .focused_agent
+            .as_ref()
+            .and_then(|name| self.pty_views.get_mut(name))
+        else {
+            return false;
+        };
+        // Plain paging keys go to agents that track the mouse, since they
+        // do their own scrolling; Shift+PgUp/PgDn/Home/End still scroll.
+        let scroll = focused_scroll_key(key, view.use_alt_screen).or_else(|| {
+            if view.scroll_offset > 0 && !view.use_alt_screen && !view.mouse_tracking {
+                preview_page_key(key, &mut self.pending_g)
+            } else {
+                self.pending_g = false;
+                None
+            }
+        });
+        match scroll {
+            Some(scroll) => {
+                view.scroll_by(scroll);
+                true
+            }
+            // The first `g` of `gg` is held back rather than typed.
+            None => self.pending_g,
+        }
+    }
+
+    /// Keys for `/` search in the focused agent, returning whether the key
+    /// was taken. `/` only starts a search while scrolled back, so typing it
+    /// into the agent still works; once a search is running `n` and `N` step
+    /// to older and newer matches and any other key ends it.
+    fn search_focused_view(&mut self, key: &KeyEvent) -> bool {
+        let Some(agent) = self.focused_agent.clone() else {
+            return false;
+        };
+        let Some(view) = self.pty_views.get_mut(&agent) else {
+            return false;
+        };
+        let ctrl = key.modifiers.contains(Modifiers::CTRL);
+        let Some(search) = self
+            .preview_search
+            .as_mut()
+            .filter(|search| search.agent == agent)
+        else {
+            if key.key == KeyCode::Char('/') && view.scroll_offset > 0 && !view.use_alt_screen {
+                self.preview_search = Some(PreviewSearch {
+                    agent,
+                    query: String::new(),
+                    editing: true,
+                    current: None,
+                });
+                return true;
+            }
+            return false;
+        };
+
+        let mut finished = false;
+        let mut status = None;
+        let handled = if search.editing {
+            match key.key {
+                KeyCode::Escape => finished = true,
+                KeyCode::Enter => {
+                    search.editing = false;
+                    let matches = view.search(&search.query);
+                    search.current = nearest_match(&matches, view.bottom_line());
+                    match search.current {
+                        Some(found) => view.reveal_line(found.line),
+                        None => {
+                            status = Some(format!("no matches for {}", search.query));
+                            finished = true;
+                        }
+                    }
+                }
+                KeyCode::Backspace => {
+                    search.query.pop();
+                }
+                KeyCode::Char(ch) if !ctrl => search.query.push(ch),
+                _ => {}
+            }
+            true
+        } else {
+            match key.key {
+                KeyCode::Char(ch @ ('n' | 'N')) if !ctrl => {
+                    let matches = view.search(&search.query);
+                    search.current = step_match(&matches, search.current, ch == 'n');
+                    if let Some(found) = search.current {
+                        view.reveal_line(found.line);
+                    }
+                    true
+                }
+                KeyCode::Escape => {
+                    finished = true;
+                    true
+                }
+                _ => {
+                    finished = true;
+                    false
+                }
+            }
+        };
+        if finished {
+            self.preview_search = None;
+        }
+        if let Some(status) = status {
+            self.set_status(status);
+        }
+        handled
+    }
+
+    /// The running search, if it belongs to `agent`.
+    fn search_for(&self, agent: &str) -> Option<&PreviewSearch> {
+        self.preview_search
+            .as_ref()
+            .filter(|search| search.agent == agent)
+    }
+
+    /// The focused agent's title, when it should be shown on the outer
+    /// terminal.
+    fn forwarded_title(&self) -> Option<&str> {
+        if !self.forward_title {
+            return None;
+        }
+        let agent = self.focused_agent.as_ref()?;
+        self.pty_views.get(agent)?.title.as_deref()
+    }
+
+    fn set_status(&mut self, message: impl Into<String>) {
+        self.status_log.push(message.into(), Instant::now());
+    }
+
+    /// Shows a failed request: unwritable-config errors go to the banner,
+    /// anything else to the status line.
+    fn report_request_error(&mut self, err: FetchError) {
+        match err {
+            FetchError::Http {
+                status: CONFIG_NOT_WRITABLE_STATUS,
+                message,
+            } => self.config_error = Some(message),
+            FetchError::Http { message, .. } if !message.is_empty() => self.set_status(message),
+            err => self.set_status(err.to_string()),
+        }
+    }
+
+    fn pump_pty_output(&mut self, actions: &mut Vec<Action>) -> bool {
+        let mut updated = false;
+        let mut status_error = None;
+        let mut ended = Vec::new();
+        let socket_path = self.pty_socket_path.clone();
+        for view in self.pty_views.values_mut() {
+            let mut drained = 0;
+            while drained < PTY_PUMP_BUDGET_BYTES {
+                let chunk = match view.receiver.try_recv() {
+                    Ok(chunk) => chunk,
+                    Err(TryRecvError::Empty) => break,
+                    Err(TryRecvError::Disconnected) => {
+                        if !view.ended {
+                            view.ended = true;
+                            ended.push(view.agent.clone());
+                            updated = true;
+                        }
+                        break;
+                    }
+                };
+                if view.follow {
+                    view.scroll_offset = 0;
+                }
+                drained += chunk.len();
+                actions.clear();
+                view.parser.parse(&chunk, |action| actions.push(action));
+                for action in actions.drain(..) {
+                    if let Some(reply) = apply_action_to_view(action, view) {
+                        if let Err(err) =
+                            send_input(&mut view.control, &socket_path, &view.agent, &reply)
+                        {
+                            status_error = Some(err);
+                        }
+                    }
+                }
+                updated = true;
+            }
+        }
+        if let Some(err) = status_error {
+            self.set_status(err);
+        }
+        for agent in ended {
+            if self.focused_agent.as_deref() == Some(agent.as_str()) {
+                self.focused_agent = None;
+                self.set_status(format!("session ended for {agent}"));
+            }
+        }
+        updated
+    }
+
+    fn ensure_pty_view(&mut self, agent_name: &str, area: Rect) {
         let size = (area.width.max(1), area.height.max(1));
         if let Some(view) = self.pty_views.get_mut(agent_name) {
             if view.last_size != size {
-                view.last_size = size;
-                view.resize(size);
-                if let Err(err) = send_resize(&self.pty_socket_path, &view.agent, size) {
-                    self.set_status(err);
-                }
+                view.resize_debounce.request(size, Instant::now());
+            } else {
+                view.resize_debounce.cancel();
             }
             return;
         }
@@ -452,17 +1409,55 @@ impl App {
             pending.size = size;
             return;
         }
-        self.start_pty_attach(agent_name, size);
+        self.start_pty_attach(agent_name, size, AttachMode::Reuse);
+    }
+
+    /// Drops the agent's view and reattaches to a brand-new session.
+    fn attach_fresh(&mut self, agent_name: &str) {
+        let size = self
+            .pty_views
+            .remove(agent_name)
+            .map(|view| view.last_size)
+            .or_else(|| self.pending_pty.get(agent_name).map(|pending| pending.size))
+            .or_else(|| {
+                self.preview_area
+                    .map(|area| (area.width.max(1), area.height.max(1)))
+            })
+            .unwrap_or((80, 24));
+        self.start_pty_attach(agent_name, size, AttachMode::Fresh);
+    }
+
+    fn apply_pending_resizes(&mut self) -> bool {
+        let now = Instant::now();
+        let mut updated = false;
+        let mut status_error = None;
+        for view in self.pty_views.values_mut() {
+            let Some(size) = view.resize_debounce.take_ready(now) else {
+                continue;
+            };
+            view.last_size = size;
+            view.resize(size);
+            if let Err(err) =
+                send_resize(&mut view.control, &self.pty_socket_path, &view.agent, size)
+            {
+                status_error = Some(err);
+            }
+            updated = true;
+        }
+        if let Some(err) = status_error {
+            self.set_status(err);
+        }
+        updated
     }
 
-    fn start_pty_attach(&mut self, agent_name: &str, size: (u16, u16)) {
+    fn start_pty_attach(&mut self, agent_name: &str, size: (u16, u16), mode: AttachMode) {
         let agent = agent_name.to_string();
         let socket_path = self.pty_socket_path.clone();
         let sender = self.attach_sender.clone();
         self.pending_pty
             .insert(agent.clone(), PendingPtyAttach { size });
         thread::spawn(move || {
-            let result = PtyView::attach(&socket_path, &agent, size);
+            let result = PtyView::attach(&socket_path, &agent, size, mode);
             let _ = sender.send(AttachResult {
                 agent,
                 result,
@@ -486,31 +1481,78 @@ impl App {
                 .map(|pending| pending.size)
                 .unwrap_or(result.size);
             match result.result {
-                Ok((mut view, history_debug, snapshot)) => {
+                Ok(attached) => {
                     if self.pty_views.contains_key(&result.agent) {
                         continue;
                     }
+                    let mut view = attached.view;
                     view.last_size = pending_size;
                     view.resize(pending_size);
                     self.update_agent_debug_on_attach(
                         &result.agent,
                         &view,
-                        history_debug,
-                        &snapshot,
+                        attached.history_debug,
+                        &attached.snapshot,
+                    );
+                    if let Some(err) = attached.modes_error {
+                        self.set_status(format!(
+                            "ignored malformed terminal modes for {}: {err}",
+                            result.agent
+                        ));
+                    }
+                    let resized = send_resize(
+                        &mut view.control,
+                        &self.pty_socket_path,
+                        &result.agent,
+                        pending_size,
                     );
                     self.pty_views.insert(result.agent.clone(), view);
-                    if let Err(err) =
-                        send_resize(&self.pty_socket_path, &result.agent, pending_size)
-                    {
-                        self.set_status(err);
+                    if let Err(err) = resized {
+                        self.report_broker_error(&result.agent, err);
                     }
                 }
-                Err(err) => self.set_status(err),
+                Err(err) => self.report_broker_error(&result.agent, err),
             }
         }
         updated
     }
 
+    /// Reports a failed broker command. An agent another client deleted is
+    /// dropped right away instead of at the next refresh, so input is not
+    /// left going to it.
+    fn report_broker_error(&mut self, agent: &str, err: String) {
+        if !is_agent_not_found(&err) {
+            self.set_status(err);
+            return;
+        }
+        if self.focused_agent.as_deref() == Some(agent) {
+            self.focused_agent = None;
+        }
+        self.pty_views.remove(agent);
+        self.pending_pty.remove(agent);
+        self.agents.retain(|entry| entry.name != agent);
+        if self.selected_agent >= self.agents.len() {
+            self.selected_agent = self.agents.len().saturating_sub(1);
+        }
+        self.set_status(format!("agent {agent} no longer exists"));
+    }
+
+    /// Checks the focused agent's outstanding broker replies. Returns true
+    /// when it turned out to be gone and focus was cleared.
+    fn check_focused_agent(&mut self) -> bool {
+        let Some(agent) = self.focused_agent.clone() else {
+            return false;
+        };
+        let missing = self
+            .pty_views
+            .get_mut(&agent)
+            .is_some_and(|view| view.control.agent_missing());
+        if missing {
+            self.report_broker_error(&agent, AGENT_NOT_FOUND.to_string());
+        }
+        missing
+    }
+
     fn update_agent_debug_on_attach(
         &mut self,
         agent_name: &str,
@@ -527,6 +1569,8 @@ impl App {
             snapshot.alt_screen = view.use_alt_screen;
             snapshot.mouse_tracking = view.mouse_tracking;
             snapshot.mouse_sgr = view.mouse_sgr;
+            snapshot.bracketed_paste = view.bracketed_paste;
+            snapshot.application_cursor_keys = view.application_cursor_keys;
             agent.debug_data.terminal_snapshot = Some(snapshot);
             agent.debug_data.history_on_attach = Some(history_debug);
         }
@@ -545,6 +1589,8 @@ impl App {
                 snapshot.alt_screen = view.use_alt_screen;
                 snapshot.mouse_tracking = view.mouse_tracking;
                 snapshot.mouse_sgr = view.mouse_sgr;
+                snapshot.bracketed_paste = view.bracketed_paste;
+                snapshot.application_cursor_keys = view.application_cursor_keys;
                 snapshot.scroll_region = view
                     .scroll_region
                     .map(|(top, bottom)| ScrollRegion { top, bottom });
@@ -565,39 +1611,71 @@ impl PtyView {
         socket_path: &PathBuf,
         agent_name: &str,
         size: (u16, u16),
-    ) -> Result<(Self, HistoryDebug, TerminalSnapshot), String> {
-        let (fd, history, snapshot) = request_attach(socket_path, agent_name)?;
-        let parser = Parser::new();
-        let main_surface = Surface::new(size.0 as usize, size.1 as usize);
-        let alt_surface = Surface::new(size.0 as usize, size.1 as usize);
-        let (reader, receiver) = PtyReader::spawn(fd)?;
+        mode: AttachMode,
+    ) -> Result<AttachedView, String> {
+        let response = request_attach(socket_path, agent_name, mode)?;
+        let (reader, receiver) = PtyReader::spawn(response.fd)?;
+        let history = response.history;
         let history_debug = history_debug_from_bytes(&history, "on attach");
-        let mut view = Self {
+        let mut view = Self::new(agent_name, size, receiver, reader);
+        view.subscription = response.subscriber_id.map(|id| BrokerSubscription {
+            socket_path: socket_path.clone(),
+            id,
+        });
+        apply_snapshot_to_view(&mut view, &response.snapshot);
+        if !history.is_empty() {
+            let mut actions = Vec::new();
+            view.parser.parse(&history, |action| actions.push(action));
+            for action in actions {
+                apply_action_to_view(action, &mut view);
+            }
+        }
+        Ok(AttachedView {
+            view,
+            history_debug,
+            snapshot: response.snapshot,
+            modes_error: response.modes_error,
+        })
+    }
+
+    fn new(
+        agent_name: &str,
+        size: (u16, u16),
+        receiver: Receiver<Vec<u8>>,
+        reader: PtyReader,
+    ) -> Self {
+        Self {
             agent: agent_name.to_string(),
-            main_surface,
-            alt_surface,
+            main_surface: Surface::new(size.0 as usize, size.1 as usize),
+            alt_surface: Surface::new(size.0 as usize, size.1 as usize),
             use_alt_screen: false,
             mouse_tracking: false,
+            bracketed_paste: false,
+            application_cursor_keys: false,
             mouse_sgr: false,
             saved_cursor_main: None,
             saved_cursor_alt: None,
-            parser,
+            parser: Parser::new(),
             receiver,
             _reader: reader,
             last_size: size,
             scroll_region: None,
             scrollback: Vec::new(),
             scroll_offset: 0,
-        };
-        apply_snapshot_to_view(&mut view, &snapshot);
-        if !history.is_empty() {
-            let mut actions = Vec::new();
-            view.parser.parse(&history, |action| actions.push(action));
-            for action in actions {
-                apply_action_to_view(action, &mut view);
-            }
+            resize_debounce: ResizeDebounce::default(),
+            ended: false,
+            origin_mode: false,
+            pending_wrap: false,
+            attributes: CellAttributes::default(),
+            follow: false,
+            tab_stops: default_tab_stops(size.0 as usize),
+            insert_mode: false,
+            default_foreground: None,
+            default_background: None,
+            subscription: None,
+            control: BrokerControl::default(),
+            title: None,
         }
-        Ok((view, history_debug, snapshot))
     }
 
     pub(crate) fn active_surface(&self) -> &Surface {
@@ -616,6 +1694,16 @@ impl PtyView {
         }
     }
 
+    /// The surface the preview draws: the active one, or with `inactive` the
+    /// other screen, e.g. the main screen while an app holds the alt screen.
+    pub(crate) fn preview_surface(&self, inactive: bool) -> &Surface {
+        if self.use_alt_screen != inactive {
+            &self.alt_surface
+        } else {
+            &self.main_surface
+        }
+    }
+
     fn active_saved_cursor_mut(&mut self) -> &mut Option<(usize, usize)> {
         if self.use_alt_screen {
             &mut self.saved_cursor_alt
@@ -624,13 +1712,14 @@ impl PtyView {
         }
     }
 
-    fn preview_lines(&self) -> Vec<std::borrow::Cow<'_, TermwizLine>> {
-        let height = self.active_surface().dimensions().1;
+    fn preview_lines(&self, inactive: bool) -> Vec<std::borrow::Cow<'_, TermwizLine>> {
+        let surface = self.preview_surface(inactive);
+        let height = surface.dimensions().1;
         let mut lines = Vec::with_capacity(self.scrollback.len() + height);
         for line in &self.scrollback {
             lines.push(std::borrow::Cow::Borrowed(line));
         }
-        lines.extend(self.active_surface().screen_lines());
+        lines.extend(surface.screen_lines());
         lines
     }
 
@@ -639,6 +1728,8 @@ impl PtyView {
             return;
         }
         self.scrollback.extend(lines.iter().cloned());
+        self.scroll_offset =
+            scroll_offset_after_output(self.scroll_offset, lines.len(), self.follow);
         if self.scrollback.len() > SCROLLBACK_LIMIT {
             let overflow = self.scrollback.len() - SCROLLBACK_LIMIT;
             self.scrollback.drain(0..overflow);
@@ -646,6 +1737,14 @@ impl PtyView {
         }
     }
 
+    fn toggle_follow(&mut self) -> bool {
+        self.follow = !self.follow;
+        if self.follow {
+            self.scroll_offset = 0;
+        }
+        self.follow
+    }
+
     fn clamp_scroll_offset(&mut self, height: usize) {
         let total_lines = self.scrollback.len().saturating_add(height);
         let max_offset = total_lines.saturating_sub(height);
@@ -654,7 +1753,59 @@ impl PtyView {
         }
     }
 
+    /// Every match of `query` in the scrollback and active screen, top to
+    /// bottom. Case is ignored.
+    fn search(&self, query: &str) -> Vec<SearchMatch> {
+        self.preview_lines(false)
+            .iter()
+            .enumerate()
+            .flat_map(|(line, text)| {
+                line_matches(text, query)
+                    .into_iter()
+                    .map(move |(start, end)| SearchMatch { line, start, end })
+            })
+            .collect()
+    }
+
+    /// Index in `preview_lines` of the last row on screen.
+    fn bottom_line(&self) -> usize {
+        let height = self.active_surface().dimensions().1;
+        (self.scrollback.len() + height)
+            .saturating_sub(self.scroll_offset)
+            .saturating_sub(1)
+    }
+
+    /// Scrolls just far enough to show `line` of `preview_lines`. Lines on
+    /// the live screen are shown by returning to the bottom.
+    fn reveal_line(&mut self, line: usize) {
+        let height = self.active_surface().dimensions().1;
+        let top = self.scrollback.len().saturating_sub(self.scroll_offset);
+        if line >= self.scrollback.len() {
+            self.scroll_offset = 0;
+        } else if line < top {
+            self.scroll_offset = self.scrollback.len() - line;
+        } else if line >= top + height {
+            self.scroll_offset = (self.scrollback.len() + height).saturating_sub(line + 1);
+        }
+        self.clamp_scroll_offset(height);
+    }
+
+    fn scroll_by(&mut self, scroll: PreviewScroll) {
+        let height = self.active_surface().dimensions().1;
+        let max_offset = self.scrollback.len();
+        self.scroll_offset = scrolled_offset(self.scroll_offset, max_offset, height, scroll);
+    }
+
     fn resize(&mut self, size: (u16, u16)) {
+        let (old_width, _) = self.main_surface.dimensions();
+        let new_width = size.0 as usize;
+        if new_width > old_width {
+            self.tab_stops.extend(
+                default_tab_stops(new_width)
+                    .into_iter()
+                    .filter(|column| *column >= old_width),
+            );
+        }
         self.main_surface.resize(size.0 as usize, size.1 as usize);
         self.alt_surface.resize(size.0 as usize, size.1 as usize);
         self.scroll_region = None;
@@ -662,11 +1813,24 @@ impl PtyView {
     }
 }
 
+impl Drop for PtyView {
+    fn drop(&mut self) {
+        if let Some(subscription) = &self.subscription {
+            let _ = send_detach(
+                &mut self.control,
+                &subscription.socket_path,
+                &self.agent,
+                subscription.id,
+            );
+        }
+    }
+}
+
 impl PtyReader {
     fn spawn(fd: RawFd) -> Result<(Self, Receiver<Vec<u8>>), String> {
         fcntl(fd, FcntlArg::F_SETFL(OFlag::O_NONBLOCK)).map_err(|err| err.to_string())?;
         let stop = Arc::new(AtomicBool::new(false));
-        let (sender, receiver) = mpsc::channel();
+        let (sender, receiver) = mpsc::sync_channel(PTY_CHANNEL_CHUNKS);
         let stop_thread = stop.clone();
         let handle = thread::spawn(move || read_pty_loop(fd, stop_thread, sender));
         Ok((
@@ -692,7 +1856,7 @@ impl Drop for PtyReader {
     }
 }
 
-fn read_pty_loop(fd: RawFd, stop: Arc<AtomicBool>, sender: Sender<Vec<u8>>) {
+fn read_pty_loop(fd: RawFd, stop: Arc<AtomicBool>, sender: SyncSender<Vec<u8>>) {
     let mut file = unsafe { std::fs::File::from_raw_fd(fd) };
     let mut buffer = [0u8; 4096];
     loop {
@@ -702,7 +1866,9 @@ fn read_pty_loop(fd: RawFd, stop: Arc<AtomicBool>, sender: Sender<Vec<u8>>) {
         match file.read(&mut buffer) {
             Ok(0) => break,
             Ok(size) => {
-                let _ = sender.send(buffer[..size].to_vec());
+                if !send_with_backpressure(&sender, buffer[..size].to_vec(), &stop) {
+                    break;
+                }
             }
             Err(err) if err.kind() == io::ErrorKind::WouldBlock => {
                 thread::sleep(Duration::from_millis(20));
@@ -712,29 +1878,74 @@ fn read_pty_loop(fd: RawFd, stop: Arc<AtomicBool>, sender: Sender<Vec<u8>>) {
     }
 }
 
+/// Queues a chunk for the UI, waiting while the channel is full so a view
+/// that outpaces drawing stops reading the PTY instead of buffering without
+/// bound. Returns false once the view is gone or the reader is stopping.
+fn send_with_backpressure(sender: &SyncSender<Vec<u8>>, chunk: Vec<u8>, stop: &AtomicBool) -> bool {
+    let mut chunk = chunk;
+    loop {
+        match sender.try_send(chunk) {
+            Ok(()) => return true,
+            Err(TrySendError::Full(rejected)) => {
+                if stop.load(Ordering::Relaxed) {
+                    return false;
+                }
+                chunk = rejected;
+                thread::sleep(Duration::from_millis(5));
+            }
+            Err(TrySendError::Disconnected(_)) => return false,
+        }
+    }
+}
+
 fn request_attach(
     socket_path: &PathBuf,
     agent: &str,
-) -> Result<(RawFd, Vec<u8>, TerminalSnapshot), String> {
+    mode: AttachMode,
+) -> Result<AttachResponse, String> {
     let mut stream = UnixStream::connect(socket_path).map_err(|err| err.to_string())?;
     stream
-        .write_all(format!("ATTACH {}\n", agent).as_bytes())
+        .write_all(format!("{} {}\n", mode.command(), encode_agent_token(agent)).as_bytes())
         .map_err(|err| err.to_string())?;
-    let snapshot = receive_modes(&mut stream)?;
+    let (snapshot, modes_error) = receive_modes(&mut stream)?;
     let history = receive_history(&mut stream)?;
-    let fd = receive_fd(&stream)?;
-    Ok((fd, history, snapshot))
+    let (fd, subscriber_id) = receive_fd(&stream)?;
+    Ok(AttachResponse {
+        fd,
+        subscriber_id,
+        history,
+        snapshot,
+        modes_error,
+    })
 }
 
-fn receive_modes(stream: &mut UnixStream) -> Result<TerminalSnapshot, String> {
+fn receive_modes(stream: &mut UnixStream) -> Result<(TerminalSnapshot, Option<String>), String> {
     let header = read_line_from_stream(stream, "modes header")?;
     let mut parts = header.splitn(2, ' ');
     let label = parts.next().unwrap_or("");
     if label != "MODES" {
         return Err(format!("unexpected response: {label}"));
     }
-    let payload = parts.next().unwrap_or("");
-    serde_json::from_str(payload).map_err(|err| err.to_string())
+    Ok(parse_modes_payload(parts.next().unwrap_or("")))
+}
+
+/// Restored modes are a nicety; a payload this client cannot parse falls
+/// back to a fresh-terminal snapshot (reporting why) so the attach still
+/// succeeds with live output.
+fn parse_modes_payload(payload: &str) -> (TerminalSnapshot, Option<String>) {
+    match serde_json::from_str(payload) {
+        Ok(snapshot) => (snapshot, None),
+        Err(err) => {
+            // Match a freshly spawned terminal rather than the all-off
+            // derive, which would hide the cursor and disable wrapping.
+            let snapshot = TerminalSnapshot {
+                cursor_visible: true,
+                wrap_mode: true,
+                ..TerminalSnapshot::default()
+            };
+            (snapshot, Some(err.to_string()))
+        }
+    }
 }
 
 fn receive_history(stream: &mut UnixStream) -> Result<Vec<u8>, String> {
@@ -806,22 +2017,38 @@ fn apply_snapshot_to_view(view: &mut PtyView, snapshot: &TerminalSnapshot) {
     view.mouse_tracking =
         snapshot.mouse_tracking || snapshot.mouse_button_tracking || snapshot.mouse_any_event;
     view.mouse_sgr = snapshot.mouse_sgr;
+    view.bracketed_paste = snapshot.bracketed_paste;
+    view.application_cursor_keys = snapshot.application_cursor_keys;
     view.scroll_region = snapshot
         .scroll_region
         .as_ref()
         .map(|region| (region.top, region.bottom));
-    let surface = view.active_surface_mut();
-    surface.add_change(Change::CursorVisibility(if snapshot.cursor_visible {
+    view.origin_mode = snapshot.origin_mode;
+    set_surface_cursor(
+        view.active_surface_mut(),
+        snapshot.cursor_visible,
+        snapshot.cursor_shape.clone(),
+    );
+    if let (true, Some(main_cursor)) = (snapshot.alt_screen, &snapshot.main_cursor) {
+        set_surface_cursor(
+            &mut view.main_surface,
+            main_cursor.visible,
+            main_cursor.shape.clone(),
+        );
+    }
+    view.attributes = snapshot_attributes_to_termwiz(&snapshot.attributes);
+    let attributes = view.attributes.clone();
+    view.active_surface_mut()
+        .add_change(Change::AllAttributes(attributes));
+}
+
+fn set_surface_cursor(surface: &mut Surface, visible: bool, shape: CursorShape) {
+    surface.add_change(Change::CursorVisibility(if visible {
         termwiz::surface::CursorVisibility::Visible
     } else {
         termwiz::surface::CursorVisibility::Hidden
     }));
-    surface.add_change(Change::CursorShape(snapshot_cursor_to_termwiz(
-        snapshot.cursor_shape.clone(),
-    )));
-    surface.add_change(Change::AllAttributes(snapshot_attributes_to_termwiz(
-        &snapshot.attributes,
-    )));
+    surface.add_change(Change::CursorShape(snapshot_cursor_to_termwiz(shape)));
 }
 
 fn snapshot_cursor_to_termwiz(shape: CursorShape) -> termwiz::surface::CursorShape {
@@ -861,6 +2088,9 @@ fn snapshot_attributes_to_termwiz(attrs: &TerminalAttributes) -> CellAttributes
         TerminalUnderline::None => termwiz::cell::Underline::None,
         TerminalUnderline::Single => termwiz::cell::Underline::Single,
         TerminalUnderline::Double => termwiz::cell::Underline::Double,
+        TerminalUnderline::Curly => termwiz::cell::Underline::Curly,
+        TerminalUnderline::Dotted => termwiz::cell::Underline::Dotted,
+        TerminalUnderline::Dashed => termwiz::cell::Underline::Dashed,
     });
     result.set_blink(match attrs.blink {
         TerminalBlink::None => termwiz::cell::Blink::None,
@@ -926,7 +2156,9 @@ fn hex_bytes(bytes: &[u8]) -> String {
         .join(" ")
 }
 
-fn receive_fd(stream: &UnixStream) -> Result<RawFd, String> {
+/// Receives the PTY fd along with the subscriber id from `OK <id>`; older
+/// brokers answer a bare `OK` and cannot be detached from.
+fn receive_fd(stream: &UnixStream) -> Result<(RawFd, Option<u64>), String> {
     let mut buf = [0u8; 64];
     let mut cmsgspace = nix::cmsg_space!([RawFd; 1]);
     let mut iov = [IoSliceMut::new(&mut buf)];
@@ -952,31 +2184,192 @@ fn receive_fd(stream: &UnixStream) -> Result<RawFd, String> {
         }
         (bytes, received_fd)
     };
-    drop(iov);
     let response = String::from_utf8_lossy(&buf[..bytes]).trim().to_string();
-    if !response.starts_with("OK") {
+    let Some(rest) = response.strip_prefix("OK") else {
         return Err(response);
+    };
+    let subscriber_id = rest.trim().parse().ok();
+    let fd = received_fd.ok_or_else(|| "missing PTY fd".to_string())?;
+    Ok((fd, subscriber_id))
+}
+
+/// Tells the broker to stop sending this subscriber output, rather than
+/// leaving it registered until a write to the closed stream fails.
+fn send_detach(
+    control: &mut BrokerControl,
+    socket_path: &Path,
+    agent: &str,
+    subscriber_id: u64,
+) -> Result<(), String> {
+    let message = format!("DETACH {} {}\n", encode_agent_token(agent), subscriber_id);
+    control.send(socket_path, message.as_bytes())
+}
+
+fn send_resize(
+    control: &mut BrokerControl,
+    socket_path: &Path,
+    agent: &str,
+    size: (u16, u16),
+) -> Result<(), String> {
+    let message = format!(
+        "RESIZE {} {} {}\n",
+        encode_agent_token(agent),
+        size.0,
+        size.1
+    );
+    control.send(socket_path, message.as_bytes())
+}
+
+/// Input for the agent: a paste is wrapped in bracketed-paste markers when
+/// the agent enabled DEC 2004, so its newlines are not taken as Enter.
+fn paste_payload(raw: &[u8], bracketed: bool) -> Vec<u8> {
+    if !bracketed {
+        return raw.to_vec();
     }
-    received_fd.ok_or_else(|| "missing PTY fd".to_string())
+    let mut payload = Vec::with_capacity(raw.len() + 12);
+    payload.extend_from_slice(b"\x1b[200~");
+    payload.extend_from_slice(raw);
+    payload.extend_from_slice(b"\x1b[201~");
+    payload
 }
 
-fn send_resize(socket_path: &PathBuf, agent: &str, size: (u16, u16)) -> Result<(), String> {
-    let mut stream = UnixStream::connect(socket_path).map_err(|err| err.to_string())?;
-    stream
-        .write_all(format!("RESIZE {} {} {}\n", agent, size.0, size.1).as_bytes())
-        .map_err(|err| err.to_string())?;
-    Ok(())
+/// Input for the focused agent. A `g` held back as the start of `gg` is
+/// typed ahead of the key or paste that turned out not to complete it.
+fn focused_input_payload(view: Option<&PtyView>, ui_event: &UIEvent, held_g: bool) -> Vec<u8> {
+    let mut payload = Vec::new();
+    if held_g {
+        payload.push(b'g');
+    }
+    match &ui_event.event {
+        InputEvent::Paste(text) => payload.extend(paste_payload(
+            text.as_bytes(),
+            view.is_some_and(|view| view.bracketed_paste),
+        )),
+        InputEvent::Key(key) => payload.extend(cursor_key_payload(
+            &ui_event.raw,
+            key,
+            view.is_some_and(|view| view.application_cursor_keys),
+        )),
+        _ => payload.extend_from_slice(&ui_event.raw),
+    }
+    payload
 }
 
-fn send_input(socket_path: &PathBuf, agent: &str, payload: &[u8]) -> Result<(), String> {
-    let mut stream = UnixStream::connect(socket_path).map_err(|err| err.to_string())?;
-    stream
-        .write_all(format!("INPUT {} {}\n", agent, payload.len()).as_bytes())
-        .map_err(|err| err.to_string())?;
-    if !payload.is_empty() {
-        stream.write_all(payload).map_err(|err| err.to_string())?;
+/// Input for an agent keystroke: with DECCKM on, unmodified arrows and
+/// Home/End are re-encoded in SS3 form; everything else is forwarded as read.
+fn cursor_key_payload(raw: &[u8], key: &KeyEvent, application: bool) -> Vec<u8> {
+    let final_byte = match key.key {
+        KeyCode::UpArrow => b'A',
+        KeyCode::DownArrow => b'B',
+        KeyCode::RightArrow => b'C',
+        KeyCode::LeftArrow => b'D',
+        KeyCode::Home => b'H',
+        KeyCode::End => b'F',
+        _ => return raw.to_vec(),
+    };
+    if !application || key.modifiers != Modifiers::NONE {
+        return raw.to_vec();
+    }
+    vec![0x1b, b'O', final_byte]
+}
+
+fn send_input(
+    control: &mut BrokerControl,
+    socket_path: &Path,
+    agent: &str,
+    payload: &[u8],
+) -> Result<(), String> {
+    let mut message =
+        format!("INPUT {} {}\n", encode_agent_token(agent), payload.len()).into_bytes();
+    message.extend_from_slice(payload);
+    control.send(socket_path, &message)
+}
+
+/// A broker connection kept open across commands, so a keystroke costs a
+/// `write` instead of a fresh connect and close. Commands for an agent also
+/// reach the broker in order, since they share a stream.
+#[derive(Default)]
+struct BrokerControl {
+    stream: Option<UnixStream>,
+    /// Reply bytes read but not yet split into lines.
+    replies: Vec<u8>,
+    /// Commands sent whose reply has not been read yet.
+    unanswered: usize,
+    /// Set once the broker answers that the agent no longer exists.
+    missing: bool,
+}
+
+impl BrokerControl {
+    /// Sends one command, reconnecting once if the cached connection has
+    /// gone away. Replies are not awaited, matching the one-shot clients,
+    /// but an earlier `agent not found` fails the send.
+    fn send(&mut self, socket_path: &Path, message: &[u8]) -> Result<(), String> {
+        if self.stream.is_some() {
+            let open = self.read_replies();
+            if self.missing {
+                return Err(AGENT_NOT_FOUND.to_string());
+            }
+            let written = open
+                && self
+                    .stream
+                    .as_mut()
+                    .is_some_and(|stream| stream.write_all(message).is_ok());
+            if written {
+                self.unanswered += 1;
+                return Ok(());
+            }
+            self.stream = None;
+            self.replies.clear();
+        }
+        let mut stream = UnixStream::connect(socket_path).map_err(|err| err.to_string())?;
+        stream.write_all(message).map_err(|err| err.to_string())?;
+        self.stream = Some(stream);
+        self.unanswered = 1;
+        Ok(())
+    }
+
+    /// Reads the broker's `OK`/`ERR` replies that have arrived, without
+    /// blocking, so they cannot fill the socket buffer. Returns false once
+    /// the broker has closed the connection.
+    fn read_replies(&mut self) -> bool {
+        let Some(stream) = self.stream.as_ref() else {
+            return false;
+        };
+        let mut buffer = [0u8; 256];
+        let open = loop {
+            match nix::sys::socket::recv(stream.as_raw_fd(), &mut buffer, MsgFlags::MSG_DONTWAIT) {
+                Ok(0) => break false,
+                Ok(read) => self.replies.extend_from_slice(&buffer[..read]),
+                Err(nix::errno::Errno::EAGAIN) => break true,
+                Err(nix::errno::Errno::EINTR) => continue,
+                Err(_) => break false,
+            }
+        };
+        while let Some(end) = self.replies.iter().position(|byte| *byte == b'\n') {
+            let line: Vec<u8> = self.replies.drain(..=end).collect();
+            self.unanswered = self.unanswered.saturating_sub(1);
+            if is_agent_not_found(String::from_utf8_lossy(&line).trim()) {
+                self.missing = true;
+            }
+        }
+        open
+    }
+
+    /// Whether the broker has said the agent is gone, checking replies to
+    /// commands already sent rather than waiting for the next one.
+    fn agent_missing(&mut self) -> bool {
+        if self.unanswered > 0 && !self.missing {
+            self.read_replies();
+        }
+        self.missing
     }
-    Ok(())
+}
+
+/// The broker's answer for an agent that has been deleted.
+const AGENT_NOT_FOUND: &str = "agent not found";
+
+fn is_agent_not_found(reply: &str) -> bool {
+    reply.strip_prefix("ERR ").unwrap_or(reply) == AGENT_NOT_FOUND
 }
 
 fn capture_scrollback(view: &mut PtyView, count: usize) {
@@ -1012,17 +2405,50 @@ fn should_scroll_on_linefeed(view: &PtyView) -> bool {
     cursor_y == height.saturating_sub(1)
 }
 
+/// Prints text with VT deferred-wrap semantics: a character printed in the
+/// last column leaves the cursor there with `pending_wrap` set, and the wrap
+/// happens only when the next character is printed.
 fn apply_text_with_scrollback(view: &mut PtyView, text: &str) {
     for ch in text.chars() {
+        if ch == '\n' || ch == '\r' {
+            view.pending_wrap = false;
+        } else if view.pending_wrap {
+            view.pending_wrap = false;
+            if should_scroll_on_linefeed(view) {
+                capture_scrollback(view, 1);
+            }
+            view.active_surface_mut()
+                .add_change(Change::Text("\r\n".to_string()));
+        }
         if ch == '\n' && should_scroll_on_linefeed(view) {
             capture_scrollback(view, 1);
         }
-        view.active_surface_mut()
-            .add_change(Change::Text(ch.to_string()));
+        let surface = view.active_surface_mut();
+        surface.add_change(Change::Text(ch.to_string()));
+        let (width, _) = surface.dimensions();
+        let (cursor_x, _) = surface.cursor_position();
+        if width > 0 && cursor_x >= width {
+            surface.add_change(Change::CursorPosition {
+                x: TermwizPosition::Absolute(width - 1),
+                y: TermwizPosition::Relative(0),
+            });
+            view.pending_wrap = true;
+        }
     }
 }
 
 fn apply_action_to_view(action: Action, view: &mut PtyView) -> Option<Vec<u8>> {
+    if !matches!(
+        action,
+        Action::Print(_)
+            | Action::PrintString(_)
+            | Action::OperatingSystemCommand(_)
+            | Action::KittyImage(_)
+            | Action::Sixel(_)
+            | Action::CSI(CSI::Sgr(_))
+    ) {
+        view.pending_wrap = false;
+    }
     match action {
         Action::Print(ch) => {
             apply_text_with_scrollback(view, &ch.to_string());
@@ -1046,8 +2472,20 @@ fn apply_action_to_view(action: Action, view: &mut PtyView) -> Option<Vec<u8>> {
                         .add_change(Change::Text("\n".to_string()));
                 }
                 ControlCode::HorizontalTab => {
+                    let (x, _) = view.active_surface().cursor_position();
+                    let (width, _) = view.active_surface().dimensions();
+                    let next = view
+                        .tab_stops
+                        .range(x + 1..)
+                        .next()
+                        .copied()
+                        .unwrap_or(width.saturating_sub(1))
+                        .min(width.saturating_sub(1));
                     view.active_surface_mut()
-                        .add_change(Change::Text("\t".to_string()));
+                        .add_change(Change::CursorPosition {
+                            x: TermwizPosition::Absolute(next),
+                            y: TermwizPosition::Relative(0),
+                        });
                 }
                 ControlCode::Backspace => {
                     view.active_surface_mut()
@@ -1065,17 +2503,112 @@ fn apply_action_to_view(action: Action, view: &mut PtyView) -> Option<Vec<u8>> {
             apply_esc_to_view(esc, view);
             None
         }
-        Action::OperatingSystemCommand(osc) => {
-            apply_osc_to_view(*osc, view);
+        Action::OperatingSystemCommand(osc) => apply_osc_to_view(*osc, view),
+        Action::KittyImage(image) => {
+            if matches!(
+                *image,
+                KittyImage::TransmitDataAndDisplay { .. } | KittyImage::Display { .. }
+            ) {
+                apply_text_with_scrollback(view, IMAGE_PLACEHOLDER);
+            }
             None
         }
-        _ => None,
+        Action::Sixel(_) => {
+            apply_text_with_scrollback(view, IMAGE_PLACEHOLDER);
+            None
+        }
+        Action::DeviceControl(DeviceControlMode::ShortDeviceControl(dcs))
+            if dcs.intermediates == b"$" && dcs.byte == b'q' =>
+        {
+            Some(decrqss_reply(&dcs.data, view))
+        }
+        _ => None,
+    }
+}
+
+/// Answers a DECRQSS (request selection or setting) query. Unsupported
+/// settings get the "invalid request" reply so callers don't wait on us.
+fn decrqss_reply(setting: &[u8], view: &PtyView) -> Vec<u8> {
+    let report = match setting {
+        b"m" => Some(format!("{}m", sgr_report(&view.attributes))),
+        b"r" => {
+            let height = view.active_surface().dimensions().1;
+            let (top, size) = scroll_region(view, height);
+            Some(format!("{};{}r", top + 1, top + size))
+        }
+        _ => None,
+    };
+    match report {
+        Some(report) => format!("\x1bP1$r{}\x1b\\", report).into_bytes(),
+        None => b"\x1bP0$r\x1b\\".to_vec(),
+    }
+}
+
+fn sgr_report(attributes: &CellAttributes) -> String {
+    let mut params = vec!["0".to_string()];
+    match attributes.intensity() {
+        termwiz::cell::Intensity::Bold => params.push("1".to_string()),
+        termwiz::cell::Intensity::Half => params.push("2".to_string()),
+        termwiz::cell::Intensity::Normal => {}
+    }
+    if attributes.italic() {
+        params.push("3".to_string());
+    }
+    match attributes.underline() {
+        termwiz::cell::Underline::None => {}
+        termwiz::cell::Underline::Single => params.push("4".to_string()),
+        termwiz::cell::Underline::Double => params.push("21".to_string()),
+        termwiz::cell::Underline::Curly => params.push("4:3".to_string()),
+        termwiz::cell::Underline::Dotted => params.push("4:4".to_string()),
+        termwiz::cell::Underline::Dashed => params.push("4:5".to_string()),
+    }
+    match attributes.blink() {
+        termwiz::cell::Blink::Slow => params.push("5".to_string()),
+        termwiz::cell::Blink::Rapid => params.push("6".to_string()),
+        termwiz::cell::Blink::None => {}
+    }
+    if attributes.reverse() {
+        params.push("7".to_string());
+    }
+    if attributes.invisible() {
+        params.push("8".to_string());
+    }
+    if attributes.strikethrough() {
+        params.push("9".to_string());
+    }
+    params.extend(sgr_color_params(attributes.foreground(), 30, 90, 38));
+    params.extend(sgr_color_params(attributes.background(), 40, 100, 48));
+    params.join(";")
+}
+
+fn sgr_color_params(
+    color: ColorAttribute,
+    base: u8,
+    bright_base: u8,
+    extended: u8,
+) -> Option<String> {
+    match color {
+        ColorAttribute::Default => None,
+        ColorAttribute::PaletteIndex(index) if index < 8 => Some((base + index).to_string()),
+        ColorAttribute::PaletteIndex(index) if index < 16 => {
+            Some((bright_base + index - 8).to_string())
+        }
+        ColorAttribute::PaletteIndex(index) => Some(format!("{extended};5;{index}")),
+        ColorAttribute::TrueColorWithPaletteFallback(color, _)
+        | ColorAttribute::TrueColorWithDefaultFallback(color) => {
+            let (red, green, blue, _) = color.to_srgb_u8();
+            Some(format!("{extended};2;{red};{green};{blue}"))
+        }
     }
 }
 
 fn apply_esc_to_view(esc: Esc, view: &mut PtyView) {
-    match esc {
-        Esc::Code(code) => match code {
+    if let Esc::Code(code) = esc {
+        match code {
+            EscCode::HorizontalTabSet => {
+                let (x, _) = view.active_surface().cursor_position();
+                view.tab_stops.insert(x);
+            }
             EscCode::DecSaveCursorPosition => {
                 let cursor_pos = view.active_surface().cursor_position();
                 *view.active_saved_cursor_mut() = Some(cursor_pos);
@@ -1115,23 +2648,91 @@ fn apply_esc_to_view(esc: Esc, view: &mut PtyView) {
                 surface.add_change(Change::ClearScreen(ColorAttribute::Default));
             }
             _ => {}
-        },
-        _ => {}
+        }
     }
 }
 
-fn apply_osc_to_view(osc: OperatingSystemCommand, view: &mut PtyView) {
-    let surface = view.active_surface_mut();
+fn apply_osc_to_view(osc: OperatingSystemCommand, view: &mut PtyView) -> Option<Vec<u8>> {
     match osc {
         OperatingSystemCommand::SetIconNameAndWindowTitle(title)
         | OperatingSystemCommand::SetWindowTitle(title)
-        | OperatingSystemCommand::SetWindowTitleSun(title)
-        | OperatingSystemCommand::SetIconName(title)
+        | OperatingSystemCommand::SetWindowTitleSun(title) => {
+            view.title = Some(title.clone());
+            view.active_surface_mut().add_change(Change::Title(title));
+        }
+        OperatingSystemCommand::SetIconName(title)
         | OperatingSystemCommand::SetIconNameSun(title) => {
-            surface.add_change(Change::Title(title));
+            view.active_surface_mut().add_change(Change::Title(title));
+        }
+        OperatingSystemCommand::ITermProprietary(ITermProprietary::File(file)) if file.inline => {
+            apply_text_with_scrollback(view, IMAGE_PLACEHOLDER);
+        }
+        OperatingSystemCommand::ChangeDynamicColors(first, colors) => {
+            // Each entry applies to the next dynamic color number in turn,
+            // so `OSC 10;fg;bg` sets both.
+            let mut replies = Vec::new();
+            for (offset, color) in colors.into_iter().enumerate() {
+                let number = first as usize + offset;
+                match color {
+                    ColorOrQuery::Color(color) => set_default_color(view, number, color),
+                    ColorOrQuery::Query => {
+                        replies.extend(default_color_report(view, number).unwrap_or_default())
+                    }
+                }
+            }
+            return (!replies.is_empty()).then_some(replies);
+        }
+        OperatingSystemCommand::ResetDynamicColor(DynamicColorNumber::TextForegroundColor) => {
+            view.default_foreground = None;
+        }
+        OperatingSystemCommand::ResetDynamicColor(DynamicColorNumber::TextBackgroundColor) => {
+            view.default_background = None;
         }
         _ => {}
     }
+    None
+}
+
+const OSC_FOREGROUND: usize = DynamicColorNumber::TextForegroundColor as usize;
+const OSC_BACKGROUND: usize = DynamicColorNumber::TextBackgroundColor as usize;
+
+fn set_default_color(view: &mut PtyView, number: usize, color: SrgbaTuple) {
+    match number {
+        OSC_FOREGROUND => view.default_foreground = Some(color),
+        OSC_BACKGROUND => view.default_background = Some(color),
+        _ => {}
+    }
+}
+
+/// Answers an `OSC 10/11 ; ?` query with the agent's color, or the theme's
+/// when it has not set one, in xterm's `rgb:rrrr/gggg/bbbb` form.
+fn default_color_report(view: &PtyView, number: usize) -> Option<Vec<u8>> {
+    let (color, fallback) = match number {
+        OSC_FOREGROUND => (view.default_foreground, THEME.fg),
+        OSC_BACKGROUND => (view.default_background, THEME.bg),
+        _ => return None,
+    };
+    let (r, g, b) = match color {
+        Some(color) => {
+            let (r, g, b, _) = color.to_srgb_u8();
+            (r, g, b)
+        }
+        None => match fallback {
+            Color::Rgb(r, g, b) => (r, g, b),
+            Color::Indexed(index) => theme::indexed_to_rgb(index),
+            _ => (0, 0, 0),
+        },
+    };
+    let channel = |value: u8| u16::from(value) * 0x0101;
+    Some(
+        format!(
+            "\x1b]{number};rgb:{:04x}/{:04x}/{:04x}\x1b\\",
+            channel(r),
+            channel(g),
+            channel(b)
+        )
+        .into_bytes(),
+    )
 }
 
 fn apply_csi_to_view(csi: CSI, view: &mut PtyView) -> Option<Vec<u8>> {
@@ -1141,19 +2742,38 @@ fn apply_csi_to_view(csi: CSI, view: &mut PtyView) -> Option<Vec<u8>> {
             apply_edit_to_view(edit, view);
             None
         }
-        CSI::Mode(mode) => {
-            apply_mode_to_view(mode, view);
-            None
-        }
+        CSI::Mode(mode) => apply_mode_to_view(mode, view),
         CSI::Sgr(sgr) => {
-            let surface = view.active_surface_mut();
-            apply_sgr_to_surface(sgr, surface);
+            apply_sgr_to_view(sgr, view);
             None
         }
+        CSI::Window(window) => window_report(&window, view),
         _ => None,
     }
 }
 
+/// Replies to the reportable `CSI t` queries using the preview surface size
+/// and a nominal cell size. Window manipulation requests are ignored.
+fn window_report(window: &Window, view: &PtyView) -> Option<Vec<u8>> {
+    let (cols, rows) = view.active_surface().dimensions();
+    let (width_px, height_px) = (cols * CELL_WIDTH_PX, rows * CELL_HEIGHT_PX);
+    let report = match window {
+        Window::ReportWindowState => "1".to_string(),
+        Window::ReportWindowPosition | Window::ReportTextAreaPosition => "3;0;0".to_string(),
+        Window::ReportTextAreaSizePixels | Window::ReportWindowSizePixels => {
+            format!("4;{height_px};{width_px}")
+        }
+        Window::ReportScreenSizePixels => format!("5;{height_px};{width_px}"),
+        Window::ReportCellSizePixels => format!("6;{CELL_HEIGHT_PX};{CELL_WIDTH_PX}"),
+        Window::ReportTextAreaSizeCells => format!("8;{rows};{cols}"),
+        Window::ReportScreenSizeCells => format!("9;{rows};{cols}"),
+        Window::ReportIconLabel => return Some(b"\x1b]L\x1b\\".to_vec()),
+        Window::ReportWindowTitle => return Some(b"\x1b]l\x1b\\".to_vec()),
+        _ => return None,
+    };
+    Some(format!("\x1b[{report}t").into_bytes())
+}
+
 fn cursor_position_report(view: &PtyView) -> Vec<u8> {
     let (cursor_x, cursor_y) = view.active_surface().cursor_position();
     let line = cursor_y + 1;
@@ -1161,6 +2781,11 @@ fn cursor_position_report(view: &PtyView) -> Vec<u8> {
     format!("\x1b[{};{}R", line, col).into_bytes()
 }
 
+/// Tab stops every eight columns, matching a freshly reset VT terminal.
+fn default_tab_stops(width: usize) -> BTreeSet<usize> {
+    (8..width).step_by(8).collect()
+}
+
 fn apply_cursor_to_view(cursor: Cursor, view: &mut PtyView) -> Option<Vec<u8>> {
     match cursor {
         Cursor::Left(count) => {
@@ -1220,13 +2845,27 @@ fn apply_cursor_to_view(cursor: Cursor, view: &mut PtyView) -> Option<Vec<u8>> {
             None
         }
         Cursor::LinePositionAbsolute(pos) => {
+            let row = origin_row(view, pos.saturating_sub(1) as usize);
             let surface = view.active_surface_mut();
             surface.add_change(Change::CursorPosition {
                 x: TermwizPosition::Relative(0),
-                y: TermwizPosition::Absolute(pos.saturating_sub(1) as usize),
+                y: TermwizPosition::Absolute(row),
             });
             None
         }
+        Cursor::TabulationClear(clear) => {
+            match clear {
+                TabulationClear::ClearCharacterTabStopAtActivePosition => {
+                    let (x, _) = view.active_surface().cursor_position();
+                    view.tab_stops.remove(&x);
+                }
+                TabulationClear::ClearAllCharacterTabStops | TabulationClear::ClearAllTabStops => {
+                    view.tab_stops.clear();
+                }
+                _ => {}
+            }
+            None
+        }
         Cursor::LinePositionForward(count) => {
             let surface = view.active_surface_mut();
             surface.add_change(Change::CursorPosition {
@@ -1246,10 +2885,11 @@ fn apply_cursor_to_view(cursor: Cursor, view: &mut PtyView) -> Option<Vec<u8>> {
         Cursor::CharacterAndLinePosition { line, col }
         | Cursor::ActivePositionReport { line, col }
         | Cursor::Position { line, col } => {
+            let row = origin_row(view, line.as_zero_based() as usize);
             let surface = view.active_surface_mut();
             surface.add_change(Change::CursorPosition {
                 x: TermwizPosition::Absolute(col.as_zero_based() as usize),
-                y: TermwizPosition::Absolute(line.as_zero_based() as usize),
+                y: TermwizPosition::Absolute(row),
             });
             None
         }
@@ -1300,35 +2940,40 @@ fn apply_cursor_to_view(cursor: Cursor, view: &mut PtyView) -> Option<Vec<u8>> {
 
 fn apply_edit_to_view(edit: Edit, view: &mut PtyView) {
     match edit {
-        Edit::EraseInDisplay(mode) => {
-            let surface = view.active_surface_mut();
-            match mode {
-                EraseInDisplay::EraseToEndOfDisplay => {
-                    surface.add_change(Change::ClearToEndOfScreen(ColorAttribute::Default));
-                }
-                EraseInDisplay::EraseToStartOfDisplay => {
-                    surface.add_change(Change::ClearScreen(ColorAttribute::Default));
-                }
-                EraseInDisplay::EraseDisplay => {
-                    surface.add_change(Change::ClearScreen(ColorAttribute::Default));
-                }
-                _ => {}
+        Edit::EraseInDisplay(mode) => match mode {
+            EraseInDisplay::EraseToEndOfDisplay => {
+                let surface = view.active_surface_mut();
+                surface.add_change(Change::ClearToEndOfScreen(ColorAttribute::Default));
             }
-        }
-        Edit::EraseInLine(mode) => {
-            let surface = view.active_surface_mut();
-            match mode {
-                EraseInLine::EraseToEndOfLine => {
-                    surface.add_change(Change::ClearToEndOfLine(ColorAttribute::Default));
-                }
-                EraseInLine::EraseToStartOfLine => {
-                    surface.add_change(Change::ClearToEndOfLine(ColorAttribute::Default));
-                }
-                EraseInLine::EraseLine => {
-                    surface.add_change(Change::ClearToEndOfLine(ColorAttribute::Default));
+            EraseInDisplay::EraseToStartOfDisplay => {
+                let (cursor_x, cursor_y) = view.active_surface().cursor_position();
+                let width = view.active_surface().dimensions().0;
+                for row in 0..cursor_y {
+                    blank_row_prefix(view, row, width);
                 }
+                blank_row_prefix(view, cursor_y, cursor_x + 1);
             }
-        }
+            EraseInDisplay::EraseDisplay => {
+                let surface = view.active_surface_mut();
+                surface.add_change(Change::ClearScreen(ColorAttribute::Default));
+            }
+            _ => {}
+        },
+        Edit::EraseInLine(mode) => match mode {
+            EraseInLine::EraseToEndOfLine => {
+                let surface = view.active_surface_mut();
+                surface.add_change(Change::ClearToEndOfLine(ColorAttribute::Default));
+            }
+            EraseInLine::EraseToStartOfLine => {
+                let (cursor_x, cursor_y) = view.active_surface().cursor_position();
+                blank_row_prefix(view, cursor_y, cursor_x + 1);
+            }
+            EraseInLine::EraseLine => {
+                let cursor_y = view.active_surface().cursor_position().1;
+                let width = view.active_surface().dimensions().0;
+                blank_row_prefix(view, cursor_y, width);
+            }
+        },
         Edit::ScrollUp(count) => {
             let height = view.active_surface().dimensions().1;
             let (first_row, region_size) = scroll_region(view, height);
@@ -1342,6 +2987,11 @@ fn apply_edit_to_view(edit: Edit, view: &mut PtyView) {
                 scroll_count: count as usize,
             });
         }
+        Edit::DeleteCharacter(count) => edit_cursor_row(view, RowEdit::Delete, count),
+        Edit::InsertCharacter(count) => edit_cursor_row(view, RowEdit::Insert, count),
+        Edit::EraseCharacter(count) => edit_cursor_row(view, RowEdit::Erase, count),
+        Edit::InsertLine(count) => shift_lines_at_cursor(view, count as usize, true),
+        Edit::DeleteLine(count) => shift_lines_at_cursor(view, count as usize, false),
         Edit::ScrollDown(count) => {
             let height = view.active_surface().dimensions().1;
             let (first_row, region_size) = scroll_region(view, height);
@@ -1356,14 +3006,65 @@ fn apply_edit_to_view(edit: Edit, view: &mut PtyView) {
     }
 }
 
-fn apply_mode_to_view(mode: Mode, view: &mut PtyView) {
+/// Overwrites the first `count` cells of `row` with spaces in the current
+/// background, leaving the cursor and pen where they were. termwiz's
+/// `Surface` only clears towards the end, so EL 1/2 and ED 1 are built here.
+fn blank_row_prefix(view: &mut PtyView, row: usize, count: usize) {
+    let width = view.active_surface().dimensions().0;
+    let count = count.min(width);
+    let mut blank = CellAttributes::default();
+    blank.set_background(view.attributes.background());
+    let pen = view.attributes.clone();
+    let surface = view.active_surface_mut();
+    let (cursor_x, cursor_y) = surface.cursor_position();
+    surface.add_change(Change::CursorPosition {
+        x: TermwizPosition::Absolute(0),
+        y: TermwizPosition::Absolute(row),
+    });
+    surface.add_change(Change::AllAttributes(blank));
+    surface.add_change(Change::Text(" ".repeat(count)));
+    surface.add_change(Change::AllAttributes(pen));
+    surface.add_change(Change::CursorPosition {
+        x: TermwizPosition::Absolute(cursor_x),
+        y: TermwizPosition::Absolute(cursor_y),
+    });
+}
+
+fn apply_mode_to_view(mode: Mode, view: &mut PtyView) -> Option<Vec<u8>> {
     match mode {
         Mode::SetDecPrivateMode(mode) => apply_dec_private_mode(mode, view, true),
         Mode::ResetDecPrivateMode(mode) => apply_dec_private_mode(mode, view, false),
         Mode::SetMode(mode) => apply_terminal_mode(mode, view, true),
         Mode::ResetMode(mode) => apply_terminal_mode(mode, view, false),
+        Mode::QueryMode(mode) => return Some(terminal_mode_report(&mode, view)),
         _ => {}
     }
+    None
+}
+
+/// DECRPM reply for an ANSI mode query (`CSI Ps $ p`): 1 set, 2 reset, 0
+/// for modes we don't track.
+fn terminal_mode_report(mode: &TerminalMode, view: &PtyView) -> Vec<u8> {
+    let (number, state) = match mode {
+        TerminalMode::Code(code) => {
+            let state = match code {
+                TerminalModeCode::Insert => Some(view.insert_mode),
+                TerminalModeCode::ShowCursor => Some(matches!(
+                    view.active_surface().cursor_visibility(),
+                    termwiz::surface::CursorVisibility::Visible
+                )),
+                _ => None,
+            };
+            (code.clone() as u16, state)
+        }
+        TerminalMode::Unspecified(number) => (*number, None),
+    };
+    let value = match state {
+        Some(true) => 1,
+        Some(false) => 2,
+        None => 0,
+    };
+    format!("\x1b[{number};{value}$y").into_bytes()
 }
 
 fn apply_dec_private_mode(mode: DecPrivateMode, view: &mut PtyView, enabled: bool) {
@@ -1380,24 +3081,29 @@ fn apply_dec_private_mode(mode: DecPrivateMode, view: &mut PtyView, enabled: boo
                 termwiz::surface::CursorVisibility::Hidden
             }));
         }
-        DecPrivateModeCode::StartBlinkingCursor => {
-            if enabled {
-                let surface = view.active_surface_mut();
-                surface.add_change(Change::CursorShape(
-                    termwiz::surface::CursorShape::BlinkingBlock,
-                ));
-            }
+        DecPrivateModeCode::StartBlinkingCursor if enabled => {
+            let surface = view.active_surface_mut();
+            surface.add_change(Change::CursorShape(
+                termwiz::surface::CursorShape::BlinkingBlock,
+            ));
         }
-        DecPrivateModeCode::SaveCursor => {
-            if enabled {
-                let cursor_pos = view.active_surface().cursor_position();
-                *view.active_saved_cursor_mut() = Some(cursor_pos);
-            }
+        DecPrivateModeCode::SaveCursor if enabled => {
+            let cursor_pos = view.active_surface().cursor_position();
+            *view.active_saved_cursor_mut() = Some(cursor_pos);
         }
         DecPrivateModeCode::ClearAndEnableAlternateScreen
         | DecPrivateModeCode::EnableAlternateScreen
         | DecPrivateModeCode::OptEnableAlternateScreen => {
             if enabled {
+                if !view.use_alt_screen {
+                    // The alternate screen starts from the main screen's
+                    // cursor; from here on the two are tracked separately.
+                    let visibility = view.main_surface.cursor_visibility();
+                    let shape = view.main_surface.cursor_shape().unwrap_or_default();
+                    view.alt_surface
+                        .add_change(Change::CursorVisibility(visibility));
+                    view.alt_surface.add_change(Change::CursorShape(shape));
+                }
                 view.use_alt_screen = true;
                 if matches!(code, DecPrivateModeCode::ClearAndEnableAlternateScreen) {
                     view.alt_surface
@@ -1415,26 +3121,125 @@ fn apply_dec_private_mode(mode: DecPrivateMode, view: &mut PtyView, enabled: boo
         DecPrivateModeCode::SGRMouse => {
             view.mouse_sgr = enabled;
         }
+        DecPrivateModeCode::BracketedPaste => {
+            view.bracketed_paste = enabled;
+        }
+        DecPrivateModeCode::ApplicationCursorKeys => {
+            view.application_cursor_keys = enabled;
+        }
+        DecPrivateModeCode::OriginMode => {
+            view.origin_mode = enabled;
+            let row = origin_row(view, 0);
+            view.active_surface_mut()
+                .add_change(Change::CursorPosition {
+                    x: TermwizPosition::Absolute(0),
+                    y: TermwizPosition::Absolute(row),
+                });
+        }
         _ => {}
     }
 }
 
+/// Maps a cursor row to the screen, offsetting by the scroll region when
+/// origin mode (DECOM) is enabled.
+fn origin_row(view: &PtyView, row: usize) -> usize {
+    match view.scroll_region {
+        Some((top, bottom)) if view.origin_mode && bottom >= top => (top + row).min(bottom),
+        _ => row,
+    }
+}
+
 fn apply_terminal_mode(mode: TerminalMode, view: &mut PtyView, enabled: bool) {
-    let surface = view.active_surface_mut();
     let code = match mode {
         TerminalMode::Code(code) => code,
         TerminalMode::Unspecified(_) => return,
     };
-    match code {
-        TerminalModeCode::ShowCursor => {
-            surface.add_change(Change::CursorVisibility(if enabled {
-                termwiz::surface::CursorVisibility::Visible
-            } else {
-                termwiz::surface::CursorVisibility::Hidden
-            }));
+    if code == TerminalModeCode::Insert {
+        view.insert_mode = enabled;
+    }
+    if code == TerminalModeCode::ShowCursor {
+        let surface = view.active_surface_mut();
+        surface.add_change(Change::CursorVisibility(if enabled {
+            termwiz::surface::CursorVisibility::Visible
+        } else {
+            termwiz::surface::CursorVisibility::Hidden
+        }));
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RowEdit {
+    Delete,
+    Insert,
+    Erase,
+}
+
+/// DCH/ICH/ECH on the cursor row. Delete pulls the cells right of the
+/// cursor left and pads the end, insert pushes them right off the edge, and
+/// erase blanks in place; blanks take the current background. Counts are
+/// clamped to the columns from the cursor to the edge.
+fn edit_cursor_row(view: &mut PtyView, edit: RowEdit, count: u32) {
+    let mut blank_attrs = CellAttributes::default();
+    blank_attrs.set_background(view.attributes.background());
+    let blank = Cell::new(' ', blank_attrs);
+    let surface = view.active_surface_mut();
+    let (cursor_x, cursor_y) = surface.cursor_position();
+    let width = surface.dimensions().0;
+    if cursor_x >= width {
+        return;
+    }
+    let count = (count.max(1) as usize).min(width - cursor_x);
+    let mut rows = surface.screen_cells();
+    let Some(row) = rows.get_mut(cursor_y) else {
+        return;
+    };
+    let tail = &mut row[cursor_x..];
+    let blanks = match edit {
+        RowEdit::Delete => {
+            tail.rotate_left(count);
+            let len = tail.len();
+            &mut tail[len - count..]
         }
-        _ => {}
+        RowEdit::Insert => {
+            tail.rotate_right(count);
+            &mut tail[..count]
+        }
+        RowEdit::Erase => &mut tail[..count],
+    };
+    blanks.fill(blank);
+}
+
+/// IL/DL: scrolls the part of the scroll region from the cursor row down,
+/// inserting blank lines at the cursor (`insert`) or pulling the lines
+/// below up over it. Outside the region it does nothing; like xterm the
+/// cursor returns to the first column.
+fn shift_lines_at_cursor(view: &mut PtyView, count: usize, insert: bool) {
+    let height = view.active_surface().dimensions().1;
+    let (top, size) = scroll_region(view, height);
+    let cursor_y = view.active_surface().cursor_position().1;
+    if cursor_y < top || cursor_y >= top + size {
+        return;
     }
+    let region_size = top + size - cursor_y;
+    let scroll_count = count.clamp(1, region_size);
+    let surface = view.active_surface_mut();
+    surface.add_change(if insert {
+        Change::ScrollRegionDown {
+            first_row: cursor_y,
+            region_size,
+            scroll_count,
+        }
+    } else {
+        Change::ScrollRegionUp {
+            first_row: cursor_y,
+            region_size,
+            scroll_count,
+        }
+    });
+    surface.add_change(Change::CursorPosition {
+        x: TermwizPosition::Absolute(0),
+        y: TermwizPosition::Absolute(cursor_y),
+    });
 }
 
 fn scroll_region(view: &PtyView, height: usize) -> (usize, usize) {
@@ -1446,44 +3251,32 @@ fn scroll_region(view: &PtyView, height: usize) -> (usize, usize) {
     (0, height)
 }
 
-fn apply_sgr_to_surface(sgr: Sgr, surface: &mut Surface) {
-    match sgr {
-        Sgr::Reset => {
-            surface.add_change(Change::AllAttributes(CellAttributes::default()));
-        }
-        Sgr::Intensity(value) => {
-            surface.add_change(Change::Attribute(AttributeChange::Intensity(value)));
-        }
-        Sgr::Underline(value) => {
-            surface.add_change(Change::Attribute(AttributeChange::Underline(value)));
-        }
-        Sgr::Blink(value) => {
-            surface.add_change(Change::Attribute(AttributeChange::Blink(value)));
-        }
-        Sgr::Italic(value) => {
-            surface.add_change(Change::Attribute(AttributeChange::Italic(value)));
-        }
-        Sgr::Inverse(value) => {
-            surface.add_change(Change::Attribute(AttributeChange::Reverse(value)));
-        }
-        Sgr::Invisible(value) => {
-            surface.add_change(Change::Attribute(AttributeChange::Invisible(value)));
-        }
-        Sgr::StrikeThrough(value) => {
-            surface.add_change(Change::Attribute(AttributeChange::StrikeThrough(value)));
-        }
+fn apply_sgr_to_view(sgr: Sgr, view: &mut PtyView) {
+    let change = match sgr {
+        Sgr::Reset => Change::AllAttributes(CellAttributes::default()),
+        Sgr::Intensity(value) => Change::Attribute(AttributeChange::Intensity(value)),
+        Sgr::Underline(value) => Change::Attribute(AttributeChange::Underline(value)),
+        Sgr::Blink(value) => Change::Attribute(AttributeChange::Blink(value)),
+        Sgr::Italic(value) => Change::Attribute(AttributeChange::Italic(value)),
+        Sgr::Inverse(value) => Change::Attribute(AttributeChange::Reverse(value)),
+        Sgr::Invisible(value) => Change::Attribute(AttributeChange::Invisible(value)),
+        Sgr::StrikeThrough(value) => Change::Attribute(AttributeChange::StrikeThrough(value)),
         Sgr::Foreground(color) => {
-            surface.add_change(Change::Attribute(AttributeChange::Foreground(
-                ColorAttribute::from(color),
-            )));
+            Change::Attribute(AttributeChange::Foreground(ColorAttribute::from(color)))
         }
         Sgr::Background(color) => {
-            surface.add_change(Change::Attribute(AttributeChange::Background(
-                ColorAttribute::from(color),
-            )));
+            Change::Attribute(AttributeChange::Background(ColorAttribute::from(color)))
+        }
+        Sgr::UnderlineColor(_) | Sgr::Font(_) | Sgr::Overline(_) | Sgr::VerticalAlign(_) => {
+            return;
         }
-        Sgr::UnderlineColor(_) | Sgr::Font(_) | Sgr::Overline(_) | Sgr::VerticalAlign(_) => {}
+    };
+    match &change {
+        Change::AllAttributes(attributes) => view.attributes = attributes.clone(),
+        Change::Attribute(attribute) => view.attributes.apply_change(attribute),
+        _ => {}
     }
+    view.active_surface_mut().add_change(change);
 }
 
 fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
@@ -1558,13 +3351,14 @@ fn handle_preview_scroll(
     let Some(agent_name) = agent_name else {
         return;
     };
-    let Some(view) = app.pty_views.get(&agent_name) else {
+    let Some(view) = app.pty_views.get_mut(&agent_name) else {
         return;
     };
     if view.mouse_tracking {
         if let Some(bytes) = mouse_wheel_sgr_bytes(direction, column, row) {
-            if let Err(err) = send_input(&app.pty_socket_path, &agent_name, &bytes) {
-                app.set_status(err);
+            let sent = send_input(&mut view.control, &app.pty_socket_path, &agent_name, &bytes);
+            if let Err(err) = sent {
+                app.report_broker_error(&agent_name, err);
             }
         }
         return;
@@ -1572,20 +3366,158 @@ fn handle_preview_scroll(
     let Some(view) = app.pty_views.get_mut(&agent_name) else {
         return;
     };
-    let height = view.active_surface().dimensions().1;
-    let total_lines = view.scrollback.len().saturating_add(height);
-    let max_offset = total_lines.saturating_sub(height);
-    if max_offset == 0 {
-        return;
+    let scroll = match direction {
+        MouseScrollDirection::Up => PreviewScroll::LineUp,
+        MouseScrollDirection::Down => PreviewScroll::LineDown,
+    };
+    view.scroll_by(scroll);
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum PreviewScroll {
+    LineUp,
+    LineDown,
+    PageUp,
+    PageDown,
+    Top,
+    Bottom,
+}
+
+/// Scroll offset after `added` lines move into scrollback. Following views
+/// and views already at the bottom stay on the tail; a view scrolled up keeps
+/// showing the same lines.
+fn scroll_offset_after_output(offset: usize, added: usize, follow: bool) -> usize {
+    if follow || offset == 0 {
+        0
+    } else {
+        offset.saturating_add(added)
+    }
+}
+
+fn scrolled_offset(offset: usize, max_offset: usize, page: usize, scroll: PreviewScroll) -> usize {
+    let page = page.max(1);
+    let offset = match scroll {
+        PreviewScroll::LineUp => offset.saturating_add(1),
+        PreviewScroll::LineDown => offset.saturating_sub(1),
+        PreviewScroll::PageUp => offset.saturating_add(page),
+        PreviewScroll::PageDown => offset.saturating_sub(page),
+        PreviewScroll::Top => max_offset,
+        PreviewScroll::Bottom => 0,
+    };
+    offset.min(max_offset)
+}
+
+/// Cell ranges in `line` matching `query`, ignoring case.
+fn line_matches(line: &TermwizLine, query: &str) -> Vec<(usize, usize)> {
+    let query: Vec<char> = query.chars().flat_map(char::to_lowercase).collect();
+    if query.is_empty() {
+        return Vec::new();
     }
-    match direction {
-        MouseScrollDirection::Up => {
-            view.scroll_offset = (view.scroll_offset + 1).min(max_offset);
+    let chars: Vec<(usize, char)> = line
+        .visible_cells()
+        .flat_map(|cell| {
+            let column = cell.cell_index();
+            cell.str()
+                .chars()
+                .flat_map(char::to_lowercase)
+                .map(move |ch| (column, ch))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+    let mut matches = Vec::new();
+    let mut index = 0;
+    while index + query.len() <= chars.len() {
+        let candidate = &chars[index..index + query.len()];
+        if candidate.iter().map(|(_, ch)| ch).eq(query.iter()) {
+            matches.push((candidate[0].0, candidate[query.len() - 1].0 + 1));
+            index += query.len();
+        } else {
+            index += 1;
         }
-        MouseScrollDirection::Down => {
-            view.scroll_offset = view.scroll_offset.saturating_sub(1);
+    }
+    matches
+}
+
+/// The match a new search jumps to: the last one at or above the bottom of
+/// the screen, else the first.
+fn nearest_match(matches: &[SearchMatch], bottom_line: usize) -> Option<SearchMatch> {
+    matches
+        .iter()
+        .rev()
+        .find(|found| found.line <= bottom_line)
+        .or(matches.first())
+        .copied()
+}
+
+/// The match before (`older`) or after `current`, wrapping around.
+fn step_match(
+    matches: &[SearchMatch],
+    current: Option<SearchMatch>,
+    older: bool,
+) -> Option<SearchMatch> {
+    let current = current?;
+    let key = |found: &SearchMatch| (found.line, found.start);
+    let next = if older {
+        matches
+            .iter()
+            .rev()
+            .find(|found| key(found) < key(&current))
+            .or(matches.last())
+    } else {
+        matches
+            .iter()
+            .find(|found| key(found) > key(&current))
+            .or(matches.first())
+    };
+    next.copied()
+}
+
+/// Scroll keys the forest keeps for itself while an agent is focused. On the
+/// alternate screen there is no scrollback, so every key goes to the agent.
+fn focused_scroll_key(key: &KeyEvent, alt_screen: bool) -> Option<PreviewScroll> {
+    if alt_screen || !key.modifiers.contains(Modifiers::SHIFT) {
+        return None;
+    }
+    match key.key {
+        KeyCode::PageUp => Some(PreviewScroll::PageUp),
+        KeyCode::PageDown => Some(PreviewScroll::PageDown),
+        KeyCode::Home => Some(PreviewScroll::Top),
+        KeyCode::End => Some(PreviewScroll::Bottom),
+        _ => None,
+    }
+}
+
+/// PgUp/PgDn by a page, `G` to the bottom and `gg` to the top. `pending_g`
+/// holds a first `g` until the next key.
+fn preview_page_key(key: &KeyEvent, pending_g: &mut bool) -> Option<PreviewScroll> {
+    let first_g = std::mem::take(pending_g);
+    if key.modifiers.intersects(Modifiers::CTRL | Modifiers::ALT) {
+        return None;
+    }
+    match key.key {
+        KeyCode::PageUp => Some(PreviewScroll::PageUp),
+        KeyCode::PageDown => Some(PreviewScroll::PageDown),
+        KeyCode::Char('G') => Some(PreviewScroll::Bottom),
+        KeyCode::Char('g') if first_g => Some(PreviewScroll::Top),
+        KeyCode::Char('g') => {
+            *pending_g = true;
+            None
         }
+        _ => None,
+    }
+}
+
+fn scroll_selected_preview(app: &mut App, scroll: PreviewScroll) {
+    let Some(agent_name) = app.agents.get(app.selected_agent).map(|a| a.name.clone()) else {
+        return;
+    };
+    let Some(view) = app.pty_views.get_mut(&agent_name) else {
+        return;
+    };
+    if view.mouse_tracking {
+        return;
     }
+    view.scroll_by(scroll);
 }
 
 fn mouse_wheel_sgr_bytes(
@@ -1620,13 +3552,43 @@ fn filtered_tool_indices(app: &App) -> Vec<usize> {
             repo.tools
                 .iter()
                 .enumerate()
-                .filter(|(_, tool)| filter.is_empty() || tool.to_lowercase().contains(&filter))
+                .filter(|(_, tool)| filter.is_empty() || tool.name.to_lowercase().contains(&filter))
                 .map(|(index, _)| index)
                 .collect()
         })
         .unwrap_or_default()
 }
 
+/// Root list filter: a case-insensitive substring of the agent's name,
+/// label, repo or tool. An empty filter matches every agent.
+fn agent_matches_filter(agent: &Agent, filter: &str) -> bool {
+    let filter = filter.trim().to_lowercase();
+    filter.is_empty()
+        || [&agent.name, &agent.label, &agent.repo, &agent.tool]
+            .iter()
+            .any(|field| field.to_lowercase().contains(&filter))
+}
+
+fn filtered_agent_indices(app: &App) -> Vec<usize> {
+    app.agents
+        .iter()
+        .enumerate()
+        .filter(|(_, agent)| agent_matches_filter(agent, &app.agent_list_filter))
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Moves the sidebar selection onto the first visible agent when the
+/// filter hides the selected one.
+fn sync_agent_list_selection(app: &mut App) {
+    let indices = filtered_agent_indices(app);
+    if let Some(first) = indices.first() {
+        if !indices.contains(&app.selected_agent) {
+            app.selected_agent = *first;
+        }
+    }
+}
+
 fn sync_filtered_selection(app: &mut App) {
     match app.agent_field {
         AgentField::Repo => {
@@ -1655,14 +3617,20 @@ fn draw(frame: &mut ratatui::Frame, app: &mut App) {
     let area = frame.area();
     frame.render_widget(Block::default().style(background_style), area);
 
-    let sections = Layout::vertical([Constraint::Min(0), Constraint::Length(3)]).split(area);
+    let banner_height = u16::from(app.config_error.is_some());
+    let sections = Layout::vertical([
+        Constraint::Min(0),
+        Constraint::Length(banner_height),
+        Constraint::Length(3),
+    ])
+    .split(area);
     let content_area = sections[0];
 
     app.preview_area = None;
     app.preview_agent = None;
     render_window(WindowId::Root, frame, app, content_area);
 
-    let status = app.status_message.clone();
+    let summary = agent_summary_spans(&app.agents);
     let footer_line = if app.focused_agent.is_some() {
         let mut spans = vec![
             Span::styled(
@@ -1673,11 +3641,26 @@ fn draw(frame: &mut ratatui::Frame, app: &mut App) {
                     .add_modifier(Modifier::BOLD),
             ),
             Span::raw(" "),
-            Span::styled("Ctrl+D to unfocus", Style::default().fg(THEME.fg_dim)),
         ];
-        if let Some(message) = status {
-            spans.push(Span::raw("  "));
-            spans.push(Span::styled(message, Style::default().fg(THEME.yellow)));
+        spans.extend(summary);
+        spans.push(Span::raw("   "));
+        let search = app
+            .focused_agent
+            .as_deref()
+            .and_then(|agent| app.search_for(agent));
+        match search {
+            Some(search) if search.editing => spans.push(Span::styled(
+                format!("/{}█", search.query),
+                Style::default().fg(THEME.fg),
+            )),
+            Some(search) => spans.push(Span::styled(
+                format!("/{}   (n) older   (N) newer   (Esc) done", search.query),
+                Style::default().fg(THEME.fg_dim),
+            )),
+            None => spans.push(Span::styled(
+                "Ctrl+D to unfocus   Shift+PgUp/PgDn scroll   (PgUp/PgDn G gg /) while scrolled",
+                Style::default().fg(THEME.fg_dim),
+            )),
         }
         Line::from(spans)
     } else {
@@ -1687,55 +3670,148 @@ fn draw(frame: &mut ratatui::Frame, app: &mut App) {
                 Style::default().fg(THEME.fg_mid).bg(THEME.bg_alt2),
             ),
             Span::raw(" "),
+        ];
+        spans.extend(summary);
+        spans.extend([
+            Span::raw("   "),
             Span::styled(
-                "(a) add agent   (d) delete agent   (R) restart agent   (r) add repo   (l) show repos   (u) refresh   (Enter) focus   (q) quit",
+                "(a) add agent   (d) delete agent   (R) restart agent   (M) merge   (s) sleep   (n) rename   (v) view diff   (i/T) interrupt/terminate   (r) add repo   (l) show repos   (u) refresh   (PgUp/PgDn G gg) scroll   (f) follow   (c) compact   (m) mouse   (F) fresh session   (o/e) shell/editor   (y/Y) copy name/path   (/) filter   (L) messages   (t) tail   (Enter) focus   (q) quit",
                 Style::default().fg(THEME.fg_dim),
             ),
-        ];
-        if let Some(message) = status {
-            spans.push(Span::raw("  "));
-            spans.push(Span::styled(message, Style::default().fg(THEME.yellow)));
-        }
+        ]);
         Line::from(spans)
     };
+    if let Some(message) = &app.config_error {
+        let banner = Line::from(vec![
+            Span::styled(
+                " CONFIG ",
+                Style::default()
+                    .fg(THEME.bg)
+                    .bg(THEME.red)
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(" "),
+            Span::styled(message.clone(), Style::default().fg(THEME.red)),
+        ]);
+        frame.render_widget(Paragraph::new(banner), sections[1]);
+    }
+
     let footer = Paragraph::new(footer_line).alignment(Alignment::Left);
-    let footer_area = sections[1].inner(Margin {
+    let footer_area = sections[2].inner(Margin {
         horizontal: 1,
         vertical: 1,
     });
     frame.render_widget(footer, footer_area);
+    render_status_toasts(frame, &app.status_log, content_area, Instant::now());
 
     if let Some(window) = app.focused_window {
         render_window(window, frame, app, content_area);
     }
+
+    theme::adapt_buffer(frame.buffer_mut());
 }
 
-fn build_name_line(agent: &Agent, animation_start: Instant) -> Line<'static> {
-    match agent.status.as_str() {
-        "running" => icon_name_line(
-            ICON_ACTIVE,
-            pulsing_green_color(animation_start),
-            &agent.label,
-        ),
-        "error" => icon_name_line(ICON_ERROR, THEME.red, &agent.label),
-        "idle" => icon_name_line(ICON_IDLE, THEME.blue, &agent.label),
-        "sleep" => icon_name_line(ICON_IDLE, THEME.fg_dim, &agent.label),
-        _ => icon_name_line(ICON_IDLE, THEME.fg_dim, &agent.label),
+/// Stacks recent status messages in the bottom-right corner, fading each
+/// toward the dim text color as it ages.
+fn render_status_toasts(frame: &mut ratatui::Frame, log: &StatusLog, area: Rect, now: Instant) {
+    let toasts = log.toasts(now);
+    let bottom = area.y + area.height;
+    for (index, (message, progress)) in toasts.iter().rev().enumerate() {
+        let width = (message.chars().count() as u16 + 2).min(area.width);
+        let row = index as u16 + 1;
+        if width == 0 || row > area.height {
+            break;
+        }
+        let toast_area = Rect::new(area.x + area.width - width, bottom - row, width, 1);
+        let color = blend_color(THEME.yellow, THEME.fg_dim, *progress);
+        let toast = Paragraph::new(format!(" {message} "))
+            .style(Style::default().fg(color).bg(THEME.bg_alt2));
+        frame.render_widget(Clear, toast_area);
+        frame.render_widget(toast, toast_area);
     }
 }
 
-fn icon_name_line(icon: &str, color: Color, label: &str) -> Line<'static> {
-    Line::from(vec![
-        Span::styled(
-            label.to_string(),
-            Style::default().fg(THEME.fg).add_modifier(Modifier::BOLD),
-        ),
-        Span::raw(" "),
-        Span::styled(icon.to_string(), Style::default().fg(color)),
-    ])
-}
+const SUMMARY_STATUS_ORDER: [&str; 4] = ["running", "idle", "error", "sleep"];
 
-fn pulsing_green_color(animation_start: Instant) -> Color {
+/// Counts agents per status, known statuses first and any others after in
+/// name order. Statuses without agents are omitted.
+fn agent_status_counts(agents: &[Agent]) -> Vec<(String, usize)> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for agent in agents {
+        *counts.entry(agent.status.as_str()).or_default() += 1;
+    }
+    let mut ordered = Vec::new();
+    for status in SUMMARY_STATUS_ORDER {
+        if let Some(count) = counts.remove(status) {
+            ordered.push((status.to_string(), count));
+        }
+    }
+    ordered.extend(
+        counts
+            .into_iter()
+            .map(|(status, count)| (status.to_string(), count)),
+    );
+    ordered
+}
+
+fn agent_summary_spans(agents: &[Agent]) -> Vec<Span<'static>> {
+    let noun = if agents.len() == 1 { "agent" } else { "agents" };
+    let mut spans = vec![Span::styled(
+        format!("{} {}", agents.len(), noun),
+        Style::default().fg(THEME.fg_mid),
+    )];
+    for (status, count) in agent_status_counts(agents) {
+        spans.push(Span::styled(" · ", Style::default().fg(THEME.fg_dim)));
+        spans.push(Span::styled(
+            format!("{count} {status}"),
+            Style::default().fg(windows::root::status_color(&status)),
+        ));
+    }
+    spans
+}
+
+fn build_name_line(agent: &Agent, animation_start: Instant) -> Line<'static> {
+    let color = match agent.status.as_str() {
+        "running" => pulsing_green_color(animation_start),
+        status => windows::root::status_color(status),
+    };
+    let icon = status_icons().for_status(&agent.status);
+    let mut line = icon_name_line(icon, color, &agent.label);
+    if let (Some(code), "error") = (agent.exit_code, agent.status.as_str()) {
+        line.spans.push(Span::styled(
+            format!(" exit {code}"),
+            Style::default().fg(THEME.red),
+        ));
+    }
+    if let Some(indicator) = dirty_indicator_span(agent.dirty_count) {
+        line.spans.push(Span::raw(" "));
+        line.spans.push(indicator);
+    }
+    line
+}
+
+fn dirty_indicator_span(dirty_count: Option<usize>) -> Option<Span<'static>> {
+    match dirty_count {
+        Some(count) if count > 0 => Some(Span::styled(
+            format!("±{count}"),
+            Style::default().fg(THEME.yellow),
+        )),
+        _ => None,
+    }
+}
+
+fn icon_name_line(icon: &str, color: Color, label: &str) -> Line<'static> {
+    Line::from(vec![
+        Span::styled(
+            label.to_string(),
+            Style::default().fg(THEME.fg).add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(" "),
+        Span::styled(icon.to_string(), Style::default().fg(color)),
+    ])
+}
+
+fn pulsing_green_color(animation_start: Instant) -> Color {
     let elapsed = animation_start.elapsed().as_secs_f32();
     let pulse = (elapsed * 2.0).sin().abs();
     blend_color(THEME.green_dim, THEME.green, pulse)
@@ -1760,6 +3836,10 @@ fn blend_color(from: Color, to: Color, amount: f32) -> Color {
     }
 }
 
+fn env_flag(name: &str) -> bool {
+    flag_setting(std::env::var(name).ok().as_deref(), false)
+}
+
 fn centered_rect(percent_x: u16, percent_y: u16, rect: Rect) -> Rect {
     let popup_layout = Layout::vertical([
         Constraint::Percentage((100 - percent_y) / 2),
@@ -1776,45 +3856,101 @@ fn centered_rect(percent_x: u16, percent_y: u16, rect: Rect) -> Rect {
     .split(popup_layout[1])[1]
 }
 
+/// The kind of the agent's tool per its repo's config, so shells can be
+/// badged. Tools no longer configured are classified by name.
+fn agent_tool_kind(repos: &[RepoConfig], agent: &Agent) -> ToolKind {
+    repos
+        .iter()
+        .find(|repo| repo.name == agent.repo)
+        .and_then(|repo| repo.tools.iter().find(|tool| tool.name == agent.tool))
+        .map_or_else(
+            || ToolSpec::from(agent.tool.as_str()).kind(),
+            ToolSpec::kind,
+        )
+}
+
 fn default_tool_index(repo: &RepoConfig) -> usize {
     repo.tools
         .iter()
-        .position(|tool| tool == &repo.default_tool)
+        .position(|tool| tool.name == repo.default_tool)
         .unwrap_or(0)
 }
 
-fn fetch_repos(client: &Client, server_url: &str) -> Result<Vec<RepoConfig>, String> {
-    let url = format!("{}/repos", server_url);
-    let response = client.get(url).send().map_err(|err| err.to_string())?;
-    if !response.status().is_success() {
-        return Err(response
-            .text()
-            .unwrap_or_else(|_| "failed to load repos".to_string()));
+/// Status the server uses when it cannot write its config directory.
+const CONFIG_NOT_WRITABLE_STATUS: u16 = 507;
+
+/// Why a request to the server failed: it could not be reached, it answered
+/// with a non-2xx status, or its body did not decode.
+#[derive(Debug, PartialEq, Eq)]
+enum FetchError {
+    Transport(String),
+    Http { status: u16, message: String },
+    Decode(String),
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Transport(err) => write!(f, "server unreachable: {err}"),
+            FetchError::Http { status, message } if message.is_empty() => {
+                write!(f, "server error ({status})")
+            }
+            FetchError::Http { status, message } => {
+                write!(f, "server error ({status}): {message}")
+            }
+            FetchError::Decode(err) => write!(f, "unexpected server response: {err}"),
+        }
     }
-    response.json().map_err(|err| err.to_string())
 }
 
-fn fetch_agents(client: &Client, server_url: &str) -> Result<Vec<Agent>, String> {
-    let url = format!("{}/agents", server_url);
-    client
-        .get(&url)
-        .send()
-        .map_err(|err| err.to_string())?
+fn fetch_json<T: serde::de::DeserializeOwned>(
+    response: reqwest::Result<reqwest::blocking::Response>,
+) -> Result<T, FetchError> {
+    let response = response.map_err(|err| FetchError::Transport(err.to_string()))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(FetchError::Http {
+            status: status.as_u16(),
+            message: response.text().unwrap_or_default().trim().to_string(),
+        });
+    }
+    response
         .json()
-        .map_err(|err| err.to_string())
+        .map_err(|err| FetchError::Decode(err.to_string()))
+}
+
+fn fetch_repos(client: &Client, server_url: &str) -> Result<Vec<RepoConfig>, FetchError> {
+    let url = format!("{}/repos", server_url);
+    fetch_json(client.get(url).send())
+}
+
+fn fetch_tools(client: &Client, server_url: &str) -> Result<HashMap<String, bool>, FetchError> {
+    let url = format!("{}/tools", server_url);
+    let tools: Vec<ToolAvailability> = fetch_json(client.get(url).send())?;
+
+    Ok(tools
+        .into_iter()
+        .map(|tool| (tool.name, tool.available))
+        .collect())
+}
+
+fn fetch_agents(client: &Client, server_url: &str) -> Result<Vec<Agent>, FetchError> {
+    let url = format!("{}/agents", server_url);
+    fetch_json(client.get(url).send())
 }
 
 fn fetch_agents_output(
     client: &Client,
     server_url: &str,
-) -> Result<HashMap<String, AgentOutput>, String> {
+    feed: &TailFeed,
+) -> Result<HashMap<String, AgentOutput>, FetchError> {
     let url = format!("{}/agents/output", server_url);
-    let outputs = client
-        .get(&url)
-        .send()
-        .map_err(|err| err.to_string())?
-        .json::<Vec<AgentOutput>>()
-        .map_err(|err| err.to_string())?;
+    let since = feed.since_query();
+    let mut request = client.get(url);
+    if !since.is_empty() {
+        request = request.query(&[("since", since)]);
+    }
+    let outputs: Vec<AgentOutput> = fetch_json(request.send())?;
 
     Ok(outputs
         .into_iter()
@@ -1822,21 +3958,24 @@ fn fetch_agents_output(
         .collect())
 }
 
-fn add_repo(client: &Client, server_url: &str, path: &str) -> Result<RepoConfig, String> {
+fn add_repo(
+    client: &Client,
+    server_url: &str,
+    path: &str,
+    name: Option<String>,
+    dry_run: bool,
+) -> Result<RepoConfig, FetchError> {
     let url = format!("{}/repos", server_url);
-    let response = client
-        .post(url)
-        .json(&AddRepoRequest {
-            path: path.to_string(),
-        })
-        .send()
-        .map_err(|err| err.to_string())?;
-    if !response.status().is_success() {
-        return Err(response
-            .text()
-            .unwrap_or_else(|_| "failed to add repo".to_string()));
-    }
-    response.json().map_err(|err| err.to_string())
+    fetch_json(
+        client
+            .post(url)
+            .json(&AddRepoRequest {
+                path: path.to_string(),
+                name,
+                dry_run,
+            })
+            .send(),
+    )
 }
 
 fn add_agent(
@@ -1875,6 +4014,44 @@ fn delete_agent(client: &Client, server_url: &str, name: &str) -> Result<(), Str
     Ok(())
 }
 
+/// Removes a repo from the config; the server refuses with 409 while
+/// agents still use it.
+fn delete_repo(client: &Client, server_url: &str, name: &str) -> Result<(), FetchError> {
+    let url = format!("{}/repos/{}", server_url, name);
+    let response = client
+        .delete(url)
+        .send()
+        .map_err(|err| FetchError::Transport(err.to_string()))?;
+    let status = response.status();
+    if !status.is_success() {
+        return Err(FetchError::Http {
+            status: status.as_u16(),
+            message: response.text().unwrap_or_default().trim().to_string(),
+        });
+    }
+    Ok(())
+}
+
+#[derive(Serialize)]
+struct RenameAgentRequest<'a> {
+    label: &'a str,
+}
+
+fn rename_agent(client: &Client, server_url: &str, name: &str, label: &str) -> Result<(), String> {
+    let url = format!("{}/agents/{}", server_url, name);
+    let response = client
+        .patch(url)
+        .json(&RenameAgentRequest { label })
+        .send()
+        .map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(response
+            .text()
+            .unwrap_or_else(|_| "failed to rename agent".to_string()));
+    }
+    Ok(())
+}
+
 fn restart_agent(client: &Client, server_url: &str, name: &str) -> Result<(), String> {
     let url = format!("{}/agents/{}/restart", server_url, name);
     let response = client.post(url).send().map_err(|err| err.to_string())?;
@@ -1885,3 +4062,2082 @@ fn restart_agent(client: &Client, server_url: &str, name: &str) -> Result<(), St
     }
     Ok(())
 }
+
+/// Merges the agent's branch into the branch checked out in its repo. A
+/// conflict comes back as the server's message.
+fn merge_agent(client: &Client, server_url: &str, name: &str) -> Result<AgentMerge, String> {
+    let url = format!("{}/agents/{}/merge", server_url, name);
+    let response = client
+        .post(url)
+        .json(&MergeRequest { ff_only: false })
+        .send()
+        .map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(response
+            .text()
+            .unwrap_or_else(|_| "failed to merge agent".to_string()));
+    }
+    response.json().map_err(|err| err.to_string())
+}
+
+fn sleep_agent(client: &Client, server_url: &str, name: &str) -> Result<(), String> {
+    let url = format!("{}/agents/{}/sleep", server_url, name);
+    let response = client.post(url).send().map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(response
+            .text()
+            .unwrap_or_else(|_| "failed to put agent to sleep".to_string()));
+    }
+    Ok(())
+}
+
+fn signal_agent(client: &Client, server_url: &str, name: &str, signal: &str) -> Result<(), String> {
+    let url = format!("{}/agents/{}/signal", server_url, name);
+    let response = client
+        .post(url)
+        .json(&SignalRequest {
+            signal: signal.to_string(),
+        })
+        .send()
+        .map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(response
+            .text()
+            .unwrap_or_else(|_| "failed to signal agent".to_string()));
+    }
+    Ok(())
+}
+
+fn fetch_diff(
+    client: &Client,
+    server_url: &str,
+    name: &str,
+    stat: bool,
+) -> Result<AgentDiff, String> {
+    let url = format!("{}/agents/{}/diff", server_url, name);
+    let response = client
+        .get(url)
+        .query(&[("stat", stat)])
+        .send()
+        .map_err(|err| err.to_string())?;
+    if !response.status().is_success() {
+        return Err(response
+            .text()
+            .unwrap_or_else(|_| "failed to load diff".to_string()));
+    }
+    response.json().map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use workforest_core::CursorState;
+
+    fn test_view(agent: &str, size: (u16, u16)) -> (PtyView, SyncSender<Vec<u8>>) {
+        let (sender, receiver) = mpsc::sync_channel(PTY_CHANNEL_CHUNKS);
+        let reader = PtyReader {
+            stop: Arc::new(AtomicBool::new(false)),
+            handle: None,
+        };
+        (PtyView::new(agent, size, receiver, reader), sender)
+    }
+
+    fn feed(view: &mut PtyView, bytes: &[u8]) {
+        let mut actions = Vec::new();
+        view.parser.parse(bytes, |action| actions.push(action));
+        for action in actions {
+            apply_action_to_view(action, view);
+        }
+    }
+
+    fn screen_line(view: &PtyView, row: usize) -> String {
+        view.active_surface().screen_lines()[row]
+            .as_str()
+            .trim_end()
+            .to_string()
+    }
+
+    #[test]
+    fn erase_to_start_of_line_blanks_prefix_through_cursor() {
+        let (mut view, _sender) = test_view("wild-cat", (20, 3));
+        feed(&mut view, b"abcdefgh\r\nnext line");
+        feed(&mut view, b"\x1b[1;4H\x1b[41m\x1b[1K");
+        assert_eq!(screen_line(&view, 0), "    efgh");
+        assert_eq!(screen_line(&view, 1), "next line");
+        assert_eq!(view.active_surface().cursor_position(), (3, 0));
+
+        let background = |view: &PtyView, column: usize| {
+            view.active_surface().screen_lines()[0]
+                .get_cell(column)
+                .map(|cell| cell.attrs().background())
+        };
+        assert_eq!(background(&view, 0), Some(ColorAttribute::PaletteIndex(1)));
+        assert_eq!(background(&view, 4), Some(ColorAttribute::Default));
+
+        // The pen survives the erase.
+        feed(&mut view, b"X");
+        assert_eq!(screen_line(&view, 0), "   Xefgh");
+        assert_eq!(background(&view, 3), Some(ColorAttribute::PaletteIndex(1)));
+
+        feed(&mut view, b"\x1b[2;3H\x1b[2K");
+        assert_eq!(screen_line(&view, 1), "");
+        assert_eq!(view.active_surface().cursor_position(), (2, 1));
+    }
+
+    #[test]
+    fn erase_to_start_of_display_keeps_rows_below_cursor() {
+        let (mut view, _sender) = test_view("wild-cat", (10, 4));
+        feed(&mut view, b"row one\r\nrow two\r\nrow three\r\nrow four");
+        feed(&mut view, b"\x1b[2;5H\x1b[1J");
+        assert_eq!(screen_line(&view, 0), "");
+        assert_eq!(screen_line(&view, 1), "     wo");
+        assert_eq!(screen_line(&view, 2), "row three");
+        assert_eq!(screen_line(&view, 3), "row four");
+        assert_eq!(view.active_surface().cursor_position(), (4, 1));
+    }
+
+    #[test]
+    fn insert_and_delete_line_shift_rows_below_cursor() {
+        let (mut view, _sender) = test_view("wild-cat", (10, 6));
+        feed(&mut view, b"one\r\ntwo\r\nthree\r\nfour\r\nfive\r\nsix");
+        feed(&mut view, b"\x1b[3;2H\x1b[2L");
+        let rows: Vec<String> = (0..6).map(|row| screen_line(&view, row)).collect();
+        assert_eq!(rows, ["one", "two", "", "", "three", "four"]);
+        assert_eq!(view.active_surface().cursor_position(), (0, 2));
+
+        feed(&mut view, b"\x1b[2M");
+        let rows: Vec<String> = (0..6).map(|row| screen_line(&view, row)).collect();
+        assert_eq!(rows, ["one", "two", "three", "four", "", ""]);
+    }
+
+    #[test]
+    fn insert_line_stays_within_scroll_region() {
+        let (mut view, _sender) = test_view("wild-cat", (10, 5));
+        feed(&mut view, b"one\r\ntwo\r\nthree\r\nfour\r\nfive");
+        feed(&mut view, b"\x1b[2;4r\x1b[3;1H\x1b[L");
+        let rows: Vec<String> = (0..5).map(|row| screen_line(&view, row)).collect();
+        assert_eq!(rows, ["one", "two", "", "three", "five"]);
+
+        // Outside the region the sequence is ignored.
+        feed(&mut view, b"\x1b[5;1H\x1b[L");
+        assert_eq!(screen_line(&view, 4), "five");
+    }
+
+    #[test]
+    fn delete_insert_and_erase_characters_edit_the_cursor_row() {
+        let (mut view, _sender) = test_view("wild-cat", (10, 2));
+        feed(&mut view, b"abcdefghij\r\nnext");
+        feed(&mut view, b"\x1b[1;3H\x1b[2P");
+        assert_eq!(screen_line(&view, 0), "abefghij");
+
+        feed(&mut view, b"\x1b[2@");
+        assert_eq!(screen_line(&view, 0), "ab  efghij");
+
+        feed(&mut view, b"\x1b[1;5H\x1b[44m\x1b[3X");
+        assert_eq!(screen_line(&view, 0), "ab     hij");
+        let background = |view: &PtyView, column: usize| {
+            view.active_surface().screen_lines()[0]
+                .get_cell(column)
+                .map(|cell| cell.attrs().background())
+        };
+        assert_eq!(background(&view, 6), Some(ColorAttribute::PaletteIndex(4)));
+        assert_eq!(background(&view, 7), Some(ColorAttribute::Default));
+
+        // Counts past the edge are clamped and the cursor never moves.
+        feed(&mut view, b"\x1b[99P");
+        assert_eq!(screen_line(&view, 0), "ab");
+        assert_eq!(view.active_surface().cursor_position(), (4, 0));
+        assert_eq!(screen_line(&view, 1), "next");
+    }
+
+    #[test]
+    fn kitty_image_is_replaced_by_placeholder() {
+        let (mut view, _sender) = test_view("wild-cat", (30, 3));
+        feed(
+            &mut view,
+            b"before \x1b_Ga=T,f=100;iVBORw0KGgo=\x1b\\ after",
+        );
+        assert_eq!(screen_line(&view, 0), "before [image] after");
+    }
+
+    #[test]
+    fn iterm_image_is_replaced_by_placeholder() {
+        let (mut view, _sender) = test_view("wild-cat", (30, 3));
+        feed(
+            &mut view,
+            b"before \x1b]1337;File=inline=1;size=4:aGVsbG8=\x07 after",
+        );
+        assert_eq!(screen_line(&view, 0), "before [image] after");
+    }
+
+    #[test]
+    fn bracketed_paste_mode_wraps_pastes() {
+        let (mut view, _sender) = test_view("wild-cat", (20, 3));
+        feed(&mut view, b"\x1b[?2004h");
+        assert!(view.bracketed_paste);
+
+        let mut parser = termwiz::input::InputParser::new();
+        let events = event::parse_ui_events(
+            &mut parser,
+            b"\x1b[200~line one\rline two\x1b[201~".to_vec(),
+        );
+        assert_eq!(events.len(), 1);
+        let InputEvent::Paste(text) = &events[0].event else {
+            panic!("expected a paste, got {:?}", events[0].event);
+        };
+        assert_eq!(
+            paste_payload(text.as_bytes(), view.bracketed_paste),
+            b"\x1b[200~line one\rline two\x1b[201~"
+        );
+
+        feed(&mut view, b"\x1b[?2004l");
+        assert_eq!(paste_payload(b"a\rb", view.bracketed_paste), b"a\rb");
+
+        let (mut restored, _sender) = test_view("wild-cat", (20, 3));
+        let snapshot = TerminalSnapshot {
+            bracketed_paste: true,
+            ..TerminalSnapshot::default()
+        };
+        apply_snapshot_to_view(&mut restored, &snapshot);
+        assert!(restored.bracketed_paste);
+    }
+
+    #[test]
+    fn fast_typing_in_one_read_stays_keystrokes() {
+        let mut parser = termwiz::input::InputParser::new();
+        let events = event::parse_ui_events(&mut parser, b"ls\r\x04\x1b[A".to_vec());
+        let raws: Vec<&[u8]> = events.iter().map(|event| event.raw.as_slice()).collect();
+        assert_eq!(raws, [&b"l"[..], b"s", b"\r", b"\x04", b"\x1b[A"]);
+        assert!(events
+            .iter()
+            .all(|event| matches!(event.event, InputEvent::Key(_))));
+        let InputEvent::Key(ctrl_d) = &events[3].event else {
+            unreachable!();
+        };
+        assert_eq!(ctrl_d.key, KeyCode::Char('d'));
+        assert!(ctrl_d.modifiers.contains(Modifiers::CTRL));
+
+        // A lone ESC at the end of a read is still delivered.
+        let events = event::parse_ui_events(&mut parser, b"\x1b".to_vec());
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].raw, b"\x1b");
+    }
+
+    #[test]
+    fn application_cursor_keys_reencode_arrows() {
+        let (mut view, _sender) = test_view("wild-cat", (20, 3));
+        feed(&mut view, b"\x1b[?1h");
+        assert!(view.application_cursor_keys);
+
+        let up = plain_key(KeyCode::UpArrow);
+        assert_eq!(cursor_key_payload(b"\x1b[A", &up, true), b"\x1bOA");
+        assert_eq!(
+            cursor_key_payload(b"\x1b[H", &plain_key(KeyCode::Home), true),
+            b"\x1bOH"
+        );
+        assert_eq!(cursor_key_payload(b"\x1b[A", &up, false), b"\x1b[A");
+        let shifted = KeyEvent {
+            key: KeyCode::UpArrow,
+            modifiers: Modifiers::SHIFT,
+        };
+        assert_eq!(
+            cursor_key_payload(b"\x1b[1;2A", &shifted, true),
+            b"\x1b[1;2A"
+        );
+        assert_eq!(
+            cursor_key_payload(b"x", &plain_key(KeyCode::Char('x')), true),
+            b"x"
+        );
+
+        feed(&mut view, b"\x1b[?1l");
+        assert!(!view.application_cursor_keys);
+
+        let (mut restored, _sender) = test_view("wild-cat", (20, 3));
+        let snapshot = TerminalSnapshot {
+            application_cursor_keys: true,
+            ..TerminalSnapshot::default()
+        };
+        apply_snapshot_to_view(&mut restored, &snapshot);
+        assert!(restored.application_cursor_keys);
+    }
+
+    #[test]
+    fn snapshot_restores_origin_mode() {
+        let (mut view, _sender) = test_view("wild-cat", (20, 10));
+        let snapshot = TerminalSnapshot {
+            origin_mode: true,
+            scroll_region: Some(ScrollRegion { top: 2, bottom: 7 }),
+            ..TerminalSnapshot::default()
+        };
+        apply_snapshot_to_view(&mut view, &snapshot);
+        assert!(view.origin_mode);
+
+        feed(&mut view, b"\x1b[1;1H");
+        assert_eq!(view.active_surface().cursor_position(), (0, 2));
+        feed(&mut view, b"\x1b[20;4H");
+        assert_eq!(view.active_surface().cursor_position(), (3, 7));
+    }
+
+    fn agent_with_status(name: &str, status: &str) -> Agent {
+        Agent {
+            name: name.to_string(),
+            label: name.to_string(),
+            repo: "forest".to_string(),
+            tool: "claude".to_string(),
+            status: status.to_string(),
+            worktree_path: "/tmp".to_string(),
+            output: None,
+            dirty_count: None,
+            exit_code: None,
+            debug_data: DebugData::default(),
+        }
+    }
+
+    fn tab_to_column(view: &mut PtyView) -> usize {
+        feed(view, b"\r\t");
+        view.active_surface().cursor_position().0
+    }
+
+    #[test]
+    fn insert_mode_query_reports_view_state() {
+        let (mut view, _sender) = test_view("agent", (20, 5));
+        assert_eq!(
+            replies(&mut view, b"\x1b[4$p"),
+            vec![b"\x1b[4;2$y".to_vec()]
+        );
+        feed(&mut view, b"\x1b[4h");
+        assert_eq!(
+            replies(&mut view, b"\x1b[4$p"),
+            vec![b"\x1b[4;1$y".to_vec()]
+        );
+        feed(&mut view, b"\x1b[4l");
+        assert_eq!(
+            replies(&mut view, b"\x1b[4$p"),
+            vec![b"\x1b[4;2$y".to_vec()]
+        );
+    }
+
+    #[test]
+    fn untracked_ansi_mode_query_reports_unrecognized() {
+        let (mut view, _sender) = test_view("agent", (20, 5));
+        assert_eq!(
+            replies(&mut view, b"\x1b[20$p"),
+            vec![b"\x1b[20;0$y".to_vec()]
+        );
+        assert_eq!(
+            replies(&mut view, b"\x1b[99$p"),
+            vec![b"\x1b[99;0$y".to_vec()]
+        );
+    }
+
+    #[test]
+    fn tab_clear_all_removes_every_stop() {
+        let (mut view, _sender) = test_view("agent", (40, 5));
+        feed(&mut view, b"\x1b[12G\x1bH");
+        assert_eq!(tab_to_column(&mut view), 8);
+        feed(&mut view, b"\x1b[3g");
+        assert!(view.tab_stops.is_empty());
+        assert_eq!(tab_to_column(&mut view), 39);
+    }
+
+    #[test]
+    fn tab_clear_at_cursor_removes_only_that_stop() {
+        let (mut view, _sender) = test_view("agent", (40, 5));
+        feed(&mut view, b"\x1b[12G\x1bH");
+        feed(&mut view, b"\x1b[9G\x1b[0g");
+        assert_eq!(view.tab_stops, BTreeSet::from([11, 16, 24, 32]));
+        assert_eq!(tab_to_column(&mut view), 11);
+    }
+
+    #[test]
+    fn shell_agents_are_badged_in_the_sidebar() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.repos = vec![RepoConfig {
+            name: "forest".to_string(),
+            tools: vec![ToolSpec::from("claude"), ToolSpec::from("zsh")],
+            ..RepoConfig::default()
+        }];
+        let mut shell = agent_with_status("calm-owl", "running");
+        shell.tool = "zsh".to_string();
+        app.agents = vec![agent_with_status("wild-cat", "running"), shell];
+        assert_eq!(agent_tool_kind(&app.repos, &app.agents[0]), ToolKind::Agent);
+        assert_eq!(agent_tool_kind(&app.repos, &app.agents[1]), ToolKind::Shell);
+
+        let screen = render_screen(&mut app, 80, 24);
+        assert_eq!(screen.matches("[shell]").count(), 1, "{screen}");
+    }
+
+    fn draw_app(app: &mut App) {
+        render_screen(app, 80, 24);
+    }
+
+    /// Draws the app on a `width`×`height` test terminal and returns the
+    /// screen's text, row after row.
+    fn render_screen(app: &mut App, width: u16, height: u16) -> String {
+        let mut terminal =
+            Terminal::new(ratatui::backend::TestBackend::new(width, height)).unwrap();
+        terminal.draw(|frame| draw(frame, app)).unwrap();
+        terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect()
+    }
+
+    #[test]
+    fn hiding_the_cursor_on_the_alt_screen_keeps_the_main_cursor() {
+        let (mut view, _sender) = test_view("wild-cat", (40, 5));
+        feed(
+            &mut view,
+            b"\x1b[4 q\x1b[?1049h\x1b[?25l\x1b[6 q\x1b[?1049l",
+        );
+        assert_eq!(
+            view.active_surface().cursor_visibility(),
+            termwiz::surface::CursorVisibility::Visible
+        );
+        assert_eq!(
+            view.active_surface().cursor_shape(),
+            Some(termwiz::surface::CursorShape::SteadyUnderline)
+        );
+
+        feed(&mut view, b"\x1b[?1049h");
+        assert_eq!(
+            view.active_surface().cursor_visibility(),
+            termwiz::surface::CursorVisibility::Visible
+        );
+    }
+
+    #[test]
+    fn snapshot_restores_the_main_cursor_saved_under_the_alt_screen() {
+        let (mut view, _sender) = test_view("wild-cat", (40, 5));
+        let snapshot = TerminalSnapshot {
+            alt_screen: true,
+            cursor_visible: false,
+            main_cursor: Some(CursorState {
+                visible: true,
+                shape: CursorShape::SteadyBar,
+            }),
+            ..Default::default()
+        };
+        apply_snapshot_to_view(&mut view, &snapshot);
+        assert_eq!(
+            view.alt_surface.cursor_visibility(),
+            termwiz::surface::CursorVisibility::Hidden
+        );
+        feed(&mut view, b"\x1b[?1049l");
+        assert_eq!(
+            view.active_surface().cursor_visibility(),
+            termwiz::surface::CursorVisibility::Visible
+        );
+        assert_eq!(
+            view.active_surface().cursor_shape(),
+            Some(termwiz::surface::CursorShape::SteadyBar)
+        );
+    }
+
+    #[test]
+    fn debug_toggle_previews_the_inactive_screen() {
+        let (mut view, _sender) = test_view("wild-cat", (40, 5));
+        feed(&mut view, b"main text\x1b[?1049halt text");
+        assert_eq!(
+            view.preview_surface(false).screen_lines()[0]
+                .as_str()
+                .trim_end(),
+            "alt text"
+        );
+        assert_eq!(
+            view.preview_surface(true).screen_lines()[0]
+                .as_str()
+                .trim_end(),
+            "main text"
+        );
+
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.agents = vec![agent_with_status("wild-cat", "running")];
+        app.pty_views.insert("wild-cat".to_string(), view);
+
+        // The override only takes effect with the debug sidebar open.
+        handle_key_event(&mut app, shift_key(KeyCode::Char('S'))).unwrap();
+        assert!(!app.show_inactive_surface);
+        handle_key_event(&mut app, shift_key(KeyCode::Char('D'))).unwrap();
+        let active = render_screen(&mut app, 160, 24);
+        assert!(active.contains("alt text") && !active.contains("main text"));
+
+        handle_key_event(&mut app, shift_key(KeyCode::Char('S'))).unwrap();
+        let inactive = render_screen(&mut app, 160, 24);
+        assert!(inactive.contains("main text") && !inactive.contains("alt text"));
+        assert!(inactive.contains("[main screen, inactive]"), "{inactive}");
+
+        handle_key_event(&mut app, shift_key(KeyCode::Char('D'))).unwrap();
+        assert!(render_screen(&mut app, 160, 24).contains("alt text"));
+    }
+
+    #[test]
+    fn tiny_focused_pane_follows_the_cursor_row() {
+        // The agent's 24-row screen has not been resized to the pane yet and
+        // its cursor sits on screen row 5, well above the bottom.
+        let (mut view, _sender) = test_view("wild-cat", (40, 24));
+        for line in 0..40 {
+            feed(&mut view, format!("line {line}\r\n").as_bytes());
+        }
+        feed(&mut view, b"\x1b[6;1H\x1b[2Kprompt$ ");
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.agents = vec![agent_with_status("wild-cat", "running")];
+        app.pty_views.insert("wild-cat".to_string(), view);
+        app.focused_agent = Some("wild-cat".to_string());
+
+        let screen = render_screen(&mut app, 80, 8);
+        assert!(screen.contains("prompt$"), "{screen}");
+        assert!(!screen.contains("line 39"), "{screen}");
+
+        // Scrolled back, the window is placed by the scroll offset instead.
+        app.pty_views.get_mut("wild-cat").unwrap().scroll_offset = 1;
+        let screen = render_screen(&mut app, 80, 8);
+        assert!(!screen.contains("prompt$"), "{screen}");
+        assert!(screen.contains("line 16"), "{screen}");
+    }
+
+    #[test]
+    fn debug_sidebar_resizes_and_scrolls_within_its_height() {
+        let (mut view, _sender) = test_view("wild-cat", (40, 5));
+        feed(&mut view, b"text");
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.agents = vec![agent_with_status("wild-cat", "running")];
+        app.pty_views.insert("wild-cat".to_string(), view);
+
+        handle_key_event(&mut app, shift_key(KeyCode::Char('D'))).unwrap();
+        handle_key_event(&mut app, shift_key(KeyCode::Char('>'))).unwrap();
+        assert_eq!(app.debug_sidebar_width, 36);
+        for _ in 0..10 {
+            handle_key_event(&mut app, shift_key(KeyCode::Char('<'))).unwrap();
+        }
+        assert_eq!(app.debug_sidebar_width, MIN_DEBUG_SIDEBAR_WIDTH);
+        handle_key_event(&mut app, shift_key(KeyCode::Char('>'))).unwrap();
+
+        let top = render_screen(&mut app, 160, 8);
+        // Growing stops at the width the sidebar can actually take.
+        let width = app.debug_sidebar_width;
+        for _ in 0..50 {
+            handle_key_event(&mut app, shift_key(KeyCode::Char('>'))).unwrap();
+        }
+        assert_eq!(app.debug_sidebar_width, 160 / 3);
+        handle_key_event(&mut app, shift_key(KeyCode::Char('<'))).unwrap();
+        assert_eq!(app.debug_sidebar_width, 160 / 3 - 4);
+        app.debug_sidebar_width = width;
+        assert!(top.contains("agent: wild-cat") && !top.contains("history: none"));
+
+        for _ in 0..50 {
+            handle_key_event(&mut app, plain_key(KeyCode::Char(']'))).unwrap();
+        }
+        let bottom = render_screen(&mut app, 160, 8);
+        assert!(bottom.contains("history: none"), "{bottom}");
+        assert!(!bottom.contains("agent: wild-cat"));
+        // Rendering clamped the offset, so one step back moves the view.
+        let clamped = app.debug_scroll;
+        assert!(clamped < 50);
+        handle_key_event(&mut app, plain_key(KeyCode::Char('['))).unwrap();
+        assert_eq!(app.debug_scroll, clamped - 1);
+    }
+
+    #[test]
+    fn agent_filter_matches_name_label_repo_and_tool() {
+        let mut agent = agent_with_status("wild-cat", "running");
+        agent.label = "Fix login".to_string();
+        agent.repo = "webapp".to_string();
+        agent.tool = "codex".to_string();
+        for filter in ["", "wild", "LOGIN", "webapp", "codex", " cod "] {
+            assert!(agent_matches_filter(&agent, filter), "{filter:?}");
+        }
+        assert!(!agent_matches_filter(&agent, "claude"));
+    }
+
+    #[test]
+    fn root_filter_narrows_list_and_navigation() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        let mut codex = agent_with_status("blue-fox", "running");
+        codex.tool = "codex".to_string();
+        app.agents = vec![
+            agent_with_status("wild-cat", "running"),
+            codex,
+            agent_with_status("red-owl", "running"),
+        ];
+        app.agents[0].tool = "claude".to_string();
+        app.agents[2].tool = "claude".to_string();
+        app.selected_agent = 1;
+
+        handle_key_event(&mut app, plain_key(KeyCode::Char('/'))).unwrap();
+        for ch in "claude".chars() {
+            handle_key_event(&mut app, plain_key(KeyCode::Char(ch))).unwrap();
+        }
+        assert_eq!(filtered_agent_indices(&app), [0, 2]);
+        assert_eq!(app.selected_agent, 0);
+
+        handle_key_event(&mut app, plain_key(KeyCode::Enter)).unwrap();
+        assert!(!app.editing_agent_list_filter);
+        handle_key_event(&mut app, plain_key(KeyCode::DownArrow)).unwrap();
+        assert_eq!(app.selected_agent, 2);
+
+        // Once the input is closed, letters are root keys again.
+        handle_key_event(&mut app, plain_key(KeyCode::Char('f'))).unwrap();
+        assert_eq!(app.agent_list_filter, "claude");
+
+        handle_key_event(&mut app, plain_key(KeyCode::Char('/'))).unwrap();
+        handle_key_event(&mut app, plain_key(KeyCode::Escape)).unwrap();
+        assert_eq!(filtered_agent_indices(&app), [0, 1, 2]);
+    }
+
+    #[test]
+    fn modal_skips_preview_attach_and_resize() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.agents = vec![
+            agent_with_status("wild-cat", "running"),
+            agent_with_status("blue-fox", "running"),
+        ];
+        let (view, _sender) = test_view("wild-cat", (5, 5));
+        app.pty_views.insert("wild-cat".to_string(), view);
+        app.focused_window = Some(WindowId::Diff);
+
+        draw_app(&mut app);
+        assert!(app.pty_views["wild-cat"].resize_debounce.pending.is_none());
+        assert_eq!(app.preview_area, None);
+
+        app.selected_agent = 1;
+        draw_app(&mut app);
+        assert!(app.pending_pty.is_empty());
+    }
+
+    #[test]
+    fn preview_resizes_once_modal_closes() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.agents = vec![agent_with_status("wild-cat", "running")];
+        let (view, _sender) = test_view("wild-cat", (5, 5));
+        app.pty_views.insert("wild-cat".to_string(), view);
+
+        draw_app(&mut app);
+        assert!(app.pty_views["wild-cat"].resize_debounce.pending.is_some());
+        assert!(app.preview_area.is_some());
+    }
+
+    fn shift_key(key: KeyCode) -> KeyEvent {
+        KeyEvent {
+            key,
+            modifiers: Modifiers::SHIFT,
+        }
+    }
+
+    #[test]
+    fn focused_main_screen_intercepts_scroll_keys() {
+        assert_eq!(
+            focused_scroll_key(&shift_key(KeyCode::PageUp), false),
+            Some(PreviewScroll::PageUp)
+        );
+        assert_eq!(
+            focused_scroll_key(&shift_key(KeyCode::End), false),
+            Some(PreviewScroll::Bottom)
+        );
+        let plain = KeyEvent {
+            key: KeyCode::PageUp,
+            modifiers: Modifiers::NONE,
+        };
+        assert_eq!(focused_scroll_key(&plain, false), None);
+    }
+
+    #[test]
+    fn focused_alt_screen_forwards_scroll_keys() {
+        for key in [
+            KeyCode::PageUp,
+            KeyCode::PageDown,
+            KeyCode::Home,
+            KeyCode::End,
+        ] {
+            assert_eq!(focused_scroll_key(&shift_key(key), true), None);
+        }
+
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        let (mut view, _sender) = test_view("wild-cat", (10, 3));
+        feed(&mut view, b"\x1b[?1049h");
+        app.pty_views.insert("wild-cat".to_string(), view);
+        app.focused_agent = Some("wild-cat".to_string());
+        assert!(!app.scroll_focused_view(&shift_key(KeyCode::PageUp)));
+    }
+
+    #[test]
+    fn scrolled_back_view_pages_with_plain_keys() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        let (mut view, _sender) = test_view("wild-cat", (10, 3));
+        for line in 0..12 {
+            feed(&mut view, format!("line {line}\r\n").as_bytes());
+        }
+        app.pty_views.insert("wild-cat".to_string(), view);
+        app.focused_agent = Some("wild-cat".to_string());
+        let offset = |app: &App| app.pty_views["wild-cat"].scroll_offset;
+
+        // At the bottom plain keys still go to the agent.
+        assert!(!app.scroll_focused_view(&plain_key(KeyCode::PageUp)));
+        assert!(!app.scroll_focused_view(&shift_key(KeyCode::Char('G'))));
+        assert!(app.scroll_focused_view(&shift_key(KeyCode::PageUp)));
+        assert_eq!(offset(&app), 3);
+
+        assert!(app.scroll_focused_view(&plain_key(KeyCode::PageUp)));
+        assert_eq!(offset(&app), 6);
+        assert!(app.scroll_focused_view(&plain_key(KeyCode::PageDown)));
+        assert_eq!(offset(&app), 3);
+        assert!(app.scroll_focused_view(&plain_key(KeyCode::Char('g'))));
+        assert!(app.scroll_focused_view(&plain_key(KeyCode::Char('g'))));
+        assert_eq!(offset(&app), 10);
+        // A lone `g` followed by anything else does not jump.
+        assert!(app.scroll_focused_view(&plain_key(KeyCode::PageDown)));
+        assert!(app.scroll_focused_view(&plain_key(KeyCode::Char('g'))));
+        assert!(!app.scroll_focused_view(&plain_key(KeyCode::Char('x'))));
+        assert_eq!(offset(&app), 7);
+        assert!(app.scroll_focused_view(&shift_key(KeyCode::Char('G'))));
+        assert_eq!(offset(&app), 0);
+    }
+
+    #[test]
+    fn mouse_tracking_view_gets_its_scroll_keys() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        let (mut view, _sender) = test_view("wild-cat", (10, 3));
+        for line in 0..12 {
+            feed(&mut view, format!("line {line}\r\n").as_bytes());
+        }
+        feed(&mut view, b"\x1b[?1000h");
+        app.pty_views.insert("wild-cat".to_string(), view);
+        app.focused_agent = Some("wild-cat".to_string());
+        assert!(app.scroll_focused_view(&shift_key(KeyCode::PageUp)));
+        assert_eq!(app.pty_views["wild-cat"].scroll_offset, 3);
+        // Plain paging keys are the agent's own, even while scrolled back.
+        assert!(!app.scroll_focused_view(&plain_key(KeyCode::PageUp)));
+        assert!(!app.scroll_focused_view(&plain_key(KeyCode::Char('g'))));
+        assert_eq!(app.pty_views["wild-cat"].scroll_offset, 3);
+    }
+
+    #[test]
+    fn held_g_is_typed_before_the_key_after_it() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        let (mut view, _sender) = test_view("wild-cat", (10, 3));
+        for line in 0..12 {
+            feed(&mut view, format!("line {line}\r\n").as_bytes());
+        }
+        app.pty_views.insert("wild-cat".to_string(), view);
+        app.focused_agent = Some("wild-cat".to_string());
+        assert!(app.scroll_focused_view(&shift_key(KeyCode::PageUp)));
+
+        assert!(app.scroll_focused_view(&plain_key(KeyCode::Char('g'))));
+        let held_g = app.pending_g;
+        let x = plain_key(KeyCode::Char('x'));
+        assert!(!app.scroll_focused_view(&x));
+        let event = UIEvent {
+            raw: b"x".to_vec(),
+            event: InputEvent::Key(x),
+        };
+        let view = app.pty_views.get("wild-cat");
+        assert_eq!(focused_input_payload(view, &event, held_g), b"gx");
+        assert_eq!(focused_input_payload(view, &event, false), b"x");
+    }
+
+    #[test]
+    fn status_log_evicts_oldest_past_capacity() {
+        let mut log = StatusLog::default();
+        let now = Instant::now();
+        for index in 0..STATUS_LOG_CAPACITY + 2 {
+            log.push(format!("message {index}"), now);
+        }
+        assert_eq!(log.entries.len(), STATUS_LOG_CAPACITY);
+        assert_eq!(log.entries.front().unwrap().message, "message 2");
+        assert_eq!(
+            log.latest(),
+            Some(format!("message {}", STATUS_LOG_CAPACITY + 1).as_str())
+        );
+    }
+
+    #[test]
+    fn status_toasts_expire_with_age() {
+        let mut log = StatusLog::default();
+        let start = Instant::now();
+        log.push("old".to_string(), start);
+        log.push("new".to_string(), start + Duration::from_secs(2));
+
+        let messages = |toasts: Vec<(String, f32)>| -> Vec<String> {
+            toasts
+                .into_iter()
+                .map(|(message, _)| message.to_string())
+                .collect()
+        };
+        let toasts = log.toasts(start + Duration::from_secs(3));
+        assert_eq!(messages(toasts.clone()), vec!["old", "new"]);
+        assert!(toasts[0].1 > toasts[1].1);
+        assert_eq!(
+            messages(log.toasts(start + STATUS_TOAST_DURATION)),
+            vec!["new"]
+        );
+        assert!(log
+            .toasts(start + STATUS_TOAST_DURATION + Duration::from_secs(2))
+            .is_empty());
+    }
+
+    #[test]
+    fn status_toasts_are_capped_and_dismissable() {
+        let mut log = StatusLog::default();
+        let now = Instant::now();
+        for message in ["a", "b", "c", "d"] {
+            log.push(message.to_string(), now);
+        }
+        let shown: Vec<String> = log.toasts(now).into_iter().map(|(m, _)| m).collect();
+        assert_eq!(shown, vec!["b", "c", "d"]);
+
+        log.dismiss();
+        assert!(log.toasts(now).is_empty());
+        assert_eq!(log.latest(), Some("d"));
+        log.push("e".to_string(), now);
+        assert_eq!(log.toasts(now).len(), 1);
+    }
+
+    #[test]
+    fn repeated_status_collapses_into_a_count() {
+        let mut log = StatusLog::default();
+        let start = Instant::now();
+        log.push("server unreachable".to_string(), start);
+        log.dismiss();
+        for second in 1..=2 {
+            log.push(
+                "server unreachable".to_string(),
+                start + Duration::from_secs(second),
+            );
+        }
+        assert_eq!(log.entries.len(), 1);
+        assert_eq!(log.entries[0].text(), "server unreachable ×3");
+        // The repeat shows again as a fresh toast.
+        let toasts = log.toasts(start + Duration::from_secs(2));
+        assert_eq!(toasts.len(), 1);
+        assert_eq!(toasts[0].0, "server unreachable ×3");
+        assert_eq!(toasts[0].1, 0.0);
+
+        // Only consecutive repeats collapse.
+        log.push("agent created".to_string(), start);
+        log.push("server unreachable".to_string(), start);
+        assert_eq!(log.entries.len(), 3);
+        assert_eq!(log.entries[2].text(), "server unreachable");
+    }
+
+    fn tail_lines(feed: &TailFeed) -> Vec<String> {
+        feed.lines
+            .iter()
+            .map(|line| format!("{}: {}", line.label, line.text))
+            .collect()
+    }
+
+    fn owned(lines: &[&str]) -> Vec<String> {
+        lines.iter().map(|line| line.to_string()).collect()
+    }
+
+    #[test]
+    fn tail_feed_interleaves_new_lines_tagged_by_agent() {
+        let mut feed = TailFeed::default();
+        feed.ingest(&[
+            ("wild-cat", "cat", &owned(&["build", "test", "lint"]), 17),
+            ("calm-owl", "owl", &owned(&["hello"]), 6),
+        ]);
+        assert_eq!(
+            tail_lines(&feed),
+            vec!["cat: build", "owl: hello", "cat: test", "cat: lint"]
+        );
+        assert_eq!(feed.lines[1].agent, "calm-owl");
+    }
+
+    #[test]
+    fn tail_feed_asks_from_the_offsets_it_was_given() {
+        let mut feed = TailFeed::default();
+        assert_eq!(feed.since_query(), "");
+        feed.ingest(&[
+            ("wild-cat", "cat", &owned(&["tick"]), 5),
+            ("calm-owl", "owl", &[], 0),
+        ]);
+        feed.ingest(&[("wild-cat", "cat", &owned(&["tick"]), 10)]);
+        assert_eq!(tail_lines(&feed), vec!["cat: tick", "cat: tick"]);
+        assert_eq!(feed.since_query(), "calm-owl:0,wild-cat:10");
+
+        // Once an agent stops, its next run is read from the start.
+        feed.retain_agents(&["calm-owl"].into_iter().collect());
+        assert_eq!(feed.since_query(), "calm-owl:0");
+    }
+
+    #[test]
+    fn tail_window_prefixes_lines_with_agent_labels() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.tail_feed.ingest(&[
+            ("wild-cat", "cat", &owned(&["compiling"]), 10),
+            ("calm-owl", "owl-long", &owned(&["waiting"]), 8),
+        ]);
+        app.focused_window = Some(WindowId::Tail);
+        let screen = render_screen(&mut app, 80, 24);
+        assert!(screen.contains("cat      | compiling"), "{screen}");
+        assert!(screen.contains("owl-long | waiting"), "{screen}");
+    }
+
+    #[test]
+    fn unwritable_config_error_goes_to_banner() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.report_request_error(FetchError::Http {
+            status: CONFIG_NOT_WRITABLE_STATUS,
+            message: "/etc/workforest/repos.toml is not writable".to_string(),
+        });
+        assert_eq!(
+            app.config_error.as_deref(),
+            Some("/etc/workforest/repos.toml is not writable")
+        );
+        assert_eq!(app.status_log.latest(), None);
+
+        app.report_request_error(FetchError::Http {
+            status: 400,
+            message: "repo already exists".to_string(),
+        });
+        assert_eq!(app.status_log.latest(), Some("repo already exists"));
+    }
+
+    #[test]
+    fn cursor_blink_follows_interval() {
+        let interval = Some(Duration::from_millis(500));
+        assert!(cursor_blink_on(Duration::from_millis(0), interval));
+        assert!(cursor_blink_on(Duration::from_millis(499), interval));
+        assert!(!cursor_blink_on(Duration::from_millis(500), interval));
+        assert!(cursor_blink_on(Duration::from_millis(1000), interval));
+        assert!(!cursor_blink_on(
+            Duration::from_millis(700),
+            cursor_blink_interval(None)
+        ));
+    }
+
+    #[test]
+    fn debug_sidebar_width_falls_back_and_has_a_floor() {
+        assert_eq!(debug_sidebar_width(None), DEFAULT_DEBUG_SIDEBAR_WIDTH);
+        assert_eq!(
+            debug_sidebar_width(Some("wide")),
+            DEFAULT_DEBUG_SIDEBAR_WIDTH
+        );
+        assert_eq!(debug_sidebar_width(Some(" 48 ")), 48);
+        assert_eq!(debug_sidebar_width(Some("4")), MIN_DEBUG_SIDEBAR_WIDTH);
+    }
+
+    #[test]
+    fn disabled_cursor_blink_stays_visible() {
+        let interval = cursor_blink_interval(Some("0"));
+        assert_eq!(interval, None);
+        for millis in [0, 350, 700, 1400, 2100] {
+            assert!(cursor_blink_on(Duration::from_millis(millis), interval));
+        }
+    }
+
+    #[test]
+    fn mouse_capture_defaults_on() {
+        assert!(mouse_capture_enabled(None));
+        assert!(mouse_capture_enabled(Some("1")));
+        assert!(mouse_capture_enabled(Some("")));
+    }
+
+    #[test]
+    fn mouse_capture_can_be_disabled_at_startup() {
+        for value in ["0", "false", "no", " off "] {
+            assert!(!mouse_capture_enabled(Some(value)), "{value}");
+        }
+    }
+
+    #[test]
+    fn name_line_shows_dirty_indicator_for_uncommitted_changes() {
+        let mut agent = agent_with_status("wild-cat", "sleep");
+        agent.dirty_count = Some(4);
+        let line = build_name_line(&agent, Instant::now());
+        let last = line.spans.last().expect("indicator span");
+        assert_eq!(last.content, "±4");
+        assert_eq!(last.style.fg, Some(THEME.yellow));
+    }
+
+    #[test]
+    fn name_line_shows_exit_code_for_failed_tool() {
+        let mut agent = agent_with_status("wild-cat", "error");
+        agent.exit_code = Some(2);
+        let line = build_name_line(&agent, Instant::now());
+        let last = line.spans.last().expect("exit span");
+        assert_eq!(last.content, " exit 2");
+        assert_eq!(last.style.fg, Some(THEME.red));
+
+        agent.status = "idle".to_string();
+        agent.exit_code = Some(0);
+        let line = build_name_line(&agent, Instant::now());
+        assert!(line.spans.iter().all(|span| !span.content.contains("exit")));
+    }
+
+    #[test]
+    fn name_line_omits_dirty_indicator_for_clean_worktree() {
+        for dirty_count in [Some(0), None] {
+            let mut agent = agent_with_status("wild-cat", "sleep");
+            agent.dirty_count = dirty_count;
+            let line = build_name_line(&agent, Instant::now());
+            assert!(line.spans.iter().all(|span| !span.content.contains('±')));
+        }
+    }
+
+    #[test]
+    fn agent_summary_counts_mixed_statuses() {
+        let agents = vec![
+            agent_with_status("a", "sleep"),
+            agent_with_status("b", "running"),
+            agent_with_status("c", "error"),
+            agent_with_status("d", "sleep"),
+            agent_with_status("e", "running"),
+            agent_with_status("f", "starting"),
+        ];
+        let text: String = agent_summary_spans(&agents)
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect();
+        assert_eq!(
+            text,
+            "6 agents · 2 running · 1 error · 2 sleep · 1 starting"
+        );
+    }
+
+    #[test]
+    fn agent_summary_handles_empty_and_single() {
+        let text = |agents: &[Agent]| -> String {
+            agent_summary_spans(agents)
+                .iter()
+                .map(|span| span.content.as_ref())
+                .collect()
+        };
+        assert_eq!(text(&[]), "0 agents");
+        assert_eq!(text(&[agent_with_status("a", "idle")]), "1 agent · 1 idle");
+    }
+
+    #[test]
+    fn last_column_print_defers_wrap() {
+        let (mut view, _sender) = test_view("wild-cat", (5, 3));
+        feed(&mut view, b"abcde");
+        assert!(view.pending_wrap);
+        assert_eq!(cursor_position_report(&view), b"\x1b[1;5R".to_vec());
+
+        feed(&mut view, b"f");
+        assert!(!view.pending_wrap);
+        assert_eq!(screen_line(&view, 0), "abcde");
+        assert_eq!(screen_line(&view, 1), "f");
+        assert_eq!(view.active_surface().cursor_position(), (1, 1));
+    }
+
+    #[test]
+    fn cursor_movement_cancels_pending_wrap() {
+        let (mut view, _sender) = test_view("wild-cat", (5, 3));
+        feed(&mut view, b"abcde\x1b[DX");
+        assert_eq!(screen_line(&view, 0), "abcXe");
+        assert_eq!(screen_line(&view, 1), "");
+
+        feed(&mut view, b"\x1b[2;1Hvwxyz\rQ");
+        assert_eq!(screen_line(&view, 1), "Qwxyz");
+        assert_eq!(screen_line(&view, 2), "");
+    }
+
+    fn replies(view: &mut PtyView, bytes: &[u8]) -> Vec<Vec<u8>> {
+        let mut actions = Vec::new();
+        view.parser.parse(bytes, |action| actions.push(action));
+        actions
+            .into_iter()
+            .filter_map(|action| apply_action_to_view(action, view))
+            .collect()
+    }
+
+    #[test]
+    fn decrqss_reports_current_sgr() {
+        let (mut view, _sender) = test_view("wild-cat", (20, 5));
+        feed(&mut view, b"\x1b[1;4;31;48;5;200m");
+        assert_eq!(
+            replies(&mut view, b"\x1bP$qm\x1b\\"),
+            vec![b"\x1bP1$r0;1;4;31;48;5;200m\x1b\\".to_vec()]
+        );
+
+        feed(&mut view, b"\x1b[0m");
+        assert_eq!(
+            replies(&mut view, b"\x1bP$qm\x1b\\"),
+            vec![b"\x1bP1$r0m\x1b\\".to_vec()]
+        );
+    }
+
+    #[test]
+    fn unknown_dcs_is_ignored() {
+        let (mut view, _sender) = test_view("wild-cat", (20, 5));
+        let output = replies(&mut view, b"ab\x1bP1;2|junk data\x1b\\cd\x1bP$qxyz\x1b\\");
+        assert_eq!(output, vec![b"\x1bP0$r\x1b\\".to_vec()]);
+        assert_eq!(screen_line(&view, 0), "abcd");
+    }
+
+    #[test]
+    fn window_size_queries_report_surface_size() {
+        let (mut view, _sender) = test_view("wild-cat", (80, 24));
+        assert_eq!(
+            replies(&mut view, b"\x1b[18t\x1b[14t\x1b[16t"),
+            vec![
+                b"\x1b[8;24;80t".to_vec(),
+                b"\x1b[4;384;640t".to_vec(),
+                b"\x1b[6;16;8t".to_vec(),
+            ]
+        );
+    }
+
+    #[test]
+    fn window_manipulation_is_ignored() {
+        let (mut view, _sender) = test_view("wild-cat", (80, 24));
+        assert!(replies(&mut view, b"\x1b[8;50;100t\x1b[3;10;10t").is_empty());
+        assert_eq!(view.active_surface().dimensions(), (80, 24));
+    }
+
+    /// Answers one ATTACH like the broker would, handing over the read end
+    /// of a pipe as the PTY. The thread returns the command line it got.
+    fn fake_broker(
+        socket_path: &Path,
+        modes: &'static str,
+        history: &'static [u8],
+    ) -> thread::JoinHandle<String> {
+        let listener = std::os::unix::net::UnixListener::bind(socket_path).unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let command = read_line_from_stream(&mut stream, "attach").unwrap();
+            stream
+                .write_all(format!("MODES {modes}\n").as_bytes())
+                .unwrap();
+            stream
+                .write_all(format!("HISTORY {}\n", history.len()).as_bytes())
+                .unwrap();
+            stream.write_all(history).unwrap();
+            let (pty_read, _pty_write) = nix::unistd::pipe().unwrap();
+            let fds = [pty_read.as_raw_fd()];
+            nix::sys::socket::sendmsg::<()>(
+                stream.as_raw_fd(),
+                &[io::IoSlice::new(b"OK 7\n")],
+                &[nix::sys::socket::ControlMessage::ScmRights(&fds)],
+                MsgFlags::empty(),
+                None,
+            )
+            .unwrap();
+            command
+        })
+    }
+
+    fn plain_key(key: KeyCode) -> KeyEvent {
+        KeyEvent {
+            key,
+            modifiers: Modifiers::NONE,
+        }
+    }
+
+    #[test]
+    fn line_matches_ignore_case_and_count_cells() {
+        let line = TermwizLine::from_text("Error: 日本 error", &CellAttributes::default(), 0, None);
+        assert_eq!(line_matches(&line, "error"), vec![(0, 5), (12, 17)]);
+        assert_eq!(line_matches(&line, "本 e"), vec![(9, 13)]);
+        assert!(line_matches(&line, "").is_empty());
+    }
+
+    #[test]
+    fn search_in_focused_view_cycles_matches_through_scrollback() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        let (mut view, _sender) = test_view("wild-cat", (20, 3));
+        feed(
+            &mut view,
+            b"needle 0\r\nhay\r\nhay\r\nneedle 3\r\nhay\r\nhay\r\nhay\r\nneedle 7\r\nhay",
+        );
+        assert_eq!(view.scrollback.len(), 6);
+        app.pty_views.insert("wild-cat".to_string(), view);
+        app.focused_agent = Some("wild-cat".to_string());
+
+        // At the live tail `/` is typed into the agent.
+        assert!(!app.search_focused_view(&plain_key(KeyCode::Char('/'))));
+        app.pty_views.get_mut("wild-cat").unwrap().scroll_offset = 1;
+        assert!(app.search_focused_view(&plain_key(KeyCode::Char('/'))));
+        for ch in "NEEDLE".chars() {
+            assert!(app.search_focused_view(&plain_key(KeyCode::Char(ch))));
+        }
+        assert!(app.search_focused_view(&plain_key(KeyCode::Enter)));
+        let current = |app: &App| app.preview_search.as_ref().unwrap().current.unwrap().line;
+        let offset = |app: &App| app.pty_views["wild-cat"].scroll_offset;
+        // The screen shows lines 5-7; the nearest match is on the live
+        // screen, so the view returns to the bottom.
+        assert_eq!((current(&app), offset(&app)), (7, 0));
+
+        assert!(app.search_focused_view(&plain_key(KeyCode::Char('n'))));
+        assert_eq!((current(&app), offset(&app)), (3, 3));
+        assert!(app.search_focused_view(&plain_key(KeyCode::Char('n'))));
+        assert_eq!((current(&app), offset(&app)), (0, 6));
+        // Stepping past either end wraps around.
+        assert!(app.search_focused_view(&plain_key(KeyCode::Char('n'))));
+        assert_eq!((current(&app), offset(&app)), (7, 0));
+        assert!(app.search_focused_view(&shift_key(KeyCode::Char('N'))));
+        assert_eq!((current(&app), offset(&app)), (0, 6));
+
+        // Any other key ends the search and goes to the agent.
+        assert!(!app.search_focused_view(&plain_key(KeyCode::Char('x'))));
+        assert!(app.preview_search.is_none());
+    }
+
+    #[test]
+    fn search_without_matches_reports_and_ends() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        let (mut view, _sender) = test_view("wild-cat", (20, 2));
+        feed(&mut view, b"one\r\ntwo\r\nthree");
+        view.scroll_offset = 1;
+        app.pty_views.insert("wild-cat".to_string(), view);
+        app.focused_agent = Some("wild-cat".to_string());
+        for key in [KeyCode::Char('/'), KeyCode::Char('z'), KeyCode::Enter] {
+            assert!(app.search_focused_view(&plain_key(key)));
+        }
+        assert!(app.preview_search.is_none());
+        assert_eq!(app.status_log.latest(), Some("no matches for z"));
+    }
+
+    #[test]
+    fn restart_window_is_registered_and_dispatched() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.agents = vec![agent_with_status("wild-cat", "running")];
+        assert!(app.windows.contains(&WindowId::RestartAgent));
+
+        handle_key_event(&mut app, plain_key(KeyCode::Char('R'))).unwrap();
+        assert_eq!(app.focused_window, Some(WindowId::RestartAgent));
+        assert_eq!(app.restart_agent_action, RestartAgentAction::Cancel);
+
+        // Tab is only meaningful to the restart window's own key handler.
+        handle_key_event(&mut app, plain_key(KeyCode::Tab)).unwrap();
+        assert_eq!(app.restart_agent_action, RestartAgentAction::Restart);
+
+        let screen = render_screen(&mut app, 80, 24);
+        assert!(screen.contains("agent wild-cat?"), "{screen}");
+
+        handle_key_event(&mut app, plain_key(KeyCode::Escape)).unwrap();
+        assert_eq!(app.focused_window, None);
+        assert!(app.restart_agent.is_none());
+    }
+
+    #[test]
+    fn merge_window_confirms_before_merging() {
+        let url = serve_once(
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nConnection: close\r\n\r\n\
+             {\"branch\":\"agent/wild-cat\",\"into\":\"main\",\"sha\":\"0123456789abcdef\",\"fast_forward\":true}",
+        );
+        let mut app = App::new(url);
+        app.agents = vec![agent_with_status("wild-cat", "idle")];
+        assert!(app.windows.contains(&WindowId::MergeAgent));
+
+        handle_key_event(&mut app, shift_key(KeyCode::Char('M'))).unwrap();
+        assert_eq!(app.focused_window, Some(WindowId::MergeAgent));
+        assert_eq!(app.merge_agent_action, MergeAgentAction::Cancel);
+        let screen = render_screen(&mut app, 100, 30);
+        assert!(screen.contains("Merge wild-cat?"), "{screen}");
+
+        handle_key_event(&mut app, plain_key(KeyCode::Tab)).unwrap();
+        assert_eq!(app.merge_agent_action, MergeAgentAction::Merge);
+        handle_key_event(&mut app, plain_key(KeyCode::Enter)).unwrap();
+        assert_eq!(app.focused_window, None);
+        assert!(app.merge_agent.is_none());
+        assert_eq!(
+            app.status_log.latest(),
+            Some("merged wild-cat into main (fast-forward to 0123456)")
+        );
+    }
+
+    #[test]
+    fn merge_conflict_is_reported() {
+        let url = serve_once(
+            "HTTP/1.1 409 Conflict\r\nContent-Length: 51\r\nConnection: close\r\n\r\n\
+             merging agent/wild-cat into main conflicts in a.txt",
+        );
+        let mut app = App::new(url);
+        app.agents = vec![agent_with_status("wild-cat", "idle")];
+        handle_key_event(&mut app, shift_key(KeyCode::Char('M'))).unwrap();
+        handle_key_event(&mut app, plain_key(KeyCode::RightArrow)).unwrap();
+        handle_key_event(&mut app, plain_key(KeyCode::Enter)).unwrap();
+        assert_eq!(
+            app.status_log.latest(),
+            Some("merging agent/wild-cat into main conflicts in a.txt")
+        );
+    }
+
+    #[test]
+    fn copy_keys_queue_selected_agent_name_and_path() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        let mut second = agent_with_status("blue-fox", "running");
+        second.worktree_path = "/tmp/worktrees/blue-fox".to_string();
+        app.agents = vec![agent_with_status("wild-cat", "running"), second];
+        app.selected_agent = 1;
+
+        handle_key_event(&mut app, plain_key(KeyCode::Char('y'))).unwrap();
+        assert_eq!(app.pending_clipboard.as_deref(), Some("blue-fox"));
+        assert_eq!(app.status_log.latest(), Some("copied name of blue-fox"));
+
+        handle_key_event(&mut app, shift_key(KeyCode::Char('Y'))).unwrap();
+        assert_eq!(
+            app.pending_clipboard.as_deref(),
+            Some("/tmp/worktrees/blue-fox")
+        );
+
+        app.agents.clear();
+        app.pending_clipboard = None;
+        handle_key_event(&mut app, plain_key(KeyCode::Char('y'))).unwrap();
+        assert_eq!(app.pending_clipboard, None);
+    }
+
+    #[test]
+    fn osc_11_sets_and_resets_default_background() {
+        let (mut view, _sender) = test_view("wild-cat", (20, 3));
+        feed(&mut view, b"\x1b]11;rgb:10/20/30\x07");
+        assert_eq!(
+            view.default_background.map(|color| color.to_srgb_u8()),
+            Some((0x10, 0x20, 0x30, 0xff))
+        );
+
+        feed(&mut view, b"\x1b]111\x07");
+        assert_eq!(view.default_background, None);
+
+        feed(&mut view, b"\x1b]10;#ffffff;#000080\x1b\\");
+        assert_eq!(
+            view.default_background.map(|color| color.to_srgb_u8()),
+            Some((0, 0, 0x80, 0xff))
+        );
+    }
+
+    #[test]
+    fn osc_10_sets_foreground_and_answers_queries() {
+        let (mut view, _sender) = test_view("wild-cat", (20, 3));
+        let replies = |view: &mut PtyView, bytes: &[u8]| -> Vec<Vec<u8>> {
+            let mut actions = Vec::new();
+            view.parser.parse(bytes, |action| actions.push(action));
+            actions
+                .into_iter()
+                .filter_map(|action| apply_action_to_view(action, view))
+                .collect()
+        };
+
+        assert!(replies(&mut view, b"\x1b]10;#ff8000\x07").is_empty());
+        assert_eq!(
+            view.default_foreground.map(|color| color.to_srgb_u8()),
+            Some((0xff, 0x80, 0x00, 0xff))
+        );
+        assert_eq!(
+            replies(&mut view, b"\x1b]10;?\x07"),
+            vec![b"\x1b]10;rgb:ffff/8080/0000\x1b\\".to_vec()]
+        );
+
+        // Unset colors report the theme's.
+        assert_eq!(
+            replies(&mut view, b"\x1b]11;?\x07"),
+            vec![b"\x1b]11;rgb:0c0c/0c0c/0e0e\x1b\\".to_vec()]
+        );
+
+        feed(&mut view, b"\x1b]110\x07");
+        assert_eq!(view.default_foreground, None);
+    }
+
+    #[test]
+    fn malformed_modes_fall_back_to_fresh_snapshot() {
+        let (snapshot, err) = parse_modes_payload("{\"alt_screen\": tru");
+        assert!(err.is_some());
+        assert!(snapshot.cursor_visible);
+        assert!(snapshot.wrap_mode);
+        assert!(!snapshot.alt_screen);
+
+        let (snapshot, err) = parse_modes_payload(
+            &serde_json::to_string(&TerminalSnapshot {
+                alt_screen: true,
+                ..TerminalSnapshot::default()
+            })
+            .unwrap(),
+        );
+        assert_eq!(err, None);
+        assert!(snapshot.alt_screen);
+    }
+
+    #[test]
+    fn attach_succeeds_despite_malformed_modes() {
+        let dir = std::env::temp_dir().join(format!("workforest-modes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("pty.sock");
+        fake_broker(&socket_path, "not json", b"hello");
+
+        let attached = PtyView::attach(&socket_path, "wild-cat", (20, 3), AttachMode::Reuse);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let attached = attached.unwrap();
+        assert!(attached.modes_error.is_some());
+        assert!(attached.snapshot.cursor_visible);
+        assert_eq!(screen_line(&attached.view, 0), "hello");
+    }
+
+    #[test]
+    fn title_forwards_only_for_the_focused_agent() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.forward_title = true;
+        let (mut view, _sender) = test_view("wild-cat", (20, 3));
+        feed(&mut view, b"\x1b]2;cargo build\x07\x1b]1;icon only\x07");
+        assert_eq!(view.title.as_deref(), Some("cargo build"));
+        app.pty_views.insert("wild-cat".to_string(), view);
+
+        assert_eq!(app.forwarded_title(), None);
+        app.focused_agent = Some("wild-cat".to_string());
+        assert_eq!(app.forwarded_title(), Some("cargo build"));
+        app.forward_title = false;
+        assert_eq!(app.forwarded_title(), None);
+    }
+
+    #[test]
+    fn title_updates_set_once_and_restore_on_unfocus() {
+        assert_eq!(
+            title_update(Some("build"), None),
+            Some(TitleUpdate::Set("build".to_string()))
+        );
+        assert_eq!(title_update(Some("build"), Some("build")), None);
+        assert_eq!(
+            title_update(Some("test"), Some("build")),
+            Some(TitleUpdate::Set("test".to_string()))
+        );
+        assert_eq!(title_update(None, Some("test")), Some(TitleUpdate::Restore));
+        assert_eq!(title_update(None, None), None);
+
+        let mut out = Vec::new();
+        let title = apply_title_update(&mut out, TitleUpdate::Set("a".to_string()), false).unwrap();
+        apply_title_update(&mut out, TitleUpdate::Set("b".to_string()), true).unwrap();
+        apply_title_update(&mut out, TitleUpdate::Restore, true).unwrap();
+        assert_eq!(title.as_deref(), Some("a"));
+        assert_eq!(out, b"\x1b[22;0t\x1b]0;a\x07\x1b]0;b\x07\x1b[23;0t");
+    }
+
+    #[test]
+    fn broker_control_reuses_one_connection_for_a_burst() {
+        let dir = std::env::temp_dir().join(format!("workforest-control-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("pty.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&socket_path).unwrap();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = String::new();
+            stream.read_to_string(&mut received).unwrap();
+            listener.set_nonblocking(true).unwrap();
+            let extra = listener.accept().is_ok();
+            (received, extra)
+        });
+
+        let mut control = BrokerControl::default();
+        for _ in 0..100 {
+            send_input(&mut control, &socket_path, "wild-cat", b"k").unwrap();
+        }
+        drop(control);
+        let (received, extra) = broker.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(received, "INPUT wild-cat 1\nk".repeat(100));
+        assert!(!extra, "every keystroke should share the first connection");
+    }
+
+    #[test]
+    fn agent_not_found_reply_clears_focus() {
+        let dir = std::env::temp_dir().join(format!("workforest-gone-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("pty.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&socket_path).unwrap();
+        let broker = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let command = read_line_from_stream(&mut stream, "input").unwrap();
+            stream.write_all(b"ERR agent not found\n").unwrap();
+            let mut rest = Vec::new();
+            stream.read_to_end(&mut rest).unwrap();
+            command
+        });
+
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.pty_socket_path = socket_path.clone();
+        app.agents = vec![
+            agent_with_status("calm-owl", "running"),
+            agent_with_status("wild-cat", "running"),
+        ];
+        app.selected_agent = 1;
+        let (mut view, _sender) = test_view("wild-cat", (40, 5));
+        send_input(&mut view.control, &socket_path, "wild-cat", b"k").unwrap();
+        app.pty_views.insert("wild-cat".to_string(), view);
+        app.focused_agent = Some("wild-cat".to_string());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !app.check_focused_agent() {
+            assert!(Instant::now() < deadline, "the broker reply never arrived");
+            thread::sleep(Duration::from_millis(5));
+        }
+        let command = broker.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(command.starts_with("INPUT wild-cat 1"));
+        assert_eq!(app.focused_agent, None);
+        assert!(app.pty_views.is_empty());
+        assert_eq!(app.agents.len(), 1);
+        assert_eq!(app.selected_agent, 0);
+        assert_eq!(
+            app.status_log.latest(),
+            Some("agent wild-cat no longer exists")
+        );
+    }
+
+    #[test]
+    fn failed_attach_for_a_deleted_agent_clears_focus() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.agents = vec![agent_with_status("wild-cat", "running")];
+        app.focused_agent = Some("wild-cat".to_string());
+        app.attach_sender
+            .send(AttachResult {
+                agent: "wild-cat".to_string(),
+                result: Err("ERR agent not found".to_string()),
+                size: (40, 5),
+            })
+            .unwrap();
+        assert!(app.handle_attach_results());
+        assert_eq!(app.focused_agent, None);
+        assert!(app.agents.is_empty());
+
+        // Other broker errors are only reported.
+        app.agents = vec![agent_with_status("wild-cat", "running")];
+        app.focused_agent = Some("wild-cat".to_string());
+        app.attach_sender
+            .send(AttachResult {
+                agent: "wild-cat".to_string(),
+                result: Err("ERR resize failed".to_string()),
+                size: (40, 5),
+            })
+            .unwrap();
+        app.handle_attach_results();
+        assert_eq!(app.focused_agent.as_deref(), Some("wild-cat"));
+        assert_eq!(app.status_log.latest(), Some("ERR resize failed"));
+    }
+
+    #[test]
+    fn broker_control_reconnects_after_the_broker_hangs_up() {
+        let dir = std::env::temp_dir().join(format!("workforest-reconnect-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("pty.sock");
+        let listener = std::os::unix::net::UnixListener::bind(&socket_path).unwrap();
+        let (closed_sender, closed_receiver) = mpsc::channel();
+        let broker = thread::spawn(move || {
+            let (mut first, _) = listener.accept().unwrap();
+            let first_command = read_line_from_stream(&mut first, "first").unwrap();
+            drop(first);
+            closed_sender.send(()).unwrap();
+            let (mut second, _) = listener.accept().unwrap();
+            let second_command = read_line_from_stream(&mut second, "second").unwrap();
+            (first_command, second_command)
+        });
+
+        let mut control = BrokerControl::default();
+        send_resize(&mut control, &socket_path, "wild-cat", (80, 24)).unwrap();
+        closed_receiver.recv().unwrap();
+        send_resize(&mut control, &socket_path, "wild-cat", (100, 30)).unwrap();
+        let commands = broker.join().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(
+            commands,
+            (
+                "RESIZE wild-cat 80 24".to_string(),
+                "RESIZE wild-cat 100 30".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn dropping_attached_view_detaches_its_subscriber() {
+        let dir = std::env::temp_dir().join(format!("workforest-detach-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("pty.sock");
+        let broker = fake_broker(&socket_path, "{}", b"");
+        let attached = PtyView::attach(&socket_path, "wild cat", (20, 3), AttachMode::Reuse);
+        broker.join().unwrap();
+
+        std::fs::remove_file(&socket_path).unwrap();
+        let listener = std::os::unix::net::UnixListener::bind(&socket_path).unwrap();
+        drop(attached.unwrap());
+        let (mut stream, _) = listener.accept().unwrap();
+        let command = read_line_from_stream(&mut stream, "detach").unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(command, "DETACH wild%20cat 7");
+    }
+
+    #[test]
+    fn fresh_attach_replaces_view_through_broker() {
+        let dir = std::env::temp_dir().join(format!("workforest-fresh-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let socket_path = dir.join("pty.sock");
+        let broker = fake_broker(&socket_path, "{}", b"new session");
+
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.pty_socket_path = socket_path;
+        app.agents = vec![agent_with_status("wild-cat", "running")];
+        let (mut stale, _sender) = test_view("wild-cat", (20, 3));
+        feed(&mut stale, b"stuck");
+        app.pty_views.insert("wild-cat".to_string(), stale);
+
+        handle_key_event(&mut app, shift_key(KeyCode::Char('F'))).unwrap();
+        assert!(!app.pty_views.contains_key("wild-cat"));
+        assert_eq!(app.pending_pty["wild-cat"].size, (20, 3));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while !app.pty_views.contains_key("wild-cat") && Instant::now() < deadline {
+            app.handle_attach_results();
+            thread::sleep(Duration::from_millis(10));
+        }
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(broker.join().unwrap(), "FRESH wild-cat");
+        assert_eq!(screen_line(&app.pty_views["wild-cat"], 0), "new session");
+    }
+
+    fn serve_once(response: &'static str) -> String {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut buffer = [0u8; 1024];
+            let _ = stream.read(&mut buffer);
+            let _ = stream.write_all(response.as_bytes());
+        });
+        format!("http://{addr}")
+    }
+
+    #[test]
+    fn delete_repo_surfaces_blocking_agents() {
+        let url = serve_once(
+            "HTTP/1.1 409 Conflict\r\nContent-Length: 28\r\nConnection: close\r\n\r\nrepo has agents: a, wild-cat",
+        );
+        let err = delete_repo(&Client::new(), &url, "forest").unwrap_err();
+        assert_eq!(
+            err,
+            FetchError::Http {
+                status: 409,
+                message: "repo has agents: a, wild-cat".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn sleep_key_puts_selected_agent_to_sleep() {
+        let url = serve_once("HTTP/1.1 204 No Content\r\nConnection: close\r\n\r\n");
+        let mut app = App::new(url);
+        app.agents = vec![agent_with_status("wild-cat", "running")];
+
+        handle_key_event(&mut app, plain_key(KeyCode::Char('s'))).unwrap();
+        assert_eq!(
+            app.status_log.latest(),
+            Some("wild-cat is asleep; (Enter) wakes it")
+        );
+    }
+
+    #[test]
+    fn preview_jump_keys_scroll_the_selected_agent() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.agents = vec![agent_with_status("wild-cat", "running")];
+        let (mut view, _sender) = test_view("wild-cat", (10, 3));
+        for line in 0..12 {
+            feed(&mut view, format!("line {line}\r\n").as_bytes());
+        }
+        app.pty_views.insert("wild-cat".to_string(), view);
+        let offset = |app: &App| app.pty_views["wild-cat"].scroll_offset;
+
+        handle_key_event(&mut app, plain_key(KeyCode::Char('g'))).unwrap();
+        handle_key_event(&mut app, plain_key(KeyCode::Char('g'))).unwrap();
+        assert_eq!(offset(&app), 10);
+        handle_key_event(&mut app, plain_key(KeyCode::PageDown)).unwrap();
+        assert_eq!(offset(&app), 7);
+        handle_key_event(&mut app, shift_key(KeyCode::Char('G'))).unwrap();
+        assert_eq!(offset(&app), 0);
+    }
+
+    #[test]
+    fn rename_window_edits_and_submits_label() {
+        let url = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n");
+        let mut app = App::new(url);
+        app.agents = vec![agent_with_status("wild-cat", "running")];
+
+        handle_key_event(&mut app, plain_key(KeyCode::Char('n'))).unwrap();
+        assert_eq!(app.focused_window, Some(WindowId::RenameAgent));
+        assert_eq!(app.rename_input, "wild-cat");
+        for _ in 0.."wild-cat".len() {
+            handle_key_event(&mut app, plain_key(KeyCode::Backspace)).unwrap();
+        }
+        handle_key_event(&mut app, plain_key(KeyCode::Enter)).unwrap();
+        assert_eq!(app.status_log.latest(), Some("label is required"));
+        assert_eq!(app.focused_window, Some(WindowId::RenameAgent));
+
+        for ch in "Fix login".chars() {
+            handle_key_event(&mut app, plain_key(KeyCode::Char(ch))).unwrap();
+        }
+        handle_key_event(&mut app, plain_key(KeyCode::Enter)).unwrap();
+        assert_eq!(app.focused_window, None);
+        assert!(app.rename_agent.is_none());
+        assert_eq!(
+            app.status_log.latest(),
+            Some("renamed wild-cat to Fix login")
+        );
+    }
+
+    #[test]
+    fn tool_selection_follows_a_shrinking_tool_list() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        let repo = |tools: &[&str], default_tool: &str| RepoConfig {
+            name: "forest".to_string(),
+            path: PathBuf::from("/tmp"),
+            tools: tools.iter().map(|tool| ToolSpec::from(*tool)).collect(),
+            default_tool: default_tool.to_string(),
+            ..RepoConfig::default()
+        };
+        app.repos = vec![repo(&["opencode", "claude", "codex"], "opencode")];
+        app.agent_field = AgentField::Tool;
+        app.selected_tool = 2;
+
+        app.repos = vec![repo(&["aider", "./bin/agent.sh"], "./bin/agent.sh")];
+        app.clamp_repo_selection();
+        assert_eq!(app.selected_tool, 1);
+        assert_eq!(filtered_tool_indices(&app), vec![0, 1]);
+
+        app.agent_filter_input = "aid".to_string();
+        app.clamp_repo_selection();
+        assert_eq!(app.selected_tool, 0);
+    }
+
+    #[test]
+    fn tool_picker_shows_spec_names() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        app.repos = vec![RepoConfig {
+            name: "forest".to_string(),
+            path: PathBuf::from("/tmp"),
+            tools: vec![
+                ToolSpec::from("opencode"),
+                ToolSpec {
+                    name: "opus".to_string(),
+                    command: Some("claude".to_string()),
+                    args: vec!["--model".to_string(), "claude-opus".to_string()],
+                    env: Default::default(),
+                    kind: None,
+                },
+            ],
+            default_tool: "opus".to_string(),
+            ..RepoConfig::default()
+        }];
+        app.selected_tool = default_tool_index(&app.repos[0]);
+        app.focused_window = Some(WindowId::AddAgent);
+        app.agent_field = AgentField::Tool;
+        let screen = render_screen(&mut app, 100, 60);
+        assert!(screen.contains("> opus"), "{screen}");
+        assert!(!screen.contains("claude-opus"), "{screen}");
+    }
+
+    #[test]
+    fn repos_window_confirms_before_deleting() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let mut app = App::new(url);
+        app.repos = ["forest", "meadow"]
+            .into_iter()
+            .map(|name| RepoConfig {
+                name: name.to_string(),
+                path: PathBuf::from("/tmp"),
+                tools: vec![ToolSpec::from("claude")],
+                default_tool: "claude".to_string(),
+                worktree_root: None,
+                prefix_agent_names: None,
+                max_agents: None,
+                initial_input: None,
+                env: Default::default(),
+            })
+            .collect();
+        handle_key_event(&mut app, plain_key(KeyCode::Char('l'))).unwrap();
+        assert_eq!(app.focused_window, Some(WindowId::ShowRepos));
+
+        handle_key_event(&mut app, plain_key(KeyCode::DownArrow)).unwrap();
+        handle_key_event(&mut app, plain_key(KeyCode::Char('d'))).unwrap();
+        assert!(app.confirming_repo_delete);
+        handle_key_event(&mut app, plain_key(KeyCode::Char('n'))).unwrap();
+        assert!(!app.confirming_repo_delete);
+        assert_eq!(app.status_log.latest(), None);
+
+        handle_key_event(&mut app, plain_key(KeyCode::Char('d'))).unwrap();
+        handle_key_event(&mut app, plain_key(KeyCode::Char('y'))).unwrap();
+        assert!(!app.confirming_repo_delete);
+        assert_eq!(app.focused_window, Some(WindowId::ShowRepos));
+        assert!(app
+            .status_log
+            .latest()
+            .is_some_and(|message| message.starts_with("server unreachable")));
+    }
+
+    #[test]
+    fn fetch_classifies_transport_errors() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        let err = fetch_repos(&Client::new(), &url).unwrap_err();
+        assert!(matches!(err, FetchError::Transport(_)), "{err:?}");
+    }
+
+    #[test]
+    fn fetch_classifies_http_errors() {
+        let url = serve_once(
+            "HTTP/1.1 500 Internal Server Error\r\nContent-Length: 4\r\nConnection: close\r\n\r\nboom",
+        );
+        let err = fetch_repos(&Client::new(), &url).unwrap_err();
+        assert_eq!(
+            err,
+            FetchError::Http {
+                status: 500,
+                message: "boom".to_string(),
+            }
+        );
+    }
+
+    #[test]
+    fn fetch_classifies_decode_errors() {
+        let url =
+            serve_once("HTTP/1.1 200 OK\r\nContent-Length: 8\r\nConnection: close\r\n\r\nnot json");
+        let err = fetch_agents(&Client::new(), &url).unwrap_err();
+        assert!(matches!(err, FetchError::Decode(_)), "{err:?}");
+    }
+
+    #[test]
+    fn output_keeps_tail_or_holds_position() {
+        assert_eq!(scroll_offset_after_output(0, 3, false), 0);
+        assert_eq!(scroll_offset_after_output(5, 3, false), 8);
+        assert_eq!(scroll_offset_after_output(5, 3, true), 0);
+        assert_eq!(scroll_offset_after_output(0, 3, true), 0);
+    }
+
+    #[test]
+    fn following_view_returns_to_tail_on_output() {
+        let (mut view, sender) = test_view("wild-cat", (10, 2));
+        feed(&mut view, b"1\r\n2\r\n3\r\n4\r\n5");
+        view.scroll_offset = 2;
+        assert!(view.toggle_follow());
+        assert_eq!(view.scroll_offset, 0);
+
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        view.scroll_offset = 2;
+        app.pty_views.insert("wild-cat".to_string(), view);
+        sender.send(b"x".to_vec()).unwrap();
+        app.pump_pty_output(&mut Vec::new());
+        assert_eq!(app.pty_views["wild-cat"].scroll_offset, 0);
+    }
+
+    fn vars<'a>(pairs: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name| {
+            pairs
+                .iter()
+                .find(|(key, _)| *key == name)
+                .map(|(_, value)| value.to_string())
+        }
+    }
+
+    #[test]
+    fn worktree_shell_runs_in_worktree_with_agent_env() {
+        let worktree = Path::new("/trees/wild-cat");
+        let command = worktree_command(
+            WorktreeTool::Shell,
+            "wild-cat",
+            worktree,
+            vars(&[("SHELL", "/bin/zsh")]),
+        );
+        assert_eq!(
+            command,
+            ExternalCommand {
+                program: "/bin/zsh".to_string(),
+                args: Vec::new(),
+                cwd: worktree.to_path_buf(),
+                env: vec![("WORKFOREST_AGENT".to_string(), "wild-cat".to_string())],
+            }
+        );
+        let fallback = worktree_command(WorktreeTool::Shell, "wild-cat", worktree, vars(&[]));
+        assert_eq!(fallback.program, "/bin/sh");
+    }
+
+    #[test]
+    fn worktree_editor_prefers_configured_command() {
+        let worktree = Path::new("/trees/wild-cat");
+        let configured = worktree_command(
+            WorktreeTool::Editor,
+            "wild-cat",
+            worktree,
+            vars(&[
+                ("EDITOR", "vim"),
+                ("WORKFOREST_EDITOR_COMMAND", "code --wait"),
+            ]),
+        );
+        assert_eq!(configured.program, "code");
+        assert_eq!(configured.args, vec!["--wait", "."]);
+        assert_eq!(configured.cwd, worktree);
+
+        let editor = worktree_command(
+            WorktreeTool::Editor,
+            "wild-cat",
+            worktree,
+            vars(&[("EDITOR", "nvim"), ("WORKFOREST_EDITOR_COMMAND", " ")]),
+        );
+        assert_eq!(
+            (editor.program.as_str(), editor.args),
+            ("nvim", vec![".".to_string()])
+        );
+    }
+
+    #[test]
+    fn full_output_channel_applies_backpressure() {
+        let (_view, sender) = test_view("wild-cat", (20, 5));
+        let stop = AtomicBool::new(false);
+        for _ in 0..PTY_CHANNEL_CHUNKS {
+            assert!(send_with_backpressure(&sender, b"x".to_vec(), &stop));
+        }
+        assert!(matches!(
+            sender.try_send(b"x".to_vec()),
+            Err(TrySendError::Full(_))
+        ));
+        stop.store(true, Ordering::Relaxed);
+        assert!(!send_with_backpressure(&sender, b"x".to_vec(), &stop));
+    }
+
+    #[test]
+    fn sustained_output_is_bounded_and_keeps_final_screen() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        let (view, sender) = test_view("wild-cat", (20, 3));
+        app.pty_views.insert("wild-cat".to_string(), view);
+        let producer = thread::spawn(move || {
+            let stop = AtomicBool::new(false);
+            for line in 0..5000 {
+                let chunk = format!("line {line}\r\n").into_bytes();
+                assert!(send_with_backpressure(&sender, chunk, &stop));
+            }
+        });
+
+        let mut actions = Vec::new();
+        while !app.pty_views["wild-cat"].ended {
+            app.pump_pty_output(&mut actions);
+        }
+        producer.join().unwrap();
+
+        let view = &app.pty_views["wild-cat"];
+        assert_eq!(screen_line(view, 0), "line 4998");
+        assert_eq!(screen_line(view, 1), "line 4999");
+    }
+
+    #[test]
+    fn pump_stops_after_byte_budget() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        let (view, sender) = test_view("wild-cat", (20, 3));
+        app.pty_views.insert("wild-cat".to_string(), view);
+        let chunk = vec![b'x'; PTY_PUMP_BUDGET_BYTES / 2];
+        for _ in 0..3 {
+            sender.send(chunk.clone()).unwrap();
+        }
+
+        assert!(app.pump_pty_output(&mut Vec::new()));
+        assert!(app.pty_views["wild-cat"].receiver.try_recv().is_ok());
+    }
+
+    #[test]
+    fn ended_session_clears_focus() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        let (view, sender) = test_view("wild-cat", (20, 5));
+        app.pty_views.insert("wild-cat".to_string(), view);
+        app.focused_agent = Some("wild-cat".to_string());
+        sender.send(b"bye".to_vec()).unwrap();
+        drop(sender);
+
+        assert!(app.pump_pty_output(&mut Vec::new()));
+
+        assert!(app.pty_views["wild-cat"].ended);
+        assert_eq!(app.focused_agent, None);
+        assert_eq!(app.status_log.latest(), Some("session ended for wild-cat"));
+    }
+
+    #[test]
+    fn ended_background_session_keeps_focus() {
+        let mut app = App::new("http://127.0.0.1:0".to_string());
+        let (view, sender) = test_view("wild-cat", (20, 5));
+        app.pty_views.insert("wild-cat".to_string(), view);
+        app.focused_agent = Some("blue-fox".to_string());
+        drop(sender);
+
+        app.pump_pty_output(&mut Vec::new());
+
+        assert!(app.pty_views["wild-cat"].ended);
+        assert_eq!(app.focused_agent.as_deref(), Some("blue-fox"));
+    }
+
+    #[test]
+    fn preview_scroll_lines_clamp_at_bounds() {
+        assert_eq!(scrolled_offset(0, 10, 5, PreviewScroll::LineDown), 0);
+        assert_eq!(scrolled_offset(0, 10, 5, PreviewScroll::LineUp), 1);
+        assert_eq!(scrolled_offset(10, 10, 5, PreviewScroll::LineUp), 10);
+        assert_eq!(scrolled_offset(0, 0, 5, PreviewScroll::LineUp), 0);
+    }
+
+    #[test]
+    fn preview_scroll_pages_clamp_at_bounds() {
+        assert_eq!(scrolled_offset(0, 12, 5, PreviewScroll::PageUp), 5);
+        assert_eq!(scrolled_offset(10, 12, 5, PreviewScroll::PageUp), 12);
+        assert_eq!(scrolled_offset(3, 12, 5, PreviewScroll::PageDown), 0);
+        assert_eq!(scrolled_offset(4, 12, 0, PreviewScroll::PageDown), 3);
+        assert_eq!(scrolled_offset(4, 12, 5, PreviewScroll::Top), 12);
+        assert_eq!(scrolled_offset(4, 12, 5, PreviewScroll::Bottom), 0);
+    }
+
+    #[test]
+    fn resize_burst_applies_only_final_size() {
+        let start = Instant::now();
+        let mut debounce = ResizeDebounce::default();
+        let burst = [(80, 24), (90, 26), (100, 28), (110, 30)];
+        let mut applied = Vec::new();
+        for (step, size) in burst.into_iter().enumerate() {
+            let now = start + Duration::from_millis(step as u64 * 10);
+            debounce.request(size, now);
+            applied.extend(debounce.take_ready(now));
+        }
+        let settled = start + Duration::from_millis(30) + RESIZE_DEBOUNCE;
+        applied.extend(debounce.take_ready(settled));
+        applied.extend(debounce.take_ready(settled + RESIZE_DEBOUNCE));
+        assert_eq!(applied, vec![(110, 30)]);
+    }
+
+    #[test]
+    fn repeated_same_size_does_not_postpone_resize() {
+        let start = Instant::now();
+        let mut debounce = ResizeDebounce::default();
+        debounce.request((120, 40), start);
+        debounce.request((120, 40), start + RESIZE_DEBOUNCE / 2);
+        assert_eq!(
+            debounce.take_ready(start + RESIZE_DEBOUNCE),
+            Some((120, 40))
+        );
+    }
+
+    #[test]
+    fn underline_styles_round_trip_through_snapshot() {
+        let cases = [
+            (TerminalUnderline::None, termwiz::cell::Underline::None),
+            (TerminalUnderline::Single, termwiz::cell::Underline::Single),
+            (TerminalUnderline::Double, termwiz::cell::Underline::Double),
+            (TerminalUnderline::Curly, termwiz::cell::Underline::Curly),
+            (TerminalUnderline::Dotted, termwiz::cell::Underline::Dotted),
+            (TerminalUnderline::Dashed, termwiz::cell::Underline::Dashed),
+        ];
+        for (underline, expected) in cases {
+            let snapshot = TerminalSnapshot {
+                attributes: TerminalAttributes {
+                    underline,
+                    ..TerminalAttributes::default()
+                },
+                ..TerminalSnapshot::default()
+            };
+            let json = serde_json::to_string(&snapshot).unwrap();
+            let decoded: TerminalSnapshot = serde_json::from_str(&json).unwrap();
+            let attrs = snapshot_attributes_to_termwiz(&decoded.attributes);
+            assert_eq!(attrs.underline(), expected);
+        }
+    }
+}
@@ -0,0 +1,154 @@
+//! Replays recorded agent output through the same emulation a live view
+//! uses, so screen corruption reported from the field can be reproduced
+//! without a PTY:
+//!
+//! ```text
+//! workforest-tui --replay <history.log> [--size 120x40] [--snapshot snapshot.json]
+//! ```
+//!
+//! The history is the raw output the server flushes per agent; a snapshot,
+//! when given, is applied first exactly as on attach.
+
+use crate::{apply_action_to_view, apply_snapshot_to_view, PtyReader, PtyView};
+use std::error::Error;
+use std::fmt::Write as _;
+use std::sync::atomic::AtomicBool;
+use std::sync::{mpsc, Arc};
+use termwiz::surface::CursorVisibility;
+use workforest_core::TerminalSnapshot;
+
+const DEFAULT_SIZE: (u16, u16) = (80, 24);
+
+/// Runs `--replay` with the arguments that follow it and prints the dump.
+pub(crate) fn run(args: &[String]) -> Result<(), Box<dyn Error>> {
+    let mut history = None;
+    let mut size = DEFAULT_SIZE;
+    let mut snapshot = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--size" => {
+                let value = args.next().ok_or("--size needs COLSxROWS")?;
+                size = parse_size(value).ok_or_else(|| format!("invalid --size: {value}"))?;
+            }
+            "--snapshot" => {
+                let path = args.next().ok_or("--snapshot needs a file")?;
+                let data = std::fs::read_to_string(path).map_err(|err| format!("{path}: {err}"))?;
+                let parsed: TerminalSnapshot =
+                    serde_json::from_str(&data).map_err(|err| format!("{path}: {err}"))?;
+                snapshot = Some(parsed);
+            }
+            path if history.is_none() => history = Some(path.to_string()),
+            other => return Err(format!("unexpected argument: {other}").into()),
+        }
+    }
+    let path = history.ok_or("usage: --replay <history> [--size COLSxROWS] [--snapshot FILE]")?;
+    let bytes = std::fs::read(&path).map_err(|err| format!("{path}: {err}"))?;
+    let view = replay(&bytes, size, snapshot.as_ref());
+    print!("{}", dump(&view));
+    Ok(())
+}
+
+fn parse_size(value: &str) -> Option<(u16, u16)> {
+    let (cols, rows) = value.split_once('x')?;
+    let size = (cols.trim().parse().ok()?, rows.trim().parse().ok()?);
+    (size.0 > 0 && size.1 > 0).then_some(size)
+}
+
+/// A view that has seen `history`, built the way an attach builds one.
+/// Replies the emulation would send back to the agent are dropped.
+pub(crate) fn replay(
+    history: &[u8],
+    size: (u16, u16),
+    snapshot: Option<&TerminalSnapshot>,
+) -> PtyView {
+    let (_sender, receiver) = mpsc::sync_channel(1);
+    let reader = PtyReader {
+        stop: Arc::new(AtomicBool::new(false)),
+        handle: None,
+    };
+    let mut view = PtyView::new("replay", size, receiver, reader);
+    if let Some(snapshot) = snapshot {
+        apply_snapshot_to_view(&mut view, snapshot);
+    }
+    let mut actions = Vec::new();
+    view.parser.parse(history, |action| actions.push(action));
+    for action in actions {
+        apply_action_to_view(action, &mut view);
+    }
+    view
+}
+
+/// Terminal state on one header line, then scrollback and the active
+/// screen with trailing blanks trimmed.
+pub(crate) fn dump(view: &PtyView) -> String {
+    let surface = view.active_surface();
+    let (cols, rows) = surface.dimensions();
+    let (cursor_x, cursor_y) = surface.cursor_position();
+    let visible = matches!(surface.cursor_visibility(), CursorVisibility::Visible);
+    let mut out = format!(
+        "# {cols}x{rows} {} screen, cursor {cursor_x},{cursor_y} {}, {} scrollback lines",
+        if view.use_alt_screen { "alt" } else { "main" },
+        if visible { "visible" } else { "hidden" },
+        view.scrollback.len(),
+    );
+    if let Some((top, bottom)) = view.scroll_region {
+        let _ = write!(out, ", scroll region {top}-{bottom}");
+    }
+    if let Some(title) = &view.title {
+        let _ = write!(out, ", title {title:?}");
+    }
+    out.push('\n');
+    for line in &view.scrollback {
+        let _ = writeln!(out, "{}", line.as_str().trim_end());
+    }
+    if !view.scrollback.is_empty() {
+        out.push_str("---\n");
+    }
+    for line in surface.screen_lines() {
+        let _ = writeln!(out, "{}", line.as_str().trim_end());
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replay_reproduces_screen_content() {
+        let view = replay(b"hello\r\nworld\x1b[1;3HX", (10, 3), None);
+        assert_eq!(
+            dump(&view),
+            "# 10x3 main screen, cursor 3,0 visible, 0 scrollback lines\nheXlo\nworld\n\n"
+        );
+    }
+
+    #[test]
+    fn replay_keeps_scrollback_and_alt_screen() {
+        let view = replay(b"one\r\ntwo\r\nthree", (10, 2), None);
+        assert_eq!(
+            dump(&view),
+            "# 10x2 main screen, cursor 5,1 visible, 1 scrollback lines\none\n---\ntwo\nthree\n"
+        );
+
+        let view = replay(
+            b"shell\x1b[?1049h\x1b]0;vim\x07\x1b[?25lfull",
+            (10, 2),
+            None,
+        );
+        assert_eq!(
+            dump(&view),
+            "# 10x2 alt screen, cursor 4,0 hidden, 0 scrollback lines, title \"vim\"\nfull\n\n"
+        );
+    }
+
+    #[test]
+    fn replay_arguments_are_validated() {
+        assert_eq!(parse_size("120x40"), Some((120, 40)));
+        assert_eq!(parse_size("0x40"), None);
+        assert_eq!(parse_size("wide"), None);
+        assert!(run(&["--size".to_string()]).is_err());
+        assert!(run(&[]).is_err());
+    }
+}
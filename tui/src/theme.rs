@@ -1,8 +1,69 @@
-use ratatui::style::Color;
+use ratatui::{buffer::Buffer, style::Color};
+use std::sync::OnceLock;
 
-pub const ICON_IDLE: &str = "󰒲";
-pub const ICON_ERROR: &str = "󰅚";
-pub const ICON_ACTIVE: &str = "●";
+/// Glyph shown before each agent's label, one shape per status so status
+/// does not rely on color alone.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StatusIcons {
+    pub running: String,
+    pub idle: String,
+    pub error: String,
+    pub sleep: String,
+}
+
+impl StatusIcons {
+    /// Nerd Font glyphs, the default.
+    pub fn nerd() -> Self {
+        Self::from_glyphs(["●", "󰏤", "󰅚", "󰒲"])
+    }
+
+    /// Plain Unicode shapes for fonts without Nerd Font glyphs.
+    pub fn unicode() -> Self {
+        Self::from_glyphs(["▶", "‖", "✗", "z"])
+    }
+
+    fn from_glyphs([running, idle, error, sleep]: [&str; 4]) -> Self {
+        Self {
+            running: running.to_string(),
+            idle: idle.to_string(),
+            error: error.to_string(),
+            sleep: sleep.to_string(),
+        }
+    }
+
+    /// Parses `WORKFOREST_STATUS_ICONS`: `nerd`, `unicode`, or four glyphs
+    /// separated by commas for running, idle, error and sleep. Anything else
+    /// keeps the Nerd Font set.
+    pub fn from_setting(value: Option<&str>) -> Self {
+        let value = value.map(str::trim).unwrap_or_default();
+        if value.eq_ignore_ascii_case("unicode") {
+            return Self::unicode();
+        }
+        let glyphs: Vec<&str> = value.split(',').map(str::trim).collect();
+        match <[&str; 4]>::try_from(glyphs) {
+            Ok(glyphs) if glyphs.iter().all(|glyph| !glyph.is_empty()) => Self::from_glyphs(glyphs),
+            _ => Self::nerd(),
+        }
+    }
+
+    /// Unknown statuses share the sleep glyph.
+    pub fn for_status(&self, status: &str) -> &str {
+        match status {
+            "running" => &self.running,
+            "idle" => &self.idle,
+            "error" => &self.error,
+            _ => &self.sleep,
+        }
+    }
+}
+
+static STATUS_ICONS: OnceLock<StatusIcons> = OnceLock::new();
+
+pub fn status_icons() -> &'static StatusIcons {
+    STATUS_ICONS.get_or_init(|| {
+        StatusIcons::from_setting(std::env::var("WORKFOREST_STATUS_ICONS").ok().as_deref())
+    })
+}
 
 pub const THEME: Theme = Theme {
     bg: Color::Rgb(12, 12, 14),
@@ -44,3 +105,213 @@ pub struct Theme {
     pub border: Color,
     pub visual: Color,
 }
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ColorSupport {
+    TrueColor,
+    Ansi256,
+}
+
+static COLOR_SUPPORT: OnceLock<ColorSupport> = OnceLock::new();
+
+pub fn color_support() -> ColorSupport {
+    *COLOR_SUPPORT.get_or_init(|| {
+        detect_color_support(
+            std::env::var("COLORTERM").ok().as_deref(),
+            std::env::var("TERM").ok().as_deref(),
+        )
+    })
+}
+
+fn detect_color_support(colorterm: Option<&str>, term: Option<&str>) -> ColorSupport {
+    let colorterm = colorterm.unwrap_or("").to_lowercase();
+    if colorterm == "truecolor" || colorterm == "24bit" {
+        return ColorSupport::TrueColor;
+    }
+    let term = term.unwrap_or("").to_lowercase();
+    if term.contains("truecolor") || term.contains("24bit") || term.ends_with("-direct") {
+        return ColorSupport::TrueColor;
+    }
+    ColorSupport::Ansi256
+}
+
+/// Rewrites every RGB color in the buffer to its nearest 256-palette index
+/// when the outer terminal can't render truecolor.
+pub fn adapt_buffer(buffer: &mut Buffer) {
+    if color_support() == ColorSupport::TrueColor {
+        return;
+    }
+    for cell in &mut buffer.content {
+        cell.fg = adapt_color(cell.fg);
+        cell.bg = adapt_color(cell.bg);
+    }
+}
+
+pub fn adapt_color(color: Color) -> Color {
+    match color {
+        Color::Rgb(r, g, b) => Color::Indexed(rgb_to_ansi256(r, g, b)),
+        other => other,
+    }
+}
+
+const CUBE_LEVELS: [u8; 6] = [0, 95, 135, 175, 215, 255];
+
+pub fn rgb_to_ansi256(r: u8, g: u8, b: u8) -> u8 {
+    let nearest_level = |value: u8| -> usize {
+        CUBE_LEVELS
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, level)| (i32::from(**level) - i32::from(value)).abs())
+            .map(|(index, _)| index)
+            .unwrap_or(0)
+    };
+    let (ri, gi, bi) = (nearest_level(r), nearest_level(g), nearest_level(b));
+    let cube_index = 16 + 36 * ri + 6 * gi + bi;
+    let cube_color = (CUBE_LEVELS[ri], CUBE_LEVELS[gi], CUBE_LEVELS[bi]);
+
+    let average = (u32::from(r) + u32::from(g) + u32::from(b)) / 3;
+    let gray_step = ((average + 5).saturating_sub(8) / 10).min(23) as u8;
+    let gray_index = 232 + gray_step;
+    let gray_level = 8 + 10 * gray_step;
+    let gray_color = (gray_level, gray_level, gray_level);
+
+    if color_distance((r, g, b), gray_color) < color_distance((r, g, b), cube_color) {
+        gray_index
+    } else {
+        cube_index as u8
+    }
+}
+
+const ANSI_16: [(u8, u8, u8); 16] = [
+    (0, 0, 0),
+    (205, 0, 0),
+    (0, 205, 0),
+    (205, 205, 0),
+    (0, 0, 238),
+    (205, 0, 205),
+    (0, 205, 205),
+    (229, 229, 229),
+    (127, 127, 127),
+    (255, 0, 0),
+    (0, 255, 0),
+    (255, 255, 0),
+    (92, 92, 255),
+    (255, 0, 255),
+    (0, 255, 255),
+    (255, 255, 255),
+];
+
+/// RGB for an xterm 256-color palette index.
+pub fn indexed_to_rgb(index: u8) -> (u8, u8, u8) {
+    match index {
+        0..=15 => ANSI_16[index as usize],
+        16..=231 => {
+            let cube = usize::from(index - 16);
+            (
+                CUBE_LEVELS[cube / 36],
+                CUBE_LEVELS[(cube / 6) % 6],
+                CUBE_LEVELS[cube % 6],
+            )
+        }
+        _ => {
+            let level = 8 + 10 * (index - 232);
+            (level, level, level)
+        }
+    }
+}
+
+fn color_distance(a: (u8, u8, u8), b: (u8, u8, u8)) -> u32 {
+    let channel = |x: u8, y: u8| {
+        let diff = i32::from(x) - i32::from(y);
+        (diff * diff) as u32
+    };
+    channel(a.0, b.0) + channel(a.1, b.1) + channel(a.2, b.2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn indexed(color: Color) -> u8 {
+        match adapt_color(color) {
+            Color::Indexed(index) => index,
+            other => panic!("expected indexed color, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn theme_accents_map_to_cube_colors() {
+        assert_eq!(indexed(THEME.green), 84);
+        assert_eq!(indexed(THEME.orange), 215);
+        assert_eq!(indexed(THEME.yellow), 221);
+        assert_eq!(indexed(THEME.blue), 75);
+        assert_eq!(indexed(THEME.red), 203);
+        assert_eq!(indexed(THEME.fg), 231);
+    }
+
+    #[test]
+    fn theme_backgrounds_map_to_grayscale() {
+        assert_eq!(indexed(THEME.bg), 232);
+        assert_eq!(indexed(THEME.bg_alt2), 234);
+        assert_eq!(indexed(THEME.fg_dim), 242);
+    }
+
+    #[test]
+    fn palette_indices_round_trip_through_rgb() {
+        for index in [16, 67, 196, 231, 232, 255] {
+            let (r, g, b) = indexed_to_rgb(index);
+            assert_eq!(rgb_to_ansi256(r, g, b), index);
+        }
+        assert_eq!(indexed_to_rgb(1), (205, 0, 0));
+    }
+
+    #[test]
+    fn every_status_has_its_own_icon() {
+        for icons in [StatusIcons::nerd(), StatusIcons::unicode()] {
+            let glyphs: Vec<&str> = ["running", "idle", "error", "sleep"]
+                .into_iter()
+                .map(|status| icons.for_status(status))
+                .collect();
+            for (index, glyph) in glyphs.iter().enumerate() {
+                assert!(!glyphs[index + 1..].contains(glyph), "{glyphs:?}");
+            }
+            assert_eq!(icons.for_status("starting"), icons.sleep);
+        }
+    }
+
+    #[test]
+    fn status_icons_setting_picks_a_set_or_custom_glyphs() {
+        assert_eq!(StatusIcons::from_setting(None), StatusIcons::nerd());
+        assert_eq!(
+            StatusIcons::from_setting(Some(" Unicode ")),
+            StatusIcons::unicode()
+        );
+        let custom = StatusIcons::from_setting(Some("R, I, E, S"));
+        assert_eq!(custom.for_status("running"), "R");
+        assert_eq!(custom.for_status("sleep"), "S");
+        assert_eq!(
+            StatusIcons::from_setting(Some("R,I,E")),
+            StatusIcons::nerd()
+        );
+        assert_eq!(
+            StatusIcons::from_setting(Some("R,,E,S")),
+            StatusIcons::nerd()
+        );
+    }
+
+    #[test]
+    fn detects_truecolor_from_env() {
+        assert_eq!(
+            detect_color_support(Some("truecolor"), Some("xterm-256color")),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            detect_color_support(None, Some("xterm-direct")),
+            ColorSupport::TrueColor
+        );
+        assert_eq!(
+            detect_color_support(None, Some("xterm-256color")),
+            ColorSupport::Ansi256
+        );
+    }
+}
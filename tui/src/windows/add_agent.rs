@@ -122,7 +122,7 @@ fn handle_add_agent_keys(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn E
                 let tool = repo
                     .tools
                     .get(app.selected_tool)
-                    .cloned()
+                    .map(|tool| tool.name.clone())
                     .unwrap_or_else(|| repo.default_tool.clone());
                 let name = app.agent_name_input.trim();
                 let name = if name.is_empty() {
@@ -186,15 +186,16 @@ fn render_add_agent_window(frame: &mut Frame, app: &App, base: Rect) {
     let repo_box_height = (repo_list_lines + 2) as u16;
     let tool_box_height = (tool_list_lines + 2) as u16;
 
-    let mut constraints = Vec::new();
-    constraints.push(Constraint::Length(repo_box_height));
-    constraints.push(Constraint::Length(1));
-    constraints.push(Constraint::Length(3));
-    constraints.push(Constraint::Length(1));
-    constraints.push(Constraint::Length(tool_box_height));
-    constraints.push(Constraint::Length(1));
-    constraints.push(Constraint::Length(3));
-    constraints.push(Constraint::Min(0));
+    let constraints = vec![
+        Constraint::Length(repo_box_height),
+        Constraint::Length(1),
+        Constraint::Length(3),
+        Constraint::Length(1),
+        Constraint::Length(tool_box_height),
+        Constraint::Length(1),
+        Constraint::Length(3),
+        Constraint::Min(0),
+    ];
 
     let content_layout = Layout::vertical(constraints).split(sections[0]);
     let mut index = 0;
@@ -302,8 +303,12 @@ fn render_add_agent_window(frame: &mut Frame, app: &App, base: Rect) {
         .and_then(|repo| {
             repo.tools
                 .get(app.selected_tool)
-                .or_else(|| repo.tools.iter().find(|tool| *tool == &repo.default_tool))
-                .map(|tool| tool.as_str())
+                .or_else(|| {
+                    repo.tools
+                        .iter()
+                        .find(|tool| tool.name == repo.default_tool)
+                })
+                .map(|tool| tool.name.as_str())
         })
         .unwrap_or("Default agent for repo");
     let tool_selected = matches!(app.agent_field, AgentField::Tool);
@@ -358,10 +363,19 @@ fn render_add_agent_window(frame: &mut Frame, app: &App, base: Rect) {
                 } else {
                     Style::default().fg(THEME.fg_dim)
                 };
-                tool_lines.push(Line::from(Span::styled(
-                    format!("{} {}", marker, tool),
-                    style,
-                )));
+                let available = app
+                    .tool_availability
+                    .get(&tool.name)
+                    .copied()
+                    .unwrap_or(true);
+                let mut spans = vec![Span::styled(format!("{} {}", marker, tool.name), style)];
+                if !available {
+                    spans.push(Span::styled(
+                        " (not found)",
+                        Style::default().fg(THEME.fg_dim),
+                    ));
+                }
+                tool_lines.push(Line::from(spans));
             }
         }
     }
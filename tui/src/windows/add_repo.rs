@@ -1,5 +1,5 @@
 use crate::theme::THEME;
-use crate::{add_repo, App};
+use crate::{add_repo, AddRepoStep, App};
 use ratatui::{
     layout::Rect,
     style::Style,
@@ -24,32 +24,69 @@ impl Window for AddRepoWindow {
 }
 
 fn handle_add_repo_keys(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
-    match key.key {
-        KeyCode::Escape => {
+    match (app.add_repo_step, key.key) {
+        (AddRepoStep::Path, KeyCode::Escape) => {
             app.focused_window = None;
         }
-        KeyCode::Enter => {
+        (AddRepoStep::Name, KeyCode::Escape) => {
+            app.add_repo_step = AddRepoStep::Path;
+            app.repo_name_input.clear();
+        }
+        (AddRepoStep::Path, KeyCode::Enter) => {
             let path = app.input.trim();
             if path.is_empty() {
                 app.set_status("repo path is required");
                 return Ok(false);
             }
 
-            match add_repo(&app.client, &app.server_url, path) {
-                Ok(_) => {
+            match add_repo(&app.client, &app.server_url, path, None, true) {
+                Ok(repo) => {
+                    app.repo_name_input = repo.name;
+                    app.add_repo_step = AddRepoStep::Name;
+                    app.status_log.dismiss();
+                }
+                Err(err) => app.report_request_error(err),
+            }
+        }
+        (AddRepoStep::Name, KeyCode::Enter) => {
+            let name = app.repo_name_input.trim();
+            if name.is_empty() {
+                app.set_status("repo name is required");
+                return Ok(false);
+            }
+
+            let path = app.input.trim();
+            match add_repo(
+                &app.client,
+                &app.server_url,
+                path,
+                Some(name.to_string()),
+                false,
+            ) {
+                Ok(repo) => {
                     app.refresh_data();
                     app.focused_window = None;
                     app.input.clear();
+                    app.repo_name_input.clear();
+                    app.add_repo_step = AddRepoStep::Path;
+                    app.config_error = None;
+                    app.set_status(format!("added repo {}", repo.name));
                 }
-                Err(err) => app.set_status(err),
+                Err(err) => app.report_request_error(err),
             }
         }
-        KeyCode::Backspace => {
+        (AddRepoStep::Path, KeyCode::Backspace) => {
             app.input.pop();
         }
-        KeyCode::Char(value) => {
+        (AddRepoStep::Name, KeyCode::Backspace) => {
+            app.repo_name_input.pop();
+        }
+        (AddRepoStep::Path, KeyCode::Char(value)) => {
             app.input.push(value);
         }
+        (AddRepoStep::Name, KeyCode::Char(value)) => {
+            app.repo_name_input.push(value);
+        }
         _ => {}
     }
     Ok(false)
@@ -65,7 +102,13 @@ fn render_add_repo_window(frame: &mut Frame, app: &App, base: Rect) {
     frame.render_widget(&block, area);
 
     let inner = block.inner(area);
-    let text = format!("Path:\n{}\n\nEnter to save, Esc to cancel.", app.input);
+    let text = match app.add_repo_step {
+        AddRepoStep::Path => format!("Path:\n{}\n\nEnter to continue, Esc to cancel.", app.input),
+        AddRepoStep::Name => format!(
+            "Path:\n{}\n\nName:\n{}\n\nEnter to save, Esc to go back.",
+            app.input, app.repo_name_input
+        ),
+    };
     let paragraph = Paragraph::new(text)
         .wrap(Wrap { trim: true })
         .style(Style::default().fg(THEME.fg_mid));
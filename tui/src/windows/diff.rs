@@ -0,0 +1,135 @@
+use crate::theme::THEME;
+use crate::{fetch_diff, App, DiffView};
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
+    Frame,
+};
+use std::error::Error;
+use termwiz::input::{KeyCode, KeyEvent};
+
+use super::Window;
+
+pub struct DiffWindow;
+
+impl Window for DiffWindow {
+    fn render(frame: &mut Frame, app: &mut App, area: Rect) {
+        render_diff_window(frame, app, area);
+    }
+
+    fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
+        handle_diff_keys(app, key)
+    }
+}
+
+pub(crate) fn open_diff(app: &mut App, name: &str, label: &str, stat: bool) {
+    match fetch_diff(&app.client, &app.server_url, name, stat) {
+        Ok(diff) => {
+            app.diff_view = Some(DiffView {
+                name: name.to_string(),
+                label: label.to_string(),
+                lines: diff.diff.lines().map(str::to_string).collect(),
+                truncated: diff.truncated,
+                stat,
+                scroll: 0,
+                page: 0,
+            });
+            app.focused_window = Some(super::WindowId::Diff);
+        }
+        Err(err) => app.set_status(err),
+    }
+}
+
+fn handle_diff_keys(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
+    let Some(view) = app.diff_view.as_mut() else {
+        app.focused_window = None;
+        return Ok(false);
+    };
+    let max_scroll = view.lines.len().saturating_sub(view.page.max(1));
+    let page = view.page.max(1);
+    match key.key {
+        KeyCode::Escape | KeyCode::Char('q') => {
+            app.focused_window = None;
+            app.diff_view = None;
+        }
+        KeyCode::Char('s') => {
+            let (name, label, stat) = (view.name.clone(), view.label.clone(), !view.stat);
+            open_diff(app, &name, &label, stat);
+        }
+        KeyCode::UpArrow | KeyCode::Char('k') => view.scroll = view.scroll.saturating_sub(1),
+        KeyCode::DownArrow | KeyCode::Char('j') => {
+            view.scroll = (view.scroll + 1).min(max_scroll);
+        }
+        KeyCode::PageUp => view.scroll = view.scroll.saturating_sub(page),
+        KeyCode::PageDown => view.scroll = (view.scroll + page).min(max_scroll),
+        KeyCode::Home => view.scroll = 0,
+        KeyCode::End => view.scroll = max_scroll,
+        _ => {}
+    }
+    Ok(false)
+}
+
+fn diff_line_style(line: &str) -> Style {
+    if line.starts_with("+++") || line.starts_with("---") || line.starts_with("diff ") {
+        Style::default().fg(THEME.fg).add_modifier(Modifier::BOLD)
+    } else if line.starts_with('+') {
+        Style::default().fg(THEME.green)
+    } else if line.starts_with('-') {
+        Style::default().fg(THEME.red)
+    } else if line.starts_with("@@") {
+        Style::default().fg(THEME.blue)
+    } else {
+        Style::default().fg(THEME.fg_mid)
+    }
+}
+
+fn render_diff_window(frame: &mut Frame, app: &mut App, base: Rect) {
+    let Some(view) = app.diff_view.as_mut() else {
+        return;
+    };
+
+    let area = crate::centered_rect(90, 85, base);
+    frame.render_widget(Clear, area);
+    let mode = if view.stat { "stat" } else { "diff" };
+    let block = Block::bordered()
+        .title(format!("Diff · {} ({})", view.label, mode))
+        .style(Style::default().bg(THEME.bg_alt2).fg(THEME.fg))
+        .border_style(Style::default().fg(THEME.border));
+    frame.render_widget(&block, area);
+    let inner = block.inner(area);
+
+    let sections = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).split(inner);
+    view.page = sections[0].height as usize;
+    let max_scroll = view.lines.len().saturating_sub(view.page.max(1));
+    view.scroll = view.scroll.min(max_scroll);
+
+    let lines: Vec<Line> = if view.lines.is_empty() {
+        vec![Line::styled(
+            "No changes in worktree.",
+            Style::default().fg(THEME.fg_dim),
+        )]
+    } else {
+        view.lines
+            .iter()
+            .skip(view.scroll)
+            .take(view.page)
+            .map(|line| Line::styled(line.as_str(), diff_line_style(line)))
+            .collect()
+    };
+    frame.render_widget(Paragraph::new(lines), sections[0]);
+
+    let mut hint = vec![Span::styled(
+        "(↑/↓/PgUp/PgDn) scroll   (s) toggle stat   (Esc) close",
+        Style::default().fg(THEME.fg_dim),
+    )];
+    if view.truncated {
+        hint.push(Span::raw("   "));
+        hint.push(Span::styled(
+            "diff truncated",
+            Style::default().fg(THEME.yellow),
+        ));
+    }
+    frame.render_widget(Paragraph::new(Line::from(hint)), sections[1]);
+}
@@ -0,0 +1,150 @@
+use crate::theme::THEME;
+use crate::{merge_agent, App, MergeAgentAction};
+use ratatui::{
+    layout::{Alignment, Constraint, Layout, Rect},
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, Padding, Paragraph, Wrap},
+    Frame,
+};
+use std::error::Error;
+use termwiz::input::{KeyCode, KeyEvent};
+
+use super::Window;
+
+pub struct MergeAgentWindow;
+
+impl Window for MergeAgentWindow {
+    fn render(frame: &mut Frame, app: &mut App, area: Rect) {
+        render_merge_agent_window(frame, app, area);
+    }
+
+    fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
+        handle_merge_agent_keys(app, key)
+    }
+}
+
+fn handle_merge_agent_keys(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
+    match key.key {
+        KeyCode::Escape => {
+            app.focused_window = None;
+            app.merge_agent = None;
+        }
+        KeyCode::Tab | KeyCode::LeftArrow | KeyCode::RightArrow => {
+            app.merge_agent_action = match app.merge_agent_action {
+                MergeAgentAction::Cancel => MergeAgentAction::Merge,
+                MergeAgentAction::Merge => MergeAgentAction::Cancel,
+            };
+        }
+        KeyCode::Enter => match app.merge_agent_action {
+            MergeAgentAction::Cancel => {
+                app.focused_window = None;
+                app.merge_agent = None;
+            }
+            MergeAgentAction::Merge => {
+                if let Some(target) = app.merge_agent.take() {
+                    match merge_agent(&app.client, &app.server_url, &target.name) {
+                        Ok(merged) => {
+                            let how = if merged.fast_forward {
+                                "fast-forward to"
+                            } else {
+                                "merge commit"
+                            };
+                            let sha: String = merged.sha.chars().take(7).collect();
+                            app.set_status(format!(
+                                "merged {} into {} ({how} {sha})",
+                                target.label, merged.into
+                            ));
+                        }
+                        Err(err) => app.set_status(err),
+                    }
+                }
+                app.focused_window = None;
+            }
+        },
+        _ => {}
+    }
+
+    Ok(false)
+}
+
+fn render_merge_agent_window(frame: &mut Frame, app: &App, base: Rect) {
+    let label = app
+        .merge_agent
+        .as_ref()
+        .map(|agent| agent.label.as_str())
+        .unwrap_or("agent");
+
+    let area = crate::centered_rect(30, 25, base);
+    frame.render_widget(Clear, area);
+    let block = Block::bordered()
+        .title(
+            Line::from(vec![
+                Span::raw("Merge "),
+                Span::styled(label, Style::default().fg(THEME.green)).add_modifier(Modifier::BOLD),
+                Span::raw("?"),
+            ])
+            .centered(),
+        )
+        .style(Style::default().bg(THEME.bg_alt2).fg(THEME.fg))
+        .border_style(Style::default().fg(THEME.fg))
+        .padding(Padding::new(1, 1, 1, 1));
+    frame.render_widget(&block, area);
+
+    let inner = block.inner(area);
+
+    let text = "Its committed work is merged into the repo's checked-out branch. A conflict aborts the merge.";
+    let sections = Layout::vertical([
+        Constraint::Length(4),
+        Constraint::Length(3),
+        Constraint::Length(1),
+        Constraint::Length(1),
+    ])
+    .split(inner);
+    let paragraph = Paragraph::new(text)
+        .wrap(Wrap { trim: true })
+        .alignment(Alignment::Center)
+        .style(Style::default().fg(THEME.fg_mid));
+    frame.render_widget(paragraph, sections[0]);
+
+    let button_layout =
+        Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+            .split(sections[1]);
+
+    let cancel_selected = matches!(app.merge_agent_action, MergeAgentAction::Cancel);
+    let cancel_button_style = if cancel_selected {
+        THEME.fg
+    } else {
+        THEME.fg_mid
+    };
+
+    let cancel_block = Block::bordered()
+        .style(Style::default().bg(THEME.bg_alt2).fg(cancel_button_style))
+        .border_style(Style::default().fg(cancel_button_style));
+    let cancel_button = Paragraph::new("Cancel")
+        .style(Style::default().fg(cancel_button_style))
+        .alignment(Alignment::Center)
+        .block(cancel_block);
+    frame.render_widget(cancel_button, button_layout[0]);
+
+    let merge_selected = matches!(app.merge_agent_action, MergeAgentAction::Merge);
+    let merge_button_style = if merge_selected {
+        THEME.green
+    } else {
+        THEME.fg_mid
+    };
+    let merge_block = Block::bordered()
+        .style(Style::default().bg(THEME.bg_alt2).fg(merge_button_style))
+        .border_style(Style::default().fg(merge_button_style));
+    let merge_button = Paragraph::new("Merge")
+        .style(Style::default().fg(merge_button_style))
+        .alignment(Alignment::Center)
+        .block(merge_block);
+    frame.render_widget(merge_button, button_layout[1]);
+
+    let hint = Paragraph::new("Tab or arrow keys to switch, Enter to confirm, Esc to cancel.")
+        .wrap(Wrap { trim: true })
+        .style(Style::default().fg(THEME.fg_dim))
+        .alignment(Alignment::Center);
+    frame.render_widget(hint, sections[3]);
+}
@@ -6,9 +6,14 @@ use termwiz::input::KeyEvent;
 pub mod add_agent;
 pub mod add_repo;
 pub mod delete_agent;
+pub mod diff;
+pub mod merge_agent;
+pub mod rename_agent;
 pub mod restart_agent;
 pub mod root;
 pub mod show_repos;
+pub mod status_log;
+pub mod tail;
 
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum WindowId {
@@ -18,6 +23,11 @@ pub enum WindowId {
     ShowRepos,
     DeleteAgent,
     RestartAgent,
+    MergeAgent,
+    Diff,
+    StatusLog,
+    RenameAgent,
+    Tail,
 }
 
 pub trait Window {
@@ -37,6 +47,13 @@ pub fn render_window(id: WindowId, frame: &mut Frame, app: &mut App, area: Rect)
         WindowId::RestartAgent => {
             <restart_agent::RestartAgentWindow as Window>::render(frame, app, area)
         }
+        WindowId::MergeAgent => <merge_agent::MergeAgentWindow as Window>::render(frame, app, area),
+        WindowId::Diff => <diff::DiffWindow as Window>::render(frame, app, area),
+        WindowId::StatusLog => <status_log::StatusLogWindow as Window>::render(frame, app, area),
+        WindowId::RenameAgent => {
+            <rename_agent::RenameAgentWindow as Window>::render(frame, app, area)
+        }
+        WindowId::Tail => <tail::TailWindow as Window>::render(frame, app, area),
     }
 }
 
@@ -56,5 +73,14 @@ pub fn handle_window_key_event(
         WindowId::RestartAgent => {
             <restart_agent::RestartAgentWindow as Window>::handle_key_event(app, key)
         }
+        WindowId::MergeAgent => {
+            <merge_agent::MergeAgentWindow as Window>::handle_key_event(app, key)
+        }
+        WindowId::Diff => <diff::DiffWindow as Window>::handle_key_event(app, key),
+        WindowId::StatusLog => <status_log::StatusLogWindow as Window>::handle_key_event(app, key),
+        WindowId::RenameAgent => {
+            <rename_agent::RenameAgentWindow as Window>::handle_key_event(app, key)
+        }
+        WindowId::Tail => <tail::TailWindow as Window>::handle_key_event(app, key),
     }
 }
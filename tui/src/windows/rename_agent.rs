@@ -0,0 +1,103 @@
+use crate::theme::THEME;
+use crate::{rename_agent, App};
+use ratatui::{
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, Padding, Paragraph},
+    Frame,
+};
+use std::error::Error;
+use termwiz::input::{KeyCode, KeyEvent};
+
+use super::Window;
+
+pub struct RenameAgentWindow;
+
+impl Window for RenameAgentWindow {
+    fn render(frame: &mut Frame, app: &mut App, area: Rect) {
+        render_rename_agent_window(frame, app, area);
+    }
+
+    fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
+        handle_rename_agent_keys(app, key)
+    }
+}
+
+fn handle_rename_agent_keys(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
+    match key.key {
+        KeyCode::Escape => {
+            app.focused_window = None;
+            app.rename_agent = None;
+        }
+        KeyCode::Backspace => {
+            app.rename_input.pop();
+        }
+        KeyCode::Char(value) => app.rename_input.push(value),
+        KeyCode::Enter => {
+            let label = app.rename_input.trim().to_string();
+            if label.is_empty() {
+                app.set_status("label is required");
+                return Ok(false);
+            }
+            if let Some(target) = app.rename_agent.take() {
+                match rename_agent(&app.client, &app.server_url, &target.name, &label) {
+                    Ok(()) => {
+                        app.refresh_data();
+                        app.set_status(format!("renamed {} to {label}", target.label));
+                    }
+                    Err(err) => app.set_status(err),
+                }
+            }
+            app.focused_window = None;
+        }
+        _ => {}
+    }
+
+    Ok(false)
+}
+
+fn render_rename_agent_window(frame: &mut Frame, app: &App, base: Rect) {
+    let label = app
+        .rename_agent
+        .as_ref()
+        .map(|agent| agent.label.as_str())
+        .unwrap_or("agent");
+
+    let area = crate::centered_rect(40, 23, base);
+    frame.render_widget(Clear, area);
+    let block = Block::bordered()
+        .title(
+            Line::from(vec![
+                Span::raw("Rename "),
+                Span::styled(label, Style::default().fg(THEME.orange)).add_modifier(Modifier::BOLD),
+            ])
+            .centered(),
+        )
+        .style(Style::default().bg(THEME.bg_alt2).fg(THEME.fg))
+        .border_style(Style::default().fg(THEME.fg))
+        .padding(Padding::new(1, 1, 1, 1));
+    frame.render_widget(&block, area);
+
+    let inner = block.inner(area);
+    let sections = Layout::vertical([
+        Constraint::Length(1),
+        Constraint::Length(3),
+        Constraint::Min(0),
+        Constraint::Length(1),
+    ])
+    .split(inner);
+    frame.render_widget(
+        Paragraph::new("Label").style(Style::default().fg(THEME.fg_mid)),
+        sections[0],
+    );
+    let input = Paragraph::new(format!("{}▏", app.rename_input))
+        .style(Style::default().fg(THEME.fg))
+        .block(Block::bordered().border_style(Style::default().fg(THEME.border)));
+    frame.render_widget(input, sections[1]);
+
+    let hint = Paragraph::new("Enter to save, Esc to cancel.")
+        .style(Style::default().fg(THEME.fg_dim))
+        .centered();
+    frame.render_widget(hint, sections[3]);
+}
@@ -1,7 +1,11 @@
 use crate::theme::THEME;
 use crate::{
-    default_tool_index, sync_filtered_selection, Agent, AgentField, App, DeleteAgentAction,
-    DeleteAgentTarget, RestartAgentAction, RestartAgentTarget,
+    agent_tool_kind, default_tool_index, filtered_agent_indices, line_matches, preview_page_key,
+    scroll_selected_preview, signal_agent, sleep_agent, sync_agent_list_selection,
+    sync_filtered_selection, AddRepoStep, Agent, AgentField, App, DeleteAgentAction,
+    DeleteAgentTarget, MergeAgentAction, MergeAgentTarget, PreviewScroll, PreviewSearch,
+    RenameAgentTarget, RestartAgentAction, RestartAgentTarget, SearchMatch, SidebarDensity,
+    WorktreeTool, MIN_DEBUG_SIDEBAR_WIDTH,
 };
 use ratatui::{
     buffer::Buffer,
@@ -14,11 +18,16 @@ use ratatui::{
 use std::{borrow::Cow, error::Error};
 use termwiz::cell::{Blink, CellAttributes, Intensity, Underline};
 use termwiz::color::{ColorAttribute, SrgbaTuple};
-use termwiz::input::KeyCode;
+use termwiz::input::{KeyCode, Modifiers};
 use termwiz::surface::{CursorShape, CursorVisibility, Line as TermwizLine};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+use workforest_core::{TerminalColor, ToolKind};
 
 use super::Window;
 
+/// Columns `<` and `>` take from or give to the debug sidebar.
+const DEBUG_SIDEBAR_STEP: u16 = 4;
+
 pub struct RootWindow;
 
 impl Window for RootWindow {
@@ -35,12 +44,24 @@ impl Window for RootWindow {
 }
 
 fn handle_root_keys(app: &mut App, key: termwiz::input::KeyEvent) -> Result<bool, Box<dyn Error>> {
+    if app.editing_agent_list_filter && handle_filter_keys(app, &key) {
+        return Ok(false);
+    }
+    if let Some(scroll) = preview_page_key(&key, &mut app.pending_g) {
+        scroll_selected_preview(app, scroll);
+        return Ok(false);
+    }
+    if app.pending_g {
+        return Ok(false);
+    }
     match key.key {
         KeyCode::Char('q') => return Ok(true),
         KeyCode::Char('r') => {
             app.focused_window = Some(super::WindowId::AddRepo);
             app.input.clear();
-            app.status_message = None;
+            app.repo_name_input.clear();
+            app.add_repo_step = AddRepoStep::Path;
+            app.status_log.dismiss();
         }
         KeyCode::Char('a') => {
             if app.repos.is_empty() {
@@ -53,11 +74,33 @@ fn handle_root_keys(app: &mut App, key: termwiz::input::KeyEvent) -> Result<bool
                 app.agent_filter_input.clear();
                 app.agent_name_input = petname::petname(2, "-");
                 sync_filtered_selection(app);
-                app.status_message = None;
+                app.status_log.dismiss();
             }
         }
         KeyCode::Char('l') => {
             app.focused_window = Some(super::WindowId::ShowRepos);
+            app.confirming_repo_delete = false;
+            app.repo_list_selected = app
+                .repo_list_selected
+                .min(app.repos.len().saturating_sub(1));
+        }
+        KeyCode::Char('n') => {
+            if let Some(agent) = app.agents.get(app.selected_agent) {
+                app.rename_input = agent.label.clone();
+                app.rename_agent = Some(RenameAgentTarget {
+                    name: agent.name.clone(),
+                    label: agent.label.clone(),
+                });
+                app.focused_window = Some(super::WindowId::RenameAgent);
+            } else {
+                app.set_status("no agent to rename");
+            }
+        }
+        KeyCode::Char('L') => {
+            app.focused_window = Some(super::WindowId::StatusLog);
+        }
+        KeyCode::Char('t') => {
+            app.focused_window = Some(super::WindowId::Tail);
         }
         KeyCode::Char('u') => {
             app.refresh_data();
@@ -74,9 +117,39 @@ fn handle_root_keys(app: &mut App, key: termwiz::input::KeyEvent) -> Result<bool
                 app.focused_window = Some(super::WindowId::DeleteAgent);
             }
         }
+        KeyCode::Char('/') => {
+            app.editing_agent_list_filter = true;
+        }
         KeyCode::Char('D') => {
             app.debug_sidebar = !app.debug_sidebar;
         }
+        KeyCode::Char('<') if app.debug_sidebar => {
+            app.debug_sidebar_width = app
+                .debug_sidebar_width
+                .saturating_sub(DEBUG_SIDEBAR_STEP)
+                .max(MIN_DEBUG_SIDEBAR_WIDTH);
+        }
+        KeyCode::Char('>') if app.debug_sidebar => {
+            app.debug_sidebar_width = app
+                .debug_sidebar_width
+                .saturating_add(DEBUG_SIDEBAR_STEP)
+                .min(app.debug_sidebar_limit.unwrap_or(u16::MAX))
+                .max(MIN_DEBUG_SIDEBAR_WIDTH);
+        }
+        KeyCode::Char('[') if app.debug_sidebar => {
+            app.debug_scroll = app.debug_scroll.saturating_sub(1);
+        }
+        KeyCode::Char(']') if app.debug_sidebar => {
+            app.debug_scroll = app.debug_scroll.saturating_add(1);
+        }
+        KeyCode::Char('S') if app.debug_sidebar => {
+            app.show_inactive_surface = !app.show_inactive_surface;
+            app.set_status(if app.show_inactive_surface {
+                "previewing the inactive screen"
+            } else {
+                "previewing the active screen"
+            });
+        }
         KeyCode::Char('R') => {
             if app.agents.is_empty() {
                 app.set_status("no agents to restart");
@@ -89,33 +162,223 @@ fn handle_root_keys(app: &mut App, key: termwiz::input::KeyEvent) -> Result<bool
                 app.focused_window = Some(super::WindowId::RestartAgent);
             }
         }
-        KeyCode::Enter => {
+        KeyCode::Char('M') => {
             if let Some(agent) = app.agents.get(app.selected_agent) {
-                app.focused_agent = Some(agent.name.clone());
+                app.merge_agent = Some(MergeAgentTarget {
+                    name: agent.name.clone(),
+                    label: agent.label.clone(),
+                });
+                app.merge_agent_action = MergeAgentAction::Cancel;
+                app.focused_window = Some(super::WindowId::MergeAgent);
             }
         }
-        KeyCode::UpArrow => {
-            if app.selected_agent > 0 {
-                app.selected_agent -= 1;
+        KeyCode::Char('f') => {
+            if let Some(agent) = app.agents.get(app.selected_agent) {
+                let label = agent.label.clone();
+                if let Some(view) = app.pty_views.get_mut(&agent.name) {
+                    let state = if view.toggle_follow() { "on" } else { "off" };
+                    app.set_status(format!("follow {state} for {label}"));
+                }
             }
         }
-        KeyCode::DownArrow => {
-            if app.selected_agent + 1 < app.agents.len() {
-                app.selected_agent += 1;
+        KeyCode::Char('c') => {
+            app.sidebar_density = app.sidebar_density.toggled();
+        }
+        KeyCode::Char('m') => {
+            app.mouse_capture = !app.mouse_capture;
+            app.set_status(if app.mouse_capture {
+                "mouse capture on"
+            } else {
+                "mouse capture off; terminal selection restored"
+            });
+        }
+        KeyCode::Char('o') => open_selected_worktree(app, WorktreeTool::Shell),
+        KeyCode::Char('e') => open_selected_worktree(app, WorktreeTool::Editor),
+        KeyCode::Char('F') => {
+            if let Some(agent) = app.agents.get(app.selected_agent) {
+                let (name, label) = (agent.name.clone(), agent.label.clone());
+                app.attach_fresh(&name);
+                app.set_status(format!("starting a fresh session for {label}"));
             }
         }
+        KeyCode::Char('y') => copy_selected_agent(app, CopyField::Name),
+        KeyCode::Char('Y') => copy_selected_agent(app, CopyField::WorktreePath),
+        KeyCode::Char('s') => sleep_selected_agent(app),
+        KeyCode::Char('i') => send_signal_to_selected(app, "INT"),
+        KeyCode::Char('T') => send_signal_to_selected(app, "TERM"),
+        KeyCode::Char('v') => {
+            if let Some(agent) = app.agents.get(app.selected_agent) {
+                let (name, label) = (agent.name.clone(), agent.label.clone());
+                super::diff::open_diff(app, &name, &label, false);
+            }
+        }
+        KeyCode::Enter => {
+            if let Some(agent) = app.agents.get(app.selected_agent) {
+                let name = agent.name.clone();
+                if app.pty_views.get(&name).is_some_and(|view| view.ended) {
+                    app.pty_views.remove(&name);
+                }
+                app.focused_agent = Some(name);
+            }
+        }
+        KeyCode::Home => scroll_selected_preview(app, PreviewScroll::Top),
+        KeyCode::End => scroll_selected_preview(app, PreviewScroll::Bottom),
+        KeyCode::UpArrow if key.modifiers.contains(Modifiers::SHIFT) => {
+            scroll_selected_preview(app, PreviewScroll::LineUp);
+        }
+        KeyCode::DownArrow if key.modifiers.contains(Modifiers::SHIFT) => {
+            scroll_selected_preview(app, PreviewScroll::LineDown);
+        }
+        KeyCode::UpArrow => step_visible_selection(app, SelectionStep::Previous),
+        KeyCode::DownArrow => step_visible_selection(app, SelectionStep::Next),
         _ => {}
     }
 
     Ok(false)
 }
 
+/// Keys typed while the `/` filter is open. Returns false for keys that
+/// keep their usual meaning, such as the arrows.
+fn handle_filter_keys(app: &mut App, key: &termwiz::input::KeyEvent) -> bool {
+    match key.key {
+        KeyCode::Char(value) => app.agent_list_filter.push(value),
+        KeyCode::Backspace => {
+            app.agent_list_filter.pop();
+        }
+        KeyCode::Enter => app.editing_agent_list_filter = false,
+        KeyCode::Escape => {
+            app.agent_list_filter.clear();
+            app.editing_agent_list_filter = false;
+        }
+        _ => return false,
+    }
+    sync_agent_list_selection(app);
+    true
+}
+
+/// Steps the selection through the agents the filter leaves visible.
+fn step_visible_selection(app: &mut App, step: SelectionStep) {
+    let indices = filtered_agent_indices(app);
+    let position = indices
+        .iter()
+        .position(|&index| index == app.selected_agent)
+        .unwrap_or(0);
+    let position = step_selection(position, indices.len(), step, app.wrap_navigation);
+    if let Some(&index) = indices.get(position) {
+        app.selected_agent = index;
+    }
+}
+
+/// How many agents fit in a sidebar of `height` rows; always at least one.
+fn visible_sidebar_entries(height: u16, density: SidebarDensity) -> usize {
+    (height as usize / density.entry_height()).max(1)
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum SelectionStep {
+    Previous,
+    Next,
+}
+
+/// Moves the sidebar selection one entry, clamping at the ends or wrapping
+/// to the opposite end when `wrap` is set. The sidebar scroll follows the
+/// selection on the next render.
+fn step_selection(selected: usize, len: usize, step: SelectionStep, wrap: bool) -> usize {
+    if len == 0 {
+        return 0;
+    }
+    match step {
+        SelectionStep::Previous if selected == 0 => {
+            if wrap {
+                len - 1
+            } else {
+                0
+            }
+        }
+        SelectionStep::Previous => selected - 1,
+        SelectionStep::Next if selected + 1 >= len => {
+            if wrap {
+                0
+            } else {
+                len - 1
+            }
+        }
+        SelectionStep::Next => selected + 1,
+    }
+}
+
+fn open_selected_worktree(app: &mut App, tool: WorktreeTool) {
+    let Some(agent) = app.agents.get(app.selected_agent) else {
+        app.set_status("no agent selected");
+        return;
+    };
+    let command = crate::worktree_command(
+        tool,
+        &agent.name,
+        std::path::Path::new(&agent.worktree_path),
+        |name| std::env::var(name).ok(),
+    );
+    app.pending_command = Some(command);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CopyField {
+    Name,
+    WorktreePath,
+}
+
+/// Queues the selected agent's name or worktree path for the clipboard; the
+/// main loop writes it to the host terminal as OSC 52.
+fn copy_selected_agent(app: &mut App, field: CopyField) {
+    let Some(agent) = app.agents.get(app.selected_agent) else {
+        app.set_status("no agent selected");
+        return;
+    };
+    let (text, what) = match field {
+        CopyField::Name => (agent.name.clone(), "name"),
+        CopyField::WorktreePath => (agent.worktree_path.clone(), "worktree path"),
+    };
+    let label = agent.label.clone();
+    app.pending_clipboard = Some(text);
+    app.set_status(format!("copied {what} of {label}"));
+}
+
+fn send_signal_to_selected(app: &mut App, signal: &str) {
+    let Some(agent) = app.agents.get(app.selected_agent) else {
+        app.set_status("no agent selected");
+        return;
+    };
+    let label = agent.label.clone();
+    match signal_agent(&app.client, &app.server_url, &agent.name, signal) {
+        Ok(()) => app.set_status(format!("sent SIG{signal} to {label}")),
+        Err(err) => app.set_status(err),
+    }
+}
+
+/// The preview keeps the agent's last screen; focusing it wakes it up.
+fn sleep_selected_agent(app: &mut App) {
+    let Some(agent) = app.agents.get(app.selected_agent) else {
+        app.set_status("no agent selected");
+        return;
+    };
+    let label = agent.label.clone();
+    match sleep_agent(&app.client, &app.server_url, &agent.name) {
+        Ok(()) => {
+            app.refresh_data();
+            app.set_status(format!("{label} is asleep; (Enter) wakes it"));
+        }
+        Err(err) => app.set_status(err),
+    }
+}
+
 fn render_agents(frame: &mut Frame, area: Rect, app: &mut App) {
     let padded_area = Rect {
         y: area.y.saturating_add(1),
         height: area.height.saturating_sub(1),
         ..area
     };
+    // However wide it is set, the debug sidebar leaves room for the preview.
+    app.debug_sidebar_limit = Some(padded_area.width / 3);
 
     if app.agents.is_empty() {
         let empty = Paragraph::new("No agents yet. Press (a) to add one.")
@@ -126,10 +389,11 @@ fn render_agents(frame: &mut Frame, area: Rect, app: &mut App) {
     }
 
     let sections = if app.debug_sidebar {
+        let debug_width = app.debug_sidebar_width.min(padded_area.width / 3);
         Layout::horizontal([
             Constraint::Length(32),
             Constraint::Min(0),
-            Constraint::Length(32),
+            Constraint::Length(debug_width),
         ])
         .split(padded_area)
     } else {
@@ -143,28 +407,47 @@ fn render_agents(frame: &mut Frame, area: Rect, app: &mut App) {
 }
 
 fn render_agent_sidebar(frame: &mut Frame, area: Rect, app: &mut App) {
-    let entry_height = 4usize;
-    let visible_entries = (area.height as usize / entry_height).max(1);
-    let total_entries = app.agents.len();
+    let area = if app.editing_agent_list_filter || !app.agent_list_filter.is_empty() {
+        let sections = Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).split(area);
+        render_agent_filter(frame, sections[0], app);
+        sections[1]
+    } else {
+        area
+    };
+    let indices = filtered_agent_indices(app);
+    if indices.is_empty() {
+        let empty = Paragraph::new("No agents match the filter.")
+            .style(Style::default().fg(THEME.fg_mid))
+            .alignment(ratatui::layout::Alignment::Center);
+        frame.render_widget(empty, area);
+        return;
+    }
+    let selected = indices
+        .iter()
+        .position(|&index| index == app.selected_agent)
+        .unwrap_or(0);
+
+    let density = app.sidebar_density;
+    let entry_height = density.entry_height();
+    let visible_entries = visible_sidebar_entries(area.height, density);
+    let total_entries = indices.len();
 
     if total_entries <= visible_entries {
         app.agent_scroll = 0;
     } else {
         let max_scroll = total_entries - visible_entries;
-        if app.selected_agent < app.agent_scroll {
-            app.agent_scroll = app.selected_agent;
-        } else if app.selected_agent >= app.agent_scroll + visible_entries {
-            app.agent_scroll = app.selected_agent + 1 - visible_entries;
+        if selected < app.agent_scroll {
+            app.agent_scroll = selected;
+        } else if selected >= app.agent_scroll + visible_entries {
+            app.agent_scroll = selected + 1 - visible_entries;
         }
         app.agent_scroll = app.agent_scroll.min(max_scroll);
     }
 
     let max_scroll = total_entries.saturating_sub(visible_entries);
-    let scrollbar_position = if max_scroll > 0 {
-        app.agent_scroll * (total_entries.saturating_sub(1)) / max_scroll
-    } else {
-        0
-    };
+    let scrollbar_position = (app.agent_scroll * (total_entries.saturating_sub(1)))
+        .checked_div(max_scroll)
+        .unwrap_or(0);
 
     let list_area = if total_entries > visible_entries && area.width > 1 {
         Rect {
@@ -186,7 +469,7 @@ fn render_agent_sidebar(frame: &mut Frame, area: Rect, app: &mut App) {
     }
     let row_areas = Layout::vertical(row_constraints).split(list_area);
 
-    for (visible_index, agent_index) in (start_index..end_index).enumerate() {
+    for (visible_index, &agent_index) in indices[start_index..end_index].iter().enumerate() {
         if let Some(agent) = app.agents.get(agent_index) {
             let area_index = visible_index * 2;
             let block_style = if agent_index == app.selected_agent {
@@ -194,20 +477,36 @@ fn render_agent_sidebar(frame: &mut Frame, area: Rect, app: &mut App) {
             } else {
                 Style::default().bg(THEME.bg)
             };
+            let vertical_padding = u16::from(density == SidebarDensity::Comfortable);
             let block = Block::default().style(block_style).padding(Padding {
                 left: 2,
                 right: 1,
-                top: 1,
-                bottom: 1,
+                top: vertical_padding,
+                bottom: vertical_padding,
             });
             let row_area = row_areas[area_index];
             frame.render_widget(&block, row_area);
 
             let inner_area = block.inner(row_area);
-            let name_line = build_name_line(agent, app.animation_start);
-            let repo_line =
-                Line::from(Span::styled(&agent.repo, Style::default().fg(THEME.fg_mid)));
-            let lines = vec![name_line, repo_line];
+            let mut name_line = build_name_line(agent, app.animation_start);
+            if agent_tool_kind(&app.repos, agent) == ToolKind::Shell {
+                name_line
+                    .spans
+                    .push(Span::styled(" [shell]", Style::default().fg(THEME.fg_dim)));
+            }
+            let lines = match density {
+                SidebarDensity::Comfortable => vec![
+                    name_line,
+                    Line::from(Span::styled(&agent.repo, Style::default().fg(THEME.fg_mid))),
+                ],
+                SidebarDensity::Compact => {
+                    name_line.spans.push(Span::styled(
+                        format!(" {}", agent.status),
+                        Style::default().fg(status_color(&agent.status)),
+                    ));
+                    vec![name_line]
+                }
+            };
             let paragraph = Paragraph::new(lines)
                 .style(block_style)
                 .alignment(ratatui::layout::Alignment::Left);
@@ -231,61 +530,110 @@ fn render_agent_sidebar(frame: &mut Frame, area: Rect, app: &mut App) {
     }
 }
 
-fn render_agent_preview(frame: &mut Frame, area: Rect, app: &mut App) {
-    let inner_area = area;
+fn render_agent_filter(frame: &mut Frame, area: Rect, app: &App) {
+    let area = Rect {
+        x: area.x.saturating_add(2),
+        width: area.width.saturating_sub(3),
+        ..area
+    };
+    let mut spans = vec![
+        Span::styled("/ ", Style::default().fg(THEME.fg_dim)),
+        Span::styled(app.agent_list_filter.clone(), Style::default().fg(THEME.fg)),
+    ];
+    if app.editing_agent_list_filter {
+        spans.push(Span::styled("█", Style::default().fg(THEME.fg_mid)));
+    }
+    frame.render_widget(Paragraph::new(Line::from(spans)), area);
+}
 
+fn render_agent_preview(frame: &mut Frame, area: Rect, app: &mut App) {
     if app.agents.is_empty() {
         let empty = Paragraph::new("No agents yet. Press (a) to add one.")
             .style(Style::default().fg(THEME.fg_mid))
             .alignment(ratatui::layout::Alignment::Center);
-        frame.render_widget(empty, inner_area);
+        frame.render_widget(empty, area);
         return;
     }
 
     let agent_name = app.agents[app.selected_agent].name.clone();
-    app.preview_area = Some(inner_area);
+    let show_inactive = app.debug_sidebar && app.show_inactive_surface;
+    let sections = Layout::vertical([Constraint::Length(1), Constraint::Min(0)]).split(area);
+    let mut header =
+        preview_header_line(&app.agents[app.selected_agent], sections[0].width as usize);
+    if show_inactive {
+        if let Some(view) = app.pty_views.get(&agent_name) {
+            header
+                .spans
+                .push(inactive_surface_label(view.use_alt_screen));
+        }
+    }
+    frame.render_widget(Paragraph::new(header), sections[0]);
+    let inner_area = sections[1];
+
     app.preview_agent = Some(agent_name.clone());
-    app.ensure_pty_view(&agent_name, inner_area);
+    // A modal covers the preview, so don't attach, resize or take mouse
+    // input for it until the modal closes.
+    if app.focused_window.is_none() {
+        app.preview_area = Some(inner_area);
+        app.ensure_pty_view(&agent_name, inner_area);
+    }
     app.sync_agent_debug_flags(&agent_name);
 
     if let Some(view) = app.pty_views.get_mut(&agent_name) {
-        let blink_on = !app.focused_agent.is_some()
-            || (app.animation_start.elapsed().as_millis() / 700) % 2 == 0;
+        let blink_on = app.focused_agent.is_none()
+            || crate::cursor_blink_on(app.animation_start.elapsed(), app.cursor_blink);
         let height = inner_area.height as usize;
         let total_lines = view.scrollback.len().saturating_add(height);
         let max_offset = total_lines.saturating_sub(height);
         if view.scroll_offset > max_offset {
             view.scroll_offset = max_offset;
         }
-        let start = total_lines.saturating_sub(height.saturating_add(view.scroll_offset));
+        let surface = view.preview_surface(show_inactive);
+        let (cursor_x, cursor_y) = surface.cursor_position();
+        let cursor_row = view.scrollback.len() + cursor_y;
+        // While typing into the agent, keep the row input goes to in view
+        // even when the pane is shorter than the agent's screen.
+        let follow_cursor =
+            app.focused_agent.as_deref() == Some(agent_name.as_str()) && view.scroll_offset == 0;
+        let start = preview_window_start(
+            total_lines,
+            height,
+            view.scroll_offset,
+            follow_cursor.then_some(cursor_row),
+        );
         let visible_lines = view
-            .preview_lines()
+            .preview_lines(show_inactive)
             .into_iter()
             .skip(start)
             .take(height)
             .collect::<Vec<_>>();
-        let cursor_visible = matches!(
-            view.active_surface().cursor_visibility(),
-            CursorVisibility::Visible
-        );
-        let cursor_shape = view
-            .active_surface()
-            .cursor_shape()
-            .unwrap_or(CursorShape::Default);
-        let should_blink = cursor_shape.is_blinking();
-        let show_cursor = view.scroll_offset == 0 && cursor_visible && (!should_blink || blink_on);
-        let is_block = matches!(
+        let cursor_visible = matches!(surface.cursor_visibility(), CursorVisibility::Visible);
+        let cursor_shape = surface.cursor_shape().unwrap_or(CursorShape::Default);
+        let cursor_style = preview_cursor_style(
             cursor_shape,
-            CursorShape::Default | CursorShape::BlinkingBlock | CursorShape::SteadyBlock
+            cursor_visible,
+            blink_on,
+            view.scroll_offset > 0,
         );
-        let cursor_pos = if show_cursor && is_block {
-            Some(view.active_surface().cursor_position())
-        } else {
-            None
-        };
+        let cursor_pos = cursor_style
+            .and_then(|_| cursor_row.checked_sub(start))
+            .map(|row| (cursor_x, row));
+        let highlights = app
+            .preview_search
+            .as_ref()
+            .filter(|search| search.agent == agent_name)
+            .map(|search| search_highlights(&visible_lines, start, search))
+            .unwrap_or_default();
         let preview = TermwizPreview {
             lines: visible_lines,
             cursor_pos,
+            cursor_style: cursor_style.unwrap_or(PreviewCursorStyle::Block),
+            highlights,
+            faint_blend: app.faint_blend,
+            default_colors: DefaultColors {
+                foreground: view.default_foreground.map(srgba_to_color),
+                background: view.default_background.map(srgba_to_color),
+            },
         };
         frame.render_widget(preview, inner_area);
     } else {
@@ -301,19 +649,183 @@ fn render_agent_preview(frame: &mut Frame, area: Rect, app: &mut App) {
     }
 }
 
+/// Matches of the search on the visible rows, the first of which is line
+/// `start` of the preview.
+fn search_highlights(
+    visible_lines: &[Cow<'_, TermwizLine>],
+    start: usize,
+    search: &PreviewSearch,
+) -> Vec<PreviewHighlight> {
+    visible_lines
+        .iter()
+        .enumerate()
+        .flat_map(|(row, line)| {
+            line_matches(line, &search.query)
+                .into_iter()
+                .map(move |(from, to)| PreviewHighlight {
+                    row,
+                    start: from,
+                    end: to,
+                    current: search.current
+                        == Some(SearchMatch {
+                            line: start + row,
+                            start: from,
+                            end: to,
+                        }),
+                })
+        })
+        .collect()
+}
+
+/// First of the `height` preview rows shown out of `total` (scrollback plus
+/// screen), `scroll_offset` rows up from the bottom. Given a `cursor_row`,
+/// the window slides just far enough to keep that row on screen.
+fn preview_window_start(
+    total: usize,
+    height: usize,
+    scroll_offset: usize,
+    cursor_row: Option<usize>,
+) -> usize {
+    let start = total.saturating_sub(height.saturating_add(scroll_offset));
+    match cursor_row {
+        Some(row) if row < start => row,
+        Some(row) if height > 0 && row >= start + height => row + 1 - height,
+        _ => start,
+    }
+}
+
+/// Marks a preview showing the inactive screen: the main screen while the
+/// agent is on the alt screen, and the other way round.
+fn inactive_surface_label(alt_active: bool) -> Span<'static> {
+    let label = if alt_active {
+        " [main screen, inactive]"
+    } else {
+        " [alt screen, inactive]"
+    };
+    Span::styled(label, Style::default().fg(THEME.yellow))
+}
+
+fn preview_header_line(agent: &Agent, width: usize) -> Line<'static> {
+    let separator = Style::default().fg(THEME.fg_dim);
+    let pieces = [
+        (
+            agent.label.clone(),
+            Style::default().fg(THEME.fg).add_modifier(Modifier::BOLD),
+        ),
+        (" · ".to_string(), separator),
+        (agent.repo.clone(), Style::default().fg(THEME.fg_mid)),
+        (" · ".to_string(), separator),
+        (agent.tool.clone(), Style::default().fg(THEME.fg_mid)),
+        (" · ".to_string(), separator),
+        (
+            agent.status.clone(),
+            Style::default().fg(status_color(&agent.status)),
+        ),
+    ];
+
+    let mut remaining = width;
+    let mut spans = Vec::new();
+    for (text, style) in pieces {
+        let len = text.width();
+        if len <= remaining {
+            remaining -= len;
+            spans.push(Span::styled(text, style));
+            continue;
+        }
+        if remaining > 0 {
+            // Leave a column for the ellipsis.
+            let mut used = 0;
+            let truncated: String = text
+                .chars()
+                .take_while(|ch| {
+                    used += ch.width().unwrap_or(0);
+                    used < remaining
+                })
+                .collect();
+            spans.push(Span::styled(format!("{truncated}…"), style));
+        }
+        break;
+    }
+    Line::from(spans)
+}
+
+pub(crate) fn status_color(status: &str) -> Color {
+    match status {
+        "running" => THEME.green,
+        "error" => THEME.red,
+        "idle" => THEME.blue,
+        _ => THEME.fg_dim,
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub(crate) enum PreviewCursorStyle {
+    Block,
+    Bar,
+    Underline,
+}
+
+pub(crate) fn preview_cursor_style(
+    shape: CursorShape,
+    visible: bool,
+    blink_on: bool,
+    scrolled: bool,
+) -> Option<PreviewCursorStyle> {
+    if !visible || scrolled || (shape.is_blinking() && !blink_on) {
+        return None;
+    }
+    Some(match shape {
+        CursorShape::Default | CursorShape::BlinkingBlock | CursorShape::SteadyBlock => {
+            PreviewCursorStyle::Block
+        }
+        CursorShape::BlinkingBar | CursorShape::SteadyBar => PreviewCursorStyle::Bar,
+        CursorShape::BlinkingUnderline | CursorShape::SteadyUnderline => {
+            PreviewCursorStyle::Underline
+        }
+    })
+}
+
+/// Search match cells to mark in a preview, by visible row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct PreviewHighlight {
+    pub(crate) row: usize,
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    /// The match the search last jumped to.
+    pub(crate) current: bool,
+}
+
 pub(crate) struct TermwizPreview<'a> {
     pub(crate) lines: Vec<Cow<'a, TermwizLine>>,
     pub(crate) cursor_pos: Option<(usize, usize)>,
+    pub(crate) cursor_style: PreviewCursorStyle,
+    pub(crate) highlights: Vec<PreviewHighlight>,
+    /// Approximate faint text by blending its color toward the background.
+    pub(crate) faint_blend: bool,
+    /// The agent's default colors (OSC 10/11), used for the clear and for
+    /// cells without explicit colors.
+    pub(crate) default_colors: DefaultColors,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub(crate) struct DefaultColors {
+    pub(crate) foreground: Option<Color>,
+    pub(crate) background: Option<Color>,
 }
 
 impl Widget for TermwizPreview<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
         let width = area.width as usize;
         let height = area.height as usize;
+        let clear_style = self
+            .default_colors
+            .background
+            .map_or_else(Style::default, |color| Style::default().bg(color));
         for y in 0..height {
             for x in 0..width {
                 if let Some(cell) = buf.cell_mut((area.x + x as u16, area.y + y as u16)) {
                     cell.set_symbol(" ");
+                    cell.set_style(clear_style);
                 }
             }
         }
@@ -325,7 +837,7 @@ impl Widget for TermwizPreview<'_> {
                 }
                 let symbol = cell.str();
                 let attrs = cell.attrs();
-                let style = termwiz_style_to_ratatui(attrs);
+                let style = termwiz_style_to_ratatui(attrs, self.faint_blend, self.default_colors);
                 if let Some(cell_buf) = buf.cell_mut((area.x + col as u16, area.y + row as u16)) {
                     cell_buf.set_symbol(symbol);
                     cell_buf.set_style(style);
@@ -333,14 +845,51 @@ impl Widget for TermwizPreview<'_> {
             }
         }
 
+        for highlight in &self.highlights {
+            if highlight.row >= height {
+                continue;
+            }
+            let background = if highlight.current {
+                THEME.orange
+            } else {
+                THEME.yellow_dim
+            };
+            for col in highlight.start..highlight.end.min(width) {
+                if let Some(cell) =
+                    buf.cell_mut((area.x + col as u16, area.y + highlight.row as u16))
+                {
+                    cell.set_style(cell.style().fg(THEME.bg).bg(background));
+                }
+            }
+        }
+
         if let Some((cursor_x, cursor_y)) = self.cursor_pos {
             if cursor_x < width && cursor_y < height {
                 if let Some(cursor_cell) =
                     buf.cell_mut((area.x + cursor_x as u16, area.y + cursor_y as u16))
                 {
-                    let mut style = cursor_cell.style();
-                    style = style.add_modifier(Modifier::REVERSED);
-                    cursor_cell.set_style(style);
+                    let is_blank = cursor_cell.symbol().trim().is_empty();
+                    match self.cursor_style {
+                        PreviewCursorStyle::Block => {
+                            let style = cursor_cell.style().add_modifier(Modifier::REVERSED);
+                            cursor_cell.set_style(style);
+                        }
+                        PreviewCursorStyle::Bar if is_blank => {
+                            cursor_cell.set_symbol("▏");
+                            cursor_cell.set_style(cursor_cell.style().fg(THEME.fg));
+                        }
+                        PreviewCursorStyle::Bar => {
+                            cursor_cell.set_style(cursor_cell.style().bg(THEME.visual));
+                        }
+                        PreviewCursorStyle::Underline if is_blank => {
+                            cursor_cell.set_symbol("▁");
+                            cursor_cell.set_style(cursor_cell.style().fg(THEME.fg));
+                        }
+                        PreviewCursorStyle::Underline => {
+                            let style = cursor_cell.style().add_modifier(Modifier::UNDERLINED);
+                            cursor_cell.set_style(style);
+                        }
+                    }
                 }
             }
         }
@@ -364,17 +913,52 @@ fn render_debug_sidebar(frame: &mut Frame, area: Rect, app: &mut App) {
             Style::default().fg(THEME.fg_dim),
         ))]
     });
+    let height = inner_area.height as usize;
+    app.debug_scroll = debug_scroll_offset(app.debug_scroll, lines.len(), height);
+    let total_lines = lines.len();
     let paragraph = Paragraph::new(lines)
         .style(Style::default().bg(THEME.bg_alt))
-        .alignment(ratatui::layout::Alignment::Left);
+        .alignment(ratatui::layout::Alignment::Left)
+        .scroll((app.debug_scroll as u16, 0));
     frame.render_widget(paragraph, inner_area);
+
+    if total_lines > height {
+        let scrollbar_area = Rect {
+            x: area.x + area.width.saturating_sub(1),
+            y: inner_area.y,
+            width: 1,
+            height: inner_area.height,
+        };
+        let mut scrollbar_state = ScrollbarState::new(total_lines - height + 1)
+            .position(app.debug_scroll)
+            .viewport_content_length(height);
+        let scrollbar = Scrollbar::new(ScrollbarOrientation::VerticalRight)
+            .style(Style::default().fg(THEME.fg_dim));
+        frame.render_stateful_widget(scrollbar, scrollbar_area, &mut scrollbar_state);
+    }
+}
+
+/// Clamps the debug scroll so the last line can reach the bottom of
+/// `height` rows but no further; content that fits does not scroll.
+fn debug_scroll_offset(scroll: usize, total_lines: usize, height: usize) -> usize {
+    scroll.min(total_lines.saturating_sub(height))
 }
 
 fn debug_lines_for_agent(app: &App) -> Option<Vec<Line<'static>>> {
     let agent_name = app.preview_agent.as_ref()?;
     let agent = app.agents.iter().find(|agent| &agent.name == agent_name)?;
     let mut lines = Vec::new();
+    let mut color_line = None;
     lines.push(format!("agent: {}", agent.name));
+    lines.push("keys: (<>) width ([]) scroll".to_string());
+    lines.push(format!(
+        "preview (S): {} screen",
+        if app.show_inactive_surface {
+            "inactive"
+        } else {
+            "active"
+        }
+    ));
     if let Some(snapshot) = &agent.debug_data.terminal_snapshot {
         lines.push(format!("alt screen: {}", snapshot.alt_screen));
         lines.push(format!(
@@ -382,12 +966,18 @@ fn debug_lines_for_agent(app: &App) -> Option<Vec<Line<'static>>> {
             snapshot.mouse_tracking || snapshot.mouse_button_tracking || snapshot.mouse_any_event
         ));
         lines.push(format!("mouse sgr: {}", snapshot.mouse_sgr));
+        lines.push(format!("bracketed paste: {}", snapshot.bracketed_paste));
+        lines.push(format!(
+            "app cursor keys: {}",
+            snapshot.application_cursor_keys
+        ));
         lines.push(format!("cursor visible: {}", snapshot.cursor_visible));
         lines.push(format!("cursor shape: {:?}", snapshot.cursor_shape));
         lines.push(format!("origin mode: {}", snapshot.origin_mode));
         lines.push(format!("wrap mode: {}", snapshot.wrap_mode));
         lines.push(format!("insert mode: {}", snapshot.insert_mode));
         lines.push(format!("scroll region: {:?}", snapshot.scroll_region));
+        color_line = Some((lines.len(), &snapshot.attributes));
         lines.push(format!(
             "attrs fg/bg: {:?} {:?}",
             snapshot.attributes.foreground, snapshot.attributes.background
@@ -420,29 +1010,56 @@ fn debug_lines_for_agent(app: &App) -> Option<Vec<Line<'static>>> {
     } else {
         lines.push("history: none".to_string());
     }
-    Some(
-        lines
-            .into_iter()
-            .map(|line| Line::from(Span::styled(line, Style::default().fg(THEME.fg))))
-            .collect(),
-    )
+    let mut lines: Vec<Line<'static>> = lines
+        .into_iter()
+        .map(|line| Line::from(Span::styled(line, Style::default().fg(THEME.fg))))
+        .collect();
+    if let Some((index, attributes)) = color_line {
+        lines[index].spans.extend([
+            Span::raw(" "),
+            color_swatch_span(&attributes.foreground),
+            Span::raw(" "),
+            color_swatch_span(&attributes.background),
+        ]);
+    }
+    Some(lines)
+}
+
+/// A two-cell block painted in the given snapshot color; the default color
+/// has no swatch and renders as a dim placeholder instead.
+fn color_swatch_span(color: &TerminalColor) -> Span<'static> {
+    match termwiz_color_to_ratatui(crate::snapshot_color_to_termwiz(color)) {
+        Some(color) => Span::styled("██", Style::default().fg(color)),
+        None => Span::styled("--", Style::default().fg(THEME.fg_dim)),
+    }
 }
 
-fn termwiz_style_to_ratatui(attrs: &CellAttributes) -> Style {
+fn termwiz_style_to_ratatui(
+    attrs: &CellAttributes,
+    faint_blend: bool,
+    defaults: DefaultColors,
+) -> Style {
     let mut style = Style::default();
-    if let Some(color) = termwiz_color_to_ratatui(attrs.foreground()) {
+    let foreground = termwiz_color_to_ratatui(attrs.foreground()).or(defaults.foreground);
+    let background = termwiz_color_to_ratatui(attrs.background()).or(defaults.background);
+    if let Some(color) = foreground {
         style = style.fg(color);
     }
-    if let Some(color) = termwiz_color_to_ratatui(attrs.background()) {
+    if let Some(color) = background {
         style = style.bg(color);
     }
     let mut modifier = Modifier::empty();
     match attrs.intensity() {
         Intensity::Bold => modifier |= Modifier::BOLD,
-        Intensity::Half => modifier |= Modifier::DIM,
+        Intensity::Half => {
+            modifier |= Modifier::DIM;
+            if faint_blend {
+                style = style.fg(faint_color(foreground, background));
+            }
+        }
         Intensity::Normal => {}
     }
-    if matches!(attrs.underline(), Underline::Single | Underline::Double) {
+    if attrs.underline() != Underline::None {
         modifier |= Modifier::UNDERLINED;
     }
     if attrs.italic() {
@@ -463,22 +1080,324 @@ fn termwiz_style_to_ratatui(attrs: &CellAttributes) -> Style {
     style.add_modifier(modifier)
 }
 
+const FAINT_BLEND_AMOUNT: f32 = 0.45;
+
+/// Moves a foreground color part of the way toward its background so faint
+/// text stays distinguishable on terminals that render DIM as normal.
+fn faint_color(foreground: Option<Color>, background: Option<Color>) -> Color {
+    let resolve = |color: Option<Color>, default: Color| match color.unwrap_or(default) {
+        Color::Indexed(index) => {
+            let (r, g, b) = crate::theme::indexed_to_rgb(index);
+            Color::Rgb(r, g, b)
+        }
+        color => color,
+    };
+    crate::blend_color(
+        resolve(foreground, THEME.fg),
+        resolve(background, THEME.bg),
+        FAINT_BLEND_AMOUNT,
+    )
+}
+
 fn termwiz_color_to_ratatui(color: ColorAttribute) -> Option<Color> {
     match color {
         ColorAttribute::Default => None,
         ColorAttribute::PaletteIndex(index) => Some(Color::Indexed(index)),
         ColorAttribute::TrueColorWithDefaultFallback(tuple)
-        | ColorAttribute::TrueColorWithPaletteFallback(tuple, _) => {
-            let SrgbaTuple(r, g, b, _) = tuple;
-            Some(Color::Rgb(
-                (r * 255.0) as u8,
-                (g * 255.0) as u8,
-                (b * 255.0) as u8,
-            ))
-        }
+        | ColorAttribute::TrueColorWithPaletteFallback(tuple, _) => Some(srgba_to_color(tuple)),
     }
 }
 
+fn srgba_to_color(SrgbaTuple(r, g, b, _): SrgbaTuple) -> Color {
+    Color::Rgb((r * 255.0) as u8, (g * 255.0) as u8, (b * 255.0) as u8)
+}
+
 fn build_name_line(agent: &Agent, animation_start: std::time::Instant) -> Line<'static> {
     crate::build_name_line(agent, animation_start)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn debug_scroll_clamps_to_the_available_height() {
+        // 30 lines in 10 rows scroll until the last line sits at the bottom.
+        assert_eq!(debug_scroll_offset(5, 30, 10), 5);
+        assert_eq!(debug_scroll_offset(25, 30, 10), 20);
+        // Content that fits never scrolls.
+        assert_eq!(debug_scroll_offset(3, 8, 10), 0);
+        assert_eq!(debug_scroll_offset(3, 30, 0), 3);
+    }
+
+    #[test]
+    fn visible_sidebar_entries_follow_density() {
+        assert_eq!(visible_sidebar_entries(20, SidebarDensity::Comfortable), 5);
+        assert_eq!(visible_sidebar_entries(23, SidebarDensity::Comfortable), 5);
+        assert_eq!(visible_sidebar_entries(20, SidebarDensity::Compact), 20);
+        assert_eq!(visible_sidebar_entries(2, SidebarDensity::Comfortable), 1);
+        assert_eq!(visible_sidebar_entries(0, SidebarDensity::Compact), 1);
+    }
+
+    #[test]
+    fn sidebar_density_setting_and_toggle() {
+        assert_eq!(
+            SidebarDensity::from_setting(Some(" Compact ")),
+            SidebarDensity::Compact
+        );
+        assert_eq!(
+            SidebarDensity::from_setting(Some("dense")),
+            SidebarDensity::Comfortable
+        );
+        assert_eq!(
+            SidebarDensity::from_setting(None),
+            SidebarDensity::Comfortable
+        );
+        assert_eq!(
+            SidebarDensity::Compact.toggled(),
+            SidebarDensity::Comfortable
+        );
+    }
+
+    #[test]
+    fn color_swatch_uses_snapshot_color() {
+        let swatch = color_swatch_span(&TerminalColor::Rgb {
+            r: 255,
+            g: 128,
+            b: 0,
+        });
+        assert_eq!(swatch.content, "██");
+        assert_eq!(swatch.style.fg, Some(Color::Rgb(255, 128, 0)));
+
+        let swatch = color_swatch_span(&TerminalColor::Ansi(196));
+        assert_eq!(swatch.style.fg, Some(Color::Indexed(196)));
+    }
+
+    #[test]
+    fn faint_color_blends_toward_background() {
+        assert_eq!(
+            faint_color(Some(Color::Rgb(200, 100, 0)), Some(Color::Rgb(0, 0, 100))),
+            Color::Rgb(110, 55, 45)
+        );
+        assert_eq!(
+            faint_color(None, None),
+            crate::blend_color(THEME.fg, THEME.bg, FAINT_BLEND_AMOUNT)
+        );
+        assert_eq!(
+            faint_color(Some(Color::Indexed(15)), Some(Color::Indexed(0))),
+            Color::Rgb(140, 140, 140)
+        );
+    }
+
+    #[test]
+    fn faint_blend_is_optional() {
+        let mut attrs = CellAttributes::default();
+        attrs.set_intensity(Intensity::Half);
+        let blended = termwiz_style_to_ratatui(&attrs, true, DefaultColors::default());
+        let plain = termwiz_style_to_ratatui(&attrs, false, DefaultColors::default());
+        assert!(blended.add_modifier.contains(Modifier::DIM));
+        assert!(blended.fg.is_some());
+        assert!(plain.add_modifier.contains(Modifier::DIM));
+        assert_eq!(plain.fg, None);
+    }
+
+    #[test]
+    fn search_matches_are_highlighted() {
+        let line = TermwizLine::from_text("a hit, a hit", &CellAttributes::default(), 0, None);
+        let search = PreviewSearch {
+            agent: "wild-cat".to_string(),
+            query: "hit".to_string(),
+            editing: false,
+            current: Some(SearchMatch {
+                line: 4,
+                start: 9,
+                end: 12,
+            }),
+        };
+        let lines = vec![Cow::Owned(line)];
+        let highlights = search_highlights(&lines, 4, &search);
+        assert_eq!(highlights.len(), 2);
+        assert!(!highlights[0].current && highlights[1].current);
+
+        let preview = TermwizPreview {
+            lines,
+            cursor_pos: None,
+            cursor_style: PreviewCursorStyle::Block,
+            highlights,
+            faint_blend: false,
+            default_colors: DefaultColors::default(),
+        };
+        let area = Rect::new(0, 0, 12, 1);
+        let mut buf = Buffer::empty(area);
+        preview.render(area, &mut buf);
+        assert_eq!(buf[(0, 0)].bg, Color::Reset);
+        assert_eq!(buf[(2, 0)].bg, THEME.yellow_dim);
+        assert_eq!(buf[(4, 0)].bg, THEME.yellow_dim);
+        assert_eq!(buf[(5, 0)].bg, Color::Reset);
+        assert_eq!(buf[(9, 0)].bg, THEME.orange);
+    }
+
+    #[test]
+    fn preview_clear_uses_agent_default_background() {
+        let mut line = TermwizLine::from_text("ab", &CellAttributes::default(), 0, None);
+        let mut red = CellAttributes::default();
+        red.set_background(ColorAttribute::PaletteIndex(1));
+        line.set_cell(1, termwiz::cell::Cell::new('b', red), 0);
+        let background = Color::Rgb(0x10, 0x20, 0x30);
+        let preview = TermwizPreview {
+            lines: vec![Cow::Owned(line)],
+            cursor_pos: None,
+            cursor_style: PreviewCursorStyle::Block,
+            highlights: Vec::new(),
+            faint_blend: false,
+            default_colors: DefaultColors {
+                foreground: None,
+                background: Some(background),
+            },
+        };
+        let area = Rect::new(0, 0, 4, 2);
+        let mut buf = Buffer::empty(area);
+
+        preview.render(area, &mut buf);
+
+        assert_eq!(buf[(0, 0)].bg, background);
+        assert_eq!(buf[(1, 0)].bg, Color::Indexed(1));
+        assert_eq!(buf[(3, 0)].bg, background);
+        assert_eq!(buf[(2, 1)].bg, background);
+    }
+
+    #[test]
+    fn default_colors_apply_only_to_default_colored_cells() {
+        let defaults = DefaultColors {
+            foreground: Some(Color::Rgb(1, 2, 3)),
+            background: Some(Color::Rgb(4, 5, 6)),
+        };
+        let style = termwiz_style_to_ratatui(&CellAttributes::default(), false, defaults);
+        assert_eq!(style.fg, Some(Color::Rgb(1, 2, 3)));
+        assert_eq!(style.bg, Some(Color::Rgb(4, 5, 6)));
+
+        let mut attrs = CellAttributes::default();
+        attrs.set_foreground(ColorAttribute::PaletteIndex(2));
+        let style = termwiz_style_to_ratatui(&attrs, false, defaults);
+        assert_eq!(style.fg, Some(Color::Indexed(2)));
+        assert_eq!(style.bg, Some(Color::Rgb(4, 5, 6)));
+    }
+
+    #[test]
+    fn color_swatch_marks_default_color() {
+        let swatch = color_swatch_span(&TerminalColor::Default);
+        assert_eq!(swatch.content, "--");
+        assert_eq!(swatch.style.fg, Some(THEME.fg_dim));
+    }
+
+    #[test]
+    fn selection_wraps_past_ends_when_enabled() {
+        assert_eq!(step_selection(2, 3, SelectionStep::Next, true), 0);
+        assert_eq!(step_selection(0, 3, SelectionStep::Previous, true), 2);
+        assert_eq!(step_selection(1, 3, SelectionStep::Next, true), 2);
+    }
+
+    #[test]
+    fn selection_clamps_at_ends_when_disabled() {
+        assert_eq!(step_selection(2, 3, SelectionStep::Next, false), 2);
+        assert_eq!(step_selection(0, 3, SelectionStep::Previous, false), 0);
+        assert_eq!(step_selection(0, 0, SelectionStep::Next, true), 0);
+    }
+
+    fn agent(label: &str, repo: &str, tool: &str, status: &str) -> Agent {
+        Agent {
+            name: label.to_string(),
+            label: label.to_string(),
+            repo: repo.to_string(),
+            tool: tool.to_string(),
+            status: status.to_string(),
+            worktree_path: String::new(),
+            output: None,
+            dirty_count: None,
+            exit_code: None,
+            debug_data: Default::default(),
+        }
+    }
+
+    fn line_text(line: &Line) -> String {
+        line.spans
+            .iter()
+            .map(|span| span.content.as_ref())
+            .collect()
+    }
+
+    #[test]
+    fn header_line_lists_label_repo_tool_status() {
+        let agent = agent("wild-cat", "forest", "claude", "running");
+        let line = preview_header_line(&agent, 80);
+        assert_eq!(line_text(&line), "wild-cat · forest · claude · running");
+    }
+
+    #[test]
+    fn header_line_truncates_to_width() {
+        let agent = agent("wild-cat", "forest", "claude", "running");
+        let line = preview_header_line(&agent, 14);
+        assert_eq!(line_text(&line), "wild-cat · fo…");
+        assert_eq!(line_text(&line).chars().count(), 14);
+        assert_eq!(line_text(&preview_header_line(&agent, 0)), "");
+    }
+
+    #[test]
+    fn header_line_truncates_wide_labels_by_display_width() {
+        let agent = agent("猫猫猫猫猫", "forest", "claude", "running");
+        let line = preview_header_line(&agent, 6);
+        assert_eq!(line_text(&line), "猫猫…");
+        assert_eq!(line.width(), 5);
+        assert_eq!(line_text(&preview_header_line(&agent, 2)), "…");
+    }
+
+    #[test]
+    fn block_shapes_render_as_block() {
+        for shape in [
+            CursorShape::Default,
+            CursorShape::BlinkingBlock,
+            CursorShape::SteadyBlock,
+        ] {
+            assert_eq!(
+                preview_cursor_style(shape, true, true, false),
+                Some(PreviewCursorStyle::Block)
+            );
+        }
+    }
+
+    #[test]
+    fn bar_and_underline_shapes_keep_their_style() {
+        assert_eq!(
+            preview_cursor_style(CursorShape::SteadyBar, true, false, false),
+            Some(PreviewCursorStyle::Bar)
+        );
+        assert_eq!(
+            preview_cursor_style(CursorShape::BlinkingBar, true, true, false),
+            Some(PreviewCursorStyle::Bar)
+        );
+        assert_eq!(
+            preview_cursor_style(CursorShape::SteadyUnderline, true, false, false),
+            Some(PreviewCursorStyle::Underline)
+        );
+        assert_eq!(
+            preview_cursor_style(CursorShape::BlinkingUnderline, true, true, false),
+            Some(PreviewCursorStyle::Underline)
+        );
+    }
+
+    #[test]
+    fn cursor_hidden_when_invisible_scrolled_or_blinked_off() {
+        assert_eq!(
+            preview_cursor_style(CursorShape::SteadyBar, false, true, false),
+            None
+        );
+        assert_eq!(
+            preview_cursor_style(CursorShape::SteadyBlock, true, true, true),
+            None
+        );
+        assert_eq!(
+            preview_cursor_style(CursorShape::BlinkingUnderline, true, false, false),
+            None
+        );
+    }
+}
@@ -1,9 +1,10 @@
 use crate::theme::THEME;
-use crate::App;
+use crate::{delete_repo, App};
 use ratatui::{
-    layout::Rect,
-    style::Style,
-    widgets::{Block, Clear, Paragraph, Wrap},
+    layout::{Constraint, Layout, Rect},
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
     Frame,
 };
 use std::error::Error;
@@ -24,13 +25,48 @@ impl Window for ShowReposWindow {
 }
 
 fn handle_show_repos_keys(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
+    if app.confirming_repo_delete {
+        match key.key {
+            KeyCode::Char('y') => {
+                app.confirming_repo_delete = false;
+                delete_selected_repo(app);
+            }
+            KeyCode::Char('n') | KeyCode::Escape => app.confirming_repo_delete = false,
+            _ => {}
+        }
+        return Ok(false);
+    }
     match key.key {
         KeyCode::Escape | KeyCode::Enter => app.focused_window = None,
+        KeyCode::UpArrow | KeyCode::Char('k') => {
+            app.repo_list_selected = app.repo_list_selected.saturating_sub(1);
+        }
+        KeyCode::DownArrow | KeyCode::Char('j') if app.repo_list_selected + 1 < app.repos.len() => {
+            app.repo_list_selected += 1;
+        }
+        KeyCode::Char('d') if !app.repos.is_empty() => app.confirming_repo_delete = true,
         _ => {}
     }
     Ok(false)
 }
 
+fn delete_selected_repo(app: &mut App) {
+    let Some(repo) = app.repos.get(app.repo_list_selected) else {
+        return;
+    };
+    let name = repo.name.clone();
+    match delete_repo(&app.client, &app.server_url, &name) {
+        Ok(()) => {
+            app.refresh_data();
+            app.repo_list_selected = app
+                .repo_list_selected
+                .min(app.repos.len().saturating_sub(1));
+            app.set_status(format!("removed repo {name}"));
+        }
+        Err(err) => app.report_request_error(err),
+    }
+}
+
 fn render_show_repos_window(frame: &mut Frame, app: &App, base: Rect) {
     let area = crate::centered_rect(70, 50, base);
     frame.render_widget(Clear, area);
@@ -40,15 +76,36 @@ fn render_show_repos_window(frame: &mut Frame, app: &App, base: Rect) {
         .border_style(Style::default().fg(THEME.border));
     frame.render_widget(&block, area);
     let inner = block.inner(area);
+    let sections = Layout::vertical([Constraint::Min(0), Constraint::Length(1)]).split(inner);
 
-    let repo_lines: Vec<String> = app
+    let repo_lines: Vec<Line> = app
         .repos
         .iter()
-        .map(|repo| format!("{}  {}", repo.name, repo.path.to_string_lossy()))
+        .enumerate()
+        .map(|(index, repo)| {
+            let text = format!("{}  {}", repo.name, repo.path.to_string_lossy());
+            if index == app.repo_list_selected {
+                Line::styled(
+                    format!("> {text}"),
+                    Style::default().fg(THEME.fg).add_modifier(Modifier::BOLD),
+                )
+            } else {
+                Line::styled(format!("  {text}"), Style::default().fg(THEME.fg_mid))
+            }
+        })
         .collect();
+    frame.render_widget(Paragraph::new(repo_lines), sections[0]);
 
-    let paragraph = Paragraph::new(repo_lines.join("\n"))
-        .wrap(Wrap { trim: true })
-        .style(Style::default().fg(THEME.fg_mid));
-    frame.render_widget(paragraph, inner);
+    let hint = match app.repos.get(app.repo_list_selected) {
+        Some(repo) if app.confirming_repo_delete => Line::from(vec![
+            Span::raw("Remove repo "),
+            Span::styled(repo.name.clone(), Style::default().fg(THEME.orange)),
+            Span::raw("? (y) yes  (n) no"),
+        ]),
+        _ => Line::styled(
+            "(j/k) select   (d) remove repo   (Esc) close",
+            Style::default().fg(THEME.fg_dim),
+        ),
+    };
+    frame.render_widget(Paragraph::new(hint), sections[1]);
 }
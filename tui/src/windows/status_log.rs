@@ -0,0 +1,71 @@
+use crate::theme::THEME;
+use crate::App;
+use ratatui::{
+    layout::Rect,
+    style::Style,
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
+    Frame,
+};
+use std::error::Error;
+use std::time::Instant;
+use termwiz::input::{KeyCode, KeyEvent};
+
+use super::Window;
+
+pub struct StatusLogWindow;
+
+impl Window for StatusLogWindow {
+    fn render(frame: &mut Frame, app: &mut App, area: Rect) {
+        render_status_log_window(frame, app, area);
+    }
+
+    fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
+        handle_status_log_keys(app, key)
+    }
+}
+
+fn handle_status_log_keys(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
+    match key.key {
+        KeyCode::Escape | KeyCode::Enter | KeyCode::Char('L') => app.focused_window = None,
+        _ => {}
+    }
+    Ok(false)
+}
+
+fn render_status_log_window(frame: &mut Frame, app: &App, base: Rect) {
+    let area = crate::centered_rect(70, 50, base);
+    frame.render_widget(Clear, area);
+    let block = Block::bordered()
+        .title("Messages")
+        .style(Style::default().bg(THEME.bg_alt2).fg(THEME.fg))
+        .border_style(Style::default().fg(THEME.border));
+    frame.render_widget(&block, area);
+    let inner = block.inner(area);
+
+    // Newest first; the oldest fall off the bottom when the log is long.
+    let now = Instant::now();
+    let lines: Vec<Line> = if app.status_log.entries.is_empty() {
+        vec![Line::styled(
+            "no messages yet",
+            Style::default().fg(THEME.fg_dim),
+        )]
+    } else {
+        app.status_log
+            .entries
+            .iter()
+            .rev()
+            .map(|entry| {
+                let age = now.saturating_duration_since(entry.at).as_secs();
+                Line::from(vec![
+                    Span::styled(
+                        format!("{age:>4}s ago  "),
+                        Style::default().fg(THEME.fg_dim),
+                    ),
+                    Span::styled(entry.text(), Style::default().fg(THEME.fg_mid)),
+                ])
+            })
+            .collect()
+    };
+    frame.render_widget(Paragraph::new(lines), inner);
+}
@@ -0,0 +1,96 @@
+use crate::theme::THEME;
+use crate::App;
+use ratatui::{
+    layout::Rect,
+    style::{Color, Style},
+    text::{Line, Span},
+    widgets::{Block, Clear, Paragraph},
+    Frame,
+};
+use std::error::Error;
+use termwiz::input::{KeyCode, KeyEvent};
+
+use super::Window;
+
+/// Agent prefixes cycle through these so neighbouring lines from different
+/// agents are easy to tell apart.
+const AGENT_COLORS: [Color; 6] = [
+    THEME.blue,
+    THEME.magenta,
+    THEME.green,
+    THEME.orange,
+    THEME.yellow,
+    THEME.red,
+];
+const LABEL_WIDTH_LIMIT: usize = 20;
+
+pub struct TailWindow;
+
+impl Window for TailWindow {
+    fn render(frame: &mut Frame, app: &mut App, area: Rect) {
+        render_tail_window(frame, app, area);
+    }
+
+    fn handle_key_event(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
+        handle_tail_keys(app, key)
+    }
+}
+
+fn handle_tail_keys(app: &mut App, key: KeyEvent) -> Result<bool, Box<dyn Error>> {
+    match key.key {
+        KeyCode::Escape | KeyCode::Enter | KeyCode::Char('t') => app.focused_window = None,
+        _ => {}
+    }
+    Ok(false)
+}
+
+/// The same agent always gets the same color, however the list is sorted.
+fn agent_color(agent: &str) -> Color {
+    let hash = agent.bytes().fold(0usize, |hash, byte| {
+        hash.wrapping_mul(31).wrapping_add(byte as usize)
+    });
+    AGENT_COLORS[hash % AGENT_COLORS.len()]
+}
+
+fn render_tail_window(frame: &mut Frame, app: &App, base: Rect) {
+    let area = crate::centered_rect(90, 80, base);
+    frame.render_widget(Clear, area);
+    let block = Block::bordered()
+        .title("Tail (all running agents)")
+        .style(Style::default().bg(THEME.bg_alt2).fg(THEME.fg))
+        .border_style(Style::default().fg(THEME.border));
+    frame.render_widget(&block, area);
+    let inner = block.inner(area);
+
+    // Newest at the bottom, like a terminal following a log.
+    let feed = &app.tail_feed.lines;
+    let visible = feed
+        .iter()
+        .skip(feed.len().saturating_sub(inner.height as usize));
+    let label_width = feed
+        .iter()
+        .map(|line| line.label.chars().count())
+        .max()
+        .unwrap_or(0)
+        .min(LABEL_WIDTH_LIMIT);
+    let lines: Vec<Line> = if feed.is_empty() {
+        vec![Line::styled(
+            "no output from running agents yet",
+            Style::default().fg(THEME.fg_dim),
+        )]
+    } else {
+        visible
+            .map(|line| {
+                let label: String = line.label.chars().take(label_width).collect();
+                Line::from(vec![
+                    Span::styled(
+                        format!("{label:<label_width$} | "),
+                        Style::default().fg(agent_color(&line.agent)),
+                    ),
+                    Span::styled(line.text.clone(), Style::default().fg(THEME.fg_mid)),
+                ])
+            })
+            .collect()
+    };
+    frame.render_widget(Paragraph::new(lines), inner);
+}